@@ -0,0 +1,195 @@
+// 管理页面，数据来自 /material/api/v1 下的JSON接口，每30秒刷新一次
+// 服务配置了接口密钥时需要admin密钥，第一次回复401/403时输入，保存在浏览器中
+// 路径前缀可以配置，接口地址相对脚本地址 /material/admin/admin.js
+const API = new URL("../api/v1", document.currentScript.src).pathname;
+const REFRESH_INTERVAL = 30000;
+const KEY_STORAGE = "material-api-key";
+
+const STATES = {
+  running: "运行中",
+  succeeded: "完成",
+  failed: "失败",
+  cancelled: "已取消",
+};
+
+async function fetchData(path, init = {}) {
+  const key = localStorage.getItem(KEY_STORAGE);
+  const headers = { ...init.headers, ...(key ? { Authorization: `Bearer ${key}` } : {}) };
+  const response = await fetch(API + path, { ...init, headers });
+  if (response.status === 401 || response.status === 403) {
+    // 同时加载的其他部分可能已经输入了新的密钥
+    if (localStorage.getItem(KEY_STORAGE) !== key) {
+      return fetchData(path, init);
+    }
+    const input = prompt("请输入管理接口密钥");
+    if (input) {
+      localStorage.setItem(KEY_STORAGE, input.trim());
+      return fetchData(path, init);
+    }
+  }
+  const body = await response.json();
+  if (!response.ok) {
+    throw new Error(body.message || response.statusText);
+  }
+  return body.data;
+}
+
+function cell(text, className) {
+  const td = document.createElement("td");
+  td.textContent = text ?? "";
+  if (className) {
+    td.className = className;
+  }
+  return td;
+}
+
+function fillRows(id, rows) {
+  const tbody = document.getElementById(id);
+  tbody.replaceChildren(
+    ...rows.map((cells) => {
+      const tr = document.createElement("tr");
+      tr.append(...cells.map((c) => (c instanceof Node ? c : cell(c))));
+      return tr;
+    }),
+  );
+}
+
+function fillCards(id, cards) {
+  document.getElementById(id).replaceChildren(
+    ...cards.map(([label, value]) => {
+      const card = document.createElement("div");
+      card.className = "card";
+      const v = document.createElement("div");
+      v.className = "value";
+      v.textContent = value;
+      const l = document.createElement("div");
+      l.className = "label";
+      l.textContent = label;
+      card.append(v, l);
+      return card;
+    }),
+  );
+}
+
+function counts(map) {
+  return Object.entries(map)
+    .sort((a, b) => b[1] - a[1])
+    .map(([name, count]) => [name, count]);
+}
+
+function seconds(ms) {
+  return ms == null ? "" : (ms / 1000).toFixed(1) + "s";
+}
+
+async function loadJobs() {
+  const data = await fetchData("/admin/jobs");
+  fillCards("queue", [
+    ["执行中", `${data.queue.running} / ${data.queue.capacity}`],
+    ["排队", data.queue.waiting],
+    ["维护暂停", data.queue.held],
+    ["完成", data.totals.succeeded],
+    ["失败", data.totals.failed],
+    ["已取消", data.totals.cancelled],
+  ]);
+  fillRows(
+    "jobs",
+    data.recent.map((job) => [
+      job.id.slice(0, 8),
+      job.kind,
+      cell(STATES[job.state] ?? job.state, "state-" + job.state),
+      job.stage,
+      job.input,
+      seconds(job.duration_ms),
+      new Date(job.created_at).toLocaleString(),
+      cell(job.error, "error"),
+    ]),
+  );
+  const button = document.getElementById("maintenance");
+  button.hidden = false;
+  button.dataset.readOnly = data.read_only;
+  button.textContent = data.read_only ? "恢复读写" : "进入维护";
+}
+
+// 只读模式下任务暂停执行，恢复读写后继续
+async function toggleMaintenance() {
+  const readOnly = document.getElementById("maintenance").dataset.readOnly !== "true";
+  if (readOnly && !confirm("进入维护后新任务只排队不执行，不能修改分类词表和模具状态，确定吗?")) {
+    return;
+  }
+  await fetchData("/admin/maintenance", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ read_only: readOnly }),
+  });
+  await refresh();
+}
+
+async function loadCorpus() {
+  const data = await fetchData("/admin/corpus");
+  fillCards("corpus", [
+    ["模具", data.models],
+    ["模具类型", Object.keys(data.model_types).length],
+    ["缺少id", data.missing_ids],
+  ]);
+  fillRows("model-types", counts(data.model_types));
+  fillRows("statuses", counts(data.statuses));
+  fillRows("shards", counts(data.shards));
+}
+
+async function loadQuality() {
+  const data = await fetchData("/feedback/metrics");
+  fillRows(
+    "quality",
+    data.map((m) => [
+      m.prompt_version,
+      m.model_name,
+      m.jobs,
+      m.good,
+      m.bad,
+      m.precision == null ? "" : (m.precision * 100).toFixed(1) + "%",
+    ]),
+  );
+}
+
+async function loadConfig() {
+  const data = await fetchData("/admin/config");
+  const items = [
+    ["版本", data.version],
+    ["监听地址", data.bind],
+    ["路径前缀", data.base_path],
+    ["HTTPS", data.tls ? "已启用" : "未启用"],
+    ["模型服务", data.provider.endpoint],
+    ["服务类型", data.provider.kind ?? "ollama"],
+    ["模型", data.provider.model_name],
+    ["重试次数", data.max_retries],
+    ["超时", data.timeout_seconds + "s"],
+    ["模具存储", data.storage],
+    ["代理", data.proxy ? "已配置" : "无"],
+    ["模型接口密钥", data.secrets.ai_api_key ? "已配置" : "未配置"],
+    ["机器人密钥", data.secrets.bot_api_key ? "已配置" : "未配置"],
+  ];
+  document.getElementById("config").replaceChildren(
+    ...items.flatMap(([label, value]) => {
+      const dt = document.createElement("dt");
+      dt.textContent = label;
+      const dd = document.createElement("dd");
+      dd.textContent = value ?? "";
+      return [dt, dd];
+    }),
+  );
+}
+
+async function refresh() {
+  const results = await Promise.allSettled([loadJobs(), loadCorpus(), loadQuality(), loadConfig()]);
+  const failed = results.filter((r) => r.status === "rejected");
+  document.getElementById("updated").textContent = failed.length
+    ? "部分数据加载失败: " + failed.map((r) => r.reason.message).join("; ")
+    : "更新于 " + new Date().toLocaleTimeString();
+}
+
+document.getElementById("refresh").addEventListener("click", refresh);
+document.getElementById("maintenance").addEventListener("click", () =>
+  toggleMaintenance().catch((e) => alert(e.message)),
+);
+refresh();
+setInterval(refresh, REFRESH_INTERVAL);
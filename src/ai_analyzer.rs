@@ -1,10 +1,35 @@
-use crate::{AnalyzerError, IResult, config::AiConfig};
+use crate::{
+    AnalyzerError, IResult,
+    config::AiConfig,
+    drawing::{DrawingFormat, ScaleConflict},
+    http::provider_request,
+    json_extract::extract_json,
+    text::preview,
+    usage::TokenUsage,
+};
 use base64::{Engine as _, engine::general_purpose};
+use futures_util::future::join_all;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
-use tokio::time::{Duration, timeout};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::{
+    sync::Semaphore,
+    time::{Duration, timeout},
+};
 use tracing::{debug, error, info, warn};
 
+/// 给出分析结果的模型服务
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisProvider {
+    /// 远程接口
+    Api,
+    /// 本地Ollama模型
+    Local,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ViewAnalysis {
     Model(ModelAnalysis),
@@ -39,6 +64,9 @@ pub struct ModelAnalysis {
     pub y_max: Option<f64>,
     pub x_tolerance: Option<String>,
     pub y_tolerance: Option<String>,
+    /// 之前的结果中没有
+    #[serde(default)]
+    pub provider: Option<AnalysisProvider>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,11 +74,17 @@ pub struct ErrAnalysis {
     pub image_path: PathBuf,
     pub error_message: String,
     pub attempt_number: u32,
+    /// 最后尝试的模型服务，之前的结果中没有
+    #[serde(default)]
+    pub provider: Option<AnalysisProvider>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InfoAnalysis {
     pub image_path: PathBuf,
+    /// 之前的结果中没有
+    #[serde(default)]
+    pub provider: Option<AnalysisProvider>,
     pub part_info: Option<PartInfo>,
     pub company: Option<String>,
     pub text_content: Option<Vec<String>>,
@@ -61,6 +95,9 @@ pub struct PartInfo {
     pub name: Option<String>,
     pub material: Option<String>,
     pub scale: Option<String>,
+    /// 图幅(A0~A4)
+    #[serde(default)]
+    pub sheet_size: Option<String>,
     pub drawing_number: Option<String>,
 }
 
@@ -72,8 +109,13 @@ pub struct AnalysisIResult {
     pub engineering_views: u32,
     pub info_views: u32,
     pub views: Vec<ViewAnalysis>,
+    /// 标题栏中的比例和图幅
+    pub drawing: DrawingFormat,
     pub dimensions: DimensionSummary,
     pub anomalies: AnomalyReport,
+    /// 分析过程中模型服务的token用量
+    #[serde(default)]
+    pub usage: TokenUsage,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +133,8 @@ pub struct AnomalyReport {
     pub corrected_x_max: Option<f64>,
     pub corrected_y_max: Option<f64>,
     pub gap_analysis: Option<GapAnalysis>,
+    /// 修正后的尺寸与图纸比例/图幅矛盾
+    pub scale_conflict: Option<ScaleConflict>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,12 +150,22 @@ pub struct GapAnalysis {
 pub struct AiAnalyzer {
     config: AiConfig,
     client: reqwest::Client,
+    /// 上次取出后累计的token用量
+    usage: Mutex<TokenUsage>,
 }
 
 impl AiAnalyzer {
-    pub fn new(config: AiConfig) -> Self {
-        let client = reqwest::Client::new();
-        Self { config, client }
+    pub fn new(config: AiConfig, client: reqwest::Client) -> Self {
+        Self {
+            config,
+            client,
+            usage: Mutex::default(),
+        }
+    }
+
+    /// 取出累计的token用量并清零
+    fn take_usage(&self) -> TokenUsage {
+        std::mem::take(&mut *self.usage.lock().unwrap())
     }
 
     /// Create analysis prompt for vision model
@@ -139,7 +193,8 @@ impl AiAnalyzer {
     "part_info": {
         "name": "零件名称",
         "material": "材料代码",
-        "scale": "图纸比例",
+        "scale": "图纸比例，例如1:1",
+        "sheet_size": "图幅，例如A3",
         "drawing_number": "图纸编号"
     },
     "company": "公司名称，如果有，否则为null",
@@ -236,6 +291,7 @@ impl AiAnalyzer {
                     );
                     return Ok(ErrAnalysis {
                         image_path: image_path.to_path_buf(),
+                        provider: Some(AnalysisProvider::Local),
                         error_message: e.to_string(),
                         attempt_number: attempt,
                     }
@@ -283,6 +339,7 @@ impl AiAnalyzer {
                     );
                     return Ok(ErrAnalysis {
                         image_path: image_path.to_path_buf(),
+                        provider: Some(AnalysisProvider::Api),
                         error_message: e.to_string(),
                         attempt_number: attempt,
                     }
@@ -342,13 +399,14 @@ impl AiAnalyzer {
             .json()
             .await
             .map_err(|e| AnalyzerError::AiError(format!("Failed to parse response: {}", e)))?;
+        *self.usage.lock().unwrap() += TokenUsage::from_response(&response_json, None);
 
         let content = response_json
             .get("response")
             .and_then(|v| v.as_str())
             .ok_or_else(|| AnalyzerError::AiError("No response content".to_string()))?;
 
-        debug!("Response length: {} characters", content.len());
+        debug!("Response length: {} characters", content.chars().count());
 
         // 解析JSON响应
         let parsed_result = self.parse_ai_response(content)?;
@@ -366,6 +424,7 @@ impl AiAnalyzer {
             "engineering" => {
                 let model_analysis = ModelAnalysis {
                     image_path: image_path.to_path_buf(),
+                    provider: Some(AnalysisProvider::Local),
                     view_category: cleaned_result
                         .get("view_category")
                         .and_then(|v| v.as_str())
@@ -393,31 +452,36 @@ impl AiAnalyzer {
                 let mut part_info = None;
 
                 // 处理part_info
-                if let Some(part_info_val) = cleaned_result.get("part_info") {
-                    if let Some(part_obj) = part_info_val.as_object() {
-                        part_info = Some(PartInfo {
-                            name: part_obj
-                                .get("name")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                            material: part_obj
-                                .get("material")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                            scale: part_obj
-                                .get("scale")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                            drawing_number: part_obj
-                                .get("drawing_number")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                        });
-                    }
+                if let Some(part_info_val) = cleaned_result.get("part_info")
+                    && let Some(part_obj) = part_info_val.as_object()
+                {
+                    part_info = Some(PartInfo {
+                        name: part_obj
+                            .get("name")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        material: part_obj
+                            .get("material")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        scale: part_obj
+                            .get("scale")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        sheet_size: part_obj
+                            .get("sheet_size")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        drawing_number: part_obj
+                            .get("drawing_number")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                    });
                 }
 
                 let info_analysis = InfoAnalysis {
                     image_path: image_path.to_path_buf(),
+                    provider: Some(AnalysisProvider::Local),
                     part_info,
                     company: cleaned_result
                         .get("company")
@@ -439,6 +503,7 @@ impl AiAnalyzer {
                 // 未知类型，创建错误分析
                 let err_analysis = ErrAnalysis {
                     image_path: image_path.to_path_buf(),
+                    provider: Some(AnalysisProvider::Local),
                     error_message: format!("Unknown view category: {}", view_category),
                     attempt_number: attempt,
                 };
@@ -502,9 +567,9 @@ impl AiAnalyzer {
         let image_base64 = self.encode_image_for_analysis(image_path).await?;
 
         // 根据配置选择API格式
-        let (payload, url) = if api_config.use_compatible_mode {
+        let payload = if api_config.openai_format() {
             // OpenAI兼容格式
-            let payload = serde_json::json!({
+            serde_json::json!({
                 "model": api_config.model_name,
                 "messages": [
                     {
@@ -523,15 +588,12 @@ impl AiAnalyzer {
                         ]
                     }
                 ],
-                "temperature": 0.1,
                 "max_tokens": 1000,
                 "stream": false
-            });
-            let url = format!("{}/chat/completions", api_config.endpoint);
-            (payload, url)
+            })
         } else {
             // DashScope原生格式
-            let payload = serde_json::json!({
+            serde_json::json!({
                 "model": api_config.model_name,
                 "input": {
                     "messages": [
@@ -550,21 +612,17 @@ impl AiAnalyzer {
                 },
                 "parameters": {
                     "result_format": "message",
-                    "temperature": 0.1,
                     "max_tokens": 1000
                 }
-            });
-            let url = format!(
-                "{}/services/aigc/text-generation/generation",
-                api_config.endpoint.replace("/compatible-mode/v1", "")
-            );
-            (payload, url)
+            })
         };
+        let url = api_config.chat_url();
 
         debug!("Sending request to remote API...");
         debug!("API URL: {}", url);
         debug!("Model: {}", api_config.model_name);
-        debug!("Compatible mode: {}", api_config.use_compatible_mode);
+        debug!("Provider: {:?}", api_config.provider);
+        debug!("OpenAI format: {}", api_config.openai_format());
         debug!(
             "Request payload: {}",
             serde_json::to_string_pretty(&payload)
@@ -574,12 +632,7 @@ impl AiAnalyzer {
         // 发送请求到远程API
         let response = timeout(
             Duration::from_secs(self.config.timeout_seconds),
-            self.client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", api_config.api_key))
-                .header("Content-Type", "application/json")
-                .json(&payload)
-                .send(),
+            provider_request(&self.client, &url, api_config, payload).send(),
         )
         .await
         .map_err(|_| AnalyzerError::AiError("API request timeout".to_string()))?
@@ -594,7 +647,8 @@ impl AiAnalyzer {
             debug!("Full API error response: {}", error_text);
             return Err(AnalyzerError::AiError(format!(
                 "API request failed with status {}: {}",
-                status, error_text
+                status,
+                preview(&error_text, 500)
             )));
         }
 
@@ -602,6 +656,8 @@ impl AiAnalyzer {
             .json()
             .await
             .map_err(|e| AnalyzerError::AiError(format!("Failed to parse API response: {}", e)))?;
+        *self.usage.lock().unwrap() +=
+            TokenUsage::from_response(&response_json, Some(&api_config.pricing));
 
         debug!(
             "Full API response: {}",
@@ -610,7 +666,7 @@ impl AiAnalyzer {
         );
 
         // 根据API格式解析响应
-        let content = if api_config.use_compatible_mode {
+        let content = if api_config.openai_format() {
             // OpenAI兼容格式
             response_json
                 .get("choices")
@@ -628,7 +684,10 @@ impl AiAnalyzer {
         }
         .ok_or_else(|| AnalyzerError::AiError("No content in API response".to_string()))?;
 
-        debug!("API response length: {} characters", content.len());
+        debug!(
+            "API response length: {} characters",
+            content.chars().count()
+        );
 
         // 解析JSON响应
         let parsed_result = self.parse_ai_response(content)?;
@@ -646,6 +705,7 @@ impl AiAnalyzer {
             "engineering" => {
                 let model_analysis = ModelAnalysis {
                     image_path: image_path.to_path_buf(),
+                    provider: Some(AnalysisProvider::Api),
                     view_category: cleaned_result
                         .get("view_category")
                         .and_then(|v| v.as_str())
@@ -673,31 +733,36 @@ impl AiAnalyzer {
                 let mut part_info = None;
 
                 // 处理part_info
-                if let Some(part_info_val) = cleaned_result.get("part_info") {
-                    if let Some(part_obj) = part_info_val.as_object() {
-                        part_info = Some(PartInfo {
-                            name: part_obj
-                                .get("name")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                            material: part_obj
-                                .get("material")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                            scale: part_obj
-                                .get("scale")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                            drawing_number: part_obj
-                                .get("drawing_number")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string()),
-                        });
-                    }
+                if let Some(part_info_val) = cleaned_result.get("part_info")
+                    && let Some(part_obj) = part_info_val.as_object()
+                {
+                    part_info = Some(PartInfo {
+                        name: part_obj
+                            .get("name")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        material: part_obj
+                            .get("material")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        scale: part_obj
+                            .get("scale")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        sheet_size: part_obj
+                            .get("sheet_size")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        drawing_number: part_obj
+                            .get("drawing_number")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                    });
                 }
 
                 let info_analysis = InfoAnalysis {
                     image_path: image_path.to_path_buf(),
+                    provider: Some(AnalysisProvider::Api),
                     part_info,
                     company: cleaned_result
                         .get("company")
@@ -719,6 +784,7 @@ impl AiAnalyzer {
                 // 未知类型，创建错误分析
                 let err_analysis = ErrAnalysis {
                     image_path: image_path.to_path_buf(),
+                    provider: Some(AnalysisProvider::Api),
                     error_message: format!("Unknown view category: {}", view_category),
                     attempt_number: attempt,
                 };
@@ -771,44 +837,59 @@ impl AiAnalyzer {
     }
 
     /// Analyze single view - automatically choose between local and API based on configuration
+    ///
+    /// 开启`fallback`时，一种服务重试都失败后改用另一种(远程接口↔本地模型)，
+    /// 结果中的`provider`为实际给出结果的服务
     pub async fn analyze_single_view<P: AsRef<Path>>(
         &self,
         image_path: P,
         use_api: bool,
     ) -> IResult<ViewAnalysis> {
-        if use_api && self.config.api.is_some() {
-            self.analyze_single_view_api(image_path).await
-        } else {
-            self.analyze_single_view_local(image_path).await
-        }
-    }
-
-    fn parse_ai_response(&self, content: &str) -> IResult<serde_json::Value> {
-        // 尝试直接解析
-        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(content) {
-            return Ok(parsed);
+        let image_path = image_path.as_ref();
+        let use_api = use_api && self.config.api.is_some();
+        let analysis = self.analyze_with(image_path, use_api).await?;
+        let ViewAnalysis::Error(first) = &analysis else {
+            return Ok(analysis);
+        };
+        // 没有配置远程接口时本地模型没有可以改用的服务
+        if !self.config.fallback || !use_api && self.config.api.is_none() {
+            return Ok(analysis);
         }
 
-        // 尝试提取JSON部分
-        let content = content.trim();
-        let json_content = if let Some(start) = content.find("```json") {
-            let start = start + 7;
-            if let Some(end) = content[start..].find("```") {
-                &content[start..start + end]
+        warn!(
+            "{} 使用{}分析失败，改用{}",
+            image_path.display(),
+            if use_api {
+                "远程接口"
             } else {
-                content
-            }
-        } else if let Some(start) = content.find('{') {
-            if let Some(end) = content.rfind('}') {
-                &content[start..=end]
+                "本地模型"
+            },
+            if use_api {
+                "本地模型"
             } else {
-                content
+                "远程接口"
+            }
+        );
+        match self.analyze_with(image_path, !use_api).await? {
+            // 都失败时保留两次的错误
+            ViewAnalysis::Error(mut err) => {
+                err.error_message = format!("{}; {}", first.error_message, err.error_message);
+                Ok(err.into())
             }
+            analysis => Ok(analysis),
+        }
+    }
+
+    async fn analyze_with(&self, image_path: &Path, use_api: bool) -> IResult<ViewAnalysis> {
+        if use_api {
+            self.analyze_single_view_api(image_path).await
         } else {
-            content
-        };
+            self.analyze_single_view_local(image_path).await
+        }
+    }
 
-        serde_json::from_str(json_content.trim())
+    fn parse_ai_response(&self, content: &str) -> IResult<serde_json::Value> {
+        extract_json(content)
             .map_err(|e| AnalyzerError::AiError(format!("JSON parse error: {}", e)))
     }
 
@@ -861,6 +942,7 @@ impl AiAnalyzer {
             views_dir.display()
         );
 
+        self.take_usage();
         // 收集所有PNG文件
         let mut view_files = Vec::new();
         let mut entries = tokio::fs::read_dir(views_dir).await?;
@@ -887,22 +969,35 @@ impl AiAnalyzer {
 
         info!("Found {} view files for analysis", view_files.len());
 
-        // 分析所有视图
+        // 分析所有视图，同时进行的请求数不超过配置
+        let semaphore = Semaphore::new(self.config.max_concurrent_requests.max(1));
+        let results = join_all(view_files.iter().enumerate().map(|(i, view_file)| {
+            let semaphore = &semaphore;
+            let total = view_files.len();
+            async move {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .expect("视图分析的信号量不会被关闭");
+                info!(
+                    "Analyzing view {}/{}: {}",
+                    i + 1,
+                    total,
+                    view_file.display()
+                );
+                self.analyze_single_view(view_file, use_api).await
+            }
+        }))
+        .await;
+
         let mut analyses = Vec::new();
         let mut successful_analyses = 0;
         let mut failed_analyses = 0;
         let mut engineering_views = 0;
         let mut info_views = 0;
 
-        for (i, view_file) in view_files.iter().enumerate() {
-            info!(
-                "Analyzing view {}/{}: {}",
-                i + 1,
-                view_files.len(),
-                view_file.display()
-            );
-
-            let analysis = self.analyze_single_view(view_file, use_api).await?;
+        for analysis in results {
+            let analysis = analysis?;
 
             match &analysis {
                 ViewAnalysis::Model(_) => {
@@ -922,8 +1017,9 @@ impl AiAnalyzer {
         }
 
         // 计算维度汇总和异常检测
+        let drawing = self.drawing_format(&analyses);
         let dimensions = self.calculate_dimension_summary(&analyses);
-        let anomalies = self.detect_anomalies(&analyses);
+        let anomalies = self.detect_anomalies(&analyses, &drawing);
 
         let result = AnalysisIResult {
             successful_analyses,
@@ -932,8 +1028,10 @@ impl AiAnalyzer {
             engineering_views,
             info_views,
             views: analyses,
+            drawing,
             dimensions,
             anomalies,
+            usage: self.take_usage(),
         };
 
         info!(
@@ -970,7 +1068,26 @@ impl AiAnalyzer {
         }
     }
 
-    fn detect_anomalies(&self, analyses: &[ViewAnalysis]) -> AnomalyReport {
+    /// 从信息视图中取标题栏的比例和图幅
+    fn drawing_format(&self, analyses: &[ViewAnalysis]) -> DrawingFormat {
+        let part_infos = analyses.iter().filter_map(|analysis| match analysis {
+            ViewAnalysis::Info(info) => info.part_info.as_ref(),
+            _ => None,
+        });
+        let mut scale = None;
+        let mut sheet_size = None;
+        for part_info in part_infos {
+            scale = scale.or_else(|| part_info.scale.clone());
+            sheet_size = sheet_size.or_else(|| part_info.sheet_size.clone());
+        }
+        DrawingFormat::new(scale, sheet_size)
+    }
+
+    fn detect_anomalies(
+        &self,
+        analyses: &[ViewAnalysis],
+        drawing: &DrawingFormat,
+    ) -> AnomalyReport {
         let dimensions = self.calculate_dimension_summary(analyses);
 
         let (corrected_x_max, x_mistake_value, x_gap_analysis) =
@@ -978,12 +1095,18 @@ impl AiAnalyzer {
         let (corrected_y_max, y_mistake_value, y_gap_analysis) =
             self.detect_anomaly_with_gaps(&dimensions.y_values);
 
+        let scale_conflict = drawing.check(corrected_x_max, corrected_y_max);
+        if let Some(conflict) = &scale_conflict {
+            warn!("⚠️ {}", conflict.message);
+        }
+
         AnomalyReport {
             x_mistake_value,
             y_mistake_value,
             corrected_x_max,
             corrected_y_max,
             gap_analysis: x_gap_analysis.or(y_gap_analysis),
+            scale_conflict,
         }
     }
 
@@ -1045,3 +1168,55 @@ impl AiAnalyzer {
         self.analyze_view_directory(views_dir, use_api).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ApiConfig, paths::TempDir};
+
+    #[tokio::test]
+    async fn fallback_to_other_provider() {
+        let dir = TempDir::new("view");
+        let image = dir.join("view.png");
+        image::RgbImage::new(20, 20).save(&image).unwrap();
+        // 两种服务都无法连接
+        let mut config = AiConfig {
+            ollama_base: "http://127.0.0.1:1".to_string(),
+            api: Some(ApiConfig {
+                endpoint: "http://127.0.0.1:1/v1".to_string(),
+                ..ApiConfig::default()
+            }),
+            max_retries: 1,
+            fallback: false,
+            ..AiConfig::default()
+        };
+
+        let analyzer = AiAnalyzer::new(config.clone(), reqwest::Client::new());
+        let ViewAnalysis::Error(err) = analyzer.analyze_single_view(&image, true).await.unwrap()
+        else {
+            panic!("无法连接时应该返回错误");
+        };
+        assert_eq!(err.provider, Some(AnalysisProvider::Api));
+        assert!(!err.error_message.contains("; "));
+
+        config.fallback = true;
+        let analyzer = AiAnalyzer::new(config.clone(), reqwest::Client::new());
+        let ViewAnalysis::Error(err) = analyzer.analyze_single_view(&image, true).await.unwrap()
+        else {
+            panic!("无法连接时应该返回错误");
+        };
+        // 改用本地模型，保留两次的错误
+        assert_eq!(err.provider, Some(AnalysisProvider::Local));
+        assert!(err.error_message.contains("; "), "{}", err.error_message);
+
+        // 没有远程接口时不改用
+        config.api = None;
+        let analyzer = AiAnalyzer::new(config, reqwest::Client::new());
+        let ViewAnalysis::Error(err) = analyzer.analyze_single_view(&image, true).await.unwrap()
+        else {
+            panic!("无法连接时应该返回错误");
+        };
+        assert_eq!(err.provider, Some(AnalysisProvider::Local));
+        assert!(!err.error_message.contains("; "));
+    }
+}
@@ -1,10 +1,138 @@
-use crate::{AnalyzerError, IResult, config::AiConfig};
-use base64::{Engine as _, engine::general_purpose};
+use crate::{
+    config::{ApiProvider, Sampling},
+    dimension::Dimensions,
+    drawing::DrawingFormat,
+    finish::Finish,
+    usage::TokenUsage,
+};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+
+// 调用模型服务的部分需要`ai`功能
+#[cfg(feature = "ai")]
+use crate::{
+    AnalyzerError, IResult,
+    blank_page::is_blank,
+    config::{AiConfig, BlankPageConfig},
+    http::provider_request,
+    json_extract::extract_json,
+    pdf_converter::PageManifest,
+    progress_sink::ProgressSink,
+    response_archive::ResponseArchive,
+    tags::normalized_tags,
+    text::{preview, truncate_chars},
+};
+#[cfg(feature = "ai")]
+use base64::{Engine as _, engine::general_purpose};
+#[cfg(feature = "ai")]
+use futures_util::StreamExt;
+#[cfg(feature = "ai")]
+use std::path::Path;
+#[cfg(feature = "ai")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "ai")]
 use tokio::time::{Duration, timeout};
+#[cfg(feature = "ai")]
+use tokio_util::sync::CancellationToken;
+#[cfg(feature = "ai")]
 use tracing::{debug, error, info, warn};
 
+/// 文本提取提示词的版本，用于按版本统计用户反馈
+pub const TEXT_EXTRACT_PROMPT_VERSION: &str = "text-extract-v6";
+
+/// 文本提取提示词，修改时需要同步更新`TEXT_EXTRACT_PROMPT_VERSION`，启动时登记到提示词版本表中
+pub const TEXT_EXTRACT_PROMPT: &str = r#"
+请仔细分析这张模具图片，提取所有可见的文字信息，特别关注模具的基本信息。
+
+**重点提取内容：**
+1. 模具类型/零件类型 - 通常在标题栏或图纸名称处，通常名为名称, 详细类型如下:
+```
+["基座-H", "外基座", "防尘盖", "线轮 Bobbin", "上盖-037", "支架", "衔铁组件-026", "R53G 底板(60A)", "HAG12线架", "外壳-W", 
+"HAT904G 基座", "基座-049", "罩壳", "防水塞", "HAT902-ET外壳 (C型)", "H157S护套", "HAG02动衔组件", "HAT905G底板", "Plug外壳", 
+"HAT904G 外壳", "头外壳", "固定板", "外壳-H", "辅助开关底座", "基座-1A型", "ZC75N基座", "上盖-050", "HAGO2动衔连接件", "保 险丝盖板", 
+"Plug盖板", "塞子", "基座盖板", "推动杆", "上盖", "推片", "ZC75N后盖", "线圈支架-032", "枪头后盖", "底座", "95316-3底板B模", "前基座", 
+"安装板外壳", "线轮", "推板-034", "控制盒上壳", "C型基座", "固定板-025", "HAG12支撑座", "Y3F-外壳", " 底板", "拉带", "上基座", "座外壳", 
+"线圈架-W", "ZC75N基座(60A-ASSLY带护针)", "NTC基座", "内基座", "线圈架", "基座-042", "底 座(1常开1常闭型)", "推杆", "上盖-048", "衔铁托板", 
+"隔弧片", "骨架", "衔铁组件", "Y3F-顶面孔外壳", "底座(组常开型)", "HAT904G 骨架", "尾盖", "HAG12线圈架", "Header外壳", "动簧片组件", 
+"SHG.SPRC2C.P03-1", "Y3F骨架", "外壳", "绝缘片", "基座", "基座-038", "基座-047", "上盖004", "Header盖板", "外盖"]
+```
+2. 材料/材质信息 - 可能标注为"材料"、"材质"、"Material"等，材料需要完整读出，包括后面跟着的型号, 详细材料有：
+```
+["PET FR530 BLACK BY DUPONT", "尼龙 PA66 K225-KS 黑色 (帝斯曼)", "MZCA-H", "UL746C", "PBT 543", "PBT RG301 BK", "PA66 RG301 黑色", "LCP-4008 (黑色)", 
+"PA66 NPG30 黑色", "PBT", "PBT-RG301 黑色阻燃等级：V-0", "PET T102G30 TH3013", "PBT 4130", "PBT R212G30GT OG", "PC 3001-33201 黑色 沃特 UL94V-0 f1", 
+"PA6 C0-FKGS6 黑色", "PET FR530", "RoHS UL94 V-0", "尼龙 PC FR7沙伯基础", "PA66-B30", "E202G30", "PBT RG301 BK165 UL94 V-0 RoHS 黑色 (金发)", "PBT 102G30 TH3013", 
+"PAG K-FX56/B", "PBT RG530 黑色", "南亚 PBT 1403G6 (黑色)", "PET-FR530 黑色 (再生材35%)", "PA6 K-FKGS6/B 黑色 UL94-V0 DSM", "DT4E", "PBT 3316", "LCP E130i", 
+"PET T102G30 TH3013 BK", "PBT FR530 黑色", "PBT E202G30(黑色)", "PBT RG301", "PET FR533NH 本色", "尼龙PA66 FR50 BK086", "PBT FR530 BK", "5010GN6-30MBX", "PET FR530 黑色", 
+"尼龙 PA66 RG251 (F1) 黑色 UL94 V-0", "PBT RG301 (白色)", "PC PC3001-33201L 黑色 BK", "新光 PBT D202G30@ (黑色)", "尼龙 PC 121R", "UL94V-0", "衔铁 DT4E", "Lkh7.810.538", 
+"UL746C f1 K-FK6G/B DSM", "PBT RG301 黑色", "PBT 201G20 BK", "UL746C F1 L/P/SS/D DSM", "PBT 5010GN6-30MBX", "PEI1000", "PA66 T303 G30 VO BK", "PET FR530 BLACK", "PTFE T1026M T18013", 
+"阻燃等级：V-0", "PA66 HTNFR52G30NH或PA66-A3 GF25 VOX1", "PBT RG301 蓝色", "塑料 PBT RG301", "PBT 3316 (黑色阻燃)", "PPS R-7", "金发PBT RG301(白)", "东方PET", "UL94 V-0 RoHS (美国杜邦)", 
+"HYZ01-2X3T", "PBT R212G30GT NC", "PET RG305 BLACK", "PET FRG30 BLACK BY DUPONT", "金发 PBT RG301(黑)", "PA4T", "尼龙 PA6-GF30", "C17410", "PET RG301 黑色", "尼龙 PA6 GF30 FR (17)", 
+"PBT-RG301 黑色", "LCP E4008 BK", "Lk17.810.541", "PET RG305", "PBT RG301 (黑色)", "PPS B4200 G8 BK", "PA66 A3 GF25 VOX1(本色)", "PET FR530NH或PA4T TX-1", "PPA AFA6133 (本)", "PBT G30 白色", 
+"金发 PBT RG301白", "LCP E4008", "PA46-GF30 TE250F6 黑色 UL94V-0", "PPS 6165 A6/A7 BLACK BY POLYPLASTIC", "PET FRF520", "PBT RG301 白色", "LCP E130i 黑色", "PBT 1403G6 黑色", "HY050-ZS1S-K", 
+"PBT 3316 黑色 UL94 V-0", "PET-FR530", "尼龙 PA66 RG251 (f1) 黑色 UL94 V-0", "PA66+GF A26FM0 黑色", "尼龙 PA66 FR50", "PAG K-FXG56/B", "PA46", "PBT 5010GNG6-30M8X", "PA66 RPG25", "PBT RG301（黑）", 
+"PBT T102G30 TH3013", "DSM尼龙 PA6 K-FKGS6/B 黑色 BK26037", "UL94 HB", "PA46 TE250F8", "南亚PBT 1403 G6(黑)", "PBT R0301", "磁钢 镍铁氧体", "PBT RG301+30GF 黑色", "PBT R212G30GT BK", "尼龙 PA66 EPR27", 
+"PBT RG301 BLACK BY KINGFA", "PPS 4500 BK", "PET T102G30", "PC PC3001-33201L BK 黑色", "PBT 3316 黑色", "PBT 5010GN6-30 M6X黑色", "PBT 1403G6", "PBT 4130-104F", "尼龙 PA6-GF30 FR (17)", "PBT 4130(FNGW)", 
+"PBT 5010G6N6-30 MBX", "PBT4130-104K", "PBT 4130-104K", "尼龙 PA6 K-FKGS6 绿色 PANTONE 7730C UL94 V-0 (DSM)", "PBT 4130 黑色 防紫外线", "PET EMC 130-20", "PBT4130-104F", "PET FR530 BK", "PPS R-4 黑色", 
+"PBT E202630(黑色)", "PAA6+GF A26FM0 黑色", "PET-FR531", "PBT 4130 黑色", "PBT 5010GN6 BK", "PBT 3316 BK", "C18150-R540", "PA6 K-FKGS6 黑色 DSM UL94V-0", "PET FG550 BK", "PBT 1430", "PBT RG530 白色", 
+"再生材 黑色", "UL94 V-0", "PBT FR530", "PET FR530 本色", "TPE EFT85B030MB-B 黑色", "PET FR830 BLACK", "尼龙 PA6-30GF, K-PESS6/B", "PBT 1430G6"]
+```
+3. 项目名称或称为型号
+4. 公司/客户名称 - 通常在标题栏中，例如"TCL"、"宏发"
+5. 技术要求 - 标题栏和"技术要求"中的文字，特别是热处理、表面处理、缩水率等要求，每条一项
+6. 表面处理(如镀镍、喷砂)、皮纹(如VDI 24)、模具钢材(如S136)和硬度要求(如HRC48-52)，通常在技术要求中
+7. 外形尺寸 - 零件的最大长度和最大宽度(mm，按标注的数值，不要按比例换算)，以及这两个尺寸标注的公差(如±0.05)
+8. 图纸比例(如1:1、2:1)和图幅(如A3)，通常在标题栏中
+
+**注意事项：**
+- 材料信息可能有多个，请全部提取
+- 材料可能称为"材质"、"Material"、"原材料"等
+- 保持原始文字的准确性，不要修改或简化
+
+**输出JSON格式：**
+```json
+{
+    "model_type": "模具类型或零件类型",
+    "materials": ["材料1", "材料2"],
+    "project_name": "项目名称或型号",
+    "company": "公司或客户名称",
+    "text_content": ["技术要求1", "技术要求2"],
+    "surface_finish": "表面处理",
+    "texture": "皮纹",
+    "steel": "模具钢材",
+    "hardness": "硬度要求",
+    "x_max": "最大长度，只写数字",
+    "y_max": "最大宽度，只写数字",
+    "x_tolerance": "最大长度的公差",
+    "y_tolerance": "最大宽度的公差",
+    "scale": "图纸比例",
+    "sheet_size": "图幅"
+}
+```
+
+请确保：
+1. 准确识别所有可见文字
+2. 正确分类文字信息
+3. 保持原始文字的准确性
+4. 如果某些字段无法识别，设为null或空数组
+5. 材料信息特别重要，请仔细提取
+"#;
+
+/// 把技术要求整理为标签的提示词，`{text}`替换为合并后的`text_content`，见`AiConfig::normalize_tags`
+#[cfg(feature = "ai")]
+const TAG_NORMALIZE_PROMPT: &str = r#"
+下面是模具图纸标题栏和技术要求中的文字，每行一条：
+```
+{text}
+```
+请把其中的热处理、表面处理、缩水率要求整理为标签，格式为"类别:要求"，类别只能是"热处理"、"表面处理"、"缩水率"。
+同一类别的不同要求分别列出，例如"热处理:调质"和"热处理:氮化"。要求尽量简短，不要添加文字中没有的要求。
+
+只输出JSON数组，例如：
+```json
+["热处理:调质", "热处理:氮化", "表面处理:镀镍", "缩水率:5‰"]
+```
+"#;
+
 /// 文本提取结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextExtractionResult {
@@ -12,7 +140,28 @@ pub struct TextExtractionResult {
     pub model_type: Option<String>,
     pub materials: Vec<String>,
     pub project_name: Option<String>,
+    /// 图纸标题栏中的公司/客户
+    #[serde(default)]
+    pub company: Option<String>,
+    /// 标题栏和技术要求中的原始文字
+    #[serde(default)]
+    pub text_content: Vec<String>,
+    /// 技术要求中的表面处理、皮纹和钢材硬度
+    #[serde(default)]
+    pub finish: Finish,
+    /// 标注的外形尺寸和公差
+    #[serde(default)]
+    pub dimensions: Option<Dimensions>,
+    /// 标题栏中的比例和图幅
+    #[serde(default)]
+    pub drawing: DrawingFormat,
+    /// 模型整理的技术要求标签，只有合并结果中有，为空时按关键词规则整理，见`tags::mine_tags`
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub error: Option<String>,
+    /// PDF书签中这一页的标题，例如`装配图`，合并结果和没有书签的PDF为空
+    #[serde(default)]
+    pub page_label: Option<String>,
 }
 
 impl TextExtractionResult {
@@ -22,7 +171,14 @@ impl TextExtractionResult {
             model_type: None,
             materials: Vec::new(),
             project_name: None,
+            company: None,
+            text_content: Vec::new(),
+            finish: Finish::default(),
+            dimensions: None,
+            drawing: DrawingFormat::default(),
+            tags: Vec::new(),
             error: Some(error),
+            page_label: None,
         }
     }
     
@@ -37,25 +193,146 @@ impl TextExtractionResult {
             model_type,
             materials,
             project_name,
+            company: None,
+            text_content: Vec::new(),
+            finish: Finish::default(),
+            dimensions: None,
+            drawing: DrawingFormat::default(),
+            tags: Vec::new(),
             error: None,
+            page_label: None,
         }
     }
     
     pub fn is_success(&self) -> bool {
         self.error.is_none()
     }
+
+    /// 用于提示的页面名称，有书签标题时使用标题，否则为图片路径
+    pub fn page_name(&self) -> String {
+        match &self.page_label {
+            Some(label) => format!("{}页", label),
+            None => self.image_path.display().to_string(),
+        }
+    }
+}
+
+/// 提取使用的模型服务，不包含密钥
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderInfo {
+    pub endpoint: String,
+    pub model_name: String,
+    /// 是否使用OpenAI兼容格式
+    pub compatible_mode: bool,
+    /// 服务类型，之前的记录中没有，视为DashScope
+    #[serde(default)]
+    pub provider: ApiProvider,
+    /// 采样参数，用于复现提取结果，之前的记录中没有
+    #[serde(default)]
+    pub sampling: Option<Sampling>,
+}
+
+/// 合并结果和每页的提取结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextExtraction {
+    pub merged: TextExtractionResult,
+    /// 按页面图片文件名排序，不包括空白页
+    pub pages: Vec<TextExtractionResult>,
+    /// 跳过的空白页
+    #[serde(default)]
+    pub blank_pages: Vec<PathBuf>,
+    pub provider: Option<ProviderInfo>,
+    pub prompt_version: String,
+    /// 提取过程中模型服务的token用量
+    #[serde(default)]
+    pub usage: TokenUsage,
 }
 
 /// AI文本分析器
+#[cfg(feature = "ai")]
 pub struct AiTextAnalyzer {
     config: AiConfig,
     client: reqwest::Client,
+    archive: Option<ResponseArchive>,
+    /// 上次取出后累计的token用量
+    usage: Mutex<TokenUsage>,
+    /// 每页的进度回调
+    progress: Option<Arc<dyn ProgressSink>>,
+    /// 取消信号，收到后不再发送请求
+    cancel: Option<CancellationToken>,
+    /// 空白页检测，空白页不发送给模型服务
+    blank_page: BlankPageConfig,
 }
 
+#[cfg(feature = "ai")]
 impl AiTextAnalyzer {
-    pub fn new(config: AiConfig) -> Self {
-        let client = reqwest::Client::new();
-        Self { config, client }
+    pub fn new(config: AiConfig, client: reqwest::Client) -> Self {
+        Self {
+            config,
+            client,
+            archive: None,
+            usage: Mutex::default(),
+            progress: None,
+            cancel: None,
+            blank_page: BlankPageConfig::default(),
+        }
+    }
+
+    /// 使用配置的空白页检测，默认只使用内置默认值和环境变量
+    pub fn with_blank_page(mut self, blank_page: BlankPageConfig) -> Self {
+        self.blank_page = blank_page;
+        self
+    }
+
+    /// 取出累计的token用量并清零
+    fn take_usage(&self) -> TokenUsage {
+        std::mem::take(&mut *self.usage.lock().unwrap())
+    }
+
+    /// 把模型服务的原始回复保存到存档中
+    pub fn with_archive(mut self, archive: ResponseArchive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// 保存原始回复，失败时只记录警告
+    fn archive_response(&self, image_path: &Path, attempt: u32, body: &str) {
+        if let Some(archive) = &self.archive
+            && let Err(e) = archive.save(image_path, attempt, body)
+        {
+            warn!("保存原始回复失败 {}: {}", image_path.display(), e);
+        }
+    }
+
+    /// 每提取完一页调用进度回调
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// 收到取消信号时停止正在进行的请求，提取返回错误
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// 等待`future`完成，先收到取消信号时返回错误
+    async fn cancellable<T>(&self, future: impl Future<Output = T>) -> IResult<T> {
+        match &self.cancel {
+            Some(cancel) => cancel
+                .run_until_cancelled(future)
+                .await
+                .ok_or_else(|| AnalyzerError::AiError("任务已取消".to_string())),
+            None => Ok(future.await),
+        }
+    }
+
+    /// 使用指定的采样参数代替配置中的默认值
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        if let Some(api) = self.config.api.as_mut() {
+            api.sampling = sampling;
+        }
+        self
     }
     
     /// 检查API是否可用
@@ -69,67 +346,25 @@ impl AiTextAnalyzer {
         Ok(())
     }
     
-    /// 创建文本提取专用提示词
-    fn create_text_extract_prompt(&self) -> String {
-        r#"
-请仔细分析这张模具图片，提取所有可见的文字信息，特别关注模具的基本信息。
-
-**重点提取内容：**
-1. 模具类型/零件类型 - 通常在标题栏或图纸名称处，通常名为名称, 详细类型如下:
-```
-["基座-H", "外基座", "防尘盖", "线轮 Bobbin", "上盖-037", "支架", "衔铁组件-026", "R53G 底板(60A)", "HAG12线架", "外壳-W", 
-"HAT904G 基座", "基座-049", "罩壳", "防水塞", "HAT902-ET外壳 (C型)", "H157S护套", "HAG02动衔组件", "HAT905G底板", "Plug外壳", 
-"HAT904G 外壳", "头外壳", "固定板", "外壳-H", "辅助开关底座", "基座-1A型", "ZC75N基座", "上盖-050", "HAGO2动衔连接件", "保 险丝盖板", 
-"Plug盖板", "塞子", "基座盖板", "推动杆", "上盖", "推片", "ZC75N后盖", "线圈支架-032", "枪头后盖", "底座", "95316-3底板B模", "前基座", 
-"安装板外壳", "线轮", "推板-034", "控制盒上壳", "C型基座", "固定板-025", "HAG12支撑座", "Y3F-外壳", " 底板", "拉带", "上基座", "座外壳", 
-"线圈架-W", "ZC75N基座(60A-ASSLY带护针)", "NTC基座", "内基座", "线圈架", "基座-042", "底 座(1常开1常闭型)", "推杆", "上盖-048", "衔铁托板", 
-"隔弧片", "骨架", "衔铁组件", "Y3F-顶面孔外壳", "底座(组常开型)", "HAT904G 骨架", "尾盖", "HAG12线圈架", "Header外壳", "动簧片组件", 
-"SHG.SPRC2C.P03-1", "Y3F骨架", "外壳", "绝缘片", "基座", "基座-038", "基座-047", "上盖004", "Header盖板", "外盖"]
-```
-2. 材料/材质信息 - 可能标注为"材料"、"材质"、"Material"等，材料需要完整读出，包括后面跟着的型号, 详细材料有：
-```
-["PET FR530 BLACK BY DUPONT", "尼龙 PA66 K225-KS 黑色 (帝斯曼)", "MZCA-H", "UL746C", "PBT 543", "PBT RG301 BK", "PA66 RG301 黑色", "LCP-4008 (黑色)", 
-"PA66 NPG30 黑色", "PBT", "PBT-RG301 黑色阻燃等级：V-0", "PET T102G30 TH3013", "PBT 4130", "PBT R212G30GT OG", "PC 3001-33201 黑色 沃特 UL94V-0 f1", 
-"PA6 C0-FKGS6 黑色", "PET FR530", "RoHS UL94 V-0", "尼龙 PC FR7沙伯基础", "PA66-B30", "E202G30", "PBT RG301 BK165 UL94 V-0 RoHS 黑色 (金发)", "PBT 102G30 TH3013", 
-"PAG K-FX56/B", "PBT RG530 黑色", "南亚 PBT 1403G6 (黑色)", "PET-FR530 黑色 (再生材35%)", "PA6 K-FKGS6/B 黑色 UL94-V0 DSM", "DT4E", "PBT 3316", "LCP E130i", 
-"PET T102G30 TH3013 BK", "PBT FR530 黑色", "PBT E202G30(黑色)", "PBT RG301", "PET FR533NH 本色", "尼龙PA66 FR50 BK086", "PBT FR530 BK", "5010GN6-30MBX", "PET FR530 黑色", 
-"尼龙 PA66 RG251 (F1) 黑色 UL94 V-0", "PBT RG301 (白色)", "PC PC3001-33201L 黑色 BK", "新光 PBT D202G30@ (黑色)", "尼龙 PC 121R", "UL94V-0", "衔铁 DT4E", "Lkh7.810.538", 
-"UL746C f1 K-FK6G/B DSM", "PBT RG301 黑色", "PBT 201G20 BK", "UL746C F1 L/P/SS/D DSM", "PBT 5010GN6-30MBX", "PEI1000", "PA66 T303 G30 VO BK", "PET FR530 BLACK", "PTFE T1026M T18013", 
-"阻燃等级：V-0", "PA66 HTNFR52G30NH或PA66-A3 GF25 VOX1", "PBT RG301 蓝色", "塑料 PBT RG301", "PBT 3316 (黑色阻燃)", "PPS R-7", "金发PBT RG301(白)", "东方PET", "UL94 V-0 RoHS (美国杜邦)", 
-"HYZ01-2X3T", "PBT R212G30GT NC", "PET RG305 BLACK", "PET FRG30 BLACK BY DUPONT", "金发 PBT RG301(黑)", "PA4T", "尼龙 PA6-GF30", "C17410", "PET RG301 黑色", "尼龙 PA6 GF30 FR (17)", 
-"PBT-RG301 黑色", "LCP E4008 BK", "Lk17.810.541", "PET RG305", "PBT RG301 (黑色)", "PPS B4200 G8 BK", "PA66 A3 GF25 VOX1(本色)", "PET FR530NH或PA4T TX-1", "PPA AFA6133 (本)", "PBT G30 白色", 
-"金发 PBT RG301白", "LCP E4008", "PA46-GF30 TE250F6 黑色 UL94V-0", "PPS 6165 A6/A7 BLACK BY POLYPLASTIC", "PET FRF520", "PBT RG301 白色", "LCP E130i 黑色", "PBT 1403G6 黑色", "HY050-ZS1S-K", 
-"PBT 3316 黑色 UL94 V-0", "PET-FR530", "尼龙 PA66 RG251 (f1) 黑色 UL94 V-0", "PA66+GF A26FM0 黑色", "尼龙 PA66 FR50", "PAG K-FXG56/B", "PA46", "PBT 5010GNG6-30M8X", "PA66 RPG25", "PBT RG301（黑）", 
-"PBT T102G30 TH3013", "DSM尼龙 PA6 K-FKGS6/B 黑色 BK26037", "UL94 HB", "PA46 TE250F8", "南亚PBT 1403 G6(黑)", "PBT R0301", "磁钢 镍铁氧体", "PBT RG301+30GF 黑色", "PBT R212G30GT BK", "尼龙 PA66 EPR27", 
-"PBT RG301 BLACK BY KINGFA", "PPS 4500 BK", "PET T102G30", "PC PC3001-33201L BK 黑色", "PBT 3316 黑色", "PBT 5010GN6-30 M6X黑色", "PBT 1403G6", "PBT 4130-104F", "尼龙 PA6-GF30 FR (17)", "PBT 4130(FNGW)", 
-"PBT 5010G6N6-30 MBX", "PBT4130-104K", "PBT 4130-104K", "尼龙 PA6 K-FKGS6 绿色 PANTONE 7730C UL94 V-0 (DSM)", "PBT 4130 黑色 防紫外线", "PET EMC 130-20", "PBT4130-104F", "PET FR530 BK", "PPS R-4 黑色", 
-"PBT E202630(黑色)", "PAA6+GF A26FM0 黑色", "PET-FR531", "PBT 4130 黑色", "PBT 5010GN6 BK", "PBT 3316 BK", "C18150-R540", "PA6 K-FKGS6 黑色 DSM UL94V-0", "PET FG550 BK", "PBT 1430", "PBT RG530 白色", 
-"再生材 黑色", "UL94 V-0", "PBT FR530", "PET FR530 本色", "TPE EFT85B030MB-B 黑色", "PET FR830 BLACK", "尼龙 PA6-30GF, K-PESS6/B", "PBT 1430G6"]
-```
-3. 项目名称或称为型号
-
-**注意事项：**
-- 材料信息可能有多个，请全部提取
-- 材料可能称为"材质"、"Material"、"原材料"等
-- 保持原始文字的准确性，不要修改或简化
+    /// 模型名称，记录在任务中用于按版本统计反馈
+    pub fn model_name(&self) -> Option<&str> {
+        self.config.api.as_ref().map(|api| api.model_name.as_str())
+    }
 
-**输出JSON格式：**
-```json
-{
-    "model_type": "模具类型或零件类型",
-    "materials": ["材料1", "材料2"],
-    "project_name": "项目名称或型号"
-}
-```
+    /// 使用的模型服务
+    pub fn provider(&self) -> Option<ProviderInfo> {
+        self.config.api.as_ref().map(|api| ProviderInfo {
+            endpoint: api.endpoint.clone(),
+            model_name: api.model_name.clone(),
+            compatible_mode: api.openai_format(),
+            provider: api.provider,
+            sampling: Some(api.sampling),
+        })
+    }
 
-请确保：
-1. 准确识别所有可见文字
-2. 正确分类文字信息
-3. 保持原始文字的准确性
-4. 如果某些字段无法识别，设为null或空数组
-5. 材料信息特别重要，请仔细提取
-"#
-        .to_string()
+    /// 创建文本提取专用提示词
+    fn create_text_extract_prompt(&self) -> String {
+        TEXT_EXTRACT_PROMPT.to_string()
     }
     
     /// 为文字识别编码图像（保持高质量）
@@ -161,11 +396,11 @@ impl AiTextAnalyzer {
         Ok(general_purpose::STANDARD.encode(&jpeg_data))
     }
     
-    /// 从文件夹中的多张图片提取文本信息并合并结果
-    pub async fn extract_text_from_folder<P: AsRef<Path>>(
+    /// 从文件夹中的多张图片提取文本信息并合并结果，同时保留每页的结果
+    pub async fn extract_pages_from_folder<P: AsRef<Path>>(
         &self,
         folder_path: P,
-    ) -> IResult<TextExtractionResult> {
+    ) -> IResult<TextExtraction> {
         let folder_path = folder_path.as_ref();
         info!("开始处理文件夹: {}", folder_path.display());
         
@@ -178,58 +413,127 @@ impl AiTextAnalyzer {
             let entry = entry.map_err(|e| AnalyzerError::ImageError(format!("读取文件项失败: {}", e)))?;
             let path = entry.path();
             
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    let ext = ext.to_string_lossy().to_lowercase();
-                    if matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "bmp" | "gif" | "tiff") {
-                        image_files.push(path);
-                    }
+            if path.is_file()
+                && let Some(ext) = path.extension()
+            {
+                let ext = ext.to_string_lossy().to_lowercase();
+                if matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "bmp" | "gif" | "tiff") {
+                    image_files.push(path);
                 }
             }
         }
         
         if image_files.is_empty() {
-            return Ok(TextExtractionResult::new_error(
-                folder_path.to_path_buf(),
-                "文件夹中没有找到图片文件".to_string()
-            ));
+            return Ok(TextExtraction {
+                merged: TextExtractionResult::new_error(
+                    folder_path.to_path_buf(),
+                    "文件夹中没有找到图片文件".to_string()
+                ),
+                pages: Vec::new(),
+                blank_pages: Vec::new(),
+                provider: self.provider(),
+                prompt_version: TEXT_EXTRACT_PROMPT_VERSION.to_string(),
+                usage: TokenUsage::default(),
+            });
         }
         
         // 按文件名排序，确保处理顺序一致
         image_files.sort();
-        info!("找到 {} 张图片，开始逐一处理", image_files.len());
-        
-        // 逐一处理每张图片
-        let mut all_results = Vec::new();
-        for (index, image_path) in image_files.iter().enumerate() {
-            info!("处理第 {}/{} 张图片: {}", index + 1, image_files.len(), image_path.display());
-            
-            match self.extract_text_from_image(image_path).await {
-                Ok(result) => {
-                    if result.is_success() {
-                        info!("✅ 第 {} 张图片处理成功", index + 1);
-                    } else {
-                        warn!("⚠️ 第 {} 张图片处理失败: {:?}", index + 1, result.error);
-                    }
-                    all_results.push(result);
+
+        // 跳过空白页，无法判断时仍然发送
+        let (blank_pages, image_files): (Vec<PathBuf>, Vec<PathBuf>) = image_files
+            .into_iter()
+            .partition(|path| is_blank(path, &self.blank_page).unwrap_or_else(|e| {
+                warn!("空白页检测失败 {}: {}", path.display(), e);
+                false
+            }));
+        if !blank_pages.is_empty() {
+            warn!("跳过 {} 张空白页: {:?}", blank_pages.len(), blank_pages);
+        }
+        if image_files.is_empty() {
+            return Ok(TextExtraction {
+                merged: TextExtractionResult::new_error(
+                    folder_path.to_path_buf(),
+                    format!("{} 张图片都是空白页", blank_pages.len())
+                ),
+                pages: Vec::new(),
+                blank_pages,
+                provider: self.provider(),
+                prompt_version: TEXT_EXTRACT_PROMPT_VERSION.to_string(),
+                usage: TokenUsage::default(),
+            });
+        }
+        let concurrency = self.config.page_concurrency.max(1);
+        info!("找到 {} 张图片，同时处理 {} 张", image_files.len(), concurrency);
+
+        // 转换时从PDF书签得到的页面标题
+        let manifest = PageManifest::load(folder_path);
+        let page_label = |image_path: &Path| {
+            let file_name = image_path.file_name()?.to_str()?;
+            manifest.as_ref()?.label(file_name).map(str::to_string)
+        };
+
+        self.take_usage();
+        // 每页单独计时，卡住的请求只影响这一页；结果按页面顺序返回，进度也按顺序报告
+        let page_timeout = Duration::from_secs(self.config.page_timeout_seconds);
+        let total = image_files.len();
+        let mut pages = futures_util::stream::iter(image_files.iter().cloned().enumerate())
+            .map(|(index, image_path)| async move {
+                // 错开请求的开始时间，避免API请求过于频繁
+                if index > 0 {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
                 }
-                Err(e) => {
-                    error!("❌ 第 {} 张图片处理出错: {}", index + 1, e);
-                    all_results.push(TextExtractionResult::new_error(
-                        image_path.clone(),
-                        format!("处理失败: {}", e)
-                    ));
+                info!("处理第 {}/{} 张图片: {}", index + 1, total, image_path.display());
+                let result = match timeout(page_timeout, self.extract_text_from_image(&image_path)).await {
+                    Ok(Ok(result)) => {
+                        if result.is_success() {
+                            info!("✅ 第 {} 张图片处理成功", index + 1);
+                        } else {
+                            warn!("⚠️ 第 {} 张图片处理失败: {:?}", index + 1, result.error);
+                        }
+                        result
+                    }
+                    Ok(Err(e)) => {
+                        error!("❌ 第 {} 张图片处理出错: {}", index + 1, e);
+                        TextExtractionResult::new_error(image_path, format!("处理失败: {}", e))
+                    }
+                    Err(_) => {
+                        error!("❌ 第 {} 张图片处理超时", index + 1);
+                        TextExtractionResult::new_error(
+                            image_path,
+                            format!("处理超时: {} 秒内没有完成", page_timeout.as_secs()),
+                        )
+                    }
+                };
+                (index, result)
+            })
+            .buffered(concurrency);
+
+        let mut all_results = Vec::new();
+        while let Some((index, mut result)) = self.cancellable(pages.next()).await? {
+            result.page_label = page_label(&image_files[index]);
+            if let Some(progress) = &self.progress {
+                if let Some(error) = &result.error {
+                    progress.on_error(Some(index + 1), error);
                 }
+                progress.on_page(index + 1, total, &result);
             }
-            
-            // 在图片之间添加小延迟，避免API请求过于频繁
-            if index < image_files.len() - 1 {
-                tokio::time::sleep(Duration::from_millis(500)).await;
-            }
+            all_results.push(result);
         }
         
         // 合并所有结果
-        self.merge_extraction_results(folder_path.to_path_buf(), all_results)
+        let mut merged = self.merge_extraction_results(folder_path.to_path_buf(), all_results.clone())?;
+        if self.config.normalize_tags && !merged.text_content.is_empty() {
+            merged.tags = self.normalize_tags(&merged.text_content).await;
+        }
+        Ok(TextExtraction {
+            merged,
+            pages: all_results,
+            blank_pages,
+            provider: self.provider(),
+            prompt_version: TEXT_EXTRACT_PROMPT_VERSION.to_string(),
+            usage: self.take_usage(),
+        })
     }
     
     /// 从单张图片提取文本信息
@@ -248,11 +552,12 @@ impl AiTextAnalyzer {
                 info!("重试第 {} 次...", attempt);
             }
             
-            match self.try_extract_text_api(image_path, attempt, api_config).await {
+            let request = self.try_extract_text_api(image_path, attempt, api_config);
+            match self.cancellable(request).await? {
                 Ok(result) => return Ok(result),
                 Err(e) if attempt < self.config.max_retries => {
                     warn!("文本提取尝试 {} 失败: {}, 重试中...", attempt, e);
-                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    self.cancellable(tokio::time::sleep(Duration::from_secs(2))).await?;
                 }
                 Err(e) => {
                     error!("所有文本提取尝试都失败了 {}: {}", image_path.display(), e);
@@ -270,7 +575,7 @@ impl AiTextAnalyzer {
     async fn try_extract_text_api<P: AsRef<Path>>(
         &self,
         image_path: P,
-        _attempt: u32,
+        attempt: u32,
         api_config: &crate::config::ApiConfig,
     ) -> IResult<TextExtractionResult> {
         let image_path = image_path.as_ref();
@@ -279,9 +584,9 @@ impl AiTextAnalyzer {
         let image_base64 = self.encode_image_for_text_extraction(image_path).await?;
         
         // 根据配置选择API格式
-        let (payload, url) = if api_config.use_compatible_mode {
+        let payload = if api_config.openai_format() {
             // OpenAI兼容格式
-            let payload = serde_json::json!({
+            serde_json::json!({
                 "model": api_config.model_name,
                 "messages": [
                     {
@@ -300,15 +605,12 @@ impl AiTextAnalyzer {
                         ]
                     }
                 ],
-                "temperature": 0.1,
                 "max_tokens": 1024,
                 "stream": false
-            });
-            let url = format!("{}/chat/completions", api_config.endpoint);
-            (payload, url)
+            })
         } else {
             // DashScope原生格式
-            let payload = serde_json::json!({
+            serde_json::json!({
                 "model": api_config.model_name,
                 "input": {
                     "messages": [
@@ -327,14 +629,11 @@ impl AiTextAnalyzer {
                 },
                 "parameters": {
                     "result_format": "message",
-                    "temperature": 0.1,
                     "max_tokens": 1024
                 }
-            });
-            let url = format!("{}/services/aigc/text-generation/generation", 
-                api_config.endpoint.replace("/compatible-mode/v1", ""));
-            (payload, url)
+            })
         };
+        let url = api_config.chat_url();
         
         debug!("发送文字提取请求到: {}", url);
         debug!("使用模型: {}", api_config.model_name);
@@ -342,12 +641,7 @@ impl AiTextAnalyzer {
         // 发送请求到远程API
         let response = timeout(
             Duration::from_secs(300), // 5分钟超时，文字提取可能需要更长时间
-            self.client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", api_config.api_key))
-                .header("Content-Type", "application/json")
-                .json(&payload)
-                .send(),
+            provider_request(&self.client, &url, api_config, payload).send(),
         )
         .await
         .map_err(|_| AnalyzerError::AiError("API request timeout".to_string()))?
@@ -356,41 +650,31 @@ impl AiTextAnalyzer {
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            self.archive_response(image_path, attempt, &error_text);
             debug!("Full API error response: {}", error_text);
             return Err(AnalyzerError::AiError(format!(
                 "API request failed with status {}: {}",
-                status, error_text
+                status, preview(&error_text, 500)
             )));
         }
         
-        let response_json: serde_json::Value = response
-            .json()
+        let body = response
+            .text()
             .await
+            .map_err(|e| AnalyzerError::AiError(format!("Failed to read API response: {}", e)))?;
+        self.archive_response(image_path, attempt, &body);
+        let response_json: serde_json::Value = serde_json::from_str(&body)
             .map_err(|e| AnalyzerError::AiError(format!("Failed to parse API response: {}", e)))?;
+        *self.usage.lock().unwrap() += TokenUsage::from_response(&response_json, Some(&api_config.pricing));
         
         debug!("Full API response: {}", serde_json::to_string_pretty(&response_json).unwrap_or_else(|_| "Failed to serialize response".to_string()));
         
         // 根据API格式解析响应
-        let content = if api_config.use_compatible_mode {
-            // OpenAI兼容格式
-            response_json
-                .get("choices")
-                .and_then(|choices| choices.as_array())
-                .and_then(|arr| arr.first())
-                .and_then(|choice| choice.get("message"))
-                .and_then(|message| message.get("content"))
-                .and_then(|content| content.as_str())
-        } else {
-            // DashScope原生格式
-            response_json
-                .get("output")
-                .and_then(|output| output.get("text"))
-                .and_then(|text| text.as_str())
-        }
-        .ok_or_else(|| AnalyzerError::AiError("No content in API response".to_string()))?;
+        let content = response_content(api_config, &response_json)
+            .ok_or_else(|| AnalyzerError::AiError("No content in API response".to_string()))?;
         
-        debug!("响应长度: {} 字符", content.len());
-        debug!("原始响应前200字符: {}", &content[..content.len().min(200)]);
+        debug!("响应长度: {} 字符", content.chars().count());
+        debug!("原始响应前200字符: {}", truncate_chars(content, 200));
         
         // 解析提取结果
         let mut result = TextExtractionResult::new_success(
@@ -420,6 +704,35 @@ impl AiTextAnalyzer {
                 result.project_name = parsed_data.get("project_name")
                     .and_then(|v| v.as_str())
                     .map(|s| s.to_string());
+
+                result.company = parsed_data.get("company")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+
+                let text = |key: &str| {
+                    parsed_data.get(key)
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                };
+                result.finish = Finish {
+                    surface: text("surface_finish"),
+                    texture: text("texture"),
+                    steel: text("steel"),
+                    hardness: text("hardness"),
+                };
+                result.dimensions = Dimensions::from_extraction(&parsed_data);
+                result.drawing = DrawingFormat::new(text("scale"), text("sheet_size"));
+
+                result.text_content = parsed_data.get("text_content")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| {
+                        arr.iter()
+                            .filter_map(|v| v.as_str())
+                            .map(|s| s.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
                 
                 // 打印提取结果摘要
                 self.print_extraction_summary(&result);
@@ -443,34 +756,67 @@ impl AiTextAnalyzer {
         Ok(result)
     }
     
-    /// 解析API返回的JSON响应
-    fn parse_text_extraction_response(&self, content: &str) -> IResult<serde_json::Value> {
-        let content = content.trim();
-        
-        // 尝试直接解析
-        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(content) {
-            return Ok(parsed);
-        }
-        
-        // 尝试提取JSON部分
-        let json_content = if let Some(start) = content.find("```json") {
-            let start = start + 7;
-            if let Some(end) = content[start..].find("```") {
-                &content[start..start + end]
-            } else {
-                content
+    /// 由模型把技术要求整理为标签，失败时返回空，之后按关键词规则整理
+    async fn normalize_tags(&self, text_content: &[String]) -> Vec<String> {
+        match self.request_tags(text_content).await {
+            Ok(Some(tags)) => {
+                info!("🏷️ 模型整理的标签: {:?}", tags);
+                tags
+            }
+            Ok(None) => {
+                warn!("模型整理的标签无效，使用关键词规则");
+                Vec::new()
             }
-        } else if let Some(start) = content.find('{') {
-            if let Some(end) = content.rfind('}') {
-                &content[start..=end]
-            } else {
-                content
+            Err(e) => {
+                warn!("整理标签失败，使用关键词规则: {}", e);
+                Vec::new()
             }
+        }
+    }
+
+    async fn request_tags(&self, text_content: &[String]) -> IResult<Option<Vec<String>>> {
+        let api_config = self.config.api.as_ref()
+            .ok_or_else(|| AnalyzerError::AiError("API configuration not found".to_string()))?;
+        let prompt = TAG_NORMALIZE_PROMPT.replace("{text}", &text_content.join("\n"));
+        let payload = if api_config.openai_format() {
+            serde_json::json!({
+                "model": api_config.model_name,
+                "messages": [{ "role": "user", "content": prompt }],
+                "max_tokens": 512,
+                "stream": false
+            })
         } else {
-            content
+            serde_json::json!({
+                "model": api_config.model_name,
+                "input": { "messages": [{ "role": "user", "content": prompt }] },
+                "parameters": { "result_format": "message", "max_tokens": 512 }
+            })
         };
-        
-        serde_json::from_str(json_content.trim())
+        let request = provider_request(&self.client, &api_config.chat_url(), api_config, payload).send();
+        let response = self
+            .cancellable(timeout(Duration::from_secs(self.config.timeout_seconds), request))
+            .await?
+            .map_err(|_| AnalyzerError::AiError("API request timeout".to_string()))?
+            .map_err(|e| AnalyzerError::AiError(format!("API HTTP request failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(AnalyzerError::AiError(format!(
+                "API request failed with status {}",
+                response.status()
+            )));
+        }
+        let response_json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| AnalyzerError::AiError(format!("Failed to parse API response: {}", e)))?;
+        *self.usage.lock().unwrap() += TokenUsage::from_response(&response_json, Some(&api_config.pricing));
+        let content = response_content(api_config, &response_json)
+            .ok_or_else(|| AnalyzerError::AiError("No content in API response".to_string()))?;
+        Ok(extract_json(content).ok().as_ref().and_then(normalized_tags))
+    }
+
+    /// 解析API返回的JSON响应
+    fn parse_text_extraction_response(&self, content: &str) -> IResult<serde_json::Value> {
+        extract_json(content)
             .map_err(|e| AnalyzerError::AiError(format!("JSON parse error: {}", e)))
     }
     
@@ -580,6 +926,10 @@ impl AiTextAnalyzer {
         if let Some(project_name) = &result.project_name {
             info!("📋 项目名称: {}", project_name);
         }
+
+        if let Some(company) = &result.company {
+            info!("🏢 公司: {}", company);
+        }
         
         if !result.materials.is_empty() {
             info!(" 材料信息: {}种", result.materials.len());
@@ -604,6 +954,11 @@ impl AiTextAnalyzer {
         let mut merged_model_types = Vec::new();
         let mut merged_materials = Vec::new();
         let mut merged_project_names = Vec::new();
+        let mut merged_companies = Vec::new();
+        let mut merged_text_content: Vec<String> = Vec::new();
+        let mut merged_finish = Finish::default();
+        let mut merged_dimensions = None;
+        let (mut merged_scale, mut merged_sheet_size) = (None, None);
         let mut errors = Vec::new();
         
         let successful_results: Vec<_> = results.iter()
@@ -614,10 +969,10 @@ impl AiTextAnalyzer {
         
         // 收集所有成功结果的信息
         for result in &successful_results {
-            if let Some(model_type) = &result.model_type {
-                if !model_type.trim().is_empty() {
-                    merged_model_types.push(model_type.clone());
-                }
+            if let Some(model_type) = &result.model_type
+                && !model_type.trim().is_empty()
+            {
+                merged_model_types.push(model_type.clone());
             }
             
             for material in &result.materials {
@@ -626,9 +981,31 @@ impl AiTextAnalyzer {
                 }
             }
             
-            if let Some(project_name) = &result.project_name {
-                if !project_name.trim().is_empty() {
-                    merged_project_names.push(project_name.clone());
+            if let Some(project_name) = &result.project_name
+                && !project_name.trim().is_empty()
+            {
+                merged_project_names.push(project_name.clone());
+            }
+
+            if let Some(company) = &result.company
+                && !company.trim().is_empty()
+            {
+                merged_companies.push(company.clone());
+            }
+
+            merged_finish = merged_finish.or(&result.finish);
+            // 外形尺寸通常只在主视图所在的页面标注一次，使用第一个有尺寸的页面
+            if merged_dimensions.is_none() {
+                merged_dimensions = result.dimensions.clone();
+            }
+            // 比例和图幅以标题栏为准，各页相同，使用第一个识别出的值
+            merged_scale = merged_scale.or_else(|| result.drawing.scale.clone());
+            merged_sheet_size = merged_sheet_size.or_else(|| result.drawing.sheet_size.clone());
+
+            // 保持原有顺序，技术要求的编号有意义
+            for text in &result.text_content {
+                if !text.trim().is_empty() && !merged_text_content.contains(text) {
+                    merged_text_content.push(text.clone());
                 }
             }
         }
@@ -636,7 +1013,7 @@ impl AiTextAnalyzer {
         // 收集错误信息
         for result in &results {
             if let Some(error) = &result.error {
-                errors.push(format!("{}: {}", result.image_path.display(), error));
+                errors.push(format!("{}: {}", result.page_name(), error));
             }
         }
         
@@ -649,6 +1026,9 @@ impl AiTextAnalyzer {
         
         merged_project_names.sort();
         merged_project_names.dedup();
+
+        merged_companies.sort();
+        merged_companies.dedup();
         
         // 选择最合适的模具类型（出现频率最高的）
         let final_model_type = if !merged_model_types.is_empty() {
@@ -665,7 +1045,7 @@ impl AiTextAnalyzer {
         };
         
         // 创建合并结果
-        let merged_result = if successful_results.is_empty() {
+        let mut merged_result = if successful_results.is_empty() {
             TextExtractionResult::new_error(
                 folder_path,
                 format!("所有图片处理都失败: {}", errors.join("; "))
@@ -678,6 +1058,11 @@ impl AiTextAnalyzer {
                 final_project_name,
             )
         };
+        merged_result.company = merged_companies.into_iter().next();
+        merged_result.text_content = merged_text_content;
+        merged_result.finish = merged_finish;
+        merged_result.dimensions = merged_dimensions;
+        merged_result.drawing = DrawingFormat::new(merged_scale, merged_sheet_size);
         
         // 打印合并结果摘要
         info!("📊 合并结果摘要:");
@@ -693,3 +1078,27 @@ impl AiTextAnalyzer {
         Ok(merged_result)
     }
 }
+
+/// 根据API格式取出回复的文字
+#[cfg(feature = "ai")]
+fn response_content<'a>(
+    api_config: &crate::config::ApiConfig,
+    response: &'a serde_json::Value,
+) -> Option<&'a str> {
+    if api_config.openai_format() {
+        // OpenAI兼容格式
+        response
+            .get("choices")
+            .and_then(|choices| choices.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|choice| choice.get("message"))
+            .and_then(|message| message.get("content"))
+            .and_then(|content| content.as_str())
+    } else {
+        // DashScope原生格式
+        response
+            .get("output")
+            .and_then(|output| output.get("text"))
+            .and_then(|text| text.as_str())
+    }
+}
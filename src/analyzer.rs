@@ -0,0 +1,180 @@
+//! 嵌入使用的分析接口
+//!
+//! 其他程序直接链接本库时不需要启动HTTP服务：`Analyzer`把PDF图纸转换为图片、调用模型服务提取
+//! 模具类型和材料，并与模具库比较，`analyze_pdf_with`通过`ProgressSink`报告进度；
+//! `ModelStore::search`按检索条件检索模具库。
+//! 分析不创建任务记录，也不发送聊天消息，结果与聊天中的报告使用相同的评分方案。
+//!
+//! ```no_run
+//! use material_rs::{Analyzer, AnalyzerError, Config, UserQuery};
+//!
+//! # async fn run() -> material_rs::IResult<()> {
+//! let analyzer = Analyzer::new(Config::load().map_err(AnalyzerError::ConfigError)?)?;
+//! let analysis = analyzer.analyze_pdf("ME121.pdf").await?;
+//! for result in &analysis.matches {
+//!     println!("{} {}", result.source_name, result.display_percentage());
+//! }
+//!
+//! let query = UserQuery {
+//!     model_type: Some("基座".to_string()),
+//!     materials: vec!["PBT".to_string()],
+//!     ..Default::default()
+//! };
+//! let hits = analyzer.store().search(&query);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    AnalyzerError, IResult,
+    ai_text_analyzer::{AiTextAnalyzer, TextExtraction},
+    config::Config,
+    diff::{DiffResult, ModelJson, Provenance},
+    http::build_client,
+    job::JobStage,
+    model_store::{ModelStore, models_dir},
+    pdf_converter::convert_to_image,
+    progress_sink::ProgressSink,
+};
+
+/// 图纸分析器，可以在多个任务之间共用
+pub struct Analyzer {
+    config: Config,
+    client: reqwest::Client,
+    store: Arc<ModelStore>,
+    /// 页面图片的输出目录，None时使用临时目录并在分析后删除
+    work_dir: Option<PathBuf>,
+}
+
+/// 一份图纸的分析结果
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// 每页和合并后的提取结果
+    pub extraction: TextExtraction,
+    /// 提取结果对应的模具记录，可以直接保存到模具库
+    pub model: ModelJson,
+    /// 相似的模具，按相似度排序，没有识别出模具类型和材料时为空
+    pub matches: Vec<DiffResult>,
+}
+
+impl Analysis {
+    /// 没有识别出模具类型和材料
+    pub fn is_unidentified(&self) -> bool {
+        self.model.is_unidentified()
+    }
+}
+
+impl Analyzer {
+    /// 按配置创建HTTP客户端，并从`models`目录或配置的数据库加载模具库
+    pub fn new(config: Config) -> IResult<Self> {
+        let client = build_client(&config.http)?;
+        let store = ModelStore::open(&models_dir(), &config.storage)?;
+        Ok(Self {
+            config,
+            client,
+            store: Arc::new(store),
+            work_dir: None,
+        })
+    }
+
+    /// 使用已经加载的模具库，例如与服务共用同一份数据
+    pub fn with_store(mut self, store: Arc<ModelStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// 把页面图片保存在`work_dir/<PDF文件名>/`中，不在分析后删除
+    pub fn with_work_dir(mut self, work_dir: PathBuf) -> Self {
+        self.work_dir = Some(work_dir);
+        self
+    }
+
+    /// 比较和检索使用的模具库
+    pub fn store(&self) -> &ModelStore {
+        &self.store
+    }
+
+    /// 分析PDF图纸：转换为图片，提取文本信息，再与模具库比较
+    pub async fn analyze_pdf(&self, path: impl AsRef<Path>) -> IResult<Analysis> {
+        self.analyze_pdf_with(path, Arc::new(())).await
+    }
+
+    /// 分析PDF图纸，转换时进入`Preparing`阶段，提取和比较时进入`Executing`阶段，
+    /// 每提取完一页调用`on_page`，失败时调用`on_error`后返回错误
+    pub async fn analyze_pdf_with(
+        &self,
+        path: impl AsRef<Path>,
+        progress: Arc<dyn ProgressSink>,
+    ) -> IResult<Analysis> {
+        let path = path.as_ref();
+        let analyzer = AiTextAnalyzer::new(self.config.ai.clone(), self.client.clone())
+            .with_blank_page(self.config.blank_page)
+            .with_progress(progress.clone());
+        if let Err(e) = analyzer.verify_api_availability() {
+            progress.on_error(None, &e.to_string());
+            return Err(e);
+        }
+
+        let (work_dir, temporary) = match &self.work_dir {
+            Some(dir) => (dir.clone(), false),
+            None => (
+                std::env::temp_dir().join(format!("material_{}", uuid::Uuid::new_v4())),
+                true,
+            ),
+        };
+        let result = self
+            .analyze_in(&analyzer, progress.as_ref(), path, &work_dir)
+            .await;
+        if temporary {
+            let _ = std::fs::remove_dir_all(&work_dir);
+        }
+        if let Err(e) = &result {
+            progress.on_error(None, &e.to_string());
+        }
+        result
+    }
+
+    async fn analyze_in(
+        &self,
+        analyzer: &AiTextAnalyzer,
+        progress: &dyn ProgressSink,
+        path: &Path,
+        work_dir: &Path,
+    ) -> IResult<Analysis> {
+        progress.on_stage(JobStage::Preparing);
+        let images_dir = convert_to_image(path, work_dir).map_err(AnalyzerError::PdfError)?;
+        progress.on_stage(JobStage::Executing);
+        let extraction = analyzer.extract_pages_from_folder(&images_dir).await?;
+        if let Some(error) = &extraction.merged.error {
+            return Err(AnalyzerError::AiError(error.clone()));
+        }
+
+        let mut model = ModelJson::from(extraction.merged.clone());
+        model.original_pdf = Some(path.to_path_buf());
+        model.extraction_timestamp = Some(chrono::Local::now().to_rfc3339());
+        model.provenance = extraction.provider.clone().map(|provider| Provenance {
+            provider: provider.provider,
+            model_name: provider.model_name,
+            prompt_version: extraction.prompt_version.clone(),
+        });
+        // 和所有模具比较只会得到噪声
+        let matches = if model.is_unidentified() {
+            Vec::new()
+        } else {
+            self.store.compare(
+                model.clone(),
+                &self.config.diff.default_profile(),
+                self.config.diff.include_scrapped,
+            )
+        };
+        Ok(Analysis {
+            extraction,
+            model,
+            matches,
+        })
+    }
+}
@@ -0,0 +1,267 @@
+use rust_embed::Embed;
+use salvo::{
+    Request, Response, handler,
+    http::{StatusCode, header::CONTENT_TYPE},
+    writing::Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    CONFIG, JOBS, MAINTENANCE, MODELS, QUEUE, TAXONOMY,
+    api::error::ApiError,
+    corpus_snapshot::{Snapshot, SnapshotDiff, parse_date, snapshots_dir, today},
+    job::{JobKind, JobStage, JobStatus},
+    model_reload::reload_now,
+    model_store::models_dir,
+    secrets::{self, AI_API_KEY, BOT_API_KEY, WEBHOOK_SECRET},
+};
+
+/// 任务概览中列出的最近任务数
+const RECENT_JOBS: usize = 50;
+
+/// 管理页面，展示任务队列、模具数据库统计、反馈准确率和配置摘要，数据来自下面的JSON接口，
+/// 编译时嵌入
+#[derive(Embed)]
+#[folder = "assets/admin/"]
+struct AdminAssets;
+
+/// 管理页面的静态文件，没有路径时返回首页
+/// GET /material/admin/{**path}
+#[handler]
+pub async fn admin_page(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let path = req.param::<String>("path").unwrap_or_default();
+    let path = if path.is_empty() { "index.html" } else { &path };
+    let file = AdminAssets::get(path).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            format!("找不到 `{}`", path),
+            format!("`{}` not found", path),
+        )
+    })?;
+    let _ = res.add_header(CONTENT_TYPE, content_type(path), true);
+    if path.ends_with(".html") {
+        // 页面中的链接按配置的路径前缀替换
+        let html = String::from_utf8_lossy(&file.data)
+            .replace("/material/admin/", &CONFIG.server.path("admin/"));
+        let _ = res.write_body(html);
+    } else {
+        let _ = res.write_body(file.data.into_owned());
+    }
+    Ok(())
+}
+
+pub(crate) fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 任务列表中的一项
+#[derive(Debug, Serialize)]
+struct JobSummary {
+    id: String,
+    kind: JobKind,
+    state: JobStatus,
+    stage: Option<JobStage>,
+    input: String,
+    model_name: Option<String>,
+    duration_ms: Option<u64>,
+    error: Option<String>,
+    created_at: String,
+}
+
+/// 队列中正在执行和排队的任务数，以及最近的任务
+/// GET /material/api/v1/admin/jobs
+#[handler]
+pub async fn jobs_overview(_req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let mut jobs = JOBS.all();
+    jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let count = |status: JobStatus| jobs.iter().filter(|j| j.status == status).count();
+    let totals = serde_json::json!({
+        "running": count(JobStatus::Running),
+        "succeeded": count(JobStatus::Succeeded),
+        "failed": count(JobStatus::Failed),
+        "cancelled": count(JobStatus::Cancelled),
+    });
+    let recent: Vec<JobSummary> = jobs
+        .into_iter()
+        .take(RECENT_JOBS)
+        .map(|job| JobSummary {
+            id: job.id,
+            kind: job.kind,
+            state: job.status,
+            stage: job.stage,
+            input: job.input,
+            model_name: job.model_name,
+            duration_ms: job.duration_ms,
+            error: job.error,
+            created_at: job.created_at,
+        })
+        .collect();
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "queue": {
+                "running": QUEUE.running(),
+                "waiting": QUEUE.waiting(),
+                "capacity": QUEUE.capacity(),
+                "held": MAINTENANCE.held(),
+            },
+            "read_only": MAINTENANCE.is_read_only(),
+            "totals": totals,
+            "recent": recent,
+        },
+    })));
+    Ok(())
+}
+
+/// 模具数据库按类型、状态和分片的统计
+/// GET /material/api/v1/admin/corpus
+#[handler]
+pub async fn corpus_stats(_req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": MODELS.load().stats(),
+    })));
+    Ok(())
+}
+
+/// 比较两个日期的模具数据库快照，每个日期使用当天或之前最近的快照，没有`to`时与当前的索引比较
+/// GET /material/api/v1/admin/snapshots/diff?from=2025-09-01&to=2025-10-01
+#[handler]
+pub async fn snapshot_diff(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let snapshot = |key: &str| -> Result<Option<Snapshot>, ApiError> {
+        let Some(text) = req.query::<String>(key) else {
+            return Ok(None);
+        };
+        let date = parse_date(&text).ok_or_else(|| {
+            ApiError::invalid_request(
+                format!("`{}`的日期格式应为YYYY-MM-DD", key),
+                format!("`{}` must be a date in YYYY-MM-DD format", key),
+            )
+        })?;
+        let snapshot = Snapshot::find(&snapshots_dir(), date)?.ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "snapshot_not_found",
+                format!("{} 及之前没有快照", text),
+                format!("No snapshot on or before {}", text),
+            )
+        })?;
+        Ok(Some(snapshot))
+    };
+    let from = snapshot("from")?.ok_or_else(|| {
+        ApiError::invalid_request("缺少参数`from`", "Missing query parameter `from`")
+    })?;
+    let to = match snapshot("to")? {
+        Some(to) => to,
+        None => Snapshot::take(&today(), &MODELS.load(), &TAXONOMY.read().unwrap()),
+    };
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": SnapshotDiff::new(&from, &to),
+    })));
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub read_only: bool,
+}
+
+/// 切换只读模式，恢复读写时暂停的任务按到达顺序继续执行
+/// PUT /material/api/v1/admin/maintenance
+/// {"read_only": true}
+#[handler]
+pub async fn set_maintenance(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let body = req.parse_json::<MaintenanceRequest>().await.map_err(|_| {
+        ApiError::invalid_request(
+            "请求格式错误，需要 read_only",
+            "Invalid request, read_only is required",
+        )
+    })?;
+    let held = MAINTENANCE.held();
+    let changed = MAINTENANCE.set_read_only(body.read_only);
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "read_only": body.read_only,
+            "changed": changed,
+            "held": held,
+        },
+    })));
+    Ok(())
+}
+
+/// 当前配置的摘要，不包括密钥和代理地址
+/// GET /material/api/v1/admin/config
+#[handler]
+pub async fn config_summary(_req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let provider = match &CONFIG.ai.api {
+        Some(api) => serde_json::json!({
+            "endpoint": api.endpoint,
+            "model_name": api.model_name,
+            "kind": api.provider,
+            "compatible_mode": api.openai_format(),
+        }),
+        None => serde_json::json!({
+            "endpoint": CONFIG.ai.ollama_base,
+            "model_name": CONFIG.ai.local_model,
+        }),
+    };
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "bind": CONFIG.server.unix_socket.as_ref().map_or_else(
+                || CONFIG.server.bind.clone(),
+                |socket| format!("unix:{}", socket.display()),
+            ),
+            "base_path": CONFIG.server.path(""),
+            "tls": CONFIG.server.tls_cert.is_some() && CONFIG.server.tls_key.is_some(),
+            "provider": provider,
+            "max_retries": CONFIG.ai.max_retries,
+            "timeout_seconds": CONFIG.ai.timeout_seconds,
+            "storage": CONFIG.storage.backend,
+            "proxy": CONFIG.http.proxy.is_some(),
+            "webhook_verification": CONFIG.webhook.verification,
+            "api_keys": CONFIG.auth.keys.len(),
+            "secrets": {
+                "ai_api_key": secrets::get(&AI_API_KEY).is_some(),
+                "bot_api_key": secrets::get(&BOT_API_KEY).is_some(),
+                "webhook_secret": secrets::get(&WEBHOOK_SECRET).is_some(),
+            },
+        },
+    })));
+    Ok(())
+}
+
+/// 立即重新读取模具记录并替换当前的索引，返回加载、无法读取和需要重新提取的记录数，
+/// 以及新增和删除的模具，新增和删除会通知配置的模具库事件地址
+/// POST /material/api/v1/admin/reindex
+#[handler]
+pub async fn reindex(_req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let summary = reload_now(&models_dir(), &CONFIG.storage)
+        .await
+        .inspect_err(|e| error!("重建模具索引失败: {}", e))?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "loaded": summary.loaded,
+            "failed": summary.failed.len(),
+            "failed_records": summary.failed,
+            "stale": summary.stale.len(),
+            "stale_records": summary.stale,
+            "ingested": summary.ingested,
+            "deleted": summary.deleted,
+        },
+    })));
+    Ok(())
+}
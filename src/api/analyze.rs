@@ -0,0 +1,84 @@
+use std::{io::Read, path::Path};
+
+use salvo::{Request, Response, handler, http::StatusCode, writing::Json};
+use tracing::info;
+
+use crate::{
+    AnalyzerError, BLOBS,
+    api::error::ApiError,
+    blob::Blob,
+    preflight::check_upload,
+    workflow::{Workflow, create_detached_analysis_workflow},
+};
+
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+/// 文件内容是否为PDF，不依赖文件名和Content-Type
+fn is_pdf(path: &Path) -> std::io::Result<bool> {
+    let mut header = [0; PDF_MAGIC.len()];
+    let mut file = std::fs::File::open(path)?;
+    let read = file.read(&mut header)?;
+    Ok(&header[..read] == PDF_MAGIC)
+}
+
+/// 直接上传PDF进行分析，与聊天中发送PDF文件使用相同的流程，但不发送消息，
+/// 立即返回任务id，结果保存在任务记录中，通过任务接口查询
+/// POST /material/api/v1/analyze (multipart/form-data，字段`file`)
+#[handler]
+pub async fn analyze(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let file = req.file("file").await.ok_or_else(|| {
+        ApiError::invalid_request(
+            "请求格式错误，需要 multipart/form-data 字段 file",
+            "Invalid request, multipart/form-data field `file` is required",
+        )
+    })?;
+    let name = file.name().unwrap_or("upload.pdf").to_string();
+    if !is_pdf(file.path()).map_err(AnalyzerError::from)? {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_file",
+            format!("`{}` 不是PDF文件", name),
+            format!("`{}` is not a PDF file", name),
+        ));
+    }
+    // 保存文件和检查PDF都要读取整个文件，在阻塞线程中进行
+    let path = file.path().clone();
+    let pdf = tokio::task::spawn_blocking(move || -> Result<Blob, ApiError> {
+        let pdf = BLOBS.put_file(&path)?;
+        // 页数或图幅超出限制时不启动分析
+        check_upload(&pdf).map_err(|e| {
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "preflight_failed",
+                e.to_string(),
+                e.message_en(),
+            )
+        })?;
+        Ok(pdf)
+    })
+    .await
+    .map_err(|e| AnalyzerError::WorkflowError(format!("保存上传的文件失败: {}", e)))??;
+
+    let workflow = create_detached_analysis_workflow(pdf, &name);
+    let job_id = workflow.job_id().to_string();
+    info!("通过接口提交的文件 {} 开始分析: {}", name, job_id);
+    workflow.start();
+    res.status_code(StatusCode::ACCEPTED);
+    res.render(Json(serde_json::json!({
+        "status": 202,
+        "data": { "job_id": job_id },
+    })));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::fixture;
+
+    #[test]
+    fn detect_pdf_content() {
+        assert!(is_pdf(&fixture("pdfs/03-jz.pdf")).unwrap());
+        assert!(!is_pdf(&fixture("models/jsons/ME121基座_text_data.json")).unwrap());
+    }
+}
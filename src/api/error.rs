@@ -0,0 +1,169 @@
+//! 接口的错误回复
+//!
+//! 错误使用对应的HTTP状态码，回复体中的`code`是稳定的机器可读错误码，
+//! `message`保留原来的中文提示，另外给出`message_zh`、`message_en`和相关的任务id。
+use salvo::{
+    Response,
+    http::StatusCode,
+    writing::{Json, Scribe},
+};
+
+use crate::AnalyzerError;
+
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message_zh: String,
+    pub message_en: String,
+    pub job_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(
+        status: StatusCode,
+        code: &'static str,
+        message_zh: impl Into<String>,
+        message_en: impl Into<String>,
+    ) -> Self {
+        Self {
+            status,
+            code,
+            message_zh: message_zh.into(),
+            message_en: message_en.into(),
+            job_id: None,
+        }
+    }
+
+    /// 请求格式错误
+    pub fn invalid_request(message_zh: impl Into<String>, message_en: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            message_zh,
+            message_en,
+        )
+    }
+
+    pub fn model_not_found(key: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "model_not_found",
+            format!("找不到模型 `{}`", key),
+            format!("Model `{}` not found", key),
+        )
+    }
+
+    pub fn job_not_found(id: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "job_not_found",
+            format!("找不到任务 `{}`", id),
+            format!("Job `{}` not found", id),
+        )
+    }
+
+    pub fn with_job(mut self, job_id: impl Into<String>) -> Self {
+        self.job_id = Some(job_id.into());
+        self
+    }
+}
+
+impl From<AnalyzerError> for ApiError {
+    fn from(e: AnalyzerError) -> Self {
+        let (status, code, message_zh) = match &e {
+            AnalyzerError::PdfError(detail) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "pdf_error",
+                format!("PDF 处理失败: {}", detail),
+            ),
+            AnalyzerError::ImageError(detail) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "image_error",
+                format!("图片处理失败: {}", detail),
+            ),
+            AnalyzerError::AiError(detail) => (
+                StatusCode::BAD_GATEWAY,
+                "ai_error",
+                format!("模型服务调用失败: {}", detail),
+            ),
+            AnalyzerError::SamError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "sam_error",
+                format!("SAM 分割失败: {}", detail),
+            ),
+            AnalyzerError::IoError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "io_error",
+                format!("读写文件失败: {}", detail),
+            ),
+            AnalyzerError::SerializationError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "serialization_error",
+                format!("数据格式错误: {}", detail),
+            ),
+            AnalyzerError::ConfigError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "config_error",
+                format!("配置错误: {}", detail),
+            ),
+            AnalyzerError::PythonError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "python_error",
+                format!("Python 脚本执行失败: {}", detail),
+            ),
+            AnalyzerError::WorkflowError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "workflow_error",
+                format!("任务执行失败: {}", detail),
+            ),
+            AnalyzerError::DatabaseError(detail) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+                format!("数据库错误: {}", detail),
+            ),
+        };
+        Self::new(status, code, message_zh, e.to_string())
+    }
+}
+
+impl Scribe for ApiError {
+    fn render(self, res: &mut Response) {
+        res.status_code(self.status);
+        res.render(Json(serde_json::json!({
+            "status": self.status.as_u16(),
+            "code": self.code,
+            "message": format!("❌ {}", self.message_zh),
+            "message_zh": self.message_zh,
+            "message_en": self.message_en,
+            "job_id": self.job_id,
+        })));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::test::ResponseExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn error_response() {
+        let error = ApiError::from(AnalyzerError::AiError("timeout".to_string())).with_job("01J");
+        assert_eq!(error.code, "ai_error");
+        assert_eq!(error.message_en, "AI analysis error: timeout");
+
+        let mut res = Response::new();
+        error.render(&mut res);
+        assert_eq!(res.status_code, Some(StatusCode::BAD_GATEWAY));
+        let body: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(body["status"], 502);
+        assert_eq!(body["code"], "ai_error");
+        assert_eq!(body["message"], "❌ 模型服务调用失败: timeout");
+        assert_eq!(body["job_id"], "01J");
+
+        let error = ApiError::model_not_found("ME121");
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+        assert!(error.job_id.is_none());
+    }
+}
@@ -0,0 +1,56 @@
+use salvo::{Request, Response, handler, http::StatusCode};
+use tracing::warn;
+
+use crate::{
+    IMAGE_URLS,
+    api::error::ApiError,
+    paths::{relative_path, upload_dir},
+};
+
+/// 只允许普通的相对路径，防止跳出上传目录
+fn is_safe_path(path: &str) -> bool {
+    relative_path(path).is_some()
+}
+
+/// 通过签名链接访问上传的文件
+/// GET /material/files/{**path}?expires=..&token=..
+#[handler]
+pub async fn signed_file(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let path = req.param::<String>("path").unwrap_or_default();
+    let expires = req.query::<i64>("expires").unwrap_or_default();
+    let token = req.query::<String>("token").unwrap_or_default();
+
+    if !is_safe_path(&path) || !IMAGE_URLS.verify(&path, expires, &token) {
+        warn!("拒绝访问文件: {}", path);
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "invalid_link",
+            "链接无效或已过期",
+            "The link is invalid or has expired",
+        ));
+    }
+
+    // 预览图保存时可能带有扩展名
+    let file = upload_dir().join(relative_path(&path).unwrap_or_default());
+    let file = if file.exists() {
+        file
+    } else {
+        file.with_extension("png")
+    };
+    res.send_file(file, req.headers()).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_unsafe_paths() {
+        assert!(is_safe_path("models/imgs/a/a_page_001"));
+        assert!(!is_safe_path("models/../../secret"));
+        assert!(!is_safe_path("/etc/passwd"));
+        assert!(!is_safe_path(""));
+        assert!(!is_safe_path("models\\..\\..\\secret"));
+    }
+}
@@ -0,0 +1,255 @@
+use futures_util::{Stream, StreamExt};
+use salvo::{
+    Request, Response, handler,
+    http::{
+        StatusCode,
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    websocket::{Message, WebSocket, WebSocketUpgrade},
+    writing::Json,
+};
+use serde::Serialize;
+use tracing::{debug, error};
+
+use crate::{
+    AnalyzerError, CONFIG, JOB_EVENTS, JOBS, MODELS,
+    api::error::ApiError,
+    diff::DiffResult,
+    job::{JobRecord, JobStatus},
+    job_diff::JobDiff,
+    job_events::{event_stream, progress_stream},
+};
+
+/// 按提示词/模型版本统计用户反馈的准确率
+/// GET /material/api/v1/feedback/metrics
+#[handler]
+pub async fn feedback_metrics(_req: &mut Request, res: &mut Response) -> Result<(), ()> {
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": JOBS.feedback_metrics(),
+    })));
+    Ok(())
+}
+
+/// 任务的状态和所处阶段，用于轮询后台分析，任务可以使用id前缀
+/// GET /material/api/v1/jobs/{id}
+#[handler]
+pub async fn job_status(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    let job = JOBS.find(&id).ok_or_else(|| ApiError::job_not_found(&id))?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "id": job.id,
+            "kind": job.kind,
+            "state": job.status,
+            "stage": job.stage,
+            "attempts": job.attempts,
+            "duration_ms": job.duration_ms,
+            "error": job.error,
+            "created_at": job.created_at,
+            "updated_at": job.updated_at,
+        },
+    })));
+    Ok(())
+}
+
+/// 完成的任务的提取结果、匹配列表和报告，任务未完成或失败时返回409
+/// GET /material/api/v1/jobs/{id}/result
+#[handler]
+pub async fn job_result(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    let job = JOBS.find(&id).ok_or_else(|| ApiError::job_not_found(&id))?;
+    if let Some(error) = unfinished(&job) {
+        return Err(error);
+    }
+    let matches = DiffResult::with_previews(
+        MODELS.load_full(),
+        job.matches.clone(),
+        CONFIG.report.preview_pages,
+    )
+    .await;
+    let matches: Vec<JobMatch> = matches
+        .iter()
+        .map(|(result, pages)| JobMatch { result, pages })
+        .collect();
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "id": job.id,
+            "extraction": job.extraction,
+            "matches": matches,
+            "scale_conflict": job.extraction.as_ref().and_then(|model| model.scale_conflict()),
+            "report": JOBS.report(&job.id),
+        },
+    })));
+    Ok(())
+}
+
+/// 任务结果中的一个匹配，附带与报告相同的预览图链接
+#[derive(Serialize)]
+struct JobMatch<'a> {
+    #[serde(flatten)]
+    result: &'a DiffResult,
+    pages: &'a [String],
+}
+
+/// 没有成功完成的任务对应的错误
+fn unfinished(job: &JobRecord) -> Option<ApiError> {
+    let (code, message_zh, message_en) = match job.status {
+        JobStatus::Succeeded => return None,
+        JobStatus::Running => (
+            "job_running",
+            format!("任务 `{}` 仍在进行", job.id),
+            format!("Job `{}` is still running", job.id),
+        ),
+        JobStatus::Failed => (
+            "job_failed",
+            format!(
+                "任务 `{}` 失败: {}",
+                job.id,
+                job.error.as_deref().unwrap_or_default()
+            ),
+            format!(
+                "Job `{}` failed: {}",
+                job.id,
+                job.error.as_deref().unwrap_or_default()
+            ),
+        ),
+        JobStatus::Cancelled => (
+            "job_cancelled",
+            format!("任务 `{}` 已取消", job.id),
+            format!("Job `{}` was cancelled", job.id),
+        ),
+    };
+    Some(ApiError::new(StatusCode::CONFLICT, code, message_zh, message_en).with_job(&job.id))
+}
+
+/// 比较两个任务的提取结果和匹配列表，任务可以使用id前缀
+/// GET /material/api/v1/jobs/{a}/diff/{b}
+#[handler]
+pub async fn job_diff(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let a = req.param::<String>("a").unwrap_or_default();
+    let b = req.param::<String>("b").unwrap_or_default();
+    let before = JOBS.find(&a).ok_or_else(|| ApiError::job_not_found(&a))?;
+    let after = JOBS.find(&b).ok_or_else(|| ApiError::job_not_found(&b))?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": JobDiff::new(&before, &after),
+    })));
+    Ok(())
+}
+
+/// 任务的合并和每页的文本提取结果，以及使用的模型服务
+/// GET /material/api/v1/jobs/{id}/extraction
+#[handler]
+pub async fn job_extraction(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    let job = JOBS.find(&id).ok_or_else(|| ApiError::job_not_found(&id))?;
+    let extraction = JOBS.extraction(&job.id).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "extraction_not_found",
+            format!("任务 `{}` 没有文本提取结果", job.id),
+            format!("Job `{}` has no text extraction", job.id),
+        )
+        .with_job(&job.id)
+    })?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": extraction,
+    })));
+    Ok(())
+}
+
+/// 打包下载任务的页面图片、视图、提取结果、匹配结果和报告
+/// GET /material/api/v1/jobs/{id}/artifacts.zip
+#[handler]
+pub async fn job_artifacts(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    let job = JOBS.find(&id).ok_or_else(|| ApiError::job_not_found(&id))?;
+    // 读取工作目录中的文件和压缩在阻塞线程中进行
+    let job_id = job.id.clone();
+    let bytes = tokio::task::spawn_blocking(move || JOBS.artifacts(&job_id))
+        .await
+        .map_err(|e| AnalyzerError::WorkflowError(format!("打包任务文件失败: {}", e)))?
+        .ok_or_else(|| ApiError::job_not_found(&id))?
+        .map_err(|e| {
+            error!("打包任务 {} 的文件失败: {}", job.id, e);
+            ApiError::from(e).with_job(&job.id)
+        })?;
+    let _ = res.add_header(CONTENT_TYPE, "application/zip", true);
+    let _ = res.add_header(
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}.zip\"", job.id),
+        true,
+    );
+    let _ = res.write_body(bytes);
+    Ok(())
+}
+
+/// 任务进度的Server-Sent Events，先发送当前阶段，之后推送阶段变化、转换和每页提取的进度，
+/// 任务结束后关闭，任务可以使用id前缀
+/// GET /material/api/v1/jobs/{id}/events
+#[handler]
+pub async fn job_events(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    // 先订阅再读取任务记录，避免错过两者之间的事件
+    let receiver = JOB_EVENTS.subscribe();
+    let job = JOBS.find(&id).ok_or_else(|| ApiError::job_not_found(&id))?;
+    let _ = res.add_header(CONTENT_TYPE, "text/event-stream", true);
+    let _ = res.add_header(CACHE_CONTROL, "no-cache", true);
+    // 关闭nginx的缓冲，否则事件会积压到连接结束
+    let _ = res.add_header("x-accel-buffering", "no", true);
+    res.stream(event_stream(&job, receiver));
+    Ok(())
+}
+
+/// 任务进度的WebSocket，消息与`/events`的事件数据相同，为带有`event`字段的JSON，
+/// 任务结束后关闭连接，任务可以使用id前缀
+/// GET /material/api/v1/jobs/{id}/ws
+#[handler]
+pub async fn job_socket(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let id = req.param::<String>("id").unwrap_or_default();
+    let receiver = JOB_EVENTS.subscribe();
+    let job = JOBS.find(&id).ok_or_else(|| ApiError::job_not_found(&id))?;
+    let messages =
+        progress_stream(&job, receiver).map(|progress| progress.map(|progress| progress.to_json()));
+    WebSocketUpgrade::new()
+        .upgrade(req, res, |socket| push(socket, messages))
+        .await
+        .map_err(|_| {
+            ApiError::invalid_request(
+                "需要WebSocket连接(版本13)",
+                "WebSocket upgrade (version 13) required",
+            )
+        })
+}
+
+/// 依次发送`messages`，None时发送ping，消息发送完或客户端关闭时关闭连接。
+/// 客户端的ping由`WebSocket`自动回复，数据帧被忽略
+async fn push(mut socket: WebSocket, messages: impl Stream<Item = Option<String>>) {
+    tokio::pin!(messages);
+    loop {
+        let message = tokio::select! {
+            message = messages.next() => match message {
+                Some(Some(text)) => Message::text(text),
+                Some(None) => Message::ping(Vec::new()),
+                None => break,
+            },
+            received = socket.recv() => match received {
+                Some(Ok(message)) if !message.is_close() => continue,
+                Some(Ok(_)) => break,
+                // 客户端直接断开
+                Some(Err(_)) | None => return,
+            },
+        };
+        if let Err(e) = socket.send(message).await {
+            debug!("WebSocket连接已断开: {}", e);
+            return;
+        }
+    }
+    if let Err(e) = socket.close().await {
+        debug!("关闭WebSocket连接失败: {}", e);
+    }
+}
@@ -1 +1,10 @@
-pub mod pdf;
\ No newline at end of file
+pub mod admin;
+pub mod analyze;
+pub mod error;
+pub mod files;
+pub mod job;
+pub mod model;
+pub mod openapi;
+pub mod pdf;
+pub mod search;
+pub mod taxonomy;
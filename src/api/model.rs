@@ -0,0 +1,234 @@
+use salvo::{Request, Response, handler, http::StatusCode, writing::Json};
+use serde::Deserialize;
+
+use crate::{
+    AnalyzerError, CONFIG, MODELS,
+    api::error::ApiError,
+    config::{DiffConfig, ScoringProfile},
+    corpus_events::{CorpusEvent, CorpusEventKind, spawn_notify},
+    diff::{DiffResult, ModelJson},
+    model_storage::open_repository,
+    model_store::models_dir,
+    mold_status::MoldStatus,
+    query::normalize_date,
+};
+
+/// 按来源名称、id或slug查看模型的完整记录和预览图
+/// GET /material/api/v1/models/{source_name}
+#[handler]
+pub async fn model_detail(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let source_name = req.param::<String>("source_name").unwrap_or_default();
+    let detail = MODELS
+        .load()
+        .detail(&source_name)
+        .ok_or_else(|| ApiError::model_not_found(&source_name))?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": detail,
+    })));
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusRequest {
+    /// 在用/封存/报废，null表示清除
+    pub status: Option<String>,
+}
+
+/// 修改实体模具的状态，保存到记录文件并立即生效
+/// PUT /material/api/v1/models/{source_name}/status
+/// ```json
+/// { "status": "报废" }
+/// ```
+#[handler]
+pub async fn update_status(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let key = req.param::<String>("source_name").unwrap_or_default();
+    let status = match req.parse_json::<StatusRequest>().await {
+        Ok(StatusRequest { status: None }) => None,
+        Ok(StatusRequest {
+            status: Some(status),
+        }) if MoldStatus::parse(&status).is_some() => MoldStatus::parse(&status),
+        _ => {
+            return Err(ApiError::invalid_request(
+                "请求格式错误，status 为 在用、封存、报废 或 null",
+                "Invalid request, status must be in_use, sealed, scrapped or null",
+            ));
+        }
+    };
+    let models = MODELS.load_full();
+    let model = models
+        .get(&key)
+        .ok_or_else(|| ApiError::model_not_found(&key))?;
+    let name = model.source_directory_name.clone();
+    let statuses = std::collections::HashMap::from([(name.clone(), status)]);
+    // 读写记录文件或数据库在阻塞线程中进行
+    let store = models.clone();
+    tokio::task::spawn_blocking(move || {
+        open_repository(&models_dir(), &CONFIG.storage)?.set_statuses(&store, &statuses)
+    })
+    .await
+    .map_err(|e| AnalyzerError::WorkflowError(format!("修改模具状态失败: {}", e)))??;
+    models.set_status(&name, status);
+    spawn_notify(vec![CorpusEvent::new(
+        CorpusEventKind::Corrected,
+        name.as_str(),
+    )]);
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": { "source_name": name, "status": status },
+    })));
+    Ok(())
+}
+
+/// 来源名称和id、slug的对应关系，给出`name`时只查询该模型，`name`可以是来源名称、id或slug
+/// GET /material/api/v1/model-ids?name=..
+#[handler]
+pub async fn model_ids(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let Some(name) = req.query::<String>("name") else {
+        res.render(Json(serde_json::json!({
+            "status": 200,
+            "data": MODELS.load().all_ids(),
+        })));
+        return Ok(());
+    };
+    let ids = MODELS
+        .load()
+        .ids(&name)
+        .ok_or_else(|| ApiError::model_not_found(&name))?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": ids,
+    })));
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SimilarityMatrixRequest {
+    pub names: Vec<String>,
+}
+
+/// 查询参数`profile`指定的评分方案，未知的方案回复400
+pub(crate) fn scoring_profile(
+    req: &Request,
+    config: &DiffConfig,
+) -> Result<ScoringProfile, ApiError> {
+    let name = req.query::<String>("profile");
+    config.profile(name.as_deref()).cloned().ok_or_else(|| {
+        let name = name.unwrap_or_default();
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "unknown_profile",
+            format!(
+                "未知的评分方案 `{}`，可选: {}",
+                name,
+                config.profile_names()
+            ),
+            format!(
+                "Unknown scoring profile `{}`, available: {}",
+                name,
+                config.profile_names()
+            ),
+        )
+    })
+}
+
+/// 一组模型两两之间的相似度及各项得分，用于整理同一产品系列的模具，
+/// `profile`为评分方案
+/// POST /material/api/v1/similarity-matrix?profile=tooling
+/// ```json
+/// { "names": ["ME121基座", "01J4HDKX3Q9Y8Z2V6B7N5M4C1A"] }
+/// ```
+#[handler]
+pub async fn similarity_matrix(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let config = &CONFIG.diff;
+    let profile = scoring_profile(req, config)?;
+    let body = match req.parse_json::<SimilarityMatrixRequest>().await {
+        Ok(body) if !body.names.is_empty() => body,
+        _ => {
+            return Err(ApiError::invalid_request(
+                "请求格式错误，需要 names",
+                "Invalid request, names is required",
+            ));
+        }
+    };
+    if body.names.len() > config.max_matrix_models {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "too_many_models",
+            format!("一次最多比较{}个模型", config.max_matrix_models),
+            format!(
+                "At most {} models can be compared at once",
+                config.max_matrix_models
+            ),
+        ));
+    }
+    let matrix = MODELS
+        .load()
+        .similarity_matrix(&body.names, &profile)
+        .map_err(|missing| ApiError::model_not_found(&missing.join("`, `")))?;
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": matrix,
+    })));
+    Ok(())
+}
+
+/// 查询参数`since`/`until`(`YYYY[-MM[-DD]]`)，无效的日期回复400
+pub(crate) fn period(req: &Request) -> Result<[Option<String>; 2], ApiError> {
+    let mut period = [None, None];
+    for (bound, key) in period.iter_mut().zip(["since", "until"]) {
+        let Some(text) = req.query::<String>(key) else {
+            continue;
+        };
+        let date = normalize_date(&text).ok_or_else(|| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_date",
+                format!("无效的日期 {}={}，格式为 YYYY[-MM[-DD]]", key, text),
+                format!("Invalid date {}={}, expected YYYY[-MM[-DD]]", key, text),
+            )
+        })?;
+        *bound = Some(date);
+    }
+    Ok(period)
+}
+
+/// 用外部系统已有的模具数据(ModelJson)与模具库比较，不需要PDF和模型分析，
+/// `limit`为返回的结果数量，默认与聊天报告一致，`profile`为评分方案，
+/// `since`/`until`按提取时间筛选候选模具(`YYYY[-MM[-DD]]`，包含边界)
+/// POST /material/api/v1/diff?limit=10&profile=purchasing&since=2023
+/// ```json
+/// { "model_type": "基座", "materials": ["PBT RG301"], "project_name": null, "extraction_timestamp": null }
+/// ```
+#[handler]
+pub async fn dry_run_diff(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let limit = req
+        .query::<usize>("limit")
+        .unwrap_or(CONFIG.report.max_results);
+    let profile = scoring_profile(req, &CONFIG.diff)?;
+    let [since, until] = period(req)?;
+    let model = match req.parse_json::<ModelJson>().await {
+        Ok(model) if model.model_type.is_some() || !model.materials.is_empty() => model,
+        _ => {
+            return Err(ApiError::invalid_request(
+                "请求格式错误，需要 model_type 或 materials",
+                "Invalid request, model_type or materials is required",
+            ));
+        }
+    };
+
+    let store = MODELS.load_full();
+    let mut models = store.grouped().clone();
+    for candidates in models.values_mut() {
+        candidates.retain(|m| m.in_period(since.as_deref(), until.as_deref()));
+    }
+    let mut results = ModelJson::diff(models, model, &profile);
+    store.apply_status(&mut results, CONFIG.diff.include_scrapped);
+    DiffResult::sort(&mut results);
+    results.truncate(limit);
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": results,
+    })));
+    Ok(())
+}
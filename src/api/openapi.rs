@@ -0,0 +1,951 @@
+use rust_embed::Embed;
+use salvo::{
+    Request, Response, handler,
+    http::{StatusCode, header::CONTENT_TYPE},
+    writing::{Json, Text},
+};
+use serde_json::{Value, json};
+
+use crate::{
+    CONFIG,
+    api::{admin::content_type, error::ApiError},
+    router::API_V1,
+};
+
+/// Swagger UI(swagger-ui-dist 5.17.14)的静态文件，编译时嵌入，内网部署时不需要访问CDN
+#[derive(Embed)]
+#[folder = "assets/swagger-ui/"]
+struct SwaggerUiAssets;
+
+/// 接口的OpenAPI 3.1描述，路径相对`ServerConfig::base_path`，与`router::build`中的路由一一对应，
+/// 修改路由或回复格式时需要同步修改
+pub fn spec() -> Value {
+    let mut paths = serde_json::Map::new();
+    paths.insert("/webhook".to_string(), webhook());
+    for (path, item) in api_v1() {
+        paths.insert(format!("/{}{}", API_V1, path), item);
+    }
+    let base = CONFIG.server.path("");
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "模具比对服务",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "VoceChat机器人的webhook和JSON接口。JSON接口的成功回复为`{\"status\": 200, \"data\": ...}`，\
+                            错误回复见`ErrorResponse`。没有版本号的`/api`是v1之前的路径，与v1相同但已弃用。",
+        },
+        "servers": [{ "url": if base == "/" { "/" } else { base.trim_end_matches('/') } }],
+        "security": [{ "bearer": [] }, { "access_token": [] }],
+        "paths": paths,
+        "components": components(),
+    })
+}
+
+/// GET /material/api/openapi.json
+#[handler]
+pub async fn openapi_json(_req: &mut Request, res: &mut Response) {
+    res.render(Json(spec()));
+}
+
+/// 浏览OpenAPI描述的Swagger UI，没有路径时返回页面，否则返回Swagger UI的静态文件
+/// GET /material/api/docs/{**path}
+#[handler]
+pub async fn swagger_ui(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let path = req.param::<String>("path").unwrap_or_default();
+    if !path.is_empty() {
+        let file = SwaggerUiAssets::get(&path).ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("找不到 `{}`", path),
+                format!("`{}` not found", path),
+            )
+        })?;
+        let _ = res.add_header(CONTENT_TYPE, content_type(&path), true);
+        let _ = res.write_body(file.data.into_owned());
+        return Ok(());
+    }
+    let _ = res.add_header(CONTENT_TYPE, "text/html; charset=utf-8", true);
+    res.render(Text::Html(format!(
+        r##"<!doctype html>
+<html lang="zh-CN">
+<head>
+  <meta charset="utf-8">
+  <title>模具比对服务 - 接口文档</title>
+  <link rel="stylesheet" href="{assets}swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="{assets}swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({{ url: "{spec}", dom_id: "#swagger-ui", persistAuthorization: true }});
+  </script>
+</body>
+</html>
+"##,
+        assets = CONFIG.server.path("api/docs/"),
+        spec = CONFIG.server.path("api/openapi.json"),
+    )));
+    Ok(())
+}
+
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// 成功回复，`data`为数据的schema
+fn ok(description: &str, data: Value) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": {
+                "schema": {
+                    "type": "object",
+                    "properties": { "status": { "type": "integer" }, "data": data },
+                    "required": ["status", "data"],
+                },
+            },
+        },
+    })
+}
+
+fn error(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema("ErrorResponse") } },
+    })
+}
+
+fn json_body(body: Value) -> Value {
+    json!({ "required": true, "content": { "application/json": { "schema": body } } })
+}
+
+fn query(name: &str, description: &str, ty: &str) -> Value {
+    json!({ "name": name, "in": "query", "description": description, "schema": { "type": ty } })
+}
+
+fn path(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+fn job_id() -> Value {
+    path("id", "任务id，可以使用前缀")
+}
+
+fn profile() -> Value {
+    query(
+        "profile",
+        "评分方案，例如`tooling`、`purchasing`，默认`default`",
+        "string",
+    )
+}
+
+/// 一个接口，`responses`中的错误码使用通用的错误回复
+fn operation(
+    id: &str,
+    tag: &str,
+    summary: &str,
+    parameters: Vec<Value>,
+    body: Option<Value>,
+    success: (&str, Value),
+    errors: &[(&str, &str)],
+) -> Value {
+    let mut responses = serde_json::Map::new();
+    responses.insert(success.0.to_string(), success.1);
+    for (status, description) in errors {
+        responses.insert(status.to_string(), error(description));
+    }
+    let mut op = json!({
+        "operationId": id,
+        "tags": [tag],
+        "summary": summary,
+        "parameters": parameters,
+        "responses": responses,
+    });
+    if let Some(body) = body {
+        op["requestBody"] = body;
+    }
+    op
+}
+
+fn webhook() -> Value {
+    let reply = json!({
+        "description": "已收到的消息，`message`为处理结果的提示",
+        "content": { "application/json": { "schema": schema("WebhookReply") } },
+    });
+    let rejected = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": schema("WebhookReply") } },
+        })
+    };
+    json!({
+        "get": {
+            "operationId": "webhookCheck",
+            "tags": ["webhook"],
+            "summary": "VoceChat配置webhook时的连通性检查",
+            "security": [],
+            "responses": { "200": { "description": "服务可用" } },
+        },
+        "post": {
+            "operationId": "webhook",
+            "tags": ["webhook"],
+            "summary": "VoceChat机器人的webhook，PDF文件开始分析，文本按检索语句检索，回应和编辑用于反馈和修改检索条件",
+            "description": "兼容模式(`MATERIAL_WEBHOOK_COMPAT`，默认开启)下无法处理的请求也回复200，避免VoceChat重复投递。\
+                            配置了校验方式时请求头需要带有共享密钥或HMAC签名。",
+            "security": [{ "webhook_token": [] }, { "webhook_signature": [] }, {}],
+            "requestBody": json_body(schema("WebhookRequest")),
+            "responses": {
+                "200": reply,
+                "400": rejected("请求体无法读取或不是JSON(关闭兼容模式时)"),
+                "401": rejected("校验失败"),
+                "415": rejected("Content-Type不是JSON(关闭兼容模式时)"),
+                "422": rejected("JSON的结构不对(关闭兼容模式时)"),
+                "429": rejected("发送过于频繁(关闭兼容模式时)"),
+            },
+        },
+    })
+}
+
+fn api_v1() -> Vec<(&'static str, Value)> {
+    let not_found = ("404", "找不到模型");
+    let job_not_found = ("404", "找不到任务");
+    let invalid = ("400", "请求格式错误");
+    let unknown_profile = ("400", "请求格式错误或未知的评分方案");
+    let read_only = ("503", "只读模式下不能修改");
+    let object = json!({ "type": "object" });
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    vec![
+        (
+            "/search",
+            json!({ "get": operation(
+                "search",
+                "models",
+                "按模具类型、材料、客户、标签和提取时间检索模具库",
+                vec![
+                    query("model_type", "模具类型", "string"),
+                    query("materials", "材料，可以用逗号分隔或重复给出", "string"),
+                    query("customer", "客户", "string"),
+                    query("tags", "标签，可以用逗号分隔或重复给出", "string"),
+                    query("since", "提取时间的起点(YYYY[-MM[-DD]]，包含)", "string"),
+                    query("until", "提取时间的终点(YYYY[-MM[-DD]]，包含)", "string"),
+                    query("limit", "返回的结果数量", "integer"),
+                    profile(),
+                ],
+                None,
+                ("200", ok("按相似度排序的结果", json!({ "type": "array", "items": schema("SearchHit") }))),
+                &[unknown_profile],
+            )}),
+        ),
+        (
+            "/suggest",
+            json!({ "get": operation(
+                "suggest",
+                "models",
+                "检索框的输入提示",
+                vec![
+                    json!({ "name": "q", "in": "query", "required": true, "schema": { "type": "string" } }),
+                    json!({
+                        "name": "kind",
+                        "in": "query",
+                        "schema": { "type": "string", "enum": ["model_type", "material", "project"] },
+                    }),
+                    query("limit", "最多50，默认10", "integer"),
+                ],
+                None,
+                ("200", ok("提示列表", json!({ "type": "array", "items": schema("Suggestion") }))),
+                &[("400", "缺少q或未知的类别")],
+            )}),
+        ),
+        (
+            "/diff",
+            json!({ "post": operation(
+                "dryRunDiff",
+                "models",
+                "用外部系统已有的模具数据与模具库比较，不需要PDF和模型分析",
+                vec![
+                    query("limit", "返回的结果数量", "integer"),
+                    profile(),
+                    query("since", "按提取时间筛选候选模具", "string"),
+                    query("until", "按提取时间筛选候选模具", "string"),
+                ],
+                Some(json_body(schema("ModelJson"))),
+                ("200", ok("按相似度排序的结果", json!({ "type": "array", "items": schema("DiffResult") }))),
+                &[unknown_profile],
+            )}),
+        ),
+        (
+            "/similarity-matrix",
+            json!({ "post": operation(
+                "similarityMatrix",
+                "models",
+                "一组模型两两之间的相似度及各项得分",
+                vec![profile()],
+                Some(json_body(json!({
+                    "type": "object",
+                    "properties": { "names": strings },
+                    "required": ["names"],
+                }))),
+                ("200", ok("相似度矩阵", object.clone())),
+                &[unknown_profile, not_found],
+            )}),
+        ),
+        (
+            "/models/{source_name}",
+            json!({ "get": operation(
+                "modelDetail",
+                "models",
+                "按来源名称、id或slug查看模型的完整记录和预览图",
+                vec![path("source_name", "来源名称、id或slug")],
+                None,
+                ("200", ok("模型记录", schema("ModelJson"))),
+                &[not_found],
+            )}),
+        ),
+        (
+            "/models/{source_name}/status",
+            json!({ "put": operation(
+                "updateStatus",
+                "models",
+                "修改实体模具的状态(需要admin权限)",
+                vec![path("source_name", "来源名称、id或slug")],
+                Some(json_body(json!({
+                    "type": "object",
+                    "properties": {
+                        "status": {
+                            "type": ["string", "null"],
+                            "description": "在用/封存/报废，null表示清除",
+                        },
+                    },
+                }))),
+                ("200", ok("修改后的状态", object.clone())),
+                &[invalid, not_found, read_only],
+            )}),
+        ),
+        (
+            "/model-ids",
+            json!({ "get": operation(
+                "modelIds",
+                "models",
+                "来源名称和id、slug的对应关系",
+                vec![query("name", "只查询该模型，可以是来源名称、id或slug", "string")],
+                None,
+                ("200", ok("对应关系", json!({ "type": "array", "items": object }))),
+                &[not_found],
+            )}),
+        ),
+        (
+            "/analyze",
+            json!({ "post": operation(
+                "analyze",
+                "jobs",
+                "上传PDF进行分析(需要admin权限)，立即返回任务id，结果通过任务接口查询",
+                vec![],
+                Some(json!({
+                    "required": true,
+                    "content": {
+                        "multipart/form-data": {
+                            "schema": {
+                                "type": "object",
+                                "properties": { "file": { "type": "string", "format": "binary" } },
+                                "required": ["file"],
+                            },
+                        },
+                    },
+                })),
+                ("202", ok("已开始分析", json!({
+                    "type": "object",
+                    "properties": { "job_id": { "type": "string" } },
+                }))),
+                &[invalid, ("415", "不是PDF文件"), ("422", "页数或图幅超出限制")],
+            )}),
+        ),
+        (
+            "/jobs/{id}",
+            json!({ "get": operation(
+                "jobStatus",
+                "jobs",
+                "任务的状态和所处阶段",
+                vec![job_id()],
+                None,
+                ("200", ok("任务状态", schema("JobStatus"))),
+                &[job_not_found],
+            )}),
+        ),
+        (
+            "/jobs/{id}/result",
+            json!({ "get": operation(
+                "jobResult",
+                "jobs",
+                "完成的任务的提取结果、匹配列表和报告",
+                vec![job_id()],
+                None,
+                ("200", ok("任务结果", json!({
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string" },
+                        "extraction": schema("ModelJson"),
+                        "matches": { "type": "array", "items": schema("JobMatch") },
+                        "scale_conflict": {
+                            "type": ["object", "null"],
+                            "description": "标注的外形尺寸按比例换算后超出图幅，尺寸或比例可能识别有误",
+                        },
+                        "report": { "type": ["string", "null"], "description": "发送的markdown报告" },
+                    },
+                }))),
+                &[job_not_found, ("409", "任务未完成、失败或已取消")],
+            )}),
+        ),
+        (
+            "/jobs/{a}/diff/{b}",
+            json!({ "get": operation(
+                "jobDiff",
+                "jobs",
+                "比较两个任务的提取结果和匹配列表",
+                vec![path("a", "任务id"), path("b", "任务id")],
+                None,
+                ("200", ok("差异", object.clone())),
+                &[job_not_found],
+            )}),
+        ),
+        (
+            "/jobs/{id}/extraction",
+            json!({ "get": operation(
+                "jobExtraction",
+                "jobs",
+                "任务的合并和每页的文本提取结果",
+                vec![job_id()],
+                None,
+                ("200", ok("提取结果", object.clone())),
+                &[job_not_found],
+            )}),
+        ),
+        (
+            "/jobs/{id}/events",
+            json!({ "get": {
+                "operationId": "jobEvents",
+                "tags": ["jobs"],
+                "summary": "任务进度的Server-Sent Events，任务结束后关闭",
+                "parameters": [job_id()],
+                "responses": {
+                    "200": { "description": "事件流", "content": { "text/event-stream": {} } },
+                    "404": error("找不到任务"),
+                },
+            }}),
+        ),
+        (
+            "/jobs/{id}/ws",
+            json!({ "get": {
+                "operationId": "jobSocket",
+                "tags": ["jobs"],
+                "summary": "任务进度的WebSocket，消息与`/events`的事件数据相同",
+                "parameters": [job_id()],
+                "responses": {
+                    "101": { "description": "升级为WebSocket" },
+                    "400": error("不是WebSocket握手请求"),
+                    "404": error("找不到任务"),
+                },
+            }}),
+        ),
+        (
+            "/jobs/{id}/artifacts.zip",
+            json!({ "get": {
+                "operationId": "jobArtifacts",
+                "tags": ["jobs"],
+                "summary": "打包下载任务的页面图片、视图、提取结果、匹配结果和报告",
+                "parameters": [job_id()],
+                "responses": {
+                    "200": { "description": "zip文件", "content": { "application/zip": {} } },
+                    "404": error("找不到任务"),
+                },
+            }}),
+        ),
+        (
+            "/feedback/metrics",
+            json!({ "get": operation(
+                "feedbackMetrics",
+                "jobs",
+                "按提示词/模型版本统计用户反馈的准确率",
+                vec![],
+                None,
+                ("200", ok("统计", json!({ "type": "array", "items": object }))),
+                &[],
+            )}),
+        ),
+        (
+            "/taxonomy/materials",
+            json!({
+                "get": operation(
+                    "materialTaxonomy",
+                    "taxonomy",
+                    "当前加载的材料词表(大类 → 牌号 → 写法)",
+                    vec![],
+                    None,
+                    ("200", ok("材料词表", object.clone())),
+                    &[],
+                ),
+                "post": operation(
+                    "addMaterial",
+                    "taxonomy",
+                    "添加材料牌号(需要admin权限)",
+                    vec![],
+                    Some(json_body(json!({
+                        "type": "object",
+                        "properties": {
+                            "family": { "type": "string" },
+                            "grade": { "type": "string" },
+                            "aliases": strings,
+                        },
+                        "required": ["family", "grade"],
+                    }))),
+                    ("200", ok("修改后的材料词表", object.clone())),
+                    &[invalid, read_only],
+                ),
+            }),
+        ),
+        (
+            "/taxonomy/model-types",
+            json!({
+                "get": operation(
+                    "modelTypeTaxonomy",
+                    "taxonomy",
+                    "当前加载的模具类型词表(分组 → 写法)",
+                    vec![],
+                    None,
+                    ("200", ok("模具类型词表", object.clone())),
+                    &[],
+                ),
+                "post": operation(
+                    "addModelType",
+                    "taxonomy",
+                    "向模具类型分组添加写法(需要admin权限)",
+                    vec![],
+                    Some(json_body(json!({
+                        "type": "object",
+                        "properties": { "group": { "type": "string" }, "names": strings },
+                        "required": ["group", "names"],
+                    }))),
+                    ("200", ok("修改后的模具类型词表", object.clone())),
+                    &[invalid, read_only],
+                ),
+            }),
+        ),
+        (
+            "/taxonomy/unrecognized",
+            json!({ "get": operation(
+                "unrecognizedTerms",
+                "taxonomy",
+                "提取结果中尚未归入词表的模具类型和材料",
+                vec![],
+                None,
+                ("200", ok("待归类词条", object.clone())),
+                &[],
+            )}),
+        ),
+        (
+            "/admin/jobs",
+            json!({ "get": operation(
+                "jobsOverview",
+                "admin",
+                "队列中正在执行和排队的任务数，以及最近的任务",
+                vec![],
+                None,
+                ("200", ok("任务概览", object.clone())),
+                &[],
+            )}),
+        ),
+        (
+            "/admin/corpus",
+            json!({ "get": operation(
+                "corpusStats",
+                "admin",
+                "模具数据库按类型、状态和分片的统计",
+                vec![],
+                None,
+                ("200", ok("统计", object.clone())),
+                &[],
+            )}),
+        ),
+        (
+            "/admin/snapshots/diff",
+            json!({ "get": operation(
+                "snapshotDiff",
+                "admin",
+                "比较两个日期的模具数据库快照",
+                vec![
+                    json!({ "name": "from", "in": "query", "required": true, "schema": { "type": "string", "format": "date" } }),
+                    json!({ "name": "to", "in": "query", "schema": { "type": "string", "format": "date" } }),
+                ],
+                None,
+                ("200", ok("快照差异", object.clone())),
+                &[invalid, ("404", "没有快照")],
+            )}),
+        ),
+        (
+            "/admin/config",
+            json!({ "get": operation(
+                "configSummary",
+                "admin",
+                "当前配置的摘要，不包括密钥和代理地址",
+                vec![],
+                None,
+                ("200", ok("配置摘要", object.clone())),
+                &[],
+            )}),
+        ),
+        (
+            "/admin/reindex",
+            json!({ "post": operation(
+                "reindex",
+                "admin",
+                "立即重新读取模具记录并替换当前的索引，新增和删除的模具(ingested、deleted)会通知模具库事件地址",
+                vec![],
+                None,
+                ("200", ok("加载结果", object.clone())),
+                &[],
+            )}),
+        ),
+        (
+            "/admin/maintenance",
+            json!({ "put": operation(
+                "setMaintenance",
+                "admin",
+                "切换只读模式",
+                vec![],
+                Some(json_body(json!({
+                    "type": "object",
+                    "properties": { "read_only": { "type": "boolean" } },
+                    "required": ["read_only"],
+                }))),
+                ("200", ok("切换结果", object)),
+                &[invalid],
+            )}),
+        ),
+    ]
+}
+
+fn components() -> Value {
+    let nullable_string = json!({ "type": ["string", "null"] });
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let mold_status =
+        json!({ "type": ["string", "null"], "enum": ["in_use", "sealed", "scrapped", null] });
+    let preview_pages = json!({
+        "type": "array",
+        "items": { "type": "string" },
+        "description": "前几页预览图的链接，页数与聊天报告相同，没有预览图时为空",
+    });
+    json!({
+        "securitySchemes": {
+            "bearer": {
+                "type": "http",
+                "scheme": "bearer",
+                "description": "配置了接口密钥时需要，read密钥只能访问只读的接口",
+            },
+            "access_token": {
+                "type": "apiKey",
+                "in": "query",
+                "name": "access_token",
+                "description": "EventSource和WebSocket不能设置请求头时使用",
+            },
+            "webhook_token": { "type": "apiKey", "in": "header", "name": "X-Webhook-Token" },
+            "webhook_signature": {
+                "type": "apiKey",
+                "in": "header",
+                "name": "X-Webhook-Signature",
+                "description": "hex(hmac_sha256(secret, body))，可以带`sha256=`前缀",
+            },
+        },
+        "schemas": {
+            "ErrorResponse": {
+                "type": "object",
+                "properties": {
+                    "status": { "type": "integer" },
+                    "code": { "type": "string", "description": "稳定的机器可读错误码，例如`job_not_found`" },
+                    "message": { "type": "string" },
+                    "message_zh": { "type": "string" },
+                    "message_en": { "type": "string" },
+                    "job_id": nullable_string,
+                },
+                "required": ["status", "code", "message"],
+            },
+            "WebhookRequest": {
+                "type": "object",
+                "description": "VoceChat机器人推送的消息，未知字段会被忽略",
+                "properties": {
+                    "mid": { "type": "integer" },
+                    "from_uid": { "type": "integer" },
+                    "created_at": { "type": "integer", "description": "毫秒时间戳" },
+                    "target": {
+                        "type": "object",
+                        "description": "`uid`为私聊，`gid`为群组",
+                        "properties": { "uid": { "type": "integer" }, "gid": { "type": "integer" } },
+                    },
+                    "type": nullable_string,
+                    "widget_id": nullable_string,
+                    "domain": { "type": ["string", "null"], "description": "消息来源的VoceChat域名" },
+                    "detail": {
+                        "type": "object",
+                        "properties": {
+                            "type": { "type": "string", "enum": ["normal", "reply", "reaction"] },
+                            "content": { "type": "string", "description": "文本内容，或文件消息的文件路径" },
+                            "content_type": { "type": "string", "examples": ["text/plain", "vocechat/file"] },
+                            "expires_in": { "type": ["integer", "null"] },
+                            "properties": {
+                                "type": "object",
+                                "description": "文件消息的文件名(`name`)、类型(`content_type`)和大小(`size`)",
+                            },
+                            "mid": { "type": ["integer", "null"], "description": "reply/reaction所针对的原消息" },
+                            "detail": {
+                                "type": ["object", "null"],
+                                "properties": {
+                                    "type": { "type": "string", "enum": ["edit", "delete", "like"] },
+                                    "content": nullable_string,
+                                    "content_type": nullable_string,
+                                    "action": { "type": ["string", "null"], "description": "表情，例如👍" },
+                                },
+                            },
+                            "files": {
+                                "type": "array",
+                                "description": "一次发送多个文件时的每个文件",
+                                "items": { "type": "object" },
+                            },
+                        },
+                        "required": ["type"],
+                    },
+                },
+                "required": ["mid", "from_uid", "detail"],
+                "examples": [{
+                    "created_at": 1754560852630u64,
+                    "detail": {
+                        "content": "2025/8/7/e034f8aa-55e5-4a4e-8c93-3fc2f4f45c72",
+                        "content_type": "vocechat/file",
+                        "expires_in": null,
+                        "properties": {
+                            "content_type": "application/pdf",
+                            "name": "03骨架 .pdf",
+                            "size": 102003,
+                        },
+                        "type": "normal",
+                    },
+                    "from_uid": 1,
+                    "mid": 1,
+                    "target": { "uid": 2 },
+                }],
+            },
+            "WebhookReply": {
+                "type": "object",
+                "properties": {
+                    "status": { "type": "integer" },
+                    "message": { "type": "string" },
+                },
+            },
+            "ModelJson": {
+                "type": "object",
+                "properties": {
+                    "model_type": nullable_string,
+                    "materials": strings,
+                    "canonical_materials": strings,
+                    "project_name": nullable_string,
+                    "company": nullable_string,
+                    "dimensions": { "type": ["object", "null"] },
+                    "drawing": {
+                        "type": ["object", "null"],
+                        "description": "标题栏中的比例(scale、scale_ratio)和图幅(sheet_size)",
+                    },
+                    "source_directory_name": { "type": "string" },
+                    "extraction_timestamp": nullable_string,
+                    "tags": strings,
+                    "finish": { "type": ["object", "null"] },
+                    "id": nullable_string,
+                    "slug": nullable_string,
+                    "status": mold_status,
+                    "provenance": { "type": ["object", "null"] },
+                },
+            },
+            "DiffResult": {
+                "type": "object",
+                "properties": {
+                    "source_name": { "type": "string" },
+                    "company": nullable_string,
+                    "dimensions": { "type": ["object", "null"] },
+                    "original_pdf": nullable_string,
+                    "exact_match": { "type": "boolean" },
+                    "status": mold_status,
+                    "percentage": { "type": "number", "description": "综合相似度(0-1)" },
+                },
+            },
+            "JobMatch": {
+                "allOf": [
+                    schema("DiffResult"),
+                    {
+                        "type": "object",
+                        "properties": { "pages": preview_pages.clone() },
+                    },
+                ],
+            },
+            "SearchHit": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "id": nullable_string,
+                    "slug": nullable_string,
+                    "model_type": nullable_string,
+                    "materials": strings,
+                    "company": nullable_string,
+                    "score": { "type": "number", "description": "综合相似度(0-1)" },
+                    "band": { "type": "string", "enum": ["high", "medium", "reference"] },
+                    "exact_match": { "type": "boolean" },
+                    "status": mold_status,
+                    "pages": preview_pages,
+                },
+            },
+            "Suggestion": {
+                "type": "object",
+                "properties": {
+                    "kind": { "type": "string", "enum": ["model_type", "material", "project"] },
+                    "text": { "type": "string" },
+                    "count": { "type": "integer" },
+                    "score": { "type": "number" },
+                },
+            },
+            "JobStatus": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "kind": { "type": "string", "enum": ["pdf", "search"] },
+                    "state": { "type": "string", "enum": ["running", "succeeded", "failed", "cancelled"] },
+                    "stage": {
+                        "type": ["string", "null"],
+                        "enum": ["paused", "queued", "preparing", "executing", "reporting", null],
+                    },
+                    "attempts": { "type": "integer" },
+                    "duration_ms": { "type": ["integer", "null"] },
+                    "error": nullable_string,
+                    "created_at": { "type": "string" },
+                    "updated_at": { "type": "string" },
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use salvo::{
+        Router, Service,
+        test::{ResponseExt, TestClient},
+    };
+
+    use super::*;
+    use crate::router;
+
+    /// 路由树中有处理函数的(路径, 方法)，salvo的`{param}`与OpenAPI的路径参数格式相同
+    fn routes(router: &Router, prefix: &str, found: &mut BTreeSet<(String, String)>) {
+        let mut path = prefix.to_string();
+        let mut method = None;
+        for filter in router.filters() {
+            let info = format!("{:?}", filter);
+            if let Some(segment) = info.strip_prefix("path:") {
+                path = format!("{}/{}", path, segment);
+            } else if let Some(name) = info.strip_prefix("method:") {
+                method = Some(name.to_lowercase());
+            }
+        }
+        if let (Some(method), Some(_)) = (method, &router.goal) {
+            found.insert((path.clone(), method));
+        }
+        for child in router.routers() {
+            routes(child, &path, found);
+        }
+    }
+
+    #[test]
+    fn spec_matches_routes() {
+        let mut registered = BTreeSet::new();
+        routes(&router::api_v1(), &format!("/{}", API_V1), &mut registered);
+        let spec = spec();
+        let documented: BTreeSet<(String, String)> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(path, _)| path.as_str() != "/webhook")
+            .flat_map(|(path, item)| {
+                item.as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (path.clone(), method.clone()))
+            })
+            .collect();
+        assert!(registered.contains(&("/api/v1/jobs/{id}/ws".to_string(), "get".to_string())));
+        let undocumented: Vec<_> = registered.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "没有描述的路由: {:?}",
+            undocumented
+        );
+        let missing: Vec<_> = documented.difference(&registered).collect();
+        assert!(missing.is_empty(), "不存在的路由: {:?}", missing);
+    }
+
+    #[tokio::test]
+    async fn swagger_ui_is_local() {
+        let service = Service::new(router::build());
+        let mut page = TestClient::get("http://127.0.0.1:5800/material/api/docs")
+            .send(&service)
+            .await;
+        let html = page.take_string().await.unwrap();
+        assert!(!html.contains("https://"));
+        assert!(html.contains("/material/api/docs/swagger-ui-bundle.js"));
+
+        for (file, content_type) in [
+            ("swagger-ui-bundle.js", "text/javascript; charset=utf-8"),
+            ("swagger-ui.css", "text/css; charset=utf-8"),
+        ] {
+            let asset =
+                TestClient::get(format!("http://127.0.0.1:5800/material/api/docs/{}", file))
+                    .send(&service)
+                    .await;
+            assert_eq!(asset.status_code, Some(StatusCode::OK));
+            assert_eq!(asset.headers()[CONTENT_TYPE], content_type);
+        }
+        let missing = TestClient::get("http://127.0.0.1:5800/material/api/docs/missing.js")
+            .send(&service)
+            .await;
+        assert_eq!(missing.status_code, Some(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn spec_is_consistent() {
+        let spec = spec();
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/webhook"));
+        assert!(paths.contains_key("/api/v1/jobs/{id}/result"));
+        let schemas = &spec["components"]["schemas"];
+        assert!(schemas["SearchHit"]["properties"]["pages"].is_object());
+        assert!(schemas["JobMatch"]["allOf"][1]["properties"]["pages"].is_object());
+
+        let mut ids = HashSet::new();
+        for item in paths.values() {
+            for op in item.as_object().unwrap().values() {
+                let id = op["operationId"].as_str().unwrap();
+                assert!(ids.insert(id), "重复的operationId: {}", id);
+                assert!(!op["responses"].as_object().unwrap().is_empty());
+            }
+        }
+        // 引用的schema都有定义
+        let text = spec.to_string();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "没有定义的schema: {}", name);
+        }
+    }
+}
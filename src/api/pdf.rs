@@ -1,13 +1,33 @@
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-};
+use std::{collections::HashMap, path::Path};
 
-use salvo::{Request, Response, handler, http::headers::ContentType, writing::Json};
+use salvo::{
+    Request, Response, handler,
+    http::{StatusCode, headers::ContentType, mime},
+    writing::Json,
+};
 use serde::Deserialize;
 use serde_json::Value;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
 
-use crate::{pdf_converter::PdfConverterRunner, workflow::create_pdf_analysis_workflow};
+use crate::{
+    BLOBS, CONFIG, HTTP_CLIENT, JOBS, PREFERENCES, TAXONOMY, UNRECOGNIZED,
+    blob::Blob,
+    command::ChatCommand,
+    config::{ReportLayout, Sampling},
+    job::{Feedback, JobRecord, Verdict},
+    paths::{relative_path, upload_dir},
+    preference::preferences_dir,
+    preflight::{check_upload, check_upload_blocking},
+    query::{QUERY_HELP, UserQuery},
+    secrets::{self, WEBHOOK_SECRET},
+    taxonomy::fmt_unrecognized_digest,
+    webhook_auth::WebhookVerifier,
+    workflow::{
+        Workflow, bot_endpoint, create_pdf_analysis_workflow, create_search_workflow, send_markdown,
+    },
+};
 
 // #[derive(Deserialize, Debug)]
 // pub struct PdfPathRequest {
@@ -139,78 +159,347 @@ pub enum IdType {
     UId,
     #[serde(rename = "gid")]
     GId,
+    /// vocechat之后新增的目标类型，忽略即可
+    #[serde(other)]
+    Unknown,
+}
+
+/// webhook请求体解析失败的原因，记录出错的字段路径
+#[derive(Error, Debug)]
+#[error("字段 `{path}` 解析失败: {message}")]
+pub struct WebhookParseError {
+    pub path: String,
+    pub message: String,
+    /// 请求体不是有效的JSON
+    pub malformed: bool,
+}
+
+impl WebhookParseError {
+    fn new(path: impl Into<String>, message: impl ToString) -> Self {
+        Self {
+            path: path.into(),
+            message: message.to_string(),
+            malformed: false,
+        }
+    }
+
+    fn malformed(message: impl ToString) -> Self {
+        Self {
+            malformed: true,
+            ..Self::new("$", message)
+        }
+    }
+
+    /// 不是JSON时为400，JSON的结构不对时为422
+    pub fn status_code(&self) -> StatusCode {
+        if self.malformed {
+            StatusCode::BAD_REQUEST
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+    }
+}
+
+/// vocechat webhook 请求体，按payload结构版本区分，便于兼容之后的新格式
+#[derive(Debug, Clone)]
+pub enum WebhookRequest {
+    /// 当前的 bot webhook 格式: `{ mid, from_uid, target, detail, ... }`
+    V1(WebhookRequestV1),
+}
+
+impl WebhookRequest {
+    /// 解析原始请求体，失败时返回具体出错的字段
+    pub fn parse(body: &[u8]) -> Result<Self, WebhookParseError> {
+        let value: Value = serde_json::from_slice(body).map_err(WebhookParseError::malformed)?;
+        let obj = value
+            .as_object()
+            .ok_or_else(|| WebhookParseError::new("$", "请求体不是JSON对象"))?;
+
+        if obj.contains_key("mid") && obj.contains_key("detail") {
+            let req: WebhookRequestV1 = serde_path_to_error::deserialize(value)
+                .map_err(|e| WebhookParseError::new(e.path().to_string(), e.inner()))?;
+            if !req.extra.is_empty() {
+                debug!(
+                    "webhook请求包含未知字段: {:?}",
+                    req.extra.keys().collect::<Vec<_>>()
+                );
+            }
+            Ok(Self::V1(req))
+        } else {
+            Err(WebhookParseError::new("$", "未知的webhook请求格式"))
+        }
+    }
+
+    pub fn from_uid(&self) -> u64 {
+        match self {
+            Self::V1(req) => req.from_uid,
+        }
+    }
+
+    pub fn mid(&self) -> u64 {
+        match self {
+            Self::V1(req) => req.mid,
+        }
+    }
+
+    /// 消息来源的VoceChat域名
+    pub fn domain(&self) -> Option<&str> {
+        match self {
+            Self::V1(req) => req.domain.as_deref().filter(|s| !s.is_empty()),
+        }
+    }
+
+    pub fn detail(&self) -> &WebhookReqDetail {
+        match self {
+            Self::V1(req) => &req.detail,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
-pub struct WebhookRequest {
+pub struct WebhookRequestV1 {
+    #[serde(default)]
     pub created_at: i64,
     pub detail: WebhookReqDetail,
     pub from_uid: u64,
     pub mid: u64,
+    #[serde(default)]
     pub target: HashMap<IdType, u64>,
-    pub r#type: String,
+    #[serde(default)]
+    pub r#type: Option<String>,
+    #[serde(default)]
     pub widget_id: Option<String>,
+    #[serde(default)]
     pub domain: Option<String>,
+    /// vocechat新增的未知字段
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct WebhookReqDetail {
-    pub content: PathBuf,
+    /// 文本内容，或文件消息的文件路径
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
     pub content_type: String,
+    #[serde(default)]
     pub expires_in: Option<i64>,
+    #[serde(default)]
     pub properties: HashMap<String, Value>,
+    /// normal | reply | reaction
     #[serde(rename = "type")]
     pub ty: String,
+    /// reply/reaction 所针对的原消息
+    #[serde(default)]
+    pub mid: Option<u64>,
+    /// reaction 的具体内容(edit/delete/like)
+    #[serde(default)]
+    pub detail: Option<ReactionDetail>,
+    /// 一次发送多个文件时的每个文件，此时`content`和`properties`为第一个文件
+    #[serde(default)]
+    pub files: Vec<Attachment>,
+    /// vocechat新增的未知字段
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReactionDetail {
+    /// edit | delete | like
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub action: Option<String>,
+}
+
+/// 从webhook消息中区分出的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookEvent<'a> {
+    /// 新消息(包括回复)
+    Message,
+    /// 编辑了消息`mid`，`content`为编辑后的内容
+    Edit { mid: u64, content: &'a str },
+    /// 撤回/删除了消息`mid`
+    Delete { mid: u64 },
+    /// 对消息`mid`点了表情`action`
+    Like { mid: u64, action: &'a str },
+    /// 暂不处理的事件类型
+    Other(&'a str),
+}
+
+/// 文件消息的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Pdf,
+    /// Excel/CSV表格
+    Spreadsheet,
+    Image,
+    /// DWG/DXF/STEP等CAD文件
+    Cad,
+    Archive,
+    Other,
+}
+
+impl FileKind {
+    fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "pdf" => Some(Self::Pdf),
+            "xls" | "xlsx" | "xlsm" | "csv" => Some(Self::Spreadsheet),
+            "png" | "jpg" | "jpeg" | "bmp" | "gif" | "webp" | "tif" | "tiff" => Some(Self::Image),
+            "dwg" | "dxf" | "step" | "stp" | "igs" | "iges" | "prt" | "x_t" => Some(Self::Cad),
+            "zip" | "rar" | "7z" | "tar" | "gz" => Some(Self::Archive),
+            _ => None,
+        }
+    }
+
+    fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "application/pdf" => Some(Self::Pdf),
+            "text/csv" | "application/vnd.ms-excel" => Some(Self::Spreadsheet),
+            t if t.contains("spreadsheetml") => Some(Self::Spreadsheet),
+            t if t.starts_with("image/") => Some(Self::Image),
+            "application/zip" | "application/x-rar-compressed" | "application/x-7z-compressed" => {
+                Some(Self::Archive)
+            }
+            _ => None,
+        }
+    }
+
+    /// 不能分析时回复给用户的提示，PDF返回None
+    fn guidance(self, name: &str) -> Option<String> {
+        let message = match self {
+            Self::Pdf => return None,
+            Self::Spreadsheet => format!(
+                "📊 收到表格文件 `{}`，目前只能分析PDF图纸。按材料检索请直接发送检索条件，例如`- 材料: PBT RG301;`",
+                name
+            ),
+            Self::Image => format!(
+                "🖼️ 收到图片 `{}`，目前只能分析PDF图纸，请发送PDF格式的图纸",
+                name
+            ),
+            Self::Cad => format!("📐 收到CAD文件 `{}`，请在CAD软件中打印为PDF后发送", name),
+            Self::Archive => format!("🗜️ 收到压缩包 `{}`，请解压后逐个发送PDF图纸", name),
+            Self::Other => format!("ℹ️ 暂不支持文件 `{}`，请发送PDF图纸", name),
+        };
+        Some(message)
+    }
 }
 
 impl WebhookReqDetail {
+    pub fn event(&self) -> WebhookEvent<'_> {
+        match (self.ty.as_str(), self.mid, &self.detail) {
+            ("normal" | "reply", _, _) => WebhookEvent::Message,
+            ("reaction", Some(mid), Some(reaction)) => match reaction.ty.as_str() {
+                "edit" => WebhookEvent::Edit {
+                    mid,
+                    content: reaction.content.as_deref().unwrap_or_default(),
+                },
+                "delete" => WebhookEvent::Delete { mid },
+                "like" => WebhookEvent::Like {
+                    mid,
+                    action: reaction.action.as_deref().unwrap_or_default(),
+                },
+                other => WebhookEvent::Other(other),
+            },
+            (other, _, _) => WebhookEvent::Other(other),
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.content_type.starts_with("text/")
+    }
+
+    /// 消息中的附件，一次发送多个文件时为`files`中的每个文件，普通文本消息为空
+    pub fn attachments(&self) -> Vec<Attachment> {
+        if self.content_type != CONTENT_TYPE_VOCECHAT {
+            return Vec::new();
+        }
+        if !self.files.is_empty() {
+            return self.files.clone();
+        }
+        vec![Attachment {
+            content: self.content.clone(),
+            properties: self.properties.clone(),
+        }]
+    }
+
+    /// 第一个附件是否为PDF
     pub fn is_pdf(&self) -> bool {
-        self.content_type == CONTENT_TYPE_VOCECHAT
-            && self
-                .properties
+        self.attachments()
+            .first()
+            .is_some_and(|a| a.file_kind() == FileKind::Pdf)
+    }
+}
+
+/// 文件消息中的一个文件
+#[derive(Deserialize, Debug, Clone)]
+pub struct Attachment {
+    /// 上传目录中的文件路径
+    pub content: String,
+    /// 文件名、大小和类型
+    #[serde(default)]
+    pub properties: HashMap<String, Value>,
+}
+
+impl Attachment {
+    pub fn file_name(&self) -> Option<&str> {
+        self.properties.get("name").and_then(|v| v.as_str())
+    }
+
+    /// 文件大小(字节)
+    pub fn file_size(&self) -> Option<u64> {
+        self.properties.get("size").and_then(|v| v.as_u64())
+    }
+
+    /// 文件的类型，优先按文件名的扩展名判断，部分客户端上传时`content_type`为`application/octet-stream`
+    pub fn file_kind(&self) -> FileKind {
+        let by_name = self
+            .file_name()
+            .and_then(|name| Path::new(name).extension())
+            .and_then(|ext| ext.to_str())
+            .and_then(FileKind::from_extension);
+        let by_type = || {
+            self.properties
                 .get("content_type")
                 .and_then(|v| v.as_str())
-                .map_or(false, |ct| ct == "application/pdf")
+                .and_then(FileKind::from_content_type)
+        };
+        by_name.or_else(by_type).unwrap_or(FileKind::Other)
+    }
+
+    /// 用于回复的文件名
+    pub fn display_name(&self) -> &str {
+        self.file_name().unwrap_or("未命名文件")
+    }
+
+    /// 不能分析时回复给用户的提示，例如空文件或不是PDF的文件
+    pub fn guidance(&self) -> Option<String> {
+        let name = self.display_name();
+        if self.file_size() == Some(0) {
+            return Some(format!("❌ 文件 `{}` 是空的，请重新上传", name));
+        }
+        self.file_kind().guidance(name)
     }
-    pub fn pdf_path(&self) -> Result<PathBuf, String> {
+
+    /// 把上传的文件保存到PDF存储中，内容相同的文件只保存一份
+    pub fn store_pdf(&self) -> Result<Blob, String> {
         // prefix: data/upload/file/${content}
-        let current_exe = std::env::current_exe().map_err(|e| e.to_string())?.parent().unwrap().to_path_buf();
         // 对这个self.content进行处理，分割`/`或`\`转为PathBuf
-        let content_path = self
-            .content
-            .components()
-            .fold(PathBuf::new(), |mut acc, comp| {
-                acc.push(comp);
-                acc
-            });
-        
-        let meta_file = current_exe
-            .join("data")
-            .join("upload")
-            .join("file")
-            .join(content_path);
-        dbg!(&meta_file);
-        // 复制这个meta_file并增加后缀
-        let pdf_path = meta_file.with_extension("pdf");
-        if meta_file.exists() {
-            std::fs::copy(&meta_file, &pdf_path)
-                .map_err(|e| format!("Failed to copy file: {}", e))?;
-        } else {
+        let content_path = relative_path(&self.content)
+            .ok_or_else(|| format!("Invalid file path: {}", self.content))?;
+
+        let meta_file = upload_dir().join(content_path);
+        if !meta_file.exists() {
             return Err("PDF file does not exist".to_string());
         }
-
-        Ok(pdf_path)
-    }
-}
-
-pub fn convert_to_image(path: &Path) -> Result<PathBuf, String> {
-    let current_exe = std::env::current_exe().map_err(|e| e.to_string())?.parent().unwrap().to_path_buf();
-    let output_dir = current_exe.join("output");
-    let name = path.file_stem().ok_or("Invalid PDF file name")?;
-    let runner = PdfConverterRunner::new(path, Some(output_dir));
-    match runner.run() {
-        Ok(_) => Ok(runner.output.join(name)),
-        Err(e) => Err(e.to_string()),
+        BLOBS
+            .put_file(&meta_file)
+            .map_err(|e| format!("Failed to store file: {}", e))
     }
 }
 
@@ -224,14 +513,14 @@ impl WebhookResponse {
     pub fn new(text: &str) -> Self {
         Self {
             content_type: ContentType::text(),
-            x_api_key: "013b93273ce0dc707e4d55a214f0b54a63bde7fe7dc803b4eda52b3bc828975a7b22756964223a322c226e6f6e6365223a223661432f436558557032674141414141646e4b666f2f76412b64774b4b455465227d".to_string(),
+            x_api_key: CONFIG.bot.api_key.clone(),
             markdown_body: text.to_string(),
         }
     }
 
     pub async fn render(&self) -> () {
         // 使用异步reqwest客户端发送POST请求
-        let client = reqwest::Client::new();
+        let client = &HTTP_CLIENT;
         let url = "https://api.vocechat.com/material/api/workhook";
         let content_type = self.content_type.to_string();
         let api_key = self.x_api_key.clone();
@@ -253,57 +542,457 @@ impl WebhookResponse {
     }
 }
 
+/// 无法处理的webhook请求，兼容模式下仍然回复200，避免VoceChat重复投递
+fn reject(res: &mut Response, status: StatusCode, message: String) {
+    let status = if CONFIG.webhook.compat_status {
+        StatusCode::OK
+    } else {
+        status
+    };
+    res.status_code(status);
+    res.render(Json(serde_json::json!({
+        "status": status.as_u16(),
+        "message": message
+    })));
+}
+
 /// 对接vocechat的机器人的webhook
 /// POST /material/api/workhook
+///
+/// 请求体无法读取或不是JSON时为400，Content-Type不是JSON时为415，JSON结构不对时为422，
+/// 见`WebhookConfig::compat_status`。已经收到的消息(包括不能分析的文件)都回复200。
+/// 配置了校验方式时，校验失败的请求回复401，不做任何处理，见`WebhookConfig::verification`。
 #[handler]
 pub async fn workhook(req: &mut Request, res: &mut Response) -> Result<(), ()> {
-    if let Ok(webhook_req) = req.parse_json::<WebhookRequest>().await {
-        // 获取到 webhook 请求体之后判断是否为pdf文件
-        if webhook_req.detail.is_pdf() {
-            // 处理pdf文件，立即返回"正在处理"响应，然后在后台处理
-            match webhook_req.detail.pdf_path() {
-                Ok(pdf_path) => {
-                    // 立即返回响应，告知用户正在处理
-                    // WebhookResponse::new("📄 收到PDF文件，正在分析中，请稍等...").render().await;
-                    res.render(Json(serde_json::json!({
-                        "status": 200,
-                        "message": "📄 收到PDF文件，正在分析中，请稍等..."
-                    })));
-                    // 启动后台分析工作流
-                    let workflow = create_pdf_analysis_workflow(pdf_path, &webhook_req);
-                    workflow.start_background_analysis();
-
-                    return Ok(());
-                }
-                Err(e) => {
-                    // WebhookResponse::new("❌ 无效的PDF文件路径").render().await;
-                    res.render(Json(serde_json::json!({
-                        "status": 200,
-                        "message": format!("❌ 无效的PDF文件路径: {}", e)
-                    })));
-                    return Err(());
+    if let Some(content_type) = req.content_type()
+        && content_type.subtype() != mime::JSON
+        && content_type.suffix() != Some(mime::JSON)
+    {
+        warn!("webhook请求的Content-Type不是JSON: {}", content_type);
+        reject(
+            res,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("❌ 不支持的Content-Type: {}", content_type),
+        );
+        return Err(());
+    }
+
+    let body = match req.payload().await {
+        Ok(body) => body.clone(),
+        Err(e) => {
+            reject(
+                res,
+                StatusCode::BAD_REQUEST,
+                format!("❌ 无法读取请求体: {}", e),
+            );
+            return Err(());
+        }
+    };
+
+    // 在解析之前用原始请求体校验
+    let verifier = WebhookVerifier::new(&CONFIG.webhook, secrets::get(&WEBHOOK_SECRET));
+    let value = req.header::<String>(verifier.header());
+    if let Err(reason) = verifier.verify(value.as_deref(), &body) {
+        warn!("拒绝未通过校验的webhook请求: {}", reason);
+        res.status_code(StatusCode::UNAUTHORIZED);
+        res.render(Json(serde_json::json!({
+            "status": StatusCode::UNAUTHORIZED.as_u16(),
+            "message": format!("❌ 请求校验失败: {}", reason)
+        })));
+        return Err(());
+    }
+
+    let webhook_req = match WebhookRequest::parse(&body) {
+        Ok(webhook_req) => webhook_req,
+        Err(e) => {
+            warn!("webhook请求解析失败: {}", e);
+            reject(res, e.status_code(), format!("❌ 无效的请求格式: {}", e));
+            return Err(());
+        }
+    };
+
+    match webhook_req.detail().event() {
+        WebhookEvent::Message => {}
+        WebhookEvent::Delete { mid } => {
+            // 消息被撤回，取消对应的任务并释放上传的文件
+            let cancelled = JOBS.cancel(mid);
+            for job in JOBS.find_by_mid(mid) {
+                if let Some(hash) = job.content_hash
+                    && let Err(e) = BLOBS.release(&hash)
+                {
+                    warn!("释放文件失败 {}: {}", hash, e);
                 }
+                JOBS.update(&job.id, |job| job.content_hash = None);
             }
-        } else {
-            // 非PDF文件，可以返回提示信息
-            // WebhookResponse::new("ℹ️ 请发送PDF文件进行分析").render().await;
+            let message = if cancelled {
+                "🛑 消息已撤回，已取消对应的分析任务"
+            } else {
+                "ℹ️ 消息已撤回"
+            };
             res.render(Json(serde_json::json!({
                 "status": 200,
-                "message": "ℹ️ 请发送PDF文件进行分析"
+                "message": message
             })));
+            return Ok(());
         }
+        WebhookEvent::Edit { mid, content } => {
+            // 用户修改了检索条件，取消旧的任务并重新检索
+            JOBS.cancel(mid);
+            let message = match UserQuery::parse(content) {
+                Some(query) => {
+                    let workflow = create_search_workflow(query, mid, &webhook_req);
+                    let job_id = workflow.job_id().to_string();
+                    JOBS.track(mid, &job_id, workflow.start());
+                    "🔍 检索条件已修改，正在重新检索..."
+                }
+                None => "ℹ️ 修改后的消息不是有效的检索条件",
+            };
+            res.render(Json(serde_json::json!({
+                "status": 200,
+                "message": message
+            })));
+            return Ok(());
+        }
+        WebhookEvent::Like { mid, action } => {
+            // 对结果消息点👍/👎作为反馈，其他表情忽略
+            let message = match (Verdict::parse(action), JOBS.find_by_result_mid(mid)) {
+                (Some(verdict), Some(job)) => {
+                    record_feedback(&job, verdict, None, webhook_req.from_uid())
+                }
+                _ => "ignored".to_string(),
+            };
+            res.render(Json(serde_json::json!({
+                "status": 200,
+                "message": message
+            })));
+            return Ok(());
+        }
+        WebhookEvent::Other(ty) => {
+            debug!("忽略webhook事件: {}", ty);
+            res.render(Json(serde_json::json!({
+                "status": 200,
+                "message": "ignored"
+            })));
+            return Ok(());
+        }
+    }
+
+    let attachments = webhook_req.detail().attachments();
+    if !attachments.is_empty() {
+        // 每个文件单独检查并启动分析任务，立即返回"正在处理"响应，然后在后台处理
+        let mut jobs = Vec::new();
+        let mut replies = Vec::new();
+        let mut previous_reports = Vec::new();
+        for attachment in &attachments {
+            match start_attachment(attachment, &webhook_req).await {
+                AttachmentOutcome::Started(job) => {
+                    jobs.push(job);
+                    replies.push(Ok(attachment.display_name()));
+                }
+                AttachmentOutcome::Previous(report) => {
+                    replies.push(Err(format!(
+                        "`{}`: 已经分析过，之前的结果单独发送",
+                        attachment.display_name()
+                    )));
+                    previous_reports.push(report);
+                }
+                AttachmentOutcome::Rejected(message) => {
+                    info!("文件 {} 不能分析: {}", attachment.display_name(), message);
+                    replies.push(Err(message));
+                }
+            }
+        }
+        JOBS.track_all(webhook_req.mid(), jobs);
+
+        let (webhook_url, api_key) = bot_endpoint(&webhook_req);
+        let message = match (replies.as_slice(), previous_reports.as_slice()) {
+            ([Ok(_)], _) => "📄 收到PDF文件，正在分析中，请稍等...".to_string(),
+            // 只有一个已经分析过的文件时只发送之前的报告
+            ([Err(_)], [report]) => report.clone(),
+            ([Err(message)], _) => message.clone(),
+            (replies, _) => fmt_attachment_replies(replies),
+        };
+        res.render(Json(serde_json::json!({
+            "status": 200,
+            "message": message
+        })));
+        // 只有一个文件且开始分析时由分析任务发送进度和结果
+        let mut messages = Vec::new();
+        if !matches!(replies.as_slice(), [Ok(_)]) {
+            messages.push(message);
+        }
+        // 多个文件时之前的报告在文件列表之后各自单独发送
+        if replies.len() > 1 {
+            messages.extend(previous_reports);
+        }
+        send_in_background(webhook_url, api_key, messages);
+    } else if let Some(command) = webhook_req
+        .detail()
+        .is_text()
+        .then(|| ChatCommand::parse(&webhook_req.detail().content))
+        .flatten()
+    {
+        let message = match command {
+            ChatCommand::Unrecognized => {
+                let taxonomy = TAXONOMY.read().unwrap();
+                fmt_unrecognized_digest(&UNRECOGNIZED.lock().unwrap(), &taxonomy)
+            }
+            ChatCommand::Feedback {
+                job,
+                verdict,
+                comment,
+            } => match JOBS.find(&job) {
+                Some(job) => record_feedback(&job, verdict, comment, webhook_req.from_uid()),
+                None => format!("❌ 找不到任务 `{}`", job),
+            },
+            ChatCommand::Layout(layout) => set_layout(webhook_req.from_uid(), layout),
+            ChatCommand::Sampling(sampling) => set_sampling(webhook_req.from_uid(), sampling),
+            ChatCommand::Force(job) => force_analysis(&job, &webhook_req).await,
+            ChatCommand::Cancel => cancel_jobs(webhook_req.from_uid()),
+        };
+        res.render(Json(serde_json::json!({
+            "status": 200,
+            "message": "ok"
+        })));
+        let (webhook_url, api_key) = bot_endpoint(&webhook_req);
+        send_in_background(webhook_url, api_key, vec![message]);
+    } else if let Some(query) = webhook_req
+        .detail()
+        .is_text()
+        .then(|| UserQuery::parse(&webhook_req.detail().content))
+        .flatten()
+    {
+        // 文本消息作为检索条件
+        res.render(Json(serde_json::json!({
+            "status": 200,
+            "message": "🔍 正在检索，请稍等..."
+        })));
+        let workflow = create_search_workflow(query, webhook_req.mid(), &webhook_req);
+        let job_id = workflow.job_id().to_string();
+        JOBS.track(webhook_req.mid(), &job_id, workflow.start());
     } else {
-        // WebhookResponse::new("❌ 无效的请求格式").render().await;
+        // 非PDF文件，可以返回提示信息
+        // WebhookResponse::new("ℹ️ 请发送PDF文件进行分析").render().await;
         res.render(Json(serde_json::json!({
             "status": 200,
-            "message": "❌ 无效的请求格式"
+            "message": QUERY_HELP
         })));
-        return Err(());
     }
 
     Ok(())
 }
 
+/// 在后台按顺序发送消息，VoceChat等待webhook响应，不能等发送完成后再回复
+fn send_in_background(webhook_url: String, api_key: String, messages: Vec<String>) {
+    if messages.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        for message in &messages {
+            send_markdown(&HTTP_CLIENT, &webhook_url, &api_key, message).await;
+        }
+    });
+}
+
+/// 处理一个附件的结果
+enum AttachmentOutcome {
+    /// 开始分析，任务id和任务
+    Started((String, JoinHandle<()>)),
+    /// 相同的文件已经分析过，附上之前报告的回复，作为单独的消息发送
+    Previous(String),
+    /// 不能分析，回复给用户的提示
+    Rejected(String),
+}
+
+/// 检查附件并启动PDF分析任务
+async fn start_attachment(
+    attachment: &Attachment,
+    webhook_req: &WebhookRequest,
+) -> AttachmentOutcome {
+    if let Some(message) = attachment.guidance() {
+        return AttachmentOutcome::Rejected(message);
+    }
+    // 保存文件和检查PDF都要读取整个文件，在阻塞线程中进行
+    let stored = {
+        let attachment = attachment.clone();
+        let from_uid = webhook_req.from_uid();
+        tokio::task::spawn_blocking(move || store_attachment(&attachment, from_uid)).await
+    };
+    let pdf = match stored {
+        Ok(Ok(pdf)) => pdf,
+        Ok(Err(outcome)) => return outcome,
+        Err(e) => return AttachmentOutcome::Rejected(format!("❌ 保存文件失败: {}", e)),
+    };
+    let workflow = create_pdf_analysis_workflow(pdf, webhook_req);
+    let job_id = workflow.job_id().to_string();
+    AttachmentOutcome::Started((job_id, workflow.start()))
+}
+
+/// 保存附件并检查PDF，已经分析过或不能分析时返回回复
+fn store_attachment(attachment: &Attachment, from_uid: u64) -> Result<Blob, AttachmentOutcome> {
+    let pdf = attachment
+        .store_pdf()
+        .map_err(|e| AttachmentOutcome::Rejected(format!("❌ 无效的PDF文件路径: {}", e)))?;
+    // 同一用户已经分析过相同的文件时直接返回之前的结果
+    if let Some(previous) = JOBS.find_analyzed(&pdf.hash, from_uid)
+        && let Some(report) = JOBS.report(&previous.id)
+    {
+        info!(
+            "文件 {} 与任务 {} 相同，返回之前的结果",
+            attachment.display_name(),
+            previous.id
+        );
+        if let Err(e) = BLOBS.release(&pdf.hash) {
+            error!("释放文件失败: {}", e);
+        }
+        return Err(AttachmentOutcome::Previous(fmt_previous_analysis(
+            attachment.display_name(),
+            &previous,
+            &report,
+        )));
+    }
+    check_upload(&pdf).map_err(|e| AttachmentOutcome::Rejected(e.to_string()))?;
+    Ok(pdf)
+}
+
+/// 一次收到多个文件时的回复，列出开始分析的文件和不能分析的原因
+fn fmt_attachment_replies(replies: &[Result<&str, String>]) -> String {
+    let started = replies.iter().filter(|r| r.is_ok()).count();
+    let mut lines = vec![format!(
+        "📄 收到 {} 个文件，其中 {} 个正在分析中，请稍等...",
+        replies.len(),
+        started
+    )];
+    for reply in replies {
+        lines.push(match reply {
+            Ok(name) => format!("- `{}`: 正在分析", name),
+            Err(message) => format!("- {}", message),
+        });
+    }
+    lines.join("\n")
+}
+
+/// 相同的文件已经分析过时的回复，附上之前的报告
+fn fmt_previous_analysis(name: &str, previous: &JobRecord, report: &str) -> String {
+    let analyzed_at = chrono::DateTime::parse_from_rfc3339(&previous.created_at)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| previous.created_at.clone());
+    let short_id = &previous.id[..previous.id.len().min(8)];
+    format!(
+        "♻️ `{}` 在 {} 已经分析过，以下是之前的结果。需要重新分析请发送 `/force {}`\n\n{}",
+        name, analyzed_at, short_id, report
+    )
+}
+
+/// 重新分析之前的任务的PDF，只能重新分析自己上传的文件，返回回复给用户的提示
+async fn force_analysis(job: &str, webhook_req: &WebhookRequest) -> String {
+    let Some(job) = JOBS.find(job) else {
+        return format!("❌ 找不到任务 `{}`", job);
+    };
+    if job.from_uid != webhook_req.from_uid() {
+        warn!(
+            "用户 {} 尝试重新分析用户 {} 的任务 {}",
+            webhook_req.from_uid(),
+            job.from_uid,
+            job.id
+        );
+        return format!("❌ 任务 `{}` 不是你上传的文件，不能重新分析", job.id);
+    }
+    let Some(pdf) = job
+        .content_hash
+        .as_deref()
+        .and_then(|hash| BLOBS.retain(hash))
+    else {
+        return format!("❌ 任务 `{}` 的PDF文件已经删除，请重新上传", job.id);
+    };
+    if let Err(e) = check_upload_blocking(&pdf).await {
+        return e.to_string();
+    }
+    let workflow = create_pdf_analysis_workflow(pdf, webhook_req);
+    let job_id = workflow.job_id().to_string();
+    JOBS.track(webhook_req.mid(), &job_id, workflow.start());
+    "🔄 正在重新分析，请稍等...".to_string()
+}
+
+/// 取消用户正在进行的任务，返回回复给用户的提示
+fn cancel_jobs(from_uid: u64) -> String {
+    let short_id = |id: &String| format!("`{}`", &id[..id.len().min(8)]);
+    match JOBS.cancel_user(from_uid).as_slice() {
+        [] => "ℹ️ 没有正在进行的任务".to_string(),
+        [id] => format!("🛑 已取消任务 {}", short_id(id)),
+        ids => format!(
+            "🛑 已取消 {} 个任务: {}",
+            ids.len(),
+            ids.iter().map(short_id).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// 把用户反馈记录到任务上，只接受任务发起者和管理员的反馈，返回回复给用户的提示
+fn record_feedback(
+    job: &JobRecord,
+    verdict: Verdict,
+    comment: Option<String>,
+    from_uid: u64,
+) -> String {
+    if !CONFIG.bot.can_review(job.from_uid, from_uid) {
+        warn!(
+            "用户 {} 尝试评价用户 {} 的任务 {}",
+            from_uid, job.from_uid, job.id
+        );
+        return format!("❌ 任务 `{}` 不是你发起的，不能评价", job.id);
+    }
+    let job_id = &job.id;
+    let feedback = Feedback {
+        verdict,
+        comment,
+        from_uid,
+        created_at: chrono::Local::now().to_rfc3339(),
+    };
+    match JOBS.add_feedback(job_id, feedback) {
+        Some(_) => {
+            info!("收到任务 {} 的反馈: {:?}", job_id, verdict);
+            "🙏 感谢反馈".to_string()
+        }
+        None => format!("❌ 找不到任务 `{}`", job_id),
+    }
+}
+
+/// 保存用户选择的展示形式，返回回复给用户的提示
+fn set_layout(from_uid: u64, layout: ReportLayout) -> String {
+    let mut preferences = PREFERENCES.lock().unwrap();
+    preferences.set_layout(from_uid, layout);
+    if let Err(e) = preferences.save(&preferences_dir()) {
+        error!("保存用户偏好设置失败: {}", e);
+    }
+    match layout {
+        ReportLayout::Table => "✅ 结果将以表格形式展示".to_string(),
+        ReportLayout::Compact => "✅ 结果将以紧凑列表形式展示".to_string(),
+    }
+}
+
+/// 保存用户选择的采样参数，返回回复给用户的提示
+fn set_sampling(from_uid: u64, sampling: Option<Sampling>) -> String {
+    let mut preferences = PREFERENCES.lock().unwrap();
+    preferences.set_sampling(from_uid, sampling);
+    if let Err(e) = preferences.save(&preferences_dir()) {
+        error!("保存用户偏好设置失败: {}", e);
+    }
+    match sampling {
+        Some(Sampling {
+            temperature,
+            seed: Some(seed),
+        }) => format!(
+            "✅ 之后的分析将使用 temperature={} seed={}",
+            temperature, seed
+        ),
+        Some(Sampling { temperature, .. }) => {
+            format!("✅ 之后的分析将使用 temperature={}", temperature)
+        }
+        None => "✅ 已恢复默认采样参数".to_string(),
+    }
+}
+
 #[handler]
 pub async fn workhook_check(_req: &mut Request, res: &mut Response) -> Result<(), ()> {
     res.render(Json(serde_json::json!({
@@ -314,18 +1003,193 @@ pub async fn workhook_check(_req: &mut Request, res: &mut Response) -> Result<()
 }
 
 #[cfg(test)]
-mod tests{
-    use std::path::PathBuf;
+mod tests {
+    use super::*;
+
+    const PDF_REQUEST: &str = r#"{
+        "created_at": 1754560852630,
+        "detail": {
+            "content": "2025/8/7/e034f8aa-55e5-4a4e-8c93-3fc2f4f45c72",
+            "content_type": "vocechat/file",
+            "expires_in": null,
+            "properties": {
+                "content_type": "application/pdf",
+                "local_id": 1754560852515,
+                "name": "03骨架 .pdf",
+                "size": 102003
+            },
+            "type": "normal"
+        },
+        "domain": null,
+        "from_uid": 1,
+        "mid": 1,
+        "target": { "uid": 2 },
+        "type": "chat",
+        "widget_id": null
+    }"#;
+
+    #[test]
+    fn parse_webhook_request() {
+        let req = WebhookRequest::parse(PDF_REQUEST.as_bytes()).unwrap();
+        assert_eq!(req.from_uid(), 1);
+        assert_eq!(req.mid(), 1);
+        assert!(req.detail().is_pdf());
+        let attachments = req.detail().attachments();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].file_name(), Some("03骨架 .pdf"));
+        assert_eq!(attachments[0].guidance(), None);
+    }
+
+    #[test]
+    fn file_kinds() {
+        let file = |name: &str, content_type: &str, size: u64| {
+            let body = PDF_REQUEST
+                .replace("03骨架 .pdf", name)
+                .replace("application/pdf", content_type)
+                .replace("102003", &size.to_string());
+            WebhookRequest::parse(body.as_bytes())
+                .unwrap()
+                .detail()
+                .attachments()
+                .remove(0)
+        };
+        let file_a = file("BOM.xlsx", "application/octet-stream", 10);
+        assert_eq!(file_a.file_kind(), FileKind::Spreadsheet);
+        assert!(file_a.guidance().unwrap().contains("BOM.xlsx"));
+
+        // 没有扩展名时按content_type判断
+        assert_eq!(
+            file("drawing", "application/pdf", 10).file_kind(),
+            FileKind::Pdf
+        );
+        assert_eq!(
+            file("图纸.PDF", "application/octet-stream", 10).file_kind(),
+            FileKind::Pdf
+        );
+        assert_eq!(
+            file("外壳.dwg", "application/octet-stream", 10).file_kind(),
+            FileKind::Cad
+        );
+        assert_eq!(
+            file("a.bin", "application/octet-stream", 10).file_kind(),
+            FileKind::Other
+        );
+
+        let empty = file("空.pdf", "application/pdf", 0);
+        assert!(empty.guidance().unwrap().contains("是空的"));
+    }
+
+    #[test]
+    fn previous_analysis_reply() {
+        let mut previous = JobRecord::new(crate::job::JobKind::Pdf, 1, 1, "a.pdf".to_string());
+        previous.created_at = "2025-09-01T10:30:00+08:00".to_string();
+        let reply = fmt_previous_analysis("03骨架.pdf", &previous, "## 匹配结果");
+        assert!(reply.contains("2025-09-01 10:30"), "{}", reply);
+        assert!(reply.contains(&format!("/force {}", &previous.id[..8])));
+        assert!(reply.ends_with("## 匹配结果"));
+    }
+
+    #[test]
+    fn multiple_attachments() {
+        let body = PDF_REQUEST.replace(
+            r#""type": "normal""#,
+            r#""type": "normal",
+            "files": [
+                {"content": "2025/8/7/a", "properties": {"name": "a.pdf", "size": 10}},
+                {"content": "2025/8/7/b", "properties": {"name": "BOM.xlsx", "size": 10}}
+            ]"#,
+        );
+        let req = WebhookRequest::parse(body.as_bytes()).unwrap();
+        let attachments = req.detail().attachments();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].content, "2025/8/7/a");
+        assert_eq!(attachments[1].file_kind(), FileKind::Spreadsheet);
+
+        let message =
+            fmt_attachment_replies(&[Ok("a.pdf"), Err(attachments[1].guidance().unwrap())]);
+        assert!(message.starts_with("📄 收到 2 个文件，其中 1 个正在分析中"));
+        assert!(message.contains("- `a.pdf`: 正在分析"));
+        assert!(message.contains("BOM.xlsx"));
+    }
+
+    #[test]
+    fn parse_webhook_request_tolerates_new_fields() {
+        let body = PDF_REQUEST.replace(
+            r#""widget_id": null"#,
+            r#""widget_id": null, "client": {"os": "ios"}, "target2": 1"#,
+        );
+        let body = body.replace(r#"{ "uid": 2 }"#, r#"{ "uid": 2, "channel": 3 }"#);
+        let WebhookRequest::V1(req) = WebhookRequest::parse(body.as_bytes()).unwrap();
+        assert!(req.extra.contains_key("client"));
+        assert_eq!(req.target.get(&IdType::UId), Some(&2));
+    }
+
+    #[test]
+    fn parse_reaction_events() {
+        let edit = r#"{
+            "created_at": 1754560852630,
+            "detail": {
+                "type": "reaction",
+                "mid": 7,
+                "detail": {"type": "edit", "content": "基座", "content_type": "text/plain"}
+            },
+            "from_uid": 1,
+            "mid": 8,
+            "target": { "uid": 2 }
+        }"#;
+        let req = WebhookRequest::parse(edit.as_bytes()).unwrap();
+        assert_eq!(
+            req.detail().event(),
+            WebhookEvent::Edit {
+                mid: 7,
+                content: "基座"
+            }
+        );
+
+        let delete = edit.replace(
+            r#"{"type": "edit", "content": "基座", "content_type": "text/plain"}"#,
+            r#"{"type": "delete"}"#,
+        );
+        let req = WebhookRequest::parse(delete.as_bytes()).unwrap();
+        assert_eq!(req.detail().event(), WebhookEvent::Delete { mid: 7 });
+
+        let like = edit.replace(
+            r#"{"type": "edit", "content": "基座", "content_type": "text/plain"}"#,
+            r#"{"type": "like", "action": "👍"}"#,
+        );
+        let req = WebhookRequest::parse(like.as_bytes()).unwrap();
+        assert_eq!(
+            req.detail().event(),
+            WebhookEvent::Like {
+                mid: 7,
+                action: "👍"
+            }
+        );
+
+        let req = WebhookRequest::parse(PDF_REQUEST.as_bytes()).unwrap();
+        assert_eq!(req.detail().event(), WebhookEvent::Message);
+    }
+
+    #[test]
+    fn parse_webhook_request_reports_field() {
+        let body = PDF_REQUEST.replace(r#""from_uid": 1"#, r#""from_uid": "abc""#);
+        let err = WebhookRequest::parse(body.as_bytes()).unwrap_err();
+        assert_eq!(err.path, "from_uid");
+
+        assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let err = WebhookRequest::parse(br#"{"foo": 1}"#).unwrap_err();
+        assert_eq!(err.path, "$");
+        assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let err = WebhookRequest::parse(b"{\"mid\": ").unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
 
     #[test]
     fn componet_path() {
         let path = "2025/8/7/e034f8aa-55e5-4a4e-8c93-3fc2f4f45c72";
-        let content_path = PathBuf::from(path)
-        .components()
-        .fold(PathBuf::new(), |mut acc, comp| {
-            acc.push(comp);
-            acc
-        });
+        let content_path = relative_path(path).unwrap();
         dbg!(content_path.display());
     }
-}
\ No newline at end of file
+}
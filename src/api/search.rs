@@ -0,0 +1,141 @@
+use salvo::{Request, Response, handler, http::StatusCode, writing::Json};
+use serde::Serialize;
+
+use crate::{
+    CONFIG, MODELS,
+    api::{
+        error::ApiError,
+        model::{period, scoring_profile},
+    },
+    calibration::ScoreBand,
+    diff::{DiffResult, ModelJson},
+    mold_status::MoldStatus,
+    query::UserQuery,
+    suggest::SuggestionKind,
+};
+
+/// 输入提示默认和最多返回的数量
+const SUGGEST_LIMIT: usize = 10;
+const MAX_SUGGEST_LIMIT: usize = 50;
+
+/// 检索结果中的一个模具
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub name: String,
+    pub id: Option<String>,
+    pub slug: Option<String>,
+    pub model_type: Option<String>,
+    pub materials: Vec<String>,
+    pub company: Option<String>,
+    /// 综合相似度(0-1)
+    pub score: f32,
+    /// 按校准后的分数划分的档次
+    pub band: ScoreBand,
+    /// 模具类型与检索的类型完全一致
+    pub exact_match: bool,
+    pub status: Option<MoldStatus>,
+    /// 前几页预览图的链接，页数与聊天报告相同
+    pub pages: Vec<String>,
+}
+
+impl SearchHit {
+    fn new(result: DiffResult, model: Option<&ModelJson>, pages: Vec<String>) -> Self {
+        Self {
+            band: result.band(),
+            id: model.and_then(|m| m.id.clone()),
+            slug: model.and_then(|m| m.slug.clone()),
+            model_type: model.and_then(|m| m.model_type.clone()),
+            materials: model.map(|m| m.materials.clone()).unwrap_or_default(),
+            name: result.source_name,
+            company: result.company,
+            score: result.percentage,
+            exact_match: result.exact_match,
+            status: result.status,
+            pages,
+        }
+    }
+}
+
+/// 按模具类型、材料、客户、标签和提取时间检索模具库，与聊天中的检索语句使用相同的条件，
+/// 材料可以用逗号分隔或重复给出，`limit`为返回的结果数量，`profile`为评分方案
+/// GET /material/api/v1/search?model_type=基座&materials=PBT RG301,PA66&customer=TCL&since=2023&limit=10
+#[handler]
+pub async fn search(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let limit = req
+        .query::<usize>("limit")
+        .unwrap_or(CONFIG.report.max_results);
+    let config = &CONFIG.diff;
+    let profile = scoring_profile(req, config)?;
+    // 检查日期格式，检索条件中的无效日期会被忽略
+    period(req)?;
+    let query = UserQuery::from_pairs(
+        req.queries()
+            .iter_all()
+            .flat_map(|(key, values)| values.iter().map(move |v| (key.as_str(), v.as_str()))),
+    )
+    .ok_or_else(|| {
+        ApiError::invalid_request(
+            "请求格式错误，需要 model_type、materials、customer、tags、since 或 until",
+            "Invalid request, one of model_type, materials, customer, tags, since or until is required",
+        )
+    })?;
+
+    let store = MODELS.load_full();
+    let mut results = store.search_with(&query, &profile, config.include_scrapped);
+    results.truncate(limit);
+    let hits: Vec<SearchHit> =
+        DiffResult::with_previews(store.clone(), results, CONFIG.report.preview_pages)
+            .await
+            .into_iter()
+            .map(|(result, pages)| {
+                let model = store.get(&result.source_name);
+                SearchHit::new(result, model, pages)
+            })
+            .collect();
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": hits,
+    })));
+    Ok(())
+}
+
+/// 检索框的输入提示，从模具库中按前缀和模糊匹配查找模具类型、材料和项目名称，
+/// `kind`只返回一类(`model_type`、`material`或`project`)，`limit`最多50
+/// GET /material/api/v1/suggest?q=PBT R&kind=material&limit=10
+#[handler]
+pub async fn suggest(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let query = req.query::<String>("q").unwrap_or_default();
+    if query.trim().is_empty() {
+        return Err(ApiError::invalid_request(
+            "请求格式错误，需要 q",
+            "Invalid request, q is required",
+        ));
+    }
+    let kind = match req.query::<String>("kind") {
+        Some(kind) => Some(
+            serde_json::from_value::<SuggestionKind>(serde_json::Value::String(kind.clone()))
+                .map_err(|_| {
+                    ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        "unknown_kind",
+                        format!("未知的类别 `{}`，可选: model_type, material, project", kind),
+                        format!(
+                            "Unknown kind `{}`, available: model_type, material, project",
+                            kind
+                        ),
+                    )
+                })?,
+        ),
+        None => None,
+    };
+    let limit = req
+        .query::<usize>("limit")
+        .unwrap_or(SUGGEST_LIMIT)
+        .min(MAX_SUGGEST_LIMIT);
+    let suggestions = MODELS.load().suggest(&query, kind, limit);
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": suggestions,
+    })));
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use salvo::{Request, Response, handler, writing::Json};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{
+    IResult, TAXONOMY, UNRECOGNIZED,
+    api::error::ApiError,
+    taxonomy::{Taxonomy, taxonomy_dir},
+};
+
+/// 修改词表的请求依次进行，避免并发的修改互相覆盖
+static UPDATING: Mutex<()> = Mutex::const_new(());
+
+/// 在当前词表的副本上修改并保存到文件，保存成功后才替换当前词表，返回`f`的结果。
+/// 保存文件时不持有`TAXONOMY`的锁，不阻塞检索和比对
+async fn update_taxonomy<T>(f: impl FnOnce(&mut Taxonomy) -> T) -> IResult<T> {
+    let _updating = UPDATING.lock().await;
+    let mut updated = TAXONOMY.read().unwrap().clone();
+    let result = f(&mut updated);
+    updated.save(&taxonomy_dir())?;
+    *TAXONOMY.write().unwrap() = updated;
+    Ok(result)
+}
+
+/// 当前加载的材料词表(大类 → 牌号 → 写法)
+/// GET /material/api/v1/taxonomy/materials
+#[handler]
+pub async fn material_taxonomy(_req: &mut Request, res: &mut Response) -> Result<(), ()> {
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": TAXONOMY.read().unwrap().materials,
+    })));
+    Ok(())
+}
+
+/// 当前加载的模具类型词表(分组 → 写法)
+/// GET /material/api/v1/taxonomy/model-types
+#[handler]
+pub async fn model_type_taxonomy(_req: &mut Request, res: &mut Response) -> Result<(), ()> {
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": TAXONOMY.read().unwrap().model_types,
+    })));
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AddMaterialRequest {
+    pub family: String,
+    pub grade: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+/// 添加材料牌号，保存到词表文件并立即生效
+/// POST /material/api/v1/taxonomy/materials
+/// ```json
+/// { "family": "PBT", "grade": "RG302", "aliases": ["RG-302"] }
+/// ```
+#[handler]
+pub async fn add_material(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let body = match req.parse_json::<AddMaterialRequest>().await {
+        Ok(body) if !body.family.trim().is_empty() && !body.grade.trim().is_empty() => body,
+        _ => {
+            return Err(ApiError::invalid_request(
+                "请求格式错误，需要 family 和 grade",
+                "Invalid request, family and grade are required",
+            ));
+        }
+    };
+
+    let family = update_taxonomy(|taxonomy| {
+        taxonomy
+            .add_material_grade(body.family.trim(), body.grade.trim(), &body.aliases)
+            .clone()
+    })
+    .await
+    .inspect_err(|e| error!("保存材料词表失败: {}", e))?;
+    info!("词表新增材料: {} {}", body.family, body.grade);
+
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": family,
+    })));
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AddModelTypeRequest {
+    pub group: String,
+    pub names: Vec<String>,
+}
+
+/// 向模具类型分组添加写法，保存到词表文件并立即生效
+/// POST /material/api/v1/taxonomy/model-types
+/// ```json
+/// { "group": "基座", "names": ["下基座"] }
+/// ```
+#[handler]
+pub async fn add_model_type(req: &mut Request, res: &mut Response) -> Result<(), ApiError> {
+    let body = match req.parse_json::<AddModelTypeRequest>().await {
+        Ok(body) if !body.group.trim().is_empty() => body,
+        _ => {
+            return Err(ApiError::invalid_request(
+                "请求格式错误，需要 group 和 names",
+                "Invalid request, group and names are required",
+            ));
+        }
+    };
+
+    let group = update_taxonomy(|taxonomy| {
+        taxonomy
+            .add_model_types(body.group.trim(), &body.names)
+            .clone()
+    })
+    .await
+    .inspect_err(|e| error!("保存模具类型词表失败: {}", e))?;
+    info!("词表新增模具类型: {} {:?}", body.group, body.names);
+
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": group,
+    })));
+    Ok(())
+}
+
+/// 提取结果中尚未归入词表的模具类型和材料，按出现次数排序
+/// GET /material/api/v1/taxonomy/unrecognized
+#[handler]
+pub async fn unrecognized_terms(_req: &mut Request, res: &mut Response) -> Result<(), ()> {
+    let taxonomy = TAXONOMY.read().unwrap();
+    let terms = UNRECOGNIZED.lock().unwrap();
+    let (model_types, materials) = terms.pending(&taxonomy);
+    res.render(Json(serde_json::json!({
+        "status": 200,
+        "data": {
+            "model_types": model_types,
+            "materials": materials,
+        },
+    })));
+    Ok(())
+}
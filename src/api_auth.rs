@@ -0,0 +1,245 @@
+//! JSON接口的鉴权
+//!
+//! 配置了接口密钥(`AuthConfig::keys`)后，`/material/api`下的请求需要带有`Authorization: Bearer <key>`，
+//! 浏览器的EventSource和WebSocket不能设置请求头，可以改用查询参数`access_token`。
+//! 每个路由组要求一种权限，`admin`密钥可以访问所有接口，`read`密钥只能访问只读的接口。
+//! 没有配置任何密钥时只读接口不鉴权，需要`admin`权限的接口全部回复503，不能匿名修改数据。
+//! VoceChat的webhook另外校验，见`webhook_auth`。
+use std::sync::Arc;
+
+use salvo::{
+    Depot, FlowCtrl, Handler, Request, Response, async_trait,
+    http::{StatusCode, header::WWW_AUTHENTICATE},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    api::error::ApiError,
+    config::{ApiKey, ApiScope, AuthConfig},
+    webhook_auth::constant_time_eq,
+};
+
+const BEARER: &str = "Bearer ";
+const TOKEN_QUERY: &str = "access_token";
+
+/// 要求`scope`权限的中间件
+#[derive(Clone)]
+pub struct Authorize {
+    keys: Arc<Vec<ApiKey>>,
+    scope: ApiScope,
+}
+
+impl Authorize {
+    pub fn new(config: &AuthConfig, scope: ApiScope) -> Self {
+        Self {
+            keys: Arc::new(config.keys.clone()),
+            scope,
+        }
+    }
+
+    /// 请求携带的密钥
+    fn token(req: &Request) -> Option<String> {
+        let header = req.header::<String>("authorization").and_then(|value| {
+            value
+                .get(..BEARER.len())
+                .filter(|prefix| prefix.eq_ignore_ascii_case(BEARER))
+                .map(|_| value[BEARER.len()..].trim().to_string())
+        });
+        header
+            .or_else(|| req.query::<String>(TOKEN_QUERY))
+            .filter(|token| !token.is_empty())
+    }
+
+    /// 检查密钥，返回匹配的密钥
+    fn authorize(&self, token: Option<&str>) -> Result<&ApiKey, ApiError> {
+        let token = token.ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "缺少接口密钥，请求头应为 Authorization: Bearer <key>",
+                "Missing API key, expected Authorization: Bearer <key>",
+            )
+        })?;
+        // 逐个比较全部密钥，比较时间与匹配的位置无关
+        let key = self
+            .keys
+            .iter()
+            .fold(None, |found, key| {
+                if constant_time_eq(key.key.as_bytes(), token.as_bytes()) {
+                    Some(key)
+                } else {
+                    found
+                }
+            })
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "unauthorized",
+                    "接口密钥无效",
+                    "Invalid API key",
+                )
+            })?;
+        if key.scope < self.scope {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                format!("接口密钥 {} 没有管理权限", key.name),
+                format!("API key {} lacks the admin scope", key.name),
+            ));
+        }
+        Ok(key)
+    }
+}
+
+#[async_trait]
+impl Handler for Authorize {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        if self.keys.is_empty() {
+            if self.scope == ApiScope::Admin {
+                warn!("没有配置接口密钥，拒绝访问 {}", req.uri().path());
+                res.render(ApiError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "admin_disabled",
+                    "没有配置接口密钥，管理接口已停用，见 auth.keys 或 MATERIAL_API_KEYS",
+                    "No API keys are configured, admin endpoints are disabled; see auth.keys or MATERIAL_API_KEYS",
+                ));
+                ctrl.skip_rest();
+            }
+            return;
+        }
+        let token = Self::token(req);
+        match self.authorize(token.as_deref()) {
+            Ok(key) => debug!("接口密钥 {} 访问 {}", key.name, req.uri().path()),
+            Err(e) => {
+                warn!("拒绝访问 {}: {}", req.uri().path(), e.message_zh);
+                if e.status == StatusCode::UNAUTHORIZED {
+                    let _ = res.add_header(WWW_AUTHENTICATE, "Bearer", true);
+                }
+                res.render(e);
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{Router, Service, handler, test::TestClient};
+
+    use super::*;
+
+    #[handler]
+    async fn ok() -> &'static str {
+        "ok"
+    }
+
+    #[test]
+    fn authorize_scopes() {
+        let key = |name: &str, scope| ApiKey {
+            name: name.to_string(),
+            key: format!("k-{}", name),
+            scope,
+        };
+        let config = AuthConfig {
+            keys: vec![key("plm", ApiScope::Read), key("ops", ApiScope::Admin)],
+        };
+        let read = Authorize::new(&config, ApiScope::Read);
+        let admin = Authorize::new(&config, ApiScope::Admin);
+
+        assert_eq!(read.authorize(Some("k-plm")).unwrap().name, "plm");
+        assert_eq!(read.authorize(Some("k-ops")).unwrap().name, "ops");
+        assert_eq!(admin.authorize(Some("k-ops")).unwrap().name, "ops");
+        assert_eq!(
+            admin.authorize(Some("k-plm")).unwrap_err().status,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            read.authorize(Some("k-other")).unwrap_err().status,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            read.authorize(None).unwrap_err().status,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn route_groups() {
+        let config = AuthConfig {
+            keys: vec![ApiKey {
+                name: "plm".to_string(),
+                key: "k-plm".to_string(),
+                scope: ApiScope::Read,
+            }],
+        };
+        // 同一路径的读写方法在不同的组中
+        let router = Router::new()
+            .push(
+                Router::new()
+                    .hoop(Authorize::new(&config, ApiScope::Admin))
+                    .push(Router::with_path("taxonomy").post(ok)),
+            )
+            .push(
+                Router::new()
+                    .hoop(Authorize::new(&config, ApiScope::Read))
+                    .push(Router::with_path("taxonomy").get(ok)),
+            );
+        let service = Service::new(router);
+        let url = "http://127.0.0.1/taxonomy";
+
+        let res = TestClient::get(url)
+            .add_header("authorization", "Bearer k-plm", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = TestClient::get(format!("{}?access_token=k-plm", url))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = TestClient::post(url)
+            .add_header("authorization", "Bearer k-plm", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+        let res = TestClient::get(url).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(res.headers()["www-authenticate"], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn admin_closed_without_keys() {
+        let config = AuthConfig::default();
+        let router = Router::new()
+            .push(
+                Router::with_path("admin/reindex")
+                    .hoop(Authorize::new(&config, ApiScope::Admin))
+                    .post(ok),
+            )
+            .push(
+                Router::with_path("search")
+                    .hoop(Authorize::new(&config, ApiScope::Read))
+                    .get(ok),
+            );
+        let service = Service::new(router);
+
+        let res = TestClient::post("http://127.0.0.1/admin/reindex")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::SERVICE_UNAVAILABLE));
+        let res = TestClient::post("http://127.0.0.1/admin/reindex")
+            .add_header("authorization", "Bearer anything", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::SERVICE_UNAVAILABLE));
+        let res = TestClient::get("http://127.0.0.1/search")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+    }
+}
@@ -0,0 +1,370 @@
+//! 运维命令行工具
+//!
+//! ```text
+//! material-cli export-dataset <输出目录> [--jobs <任务目录>]
+//! material-cli calibrate [--jobs <任务目录>] [--bins <分段数>]
+//! material-cli migrate [--models <模具目录>] [--dry-run]
+//! material-cli migrate-original-pdf [--models <模具目录>]
+//! material-cli shard-models [--models <模具目录>]
+//! material-cli assign-ids [--models <模具目录>]
+//! material-cli backfill-timestamps [--models <模具目录>]
+//! material-cli stale-extractions [--models <模具目录>] [--model <模型名称>]
+//! material-cli remove-upload-copies [<上传目录>]
+//! material-cli import-mold-status <CSV文件> [--models <模具目录>]
+//! material-cli import-models-sqlite [--models <模具目录>] [--db <数据库文件>]
+//! material-cli snapshot-corpus [--models <模具目录>]
+//! material-cli diff-snapshots <起始日期> [<结束日期>]
+//! material-cli doctor
+//! ```
+use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+
+use material_rs::{
+    CONFIG, HTTP_CLIENT, PROMPTS,
+    blob::remove_upload_copies,
+    calibration::learn,
+    config::{StorageBackend, StorageConfig},
+    corpus_events::{CorpusEvent, CorpusEventKind, notify},
+    corpus_snapshot::{Snapshot, SnapshotDiff, parse_date, snapshots_dir, today},
+    dataset::export_dataset,
+    doctor,
+    job::{JobRegistry, jobs_dir},
+    model_storage::{import_json, open_repository},
+    model_store::{
+        ModelStore, assign_ids, backfill_timestamps, migrate_original_pdfs, models_dir,
+        shard_models,
+    },
+    mold_status::parse_csv,
+    paths::upload_dir,
+    schema_migration,
+    taxonomy::{Taxonomy, taxonomy_dir},
+};
+
+const USAGE: &str = "用法:
+  material-cli export-dataset <输出目录> [--jobs <任务目录>]   导出带有用户反馈的任务作为评测数据集
+  material-cli calibrate [--jobs <任务目录>] [--bins <分段数>]     从用户反馈学习相似度的校准曲线，输出[calibration]配置
+  material-cli migrate [--models <模具目录>] [--dry-run]             把模具记录迁移到当前的格式版本，--dry-run只检查不写入
+  material-cli migrate-original-pdf [--models <模具目录>]           为历史模具记录补全原始PDF图纸
+  material-cli shard-models [--models <模具目录>]                   把模具记录和预览图按年份/客户分片
+  material-cli assign-ids [--models <模具目录>]                     为模具记录分配id和slug
+  material-cli backfill-timestamps [--models <模具目录>]            为历史模具记录补全提取时间
+  material-cli stale-extractions [--models <模具目录>] [--model <模型名称>]  列出需要重新提取的模具记录(没有提取来源、旧的提示词版本或不同的模型)
+  material-cli remove-upload-copies [<上传目录>]                    删除旧版本复制出的<名称>.pdf上传副本
+  material-cli import-mold-status <CSV文件> [--models <模具目录>]   从资产系统导出的CSV(名称,在用/封存/报废)导入模具状态
+  material-cli import-models-sqlite [--models <模具目录>] [--db <数据库文件>]  把模具记录导入SQLite数据库
+  material-cli snapshot-corpus [--models <模具目录>]                保存当天的模具数据库和词表快照
+  material-cli diff-snapshots <起始日期> [<结束日期>]               比较两个日期(YYYY-MM-DD)的快照，没有结束日期时与当前数据比较
+  material-cli doctor                                               检查运行环境并输出就绪报告";
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt().init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("export-dataset") => export(&args[1..]),
+        Some("calibrate") => calibrate(&args[1..]),
+        Some("migrate") => migrate(&args[1..]),
+        Some("migrate-original-pdf") => migrate_original_pdf(&args[1..]),
+        Some("shard-models") => shard(&args[1..]),
+        Some("assign-ids") => assign(&args[1..]),
+        Some("backfill-timestamps") => backfill(&args[1..]),
+        Some("stale-extractions") => stale_extractions(&args[1..]),
+        Some("remove-upload-copies") => remove_copies(&args[1..]),
+        Some("import-mold-status") => import_status(&args[1..]),
+        Some("import-models-sqlite") => import_sqlite(&args[1..]),
+        Some("snapshot-corpus") => snapshot_corpus(&args[1..]),
+        Some("diff-snapshots") => diff_snapshots(&args[1..]),
+        Some("doctor") if args.len() == 1 => check_environment(),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let mut out = None;
+    let mut jobs = jobs_dir();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jobs" => jobs = PathBuf::from(args.next().ok_or(USAGE)?),
+            _ if out.is_none() => out = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let out = out.ok_or(USAGE)?;
+
+    let registry = JobRegistry::open(jobs);
+    let manifest = export_dataset(&registry.all(), &out).map_err(|e| e.to_string())?;
+    println!(
+        "已导出 {} 个样本到 {} (👍 {} / 👎 {})",
+        manifest.samples.len(),
+        out.display(),
+        manifest.good,
+        manifest.bad
+    );
+    Ok(())
+}
+
+/// 默认分10段，样本少时减少分段
+fn calibrate(args: &[String]) -> Result<(), String> {
+    let mut jobs = jobs_dir();
+    let mut bins = 10;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jobs" => jobs = PathBuf::from(args.next().ok_or(USAGE)?),
+            "--bins" => {
+                bins = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or(USAGE)?
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let points = learn(&JobRegistry::open(jobs).all(), bins);
+    if points.is_empty() {
+        return Err("没有带有反馈的任务".to_string());
+    }
+    for point in &points {
+        eprintln!(
+            "原始 {:.3} -> 校准 {:.3} ({} 个样本)",
+            point.raw, point.calibrated, point.samples
+        );
+    }
+    let points: Vec<String> = points
+        .iter()
+        .map(|point| format!("[{:.3}, {:.3}]", point.raw, point.calibrated))
+        .collect();
+    println!("[calibration]\npoints = [{}]", points.join(", "));
+    Ok(())
+}
+
+/// `[--models <模具目录>]`
+fn models_arg(args: &[String]) -> Result<PathBuf, String> {
+    match args {
+        [] => Ok(models_dir()),
+        [flag, dir] if flag == "--models" => Ok(PathBuf::from(dir)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// 有记录无法解析时返回错误，修正后重新执行
+fn migrate(args: &[String]) -> Result<(), String> {
+    let mut models = models_dir();
+    let mut dry_run = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--models" => models = PathBuf::from(args.next().ok_or(USAGE)?),
+            "--dry-run" => dry_run = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let report = schema_migration::migrate(&models, dry_run).map_err(|e| e.to_string())?;
+    println!("{}", report.render());
+    if !report.invalid.is_empty() {
+        return Err(format!("{} 条记录无法解析", report.invalid.len()));
+    }
+    if !dry_run {
+        // 同一条记录可能经过多个迁移
+        let mut migrated: Vec<String> = report
+            .applied
+            .into_iter()
+            .flat_map(|(_, models)| models)
+            .collect();
+        migrated.sort();
+        migrated.dedup();
+        notify_corrected(&migrated);
+    }
+    Ok(())
+}
+
+fn migrate_original_pdf(args: &[String]) -> Result<(), String> {
+    let models = models_arg(args)?;
+    let migrated = migrate_original_pdfs(&models).map_err(|e| e.to_string())?;
+    println!("已为 {} 条模具记录补全原始图纸", migrated.len());
+    notify_corrected(&migrated);
+    Ok(())
+}
+
+fn shard(args: &[String]) -> Result<(), String> {
+    let models = models_arg(args)?;
+    let moved = shard_models(&models).map_err(|e| e.to_string())?;
+    println!("已把 {} 条模具记录移动到年份/客户分片", moved.len());
+    notify_corrected(&moved);
+    Ok(())
+}
+
+fn assign(args: &[String]) -> Result<(), String> {
+    let models = models_arg(args)?;
+    let assigned = assign_ids(&models).map_err(|e| e.to_string())?;
+    println!("已为 {} 条模具记录分配id和slug", assigned.len());
+    notify_corrected(&assigned);
+    Ok(())
+}
+
+fn backfill(args: &[String]) -> Result<(), String> {
+    let models = models_arg(args)?;
+    let backfilled = backfill_timestamps(&models).map_err(|e| e.to_string())?;
+    println!("已为 {} 条模具记录补全提取时间", backfilled.len());
+    notify_corrected(&backfilled);
+    Ok(())
+}
+
+/// 默认与配置的模型比较，每行输出一个来源名称，最旧的提示词版本提取的在前，供重新提取使用
+fn stale_extractions(args: &[String]) -> Result<(), String> {
+    let mut models = models_dir();
+    let mut model_name = CONFIG.ai.api.as_ref().map(|api| api.model_name.clone());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--models" => models = PathBuf::from(args.next().ok_or(USAGE)?),
+            "--model" => model_name = Some(args.next().ok_or(USAGE)?.clone()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let store = ModelStore::open(&models, &CONFIG.storage).map_err(|e| e.to_string())?;
+    let stale = store.stale_extractions(&PROMPTS, model_name.as_deref());
+    for name in &stale {
+        println!("{}", name);
+    }
+    eprintln!("共 {} 条模具记录需要重新提取", stale.len());
+    Ok(())
+}
+
+fn remove_copies(args: &[String]) -> Result<(), String> {
+    let dir = match args {
+        [] => upload_dir(),
+        [dir] => PathBuf::from(dir),
+        _ => return Err(USAGE.to_string()),
+    };
+    let removed = remove_upload_copies(&dir).map_err(|e| e.to_string())?;
+    for path in &removed {
+        println!("{}", path.display());
+    }
+    println!("已删除 {} 个上传文件的PDF副本", removed.len());
+    Ok(())
+}
+
+fn import_status(args: &[String]) -> Result<(), String> {
+    let (csv, models) = match args {
+        [csv, rest @ ..] => (PathBuf::from(csv), models_arg(rest)?),
+        [] => return Err(USAGE.to_string()),
+    };
+    let content = std::fs::read_to_string(&csv).map_err(|e| e.to_string())?;
+    let (rows, invalid) = parse_csv(&content);
+    for (line, text) in &invalid {
+        eprintln!("第{}行无法解析: {}", line, text);
+    }
+    // CSV中可以使用来源名称、id或slug
+    let store = ModelStore::open(&models, &CONFIG.storage).map_err(|e| e.to_string())?;
+    let mut statuses = HashMap::new();
+    for row in rows {
+        match store.get(&row.key) {
+            Some(model) => {
+                statuses.insert(model.source_directory_name.clone(), row.status);
+            }
+            None => eprintln!("找不到模具: {}", row.key),
+        }
+    }
+    let updated = open_repository(&models, &CONFIG.storage)
+        .and_then(|repository| repository.set_statuses(&store, &statuses))
+        .map_err(|e| e.to_string())?;
+    println!("已更新 {} 条模具记录的状态", updated.len());
+    notify_corrected(&updated);
+    Ok(())
+}
+
+fn import_sqlite(args: &[String]) -> Result<(), String> {
+    let mut models = models_dir();
+    let mut sqlite_path = CONFIG.storage.sqlite_path.clone();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--models" => models = PathBuf::from(args.next().ok_or(USAGE)?),
+            "--db" => sqlite_path = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let config = StorageConfig {
+        backend: StorageBackend::Sqlite,
+        sqlite_path,
+        ..CONFIG.storage.clone()
+    };
+    let imported = open_repository(&models, &config)
+        .and_then(|db| import_json(&models, db.as_ref()))
+        .map_err(|e| e.to_string())?;
+    println!(
+        "已导入 {} 条模具记录，设置 MATERIAL_MODEL_BACKEND=sqlite 后使用数据库",
+        imported
+    );
+    Ok(())
+}
+
+/// 当前的模具数据库和词表
+fn current_snapshot(models: &std::path::Path) -> Result<Snapshot, String> {
+    let store = ModelStore::open(models, &CONFIG.storage).map_err(|e| e.to_string())?;
+    let taxonomy = Taxonomy::load(&taxonomy_dir()).map_err(|e| e.to_string())?;
+    Ok(Snapshot::take(&today(), &store, &taxonomy))
+}
+
+fn snapshot_corpus(args: &[String]) -> Result<(), String> {
+    let snapshot = current_snapshot(&models_arg(args)?)?;
+    let path = snapshot.save(&snapshots_dir()).map_err(|e| e.to_string())?;
+    println!(
+        "已保存 {} 条模具记录的快照到 {}",
+        snapshot.models.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn diff_snapshots(args: &[String]) -> Result<(), String> {
+    let find = |text: &String| -> Result<Snapshot, String> {
+        let date = parse_date(text).ok_or(USAGE)?;
+        Snapshot::find(&snapshots_dir(), date)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("{} 及之前没有快照", text))
+    };
+    let (from, to) = match args {
+        [from] => (find(from)?, current_snapshot(&models_dir())?),
+        [from, to] => (find(from)?, find(to)?),
+        _ => return Err(USAGE.to_string()),
+    };
+    println!("{}", SnapshotDiff::new(&from, &to).render());
+    Ok(())
+}
+
+/// 通知外部系统这些模具记录已修改
+fn notify_corrected(source_names: &[String]) {
+    let events: Vec<CorpusEvent> = source_names
+        .iter()
+        .map(|name| CorpusEvent::new(CorpusEventKind::Corrected, name.as_str()))
+        .collect();
+    match tokio::runtime::Runtime::new() {
+        Ok(runtime) => {
+            runtime.block_on(notify(&HTTP_CLIENT, &CONFIG.corpus_webhooks, &events));
+        }
+        Err(e) => eprintln!("无法通知模具库事件: {}", e),
+    }
+}
+
+fn check_environment() -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let report = runtime.block_on(doctor::run(&HTTP_CLIENT));
+    println!("{}", report.render());
+    if report.is_ready() {
+        Ok(())
+    } else {
+        Err("运行环境检查未通过".to_string())
+    }
+}
@@ -0,0 +1,82 @@
+//! 空白页检测
+//!
+//! PDF中常有空白的背面或只有边框的页面，发送给模型服务只会浪费token。
+//! 把页面缩小后统计深色像素的占比，低于`BlankPageConfig::min_ink_ratio`的视为空白页。
+use std::path::Path;
+
+use image::{DynamicImage, imageops::FilterType};
+
+use crate::{AnalyzerError, IResult, config::BlankPageConfig};
+
+/// 统计前把页面缩小到这个宽度，细线缩小后仍会留下浅灰色像素
+const SAMPLE_WIDTH: u32 = 600;
+
+/// 深色像素的占比
+pub fn ink_ratio(img: &DynamicImage, dark_threshold: u8) -> f32 {
+    let img = if img.width() > SAMPLE_WIDTH {
+        img.resize(SAMPLE_WIDTH, u32::MAX, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let gray = img.to_luma8();
+    let total = gray.pixels().len();
+    if total == 0 {
+        return 0.0;
+    }
+    let dark = gray.pixels().filter(|p| p.0[0] < dark_threshold).count();
+    dark as f32 / total as f32
+}
+
+/// 页面图片是否为空白页，不检测时总是false
+pub fn is_blank(path: &Path, config: &BlankPageConfig) -> IResult<bool> {
+    if config.min_ink_ratio <= 0.0 {
+        return Ok(false);
+    }
+    let img = image::open(path)
+        .map_err(|e| AnalyzerError::ImageError(format!("读取页面图片失败: {}", e)))?;
+    Ok(ink_ratio(&img, config.dark_threshold) < config.min_ink_ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+    use crate::paths::TempDir;
+
+    #[test]
+    fn detect_blank_pages() {
+        let dir = TempDir::new("blank");
+        let config = BlankPageConfig {
+            min_ink_ratio: 0.002,
+            dark_threshold: 200,
+        };
+
+        // 扫描件的纸张颜色不是纯白
+        let blank = RgbImage::from_pixel(1200, 800, Rgb([245, 243, 240]));
+        blank.save(dir.join("blank.png")).unwrap();
+        assert!(is_blank(&dir.join("blank.png"), &config).unwrap());
+
+        // 一条横线和一个标题栏
+        let mut drawing = blank.clone();
+        for x in 100..1100 {
+            for y in 400..404 {
+                drawing.put_pixel(x, y, Rgb([0, 0, 0]));
+            }
+        }
+        for x in 900..1150 {
+            for y in 700..760 {
+                drawing.put_pixel(x, y, Rgb([30, 30, 30]));
+            }
+        }
+        drawing.save(dir.join("drawing.png")).unwrap();
+        assert!(!is_blank(&dir.join("drawing.png"), &config).unwrap());
+
+        let disabled = BlankPageConfig {
+            min_ink_ratio: 0.0,
+            ..config
+        };
+        assert!(!is_blank(&dir.join("blank.png"), &disabled).unwrap());
+        assert!(is_blank(&dir.join("missing.png"), &config).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+//! 按内容寻址的PDF存储
+//!
+//! 上传的PDF按sha256保存为`data/blobs/<前两位>/<sha256>.pdf`，重复上传同一文件只增加引用计数，
+//! 引用计数保存在`data/blobs/refs.json`。任务记录中的`content_hash`可以用于缓存和去重，
+//! `refs.json`损坏时按任务记录中的`content_hash`重新计算引用计数。
+//!
+//! 之前的版本把每个上传文件复制为同目录下的`<名称>.pdf`且从不删除，
+//! 这些副本可以用`remove_upload_copies`清理。
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+
+use crate::{
+    IResult,
+    paths::{data_dir, write_atomic},
+};
+
+const REFS_FILE: &str = "refs.json";
+
+/// 保存好的文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blob {
+    /// 内容的sha256
+    pub hash: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobRef {
+    pub refs: u32,
+    pub size: u64,
+    pub created_at: String,
+}
+
+pub struct BlobStore {
+    dir: PathBuf,
+    refs: Mutex<HashMap<String, BlobRef>>,
+}
+
+/// PDF存储所在的数据目录
+pub fn blobs_dir() -> PathBuf {
+    data_dir().join("blobs")
+}
+
+impl BlobStore {
+    /// 加载引用计数，`refs.json`无法读取或解析时用`referenced`返回的任务记录中的
+    /// `content_hash`重新计算，每个引用计数一次，不会当作没有引用
+    pub fn open(dir: PathBuf, referenced: impl FnOnce() -> Vec<String>) -> Self {
+        let path = dir.join(REFS_FILE);
+        let refs = match read_refs(&path) {
+            Ok(refs) => refs,
+            Err(e) => {
+                error!(
+                    "读取引用计数失败 {}，按任务记录重新计算: {}",
+                    path.display(),
+                    e
+                );
+                let refs = rebuild_refs(&dir, referenced());
+                if let Err(e) = write_refs(&dir, &refs) {
+                    error!("保存引用计数失败: {}", e);
+                }
+                refs
+            }
+        };
+        Self {
+            dir,
+            refs: Mutex::new(refs),
+        }
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(format!("{}.pdf", hash))
+    }
+
+    /// 保存文件并增加引用计数，内容相同的文件只保存一份
+    pub fn put_file(&self, source: &Path) -> IResult<Blob> {
+        let hash = hash_file(source)?;
+        let path = self.blob_path(&hash);
+
+        let mut refs = self.refs.lock().unwrap();
+        match refs.get_mut(&hash) {
+            Some(blob) if path.exists() => {
+                blob.refs += 1;
+                info!("📎 文件已存在 {}，引用计数 {}", hash, blob.refs);
+            }
+            _ => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let size = std::fs::copy(source, &path)?;
+                refs.insert(
+                    hash.clone(),
+                    BlobRef {
+                        refs: 1,
+                        size,
+                        created_at: chrono::Local::now().to_rfc3339(),
+                    },
+                );
+            }
+        }
+        self.save(&refs);
+        Ok(Blob { hash, path })
+    }
+
+    /// 减少引用计数，没有引用时删除文件，返回文件是否被删除
+    pub fn release(&self, hash: &str) -> IResult<bool> {
+        let mut refs = self.refs.lock().unwrap();
+        let Some(blob) = refs.get_mut(hash) else {
+            return Ok(false);
+        };
+        blob.refs = blob.refs.saturating_sub(1);
+        let removed = blob.refs == 0;
+        if removed {
+            refs.remove(hash);
+            let path = self.blob_path(hash);
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        self.save(&refs);
+        Ok(removed)
+    }
+
+    /// 已经保存的文件增加一次引用，用于重新分析之前上传的文件，文件已删除时为None
+    pub fn retain(&self, hash: &str) -> Option<Blob> {
+        let mut refs = self.refs.lock().unwrap();
+        let path = self.blob_path(hash);
+        let blob = refs.get_mut(hash).filter(|_| path.exists())?;
+        blob.refs += 1;
+        self.save(&refs);
+        Some(Blob {
+            hash: hash.to_string(),
+            path,
+        })
+    }
+
+    pub fn refs(&self, hash: &str) -> u32 {
+        self.refs.lock().unwrap().get(hash).map_or(0, |b| b.refs)
+    }
+
+    fn save(&self, refs: &HashMap<String, BlobRef>) {
+        if let Err(e) = write_refs(&self.dir, refs) {
+            error!("保存引用计数失败: {}", e);
+        }
+    }
+}
+
+/// 没有`refs.json`时为空
+fn read_refs(path: &Path) -> IResult<HashMap<String, BlobRef>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 按引用的哈希重新计算引用计数，只包括文件仍然存在的
+fn rebuild_refs(dir: &Path, referenced: Vec<String>) -> HashMap<String, BlobRef> {
+    let mut refs: HashMap<String, BlobRef> = HashMap::new();
+    for hash in referenced {
+        if let Some(blob) = refs.get_mut(&hash) {
+            blob.refs += 1;
+            continue;
+        }
+        if hash.len() < 2 || !hash.is_ascii() {
+            continue;
+        }
+        let Ok(metadata) = std::fs::metadata(dir.join(&hash[..2]).join(format!("{}.pdf", hash)))
+        else {
+            continue;
+        };
+        refs.insert(
+            hash,
+            BlobRef {
+                refs: 1,
+                size: metadata.len(),
+                created_at: chrono::Local::now().to_rfc3339(),
+            },
+        );
+    }
+    info!("已按任务记录重新计算 {} 个文件的引用计数", refs.len());
+    refs
+}
+
+fn write_refs(dir: &Path, refs: &HashMap<String, BlobRef>) -> IResult<()> {
+    std::fs::create_dir_all(dir)?;
+    write_atomic(&dir.join(REFS_FILE), serde_json::to_string_pretty(refs)?)?;
+    Ok(())
+}
+
+/// 删除上传目录中旧版本留下的`<名称>.pdf`副本，返回删除的文件
+///
+/// 只删除同目录下存在无扩展名的原文件且内容相同的副本。
+pub fn remove_upload_copies(upload_dir: &Path) -> IResult<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let mut dirs = vec![upload_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if path.extension().and_then(|s| s.to_str()) != Some("pdf") {
+                continue;
+            }
+            let original = path.with_extension("");
+            if !original.is_file() || hash_file(&original)? != hash_file(&path)? {
+                continue;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => removed.push(path),
+                Err(e) => warn!("删除副本失败 {}: {}", path.display(), e),
+            }
+        }
+    }
+    info!("🧹 已删除 {} 个上传文件的PDF副本", removed.len());
+    Ok(removed)
+}
+
+/// 文件内容的sha256
+pub fn hash_file(path: &Path) -> IResult<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::TempDir;
+
+    #[test]
+    fn identical_uploads_share_a_blob() {
+        let root = TempDir::new("blobs");
+        let (a, b, c) = (root.join("a"), root.join("b"), root.join("c"));
+        std::fs::write(&a, b"%PDF-1.4 same").unwrap();
+        std::fs::write(&b, b"%PDF-1.4 same").unwrap();
+        std::fs::write(&c, b"%PDF-1.4 other").unwrap();
+
+        let store = BlobStore::open(root.join("blobs"), Vec::new);
+        let first = store.put_file(&a).unwrap();
+        let second = store.put_file(&b).unwrap();
+        let other = store.put_file(&c).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first.hash, other.hash);
+        assert_eq!(first.path.extension().unwrap(), "pdf");
+        assert_eq!(store.refs(&first.hash), 2);
+
+        // 引用计数持久化
+        let store = BlobStore::open(root.join("blobs"), Vec::new);
+        assert_eq!(store.refs(&first.hash), 2);
+        assert!(!store.release(&first.hash).unwrap());
+        assert!(first.path.exists());
+        assert_eq!(store.retain(&first.hash), Some(first.clone()));
+        assert_eq!(store.refs(&first.hash), 2);
+        assert!(!store.release(&first.hash).unwrap());
+        assert!(store.release(&first.hash).unwrap());
+        assert!(!first.path.exists());
+        assert!(!store.release(&first.hash).unwrap());
+        assert!(store.retain(&first.hash).is_none());
+    }
+
+    #[test]
+    fn rebuild_corrupt_refs_from_jobs() {
+        let root = TempDir::new("blobs");
+        let source = root.join("a");
+        std::fs::write(&source, b"%PDF-1.4 shared").unwrap();
+        let dir = root.join("blobs");
+        let store = BlobStore::open(dir.clone(), Vec::new);
+        let blob = store.put_file(&source).unwrap();
+        store.put_file(&source).unwrap();
+
+        // 写了一半的refs.json不会被当作没有引用
+        std::fs::write(dir.join(REFS_FILE), "{\"").unwrap();
+        let store = BlobStore::open(dir.clone(), || {
+            vec![blob.hash.clone(), blob.hash.clone(), "ffff".to_string()]
+        });
+        assert_eq!(store.refs(&blob.hash), 2);
+        assert_eq!(store.refs("ffff"), 0);
+        assert!(!store.release(&blob.hash).unwrap());
+        assert!(blob.path.exists());
+        // 重新计算的引用计数已经保存
+        let store = BlobStore::open(dir, Vec::new);
+        assert_eq!(store.refs(&blob.hash), 1);
+    }
+
+    #[test]
+    fn remove_copies_of_uploads() {
+        let root = TempDir::new("upload");
+        let day = root.join("2025/8/7");
+        std::fs::create_dir_all(&day).unwrap();
+        for (name, content) in [
+            ("03-jz", "%PDF-1.4 a"),
+            ("03-jz.pdf", "%PDF-1.4 a"),
+            // 内容不同或没有原文件的PDF保留
+            ("04-jz", "%PDF-1.4 b"),
+            ("04-jz.pdf", "%PDF-1.4 changed"),
+            ("05-jz.pdf", "%PDF-1.4 c"),
+        ] {
+            std::fs::write(day.join(name), content).unwrap();
+        }
+
+        let removed = remove_upload_copies(&root).unwrap();
+        assert_eq!(removed, [day.join("03-jz.pdf")]);
+        assert!(day.join("03-jz").exists());
+        assert!(day.join("04-jz.pdf").exists());
+        assert!(day.join("05-jz.pdf").exists());
+    }
+}
@@ -0,0 +1,212 @@
+//! 相似度的校准和分档
+//!
+//! 综合相似度大多集中在0.5到0.8之间，用户容易把72%理解为“基本一致”。报告在原始相似度旁边
+//! 给出分档(高度相似/较相似/参考)，分档按校准后的分数划分。校准曲线是分段线性的`[原始, 校准]`点列，
+//! 可以用`material-cli calibrate`从带有反馈的任务中学习：按最佳结果的原始相似度分段统计👍的比例，
+//! 再合并相邻分段使曲线单调不减(保序回归)。
+use serde::{Deserialize, Serialize};
+
+use crate::job::{JobRecord, Verdict};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreBand {
+    High,
+    Medium,
+    Reference,
+}
+
+impl ScoreBand {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::High => "高度相似",
+            Self::Medium => "较相似",
+            Self::Reference => "参考",
+        }
+    }
+}
+
+/// 校准曲线和分档界限
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    /// 分段线性曲线的点`[原始相似度, 校准后]`，按原始相似度从小到大排列，
+    /// 超出两端时取端点的值，为空时不换算
+    pub points: Vec<[f32; 2]>,
+    /// 校准后不低于这个值为高度相似
+    pub high: f32,
+    /// 校准后不低于这个值为较相似，其余为参考
+    pub medium: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            high: 0.85,
+            medium: 0.7,
+        }
+    }
+}
+
+impl Calibration {
+    /// 校准后的分数
+    pub fn calibrate(&self, raw: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return raw;
+        };
+        if raw <= first[0] {
+            return first[1];
+        }
+        if raw >= last[0] {
+            return last[1];
+        }
+        self.points
+            .windows(2)
+            .find(|w| raw <= w[1][0])
+            .map_or(raw, |w| {
+                let ([x0, y0], [x1, y1]) = (w[0], w[1]);
+                if x1 > x0 {
+                    y0 + (raw - x0) / (x1 - x0) * (y1 - y0)
+                } else {
+                    y1
+                }
+            })
+    }
+
+    pub fn band(&self, raw: f32) -> ScoreBand {
+        let calibrated = self.calibrate(raw);
+        if calibrated >= self.high {
+            ScoreBand::High
+        } else if calibrated >= self.medium {
+            ScoreBand::Medium
+        } else {
+            ScoreBand::Reference
+        }
+    }
+}
+
+/// 学习得到的曲线上的一点
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurvePoint {
+    /// 分段内最佳结果的平均原始相似度
+    pub raw: f32,
+    /// 分段内👍的比例
+    pub calibrated: f32,
+    pub samples: usize,
+}
+
+/// 从带有反馈的任务学习校准曲线，原始相似度按`bins`等分，
+/// 以最新的反馈作为任务的结论，没有结果或没有反馈的任务不计入
+pub fn learn(jobs: &[JobRecord], bins: usize) -> Vec<CurvePoint> {
+    let bins = bins.max(1);
+    let mut sums = vec![(0.0, 0.0, 0); bins];
+    for job in jobs {
+        let (Some(verdict), Some(best)) = (job.verdict(), job.matches.first()) else {
+            continue;
+        };
+        let raw = best.percentage.clamp(0.0, 1.0);
+        let bin = ((raw * bins as f32) as usize).min(bins - 1);
+        let good = if verdict == Verdict::Good { 1.0 } else { 0.0 };
+        sums[bin].0 += raw;
+        sums[bin].1 += good;
+        sums[bin].2 += 1;
+    }
+
+    // 保序回归：比例比前一段低时与前一段合并
+    let mut blocks: Vec<(f32, f32, usize)> = Vec::new();
+    for (raw, good, samples) in sums.into_iter().filter(|(.., n)| *n > 0) {
+        let mut block = (raw, good, samples);
+        while let Some(&(raw, good, samples)) = blocks.last() {
+            if good / samples as f32 <= block.1 / block.2 as f32 {
+                break;
+            }
+            blocks.pop();
+            block = (block.0 + raw, block.1 + good, block.2 + samples);
+        }
+        blocks.push(block);
+    }
+    blocks
+        .into_iter()
+        .map(|(raw, good, samples)| CurvePoint {
+            raw: raw / samples as f32,
+            calibrated: good / samples as f32,
+            samples,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diff::DiffResult,
+        job::{Feedback, JobKind},
+    };
+
+    #[test]
+    fn calibrate_scores() {
+        let calibration = Calibration::default();
+        assert_eq!(calibration.calibrate(0.72), 0.72);
+        assert_eq!(calibration.band(0.72), ScoreBand::Medium);
+
+        let calibration = Calibration {
+            points: vec![[0.5, 0.1], [0.7, 0.4], [0.9, 0.95]],
+            ..Calibration::default()
+        };
+        assert_eq!(calibration.calibrate(0.3), 0.1);
+        assert!((calibration.calibrate(0.6) - 0.25).abs() < 1e-6);
+        assert_eq!(calibration.calibrate(1.0), 0.95);
+        assert_eq!(calibration.band(0.72), ScoreBand::Reference);
+        assert_eq!(calibration.band(0.88), ScoreBand::High);
+        assert_eq!(ScoreBand::Medium.label(), "较相似");
+    }
+
+    #[test]
+    fn learn_from_feedback() {
+        let job = |percentage: f32, verdict: Verdict| {
+            let mut job = JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string());
+            job.matches = vec![DiffResult {
+                source_directory: Default::default(),
+                source_name: "ME121".to_string(),
+                company: None,
+                dimensions: None,
+                original_pdf: None,
+                exact_match: false,
+                status: None,
+                percentage,
+            }];
+            job.feedback = vec![Feedback {
+                verdict,
+                comment: None,
+                from_uid: 1,
+                created_at: String::new(),
+            }];
+            job
+        };
+        let mut jobs = vec![
+            job(0.55, Verdict::Bad),
+            job(0.56, Verdict::Bad),
+            job(0.65, Verdict::Good),
+            job(0.66, Verdict::Bad),
+            // 比前一段低，合并
+            job(0.75, Verdict::Bad),
+            job(0.95, Verdict::Good),
+        ];
+        // 没有反馈的任务不计入
+        jobs.push(JobRecord::new(JobKind::Pdf, 2, 1, "b.pdf".to_string()));
+
+        let points = learn(&jobs, 10);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].calibrated, 0.0);
+        assert_eq!(points[1].samples, 3);
+        assert!((points[1].calibrated - 1.0 / 3.0).abs() < 1e-6);
+        assert!((points[1].raw - 0.6867).abs() < 1e-3);
+        assert_eq!(points[2].calibrated, 1.0);
+        assert!(
+            points
+                .windows(2)
+                .all(|w| w[0].calibrated <= w[1].calibrated)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! 聊天中以`/`开头的指令
+
+use crate::{
+    config::{ReportLayout, Sampling},
+    job::Verdict,
+};
+
+/// 机器人支持的指令
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatCommand {
+    /// `/待归类`: 查看词表尚未覆盖的词条
+    Unrecognized,
+    /// `/feedback <任务id> good|bad [备注]`: 评价任务结果
+    Feedback {
+        job: String,
+        verdict: Verdict,
+        comment: Option<String>,
+    },
+    /// `/layout table|compact`: 选择结果的展示形式
+    Layout(ReportLayout),
+    /// `/sampling <temperature> [seed]`: 之后的分析使用的采样参数，`/sampling default`恢复默认
+    Sampling(Option<Sampling>),
+    /// `/force <任务id>`: 重新分析任务的PDF，相同的文件已经分析过时默认直接返回之前的结果
+    Force(String),
+    /// `/cancel`: 取消自己正在进行的分析和检索
+    Cancel,
+}
+
+impl ChatCommand {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let rest = text.strip_prefix('/').or_else(|| text.strip_prefix('／'))?;
+        let mut parts = rest.split_whitespace();
+        match parts.next()? {
+            "待归类" | "unrecognized" => Some(Self::Unrecognized),
+            "feedback" | "反馈" => {
+                let job = parts.next()?.to_string();
+                let verdict = Verdict::parse(parts.next()?)?;
+                let comment = parts.collect::<Vec<_>>().join(" ");
+                Some(Self::Feedback {
+                    job,
+                    verdict,
+                    comment: (!comment.is_empty()).then_some(comment),
+                })
+            }
+            "layout" | "布局" => ReportLayout::parse(parts.next()?).map(Self::Layout),
+            "sampling" | "采样" => {
+                let args = parts.collect::<Vec<_>>().join(" ");
+                match args.as_str() {
+                    "default" | "默认" => Some(Self::Sampling(None)),
+                    _ => Sampling::parse(&args).map(|s| Self::Sampling(Some(s))),
+                }
+            }
+            "force" | "重新分析" => Some(Self::Force(parts.next()?.to_string())),
+            "cancel" | "取消" => Some(Self::Cancel),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            ChatCommand::parse("/待归类"),
+            Some(ChatCommand::Unrecognized)
+        );
+        assert_eq!(
+            ChatCommand::parse(" ／unrecognized "),
+            Some(ChatCommand::Unrecognized)
+        );
+        assert_eq!(ChatCommand::parse("待归类"), None);
+        assert_eq!(ChatCommand::parse("/unknown"), None);
+    }
+
+    #[test]
+    fn parse_feedback() {
+        assert_eq!(
+            ChatCommand::parse("/feedback 1a2b3c4d bad 材料 识别错误"),
+            Some(ChatCommand::Feedback {
+                job: "1a2b3c4d".to_string(),
+                verdict: Verdict::Bad,
+                comment: Some("材料 识别错误".to_string()),
+            })
+        );
+        assert_eq!(
+            ChatCommand::parse("/反馈 1a2b3c4d 👍"),
+            Some(ChatCommand::Feedback {
+                job: "1a2b3c4d".to_string(),
+                verdict: Verdict::Good,
+                comment: None,
+            })
+        );
+        assert_eq!(ChatCommand::parse("/feedback 1a2b3c4d maybe"), None);
+        assert_eq!(ChatCommand::parse("/feedback"), None);
+    }
+
+    #[test]
+    fn parse_layout() {
+        assert_eq!(
+            ChatCommand::parse("/layout compact"),
+            Some(ChatCommand::Layout(ReportLayout::Compact))
+        );
+        assert_eq!(
+            ChatCommand::parse("/布局 表格"),
+            Some(ChatCommand::Layout(ReportLayout::Table))
+        );
+        assert_eq!(ChatCommand::parse("/layout wide"), None);
+    }
+
+    #[test]
+    fn parse_sampling() {
+        assert_eq!(
+            ChatCommand::parse("/sampling 0 42"),
+            Some(ChatCommand::Sampling(Some(Sampling {
+                temperature: 0.0,
+                seed: Some(42),
+            })))
+        );
+        assert_eq!(
+            ChatCommand::parse("/采样 0.3"),
+            Some(ChatCommand::Sampling(Some(Sampling {
+                temperature: 0.3,
+                seed: None,
+            })))
+        );
+        assert_eq!(
+            ChatCommand::parse("/sampling default"),
+            Some(ChatCommand::Sampling(None))
+        );
+        assert_eq!(ChatCommand::parse("/sampling 5"), None);
+        assert_eq!(ChatCommand::parse("/sampling 0 x"), None);
+        assert_eq!(ChatCommand::parse("/sampling"), None);
+    }
+
+    #[test]
+    fn parse_force() {
+        assert_eq!(
+            ChatCommand::parse("/force 1a2b3c4d"),
+            Some(ChatCommand::Force("1a2b3c4d".to_string()))
+        );
+        assert_eq!(
+            ChatCommand::parse("/重新分析 1a2b3c4d"),
+            Some(ChatCommand::Force("1a2b3c4d".to_string()))
+        );
+        assert_eq!(ChatCommand::parse("/force"), None);
+    }
+
+    #[test]
+    fn parse_cancel() {
+        assert_eq!(ChatCommand::parse("/cancel"), Some(ChatCommand::Cancel));
+        assert_eq!(ChatCommand::parse("／取消"), Some(ChatCommand::Cancel));
+        assert_eq!(ChatCommand::parse("cancel"), None);
+    }
+}
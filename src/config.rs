@@ -1,8 +1,238 @@
-use std::path::PathBuf;
+//! 服务的配置
+//!
+//! 部署相关的配置(监听地址、模型服务、SAM、HTTP客户端)以及webhook、评分方案、报告、排队、链接、
+//! PDF检查、转换、空白页检测和模具库变化通知等可以写在`material.toml`中，默认放在执行目录下，也可以通过`MATERIAL_CONFIG`指定，
+//! 文件中没有的项使用内置默认值，环境变量优先于文件，见`Config::load`。程序中使用全局的`CONFIG`。
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 
+use crate::{
+    calibration::Calibration,
+    paths::exe_dir,
+    secrets::{self, AI_API_KEY, BOT_API_KEY},
+    text_metric::TextMetric,
+};
+
+const CONFIG_FILE: &str = "material.toml";
+
+/// 非空的环境变量
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|s| !s.is_empty())
+}
+
+/// 开关类的环境变量，无法识别的值记录警告并忽略
+fn env_flag(key: &str) -> Option<bool> {
+    let value = env(key)?;
+    let flag = parse_flag(&value);
+    if flag.is_none() {
+        warn!("无法识别的开关值 {}=`{}`，已忽略", key, value);
+    }
+    flag
+}
+
+/// 不区分大小写的开关值，例如`1`、`true`、`on`、`0`、`False`、`OFF`
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// 从`material.toml`加载的配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub ai: AiConfig,
+    pub sam: SamConfig,
+    pub http: HttpConfig,
+    pub storage: StorageConfig,
+    pub rate_limit: RateLimitConfig,
+    /// VoceChat webhook的回复和校验
+    pub webhook: WebhookConfig,
+    pub auth: AuthConfig,
+    /// 相似度的校准曲线和分档
+    pub calibration: Calibration,
+    /// 长时间分析时的进度提醒
+    pub progress: ProgressConfig,
+    /// 相似度的评分方案
+    pub diff: DiffConfig,
+    /// 聊天结果报告
+    pub report: ReportConfig,
+    /// PDF分析的排队
+    pub queue: QueueConfig,
+    /// 结果中的图片和模型链接
+    pub links: ImageUrlConfig,
+    /// VoceChat机器人接口
+    pub bot: BotConfig,
+    /// 疑似重复模具的提醒
+    pub duplicate_alert: DuplicateAlertConfig,
+    /// 分析前的PDF检查限制
+    pub preflight: PreflightConfig,
+    /// PDF转换出的页面图片的大小限制
+    pub convert: ConvertConfig,
+    /// 文本提取前的空白页检测
+    pub blank_page: BlankPageConfig,
+    /// 模具库变化时的通知地址
+    pub corpus_webhooks: CorpusWebhookConfig,
+    /// 模型服务原始回复的存档
+    pub archive: ArchiveConfig,
+}
+
+impl Config {
+    /// 读取配置文件并应用环境变量，文件不存在时使用默认配置。
+    /// 文件存在但无法读取或解析时返回错误，不使用默认配置，否则接口密钥和webhook校验都会失效
+    pub fn load() -> Result<Self, String> {
+        let path = env("MATERIAL_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| exe_dir().join(CONFIG_FILE));
+        if !path.is_file() {
+            // 没有配置文件时环境变量同样生效，例如`MATERIAL_API_KEYS`
+            return Self::parse("");
+        }
+        let config = Self::from_file(&path)
+            .map_err(|e| format!("读取配置文件失败 {}: {}", path.display(), e))?;
+        info!("已加载配置文件 {}", path.display());
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&content)
+    }
+
+    /// 解析配置文件内容，环境变量覆盖文件中的值
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut config: Self = toml::from_str(content).map_err(|e| e.to_string())?;
+        config.apply_env();
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) {
+        self.server.apply_env();
+        self.ai.apply_env();
+        self.http.apply_env();
+        self.storage.apply_env();
+        self.rate_limit.apply_env();
+        self.webhook.apply_env();
+        self.auth.apply_env();
+        self.progress.apply_env();
+        self.diff.apply_env();
+        self.report.apply_env();
+        self.queue.apply_env();
+        self.links.apply_env();
+        self.bot.apply_env();
+        self.duplicate_alert.apply_env();
+        self.preflight.apply_env();
+        self.convert.apply_env();
+        self.blank_page.apply_env();
+        self.corpus_webhooks.apply_env();
+        self.archive.apply_env();
+    }
+}
+
+/// HTTP服务
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// 监听地址，环境变量`MATERIAL_BIND`，环境变量`MATERIAL_PORT`只替换其中的端口
+    pub bind: String,
+    /// 监听的Unix socket，设置后不监听`bind`，用于同一台机器上的反向代理(仅Unix系统)，
+    /// 环境变量`MATERIAL_UNIX_SOCKET`
+    pub unix_socket: Option<PathBuf>,
+    /// 所有路由的路径前缀，为空时挂在根路径下，环境变量`MATERIAL_BASE_PATH`
+    pub base_path: String,
+    /// HTTPS证书链(PEM)，与`tls_key`同时配置时`bind`使用HTTPS，环境变量`MATERIAL_TLS_CERT`
+    pub tls_cert: Option<PathBuf>,
+    /// HTTPS证书的私钥(PEM)，环境变量`MATERIAL_TLS_KEY`
+    pub tls_key: Option<PathBuf>,
+    /// 启动时检查运行环境，有错误时不启动，环境变量`MATERIAL_STARTUP_CHECK`
+    pub startup_check: bool,
+    /// 启动时进入只读模式，见`Maintenance`，环境变量`MATERIAL_READ_ONLY`
+    pub read_only: bool,
+}
+
+impl ServerConfig {
+    fn apply_env(&mut self) {
+        if let Some(bind) = env("MATERIAL_BIND") {
+            self.bind = bind;
+        }
+        if let Some(port) = env("MATERIAL_PORT") {
+            let host = self
+                .bind
+                .rsplit_once(':')
+                .map_or("0.0.0.0", |(host, _)| host);
+            self.bind = format!("{}:{}", host, port);
+        }
+        if let Some(socket) = env("MATERIAL_UNIX_SOCKET") {
+            self.unix_socket = Some(PathBuf::from(socket));
+        }
+        if let Some(base_path) = env("MATERIAL_BASE_PATH") {
+            self.base_path = base_path;
+        }
+        if let Some(cert) = env("MATERIAL_TLS_CERT") {
+            self.tls_cert = Some(PathBuf::from(cert));
+        }
+        if let Some(key) = env("MATERIAL_TLS_KEY") {
+            self.tls_key = Some(PathBuf::from(key));
+        }
+        if let Some(check) = env_flag("MATERIAL_STARTUP_CHECK") {
+            self.startup_check = check;
+        }
+        if let Some(read_only) = env_flag("MATERIAL_READ_ONLY") {
+            self.read_only = read_only;
+        }
+    }
+
+    /// 去掉首尾`/`的路径前缀，例如`material`
+    pub fn base_path(&self) -> &str {
+        self.base_path.trim_matches('/')
+    }
+
+    /// 配置的HTTPS证书和私钥，没有配置时为None，只配置了其中一个时返回错误
+    pub fn tls(&self) -> Result<Option<(&Path, &Path)>, String> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            (Some(_), None) => Err("配置了tls_cert但没有配置tls_key".to_string()),
+            (None, Some(_)) => Err("配置了tls_key但没有配置tls_cert".to_string()),
+        }
+    }
+
+    /// 路由在服务中的绝对路径，例如`files`对应`/material/files`
+    pub fn path(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        match self.base_path() {
+            "" => format!("/{}", path),
+            base => format!("/{}/{}", base, path),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            bind: "0.0.0.0:5800".to_string(),
+            unix_socket: None,
+            base_path: "material".to_string(),
+            tls_cert: None,
+            tls_key: None,
+            startup_check: false,
+            read_only: false,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SamConfig {
     /// sam python script path
     pub python_script_path: PathBuf,
@@ -27,19 +257,230 @@ impl Default for SamConfig {
     }
 }
 
+/// 模型服务的类型，决定请求地址、鉴权方式和请求格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiProvider {
+    /// 阿里云DashScope，`use_compatible_mode`选择OpenAI兼容格式或原生格式
+    #[default]
+    #[serde(rename = "dashscope")]
+    DashScope,
+    /// OpenAI或其他兼容OpenAI接口的服务
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Azure OpenAI，按部署名称请求，使用`api-key`请求头鉴权
+    #[serde(rename = "azure_openai")]
+    AzureOpenAi,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ApiConfig {
-    /// API key for cloud service
+    /// 环境变量`MATERIAL_AI_PROVIDER`为`dashscope`、`openai`或`azure_openai`
+    pub provider: ApiProvider,
+    /// API key for cloud service, resolved from `MATERIAL_AI_API_KEY` or the secrets file
     pub api_key: String,
-    /// API endpoint
+    /// API endpoint，Azure OpenAI为资源地址，例如`https://<资源名>.openai.azure.com`
     pub endpoint: String,
     /// Model name for API analysis
     pub model_name: String,
-    /// Use compatible mode (OpenAI format) or native DashScope format
+    /// Use compatible mode (OpenAI format) or native DashScope format，只对DashScope有效
     pub use_compatible_mode: bool,
+    /// Azure OpenAI的部署名称，默认与`model_name`相同，环境变量`MATERIAL_AI_DEPLOYMENT`
+    pub deployment: Option<String>,
+    /// Azure OpenAI的接口版本，环境变量`MATERIAL_AI_API_VERSION`
+    pub api_version: String,
+    /// 额外的请求头，例如企业模型网关需要的`X-Org-Id`，
+    /// 环境变量`MATERIAL_AI_EXTRA_HEADERS`格式为`名称=值,名称=值`
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    /// 合并到请求参数中的额外参数，例如`top_p`、`seed`，
+    /// 环境变量`MATERIAL_AI_EXTRA_PARAMS`为JSON对象
+    #[serde(default)]
+    pub extra_params: serde_json::Map<String, serde_json::Value>,
+    /// 采样参数
+    #[serde(default)]
+    pub sampling: Sampling,
+    /// 价格，用于估算每个分析任务的费用
+    #[serde(default)]
+    pub pricing: Pricing,
+}
+
+impl ApiConfig {
+    /// 请求和响应是否使用OpenAI格式
+    pub fn openai_format(&self) -> bool {
+        self.provider != ApiProvider::DashScope || self.use_compatible_mode
+    }
+
+    /// 对话接口的地址
+    pub fn chat_url(&self) -> String {
+        let endpoint = self.endpoint.trim_end_matches('/');
+        match self.provider {
+            ApiProvider::DashScope if !self.use_compatible_mode => format!(
+                "{}/services/aigc/text-generation/generation",
+                endpoint.replace("/compatible-mode/v1", "")
+            ),
+            ApiProvider::DashScope | ApiProvider::OpenAi => {
+                format!("{}/chat/completions", endpoint)
+            }
+            ApiProvider::AzureOpenAi => format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                endpoint,
+                self.deployment.as_deref().unwrap_or(&self.model_name),
+                self.api_version
+            ),
+        }
+    }
+
+    fn apply_env(&mut self) {
+        match env("MATERIAL_AI_PROVIDER").as_deref() {
+            Some("dashscope") => self.provider = ApiProvider::DashScope,
+            Some("openai") => self.provider = ApiProvider::OpenAi,
+            Some("azure_openai") => self.provider = ApiProvider::AzureOpenAi,
+            Some(provider) => warn!("未知的模型服务 `{}`，使用 {:?}", provider, self.provider),
+            None => {}
+        }
+        if let Some(deployment) = env("MATERIAL_AI_DEPLOYMENT") {
+            self.deployment = Some(deployment);
+        }
+        if let Some(api_version) = env("MATERIAL_AI_API_VERSION") {
+            self.api_version = api_version;
+        }
+        if let Some(api_key) = secrets::get(&AI_API_KEY) {
+            self.api_key = api_key;
+        }
+        if let Some(endpoint) = env("MATERIAL_AI_ENDPOINT") {
+            self.endpoint = endpoint;
+        }
+        if let Some(model_name) = env("MATERIAL_AI_MODEL") {
+            self.model_name = model_name;
+        }
+        if let Some(headers) = env("MATERIAL_AI_EXTRA_HEADERS") {
+            self.extra_headers = headers
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .collect();
+        }
+        if let Some(params) =
+            env("MATERIAL_AI_EXTRA_PARAMS").and_then(|s| serde_json::from_str(&s).ok())
+        {
+            self.extra_params = params;
+        }
+        self.sampling.apply_env();
+        self.pricing.apply_env();
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            provider: ApiProvider::DashScope,
+            api_key: String::new(),
+            endpoint: "https://dashscope.aliyuncs.com/compatible-mode/v1".to_string(),
+            model_name: "qwen-vl-max".to_string(),
+            use_compatible_mode: true,
+            deployment: None,
+            api_version: "2024-10-21".to_string(),
+            extra_headers: HashMap::new(),
+            extra_params: serde_json::Map::new(),
+            sampling: Sampling::default(),
+            pricing: Pricing::default(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 模型的采样参数，固定`seed`并使用低`temperature`时重新分析可以复现相同的结果
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sampling {
+    /// 默认0.1，环境变量`MATERIAL_AI_TEMPERATURE`
+    pub temperature: f32,
+    /// 部分模型服务支持，为空时不传，环境变量`MATERIAL_AI_SEED`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Sampling {
+    /// 解析`<temperature> [seed]`，例如`0 42`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split_whitespace();
+        let temperature: f32 = parts.next()?.parse().ok()?;
+        if !(0.0..=2.0).contains(&temperature) {
+            return None;
+        }
+        let seed = match parts.next() {
+            Some(seed) => Some(seed.parse().ok()?),
+            None => None,
+        };
+        parts.next().is_none().then_some(Self { temperature, seed })
+    }
+
+    fn apply_env(&mut self) {
+        if let Some(temperature) = env("MATERIAL_AI_TEMPERATURE").and_then(|s| s.parse().ok()) {
+            self.temperature = temperature;
+        }
+        if let Some(seed) = env("MATERIAL_AI_SEED").and_then(|s| s.parse().ok()) {
+            self.seed = Some(seed);
+        }
+    }
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        let mut sampling = Self {
+            temperature: 0.1,
+            seed: None,
+        };
+        sampling.apply_env();
+        sampling
+    }
+}
+
+/// 模型服务的价格，默认为0，只统计token用量不估算费用
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pricing {
+    /// 每百万输入token的价格，环境变量`MATERIAL_AI_PRICE_INPUT`
+    pub input_per_million: f64,
+    /// 每百万输出token的价格，环境变量`MATERIAL_AI_PRICE_OUTPUT`
+    pub output_per_million: f64,
+    /// 货币单位，只用于显示
+    pub currency: String,
+}
+
+impl Pricing {
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_million
+            + output_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+
+    fn apply_env(&mut self) {
+        if let Some(price) = env("MATERIAL_AI_PRICE_INPUT").and_then(|s| s.parse().ok()) {
+            self.input_per_million = price;
+        }
+        if let Some(price) = env("MATERIAL_AI_PRICE_OUTPUT").and_then(|s| s.parse().ok()) {
+            self.output_per_million = price;
+        }
+    }
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        let mut pricing = Self {
+            input_per_million: 0.0,
+            output_per_million: 0.0,
+            currency: "元".to_string(),
+        };
+        pricing.apply_env();
+        pricing
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AiConfig {
     /// Ollama base URL
     pub ollama_base: String,
@@ -53,22 +494,1226 @@ pub struct AiConfig {
     pub max_retries: u32,
     /// Request timeout in seconds
     pub timeout_seconds: u64,
+    /// 分析视图时同时发送的请求数，环境变量`MATERIAL_AI_MAX_CONCURRENT`
+    pub max_concurrent_requests: usize,
+    /// 提取图纸文字时同时处理的页数，环境变量`MATERIAL_AI_PAGE_CONCURRENCY`
+    pub page_concurrency: usize,
+    /// 每页提取(包括重试)的最长时间，超时记为这一页的错误，继续处理其他页，
+    /// 环境变量`MATERIAL_AI_PAGE_TIMEOUT`
+    pub page_timeout_seconds: u64,
+    /// 远程接口重试都失败时改用本地Ollama模型，反之亦然，环境变量`MATERIAL_AI_FALLBACK`
+    pub fallback: bool,
+    /// 提取后由模型把技术要求整理为标签，失败时使用关键词规则，
+    /// 环境变量`MATERIAL_AI_NORMALIZE_TAGS`
+    pub normalize_tags: bool,
+}
+
+impl AiConfig {
+    fn apply_env(&mut self) {
+        if let Some(ollama_base) = env("MATERIAL_OLLAMA_URL") {
+            self.ollama_base = ollama_base;
+        }
+        if let Some(local_model) = env("MATERIAL_LOCAL_MODEL") {
+            self.local_model = local_model;
+        }
+        if let Some(timeout) = env("MATERIAL_AI_TIMEOUT").and_then(|s| s.parse().ok()) {
+            self.timeout_seconds = timeout;
+        }
+        if let Some(max) = env("MATERIAL_AI_MAX_CONCURRENT").and_then(|s| s.parse().ok()) {
+            self.max_concurrent_requests = max;
+        }
+        if let Some(pages) = env("MATERIAL_AI_PAGE_CONCURRENCY").and_then(|s| s.parse().ok()) {
+            self.page_concurrency = pages;
+        }
+        if let Some(timeout) = env("MATERIAL_AI_PAGE_TIMEOUT").and_then(|s| s.parse().ok()) {
+            self.page_timeout_seconds = timeout;
+        }
+        if let Some(fallback) = env_flag("MATERIAL_AI_FALLBACK") {
+            self.fallback = fallback;
+        }
+        if let Some(normalize) = env_flag("MATERIAL_AI_NORMALIZE_TAGS") {
+            self.normalize_tags = normalize;
+        }
+        if let Some(api) = self.api.as_mut() {
+            api.apply_env();
+        }
+    }
 }
 
 impl Default for AiConfig {
     fn default() -> Self {
-        Self {
+        let mut config = Self {
             ollama_base: "http://localhost:11434".to_string(),
             local_model: "qwen2.5vl:7b".to_string(),
-            api: Some(ApiConfig {
-                api_key: "sk-c725815640934b548a829fc8be7a4ce5".to_string(),
-                endpoint: "https://dashscope.aliyuncs.com/compatible-mode/v1".to_string(),
-                model_name: "qwen-vl-max".to_string(),
-                use_compatible_mode: true,
-            }),
+            api: Some(ApiConfig::default()),
             fast_mode: false,
             max_retries: 3,
             timeout_seconds: 300,
+            max_concurrent_requests: 4,
+            page_concurrency: 2,
+            page_timeout_seconds: 600,
+            fallback: false,
+            normalize_tags: false,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// VoceChat机器人接口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    /// VoceChat服务器地址，环境变量`MATERIAL_VOCECHAT_URL`
+    pub base_url: String,
+    /// 机器人的API key，见`secrets::BOT_API_KEY`
+    pub api_key: String,
+    /// 可以评价其他用户的任务的管理员uid，环境变量`MATERIAL_ADMIN_UIDS`，逗号分隔
+    pub admin_uids: Vec<u64>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            base_url: "https://huateng.voce.chat".to_string(),
+            api_key: String::new(),
+            admin_uids: Vec::new(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+impl BotConfig {
+    fn apply_env(&mut self) {
+        if let Some(base_url) = env("MATERIAL_VOCECHAT_URL") {
+            self.base_url = base_url;
+        }
+        if let Some(api_key) = secrets::get(&BOT_API_KEY) {
+            self.api_key = api_key;
+        }
+        if let Some(uids) = env("MATERIAL_ADMIN_UIDS") {
+            self.admin_uids = uids
+                .split(',')
+                .filter_map(|uid| uid.trim().parse().ok())
+                .collect();
+        }
+    }
+
+    /// 用户是否可以评价任务，只有上传者和管理员可以
+    pub fn can_review(&self, owner_uid: u64, from_uid: u64) -> bool {
+        owner_uid == from_uid || self.admin_uids.contains(&from_uid)
+    }
+
+    /// 机器人接口地址，`path`以`/`开头
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+}
+
+/// VoceChat webhook请求的校验方式，密钥见`secrets::WEBHOOK_SECRET`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum WebhookVerification {
+    /// 不校验
+    #[default]
+    None,
+    /// 请求头的值与密钥相同
+    Token,
+    /// 请求头为`hex(hmac_sha256(secret, body))`，可以带`sha256=`前缀
+    Hmac,
+    /// 无法识别的校验方式，拒绝所有请求，启动检查时报错，不会当作不校验
+    Unknown(String),
+}
+
+impl From<String> for WebhookVerification {
+    /// 不区分大小写
+    fn from(value: String) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "token" => Self::Token,
+            "hmac" => Self::Hmac,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<WebhookVerification> for String {
+    fn from(value: WebhookVerification) -> Self {
+        match value {
+            WebhookVerification::None => "none".to_string(),
+            WebhookVerification::Token => "token".to_string(),
+            WebhookVerification::Hmac => "hmac".to_string(),
+            WebhookVerification::Unknown(value) => value,
+        }
+    }
+}
+
+/// VoceChat webhook的回复和校验
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// 兼容模式，无法处理的请求也回复200，VoceChat对非200的回复会重复投递，
+    /// 环境变量`MATERIAL_WEBHOOK_COMPAT=0`时回复400/415/422
+    pub compat_status: bool,
+    /// 请求的校验方式，环境变量`MATERIAL_WEBHOOK_VERIFY`为`none`、`token`或`hmac`，不区分大小写，
+    /// 校验失败总是回复401，不受兼容模式影响
+    pub verification: WebhookVerification,
+    /// 携带密钥或签名的请求头，环境变量`MATERIAL_WEBHOOK_HEADER`，
+    /// 默认`token`方式为`X-Webhook-Token`，`hmac`方式为`X-Webhook-Signature`
+    pub header: Option<String>,
+}
+
+impl WebhookConfig {
+    fn apply_env(&mut self) {
+        if let Some(compat) = env_flag("MATERIAL_WEBHOOK_COMPAT") {
+            self.compat_status = compat;
+        }
+        if let Some(verification) = env("MATERIAL_WEBHOOK_VERIFY") {
+            self.verification = verification.into();
+        }
+        if let Some(header) = env("MATERIAL_WEBHOOK_HEADER") {
+            self.header = Some(header);
+        }
+    }
+
+    /// 携带密钥或签名的请求头，没有配置时取决于校验方式
+    pub fn header(&self) -> &str {
+        match (&self.header, &self.verification) {
+            (Some(header), _) => header,
+            (None, WebhookVerification::Hmac) => "X-Webhook-Signature",
+            (None, _) => "X-Webhook-Token",
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            compat_status: true,
+            verification: WebhookVerification::None,
+            header: None,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 结果中图片和模型链接的配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageUrlConfig {
+    /// 未配置签名密钥时使用的公开图片地址，`{path}`为相对上传目录的路径
+    pub public_url: String,
+    /// 本服务的对外地址，签名链接为`{base_url}/material/files/{path}`，
+    /// 路径前缀见`ServerConfig::base_path`，环境变量`MATERIAL_BASE_URL`
+    pub base_url: String,
+    /// 签名密钥，为空时生成公开链接，环境变量`MATERIAL_URL_SECRET`
+    pub signing_secret: Option<String>,
+    /// 签名链接的有效期(秒)
+    pub ttl_seconds: u64,
+    /// 查看模型的页面地址，`{base}`为比较页面前端的地址，`{name}`为模型名称，
+    /// 环境变量`MATERIAL_COMPARE_URL`
+    pub compare_url: String,
+    /// 比较页面前端的地址，环境变量`MATERIAL_COMPARE_BASE_URL`
+    pub compare_base_url: String,
+    /// 按VoceChat域名区分的前端地址，例如测试环境和生产环境使用不同的前端，
+    /// 环境变量`MATERIAL_TENANT_COMPARE_BASE_URLS`格式为`域名=地址,域名=地址`，覆盖同名的域名
+    pub tenant_compare_base_urls: HashMap<String, String>,
+}
+
+impl ImageUrlConfig {
+    fn apply_env(&mut self) {
+        if let Some(base_url) = env("MATERIAL_BASE_URL") {
+            self.base_url = base_url;
+        }
+        if let Some(secret) = env("MATERIAL_URL_SECRET") {
+            self.signing_secret = Some(secret);
+        }
+        if let Some(compare_url) = env("MATERIAL_COMPARE_URL") {
+            self.compare_url = compare_url;
+        }
+        if let Some(compare_base_url) = env("MATERIAL_COMPARE_BASE_URL") {
+            self.compare_base_url = compare_base_url;
+        }
+        if let Some(tenants) = env("MATERIAL_TENANT_COMPARE_BASE_URLS") {
+            self.tenant_compare_base_urls.extend(
+                tenants
+                    .split(',')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(domain, url)| (domain.trim().to_string(), url.trim().to_string())),
+            );
+        }
+    }
+}
+
+impl Default for ImageUrlConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            public_url: "https://huateng.voce.chat/api/resource/file?file_path={path}".to_string(),
+            base_url: "http://localhost:5800".to_string(),
+            signing_secret: None,
+            ttl_seconds: 7 * 24 * 3600,
+            compare_url: "{base}/#/compare?file_path={name}".to_string(),
+            compare_base_url: "http://45.76.31.59:3009".to_string(),
+            tenant_compare_base_urls: HashMap::new(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 聊天中结果的展示形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportLayout {
+    /// 带预览图的表格
+    #[default]
+    Table,
+    /// 每个结果一段的紧凑列表，适合不能正常显示宽表格的客户端
+    Compact,
+}
+
+impl ReportLayout {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "table" | "表格" => Some(Self::Table),
+            "compact" | "list" | "列表" | "紧凑" => Some(Self::Compact),
+            _ => None,
+        }
+    }
+}
+
+/// 没有指定时使用的评分方案
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// 相似度的评分方案，不同团队关注的项目不同，例如模具部门看重尺寸，采购看重材料，
+/// 配置文件中没有的项使用默认方案的值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringProfile {
+    /// 模具类型和材料相似度的权重，两者之和一般为1
+    pub model_type_weight: f32,
+    pub material_weight: f32,
+    /// 双方都有表面处理、皮纹或钢材硬度要求时，这些要求在相似度中所占的权重
+    pub finish_weight: f32,
+    /// 双方都有尺寸时，尺寸在相似度中所占的权重
+    pub dimension_weight: f32,
+    /// 相似度不超过这个值的结果不列出
+    pub min_percentage: f32,
+    /// 模具类型和材料名称的文本相似度度量
+    pub text_metric: TextMetric,
+}
+
+impl ScoringProfile {
+    /// 检查权重和阈值的范围
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, weight) in [
+            ("model_type_weight", self.model_type_weight),
+            ("material_weight", self.material_weight),
+            ("finish_weight", self.finish_weight),
+            ("dimension_weight", self.dimension_weight),
+        ] {
+            if !(0.0..=1.0).contains(&weight) {
+                return Err(format!("{} 应在0到1之间: {}", name, weight));
+            }
+        }
+        if self.model_type_weight + self.material_weight <= 0.0 {
+            return Err("model_type_weight 和 material_weight 不能都为0".to_string());
+        }
+        if !(0.0..1.0).contains(&self.min_percentage) {
+            return Err(format!(
+                "min_percentage 应不小于0且小于1: {}",
+                self.min_percentage
+            ));
+        }
+        Ok(())
+    }
+
+    /// 模具类型和材料相似度的加权和
+    pub fn weighted(&self, model_type: f32, material: f32) -> f32 {
+        model_type * self.model_type_weight + material * self.material_weight
+    }
+}
+
+impl Default for ScoringProfile {
+    fn default() -> Self {
+        Self {
+            model_type_weight: 0.3,
+            material_weight: 0.7,
+            finish_weight: 0.15,
+            dimension_weight: 0.0,
+            min_percentage: 0.1,
+            text_metric: TextMetric::default(),
+        }
+    }
+}
+
+/// 相似度计算的配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiffConfig {
+    /// 按名称选择的评分方案，配置文件中的`[diff.profiles.<名称>]`会覆盖同名的内置方案，
+    /// 无效的方案在启动时记录并忽略，环境变量`MATERIAL_FINISH_WEIGHT`修改默认方案的`finish_weight`
+    #[serde(deserialize_with = "merge_profiles")]
+    pub profiles: BTreeMap<String, ScoringProfile>,
+    /// 相似度矩阵接口一次最多比较的模型数量
+    pub max_matrix_models: usize,
+    /// 结果中保留已报废的模具(标注为已报废)，环境变量`MATERIAL_INCLUDE_SCRAPPED`
+    pub include_scrapped: bool,
+}
+
+impl DiffConfig {
+    fn apply_env(&mut self) {
+        if let Some(include) = env_flag("MATERIAL_INCLUDE_SCRAPPED") {
+            self.include_scrapped = include;
+        }
+        if let Some(weight) = env("MATERIAL_FINISH_WEIGHT").and_then(|s| s.parse::<f32>().ok()) {
+            match self.profiles.get_mut(DEFAULT_PROFILE) {
+                Some(profile) if (0.0..=1.0).contains(&weight) => profile.finish_weight = weight,
+                Some(_) => warn!("MATERIAL_FINISH_WEIGHT 应在0到1之间，已忽略: {}", weight),
+                None => {}
+            }
+        }
+    }
+
+    /// 名称对应的评分方案，忽略大小写，None时使用默认方案
+    pub fn profile(&self, name: Option<&str>) -> Option<&ScoringProfile> {
+        let name = name.map_or(DEFAULT_PROFILE.to_string(), |n| n.trim().to_lowercase());
+        self.profiles.get(&name)
+    }
+
+    /// 没有指定方案时使用的评分方案，包括配置文件对`default`的修改
+    pub fn default_profile(&self) -> ScoringProfile {
+        self.profile(None).cloned().unwrap_or_default()
+    }
+
+    /// 可选的方案名称，用于错误提示
+    pub fn profile_names(&self) -> String {
+        self.profiles
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 内置方案加上配置文件中的方案，名称不区分大小写
+fn merge_profiles<'de, D>(deserializer: D) -> Result<BTreeMap<String, ScoringProfile>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let custom = BTreeMap::<String, toml::Value>::deserialize(deserializer)?;
+    let mut profiles = builtin_profiles();
+    for (name, value) in custom {
+        let profile = value
+            .try_into::<ScoringProfile>()
+            .map_err(|e| e.to_string())
+            .and_then(|profile| profile.validate().map(|_| profile));
+        match profile {
+            Ok(profile) => {
+                profiles.insert(name.trim().to_lowercase(), profile);
+            }
+            Err(e) => warn!("评分方案 `{}` 无效，已忽略: {}", name, e),
+        }
+    }
+    Ok(profiles)
+}
+
+fn builtin_profiles() -> BTreeMap<String, ScoringProfile> {
+    let base = ScoringProfile::default();
+    BTreeMap::from([
+        (
+            "tooling".to_string(),
+            ScoringProfile {
+                model_type_weight: 0.5,
+                material_weight: 0.5,
+                dimension_weight: 0.4,
+                ..base.clone()
+            },
+        ),
+        (
+            "purchasing".to_string(),
+            ScoringProfile {
+                model_type_weight: 0.1,
+                material_weight: 0.9,
+                finish_weight: 0.0,
+                min_percentage: 0.2,
+                ..base.clone()
+            },
+        ),
+        (DEFAULT_PROFILE.to_string(), base),
+    ])
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            profiles: builtin_profiles(),
+            max_matrix_models: 50,
+            include_scrapped: false,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 聊天结果报告的配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    /// 报告中最多列出的结果数量
+    pub max_results: usize,
+    /// 每个结果最多展示的预览页数，环境变量`MATERIAL_PREVIEW_PAGES`
+    pub preview_pages: usize,
+    /// 用户没有选择时的展示形式，环境变量`MATERIAL_REPORT_LAYOUT`
+    pub layout: ReportLayout,
+}
+
+impl ReportConfig {
+    fn apply_env(&mut self) {
+        if let Some(pages) = env("MATERIAL_PREVIEW_PAGES").and_then(|s| s.parse().ok()) {
+            self.preview_pages = pages;
+        }
+        if let Some(layout) = env("MATERIAL_REPORT_LAYOUT").and_then(|s| ReportLayout::parse(&s)) {
+            self.layout = layout;
+        }
+    }
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            max_results: 10,
+            preview_pages: 2,
+            layout: ReportLayout::default(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 疑似重复模具的提醒，PDF分析的最佳结果达到`warn_threshold`时在报告开头提醒，
+/// 达到`notify_threshold`时同时通知主管所在的群组。已报废的模具不计入
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuplicateAlertConfig {
+    /// 报告开头提醒的原始相似度，环境变量`MATERIAL_DUPLICATE_THRESHOLD`
+    pub warn_threshold: f32,
+    /// 通知主管的原始相似度，环境变量`MATERIAL_DUPLICATE_NOTIFY_THRESHOLD`
+    pub notify_threshold: f32,
+    /// 接收通知的VoceChat群组，环境变量`MATERIAL_DUPLICATE_NOTIFY_GID`，None时不通知
+    pub supervisor_gid: Option<u64>,
+    /// 报告开头的提醒，`{name}`为模具名称，`{percentage}`为相似度
+    pub warning: String,
+    /// 发给主管的通知，另有`{file}`为提交的文件，`{uid}`为提交的用户，`{job}`为任务id
+    pub notification: String,
+}
+
+impl DuplicateAlertConfig {
+    fn apply_env(&mut self) {
+        if let Some(threshold) = env("MATERIAL_DUPLICATE_THRESHOLD").and_then(|s| s.parse().ok()) {
+            self.warn_threshold = threshold;
+        }
+        if let Some(threshold) =
+            env("MATERIAL_DUPLICATE_NOTIFY_THRESHOLD").and_then(|s| s.parse().ok())
+        {
+            self.notify_threshold = threshold;
+        }
+        if let Some(gid) = env("MATERIAL_DUPLICATE_NOTIFY_GID").and_then(|s| s.parse().ok()) {
+            self.supervisor_gid = Some(gid);
+        }
+    }
+}
+
+impl Default for DuplicateAlertConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            warn_threshold: 0.9,
+            notify_threshold: 0.95,
+            supervisor_gid: None,
+            warning: "⚠️ 可能已有相同模具: **{name}**, 相似度 {percentage}%，请先确认能否复用"
+                .to_string(),
+            notification: "⚠️ 用户 {uid} 提交的图纸 `{file}` 可能与已有模具 **{name}** 相同，\
+                           相似度 {percentage}%，任务 `{job}`"
+                .to_string(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 长时间分析时的进度提醒
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProgressConfig {
+    /// 提醒间隔(秒)，环境变量`MATERIAL_PROGRESS_INTERVAL`
+    pub interval_seconds: u64,
+    /// 最多提醒次数，环境变量`MATERIAL_PROGRESS_MAX_PINGS`
+    pub max_pings: u32,
+    /// 静默模式，不发送进度提醒，环境变量`MATERIAL_PROGRESS_QUIET`
+    pub quiet: bool,
+    /// 提醒内容，`{elapsed}`替换为已用时间，`{pages}`替换为提取进度(例如`，已提取 3/7 页`)
+    pub message: String,
+}
+
+impl ProgressConfig {
+    fn apply_env(&mut self) {
+        if let Some(interval) = env("MATERIAL_PROGRESS_INTERVAL").and_then(|s| s.parse().ok()) {
+            self.interval_seconds = interval;
+        }
+        if let Some(max_pings) = env("MATERIAL_PROGRESS_MAX_PINGS").and_then(|s| s.parse().ok()) {
+            self.max_pings = max_pings;
+        }
+        if let Some(quiet) = env_flag("MATERIAL_PROGRESS_QUIET") {
+            self.quiet = quiet;
+        }
+    }
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            interval_seconds: 15,
+            max_pings: 8,
+            quiet: false,
+            message: "⏳ 仍在分析中{pages}，已用时 {elapsed}，请稍等...".to_string(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 对外HTTP请求(模型接口、VoceChat)共用的客户端配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// 代理地址，例如工厂网络的`http://10.0.0.1:7890`
+    pub proxy: Option<String>,
+    /// 额外信任的CA证书(PEM)，用于内部VoceChat服务器的自签名证书
+    pub ca_cert: Option<PathBuf>,
+    /// 连接超时(秒)
+    pub connect_timeout_seconds: u64,
+    /// 整个请求的超时(秒)，模型接口较慢，需要足够长
+    pub timeout_seconds: u64,
+    /// 空闲连接保留时间(秒)
+    pub pool_idle_timeout_seconds: u64,
+}
+
+impl HttpConfig {
+    fn apply_env(&mut self) {
+        if let Some(proxy) = env("MATERIAL_HTTP_PROXY") {
+            self.proxy = Some(proxy);
+        }
+        if let Some(ca_cert) = env("MATERIAL_CA_CERT") {
+            self.ca_cert = Some(PathBuf::from(ca_cert));
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            proxy: None,
+            ca_cert: None,
+            connect_timeout_seconds: 10,
+            timeout_seconds: 600,
+            pool_idle_timeout_seconds: 90,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// webhook和JSON接口的限流，每个发送者(webhook的`from_uid`或接口请求的IP)一个令牌桶
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// 环境变量`MATERIAL_RATE_LIMIT=0`时关闭
+    pub enabled: bool,
+    /// 每个VoceChat用户连续发送的消息数
+    pub webhook_burst: u32,
+    /// 之后每分钟可以发送的消息数
+    pub webhook_per_minute: f64,
+    /// 每个IP连续请求JSON接口的次数
+    pub api_burst: u32,
+    pub api_per_minute: f64,
+    /// 在反向代理之后时，按`X-Forwarded-For`中的第一个地址区分来源，
+    /// 环境变量`MATERIAL_RATE_LIMIT_TRUST_PROXY`
+    pub trust_proxy: bool,
+    /// 超过限制时回复给用户的消息，`{seconds}`替换为需要等待的秒数，同一次限流只回复一次
+    pub message: String,
+}
+
+impl RateLimitConfig {
+    fn apply_env(&mut self) {
+        if let Some(enabled) = env_flag("MATERIAL_RATE_LIMIT") {
+            self.enabled = enabled;
+        }
+        if let Some(trust) = env_flag("MATERIAL_RATE_LIMIT_TRUST_PROXY") {
+            self.trust_proxy = trust;
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            enabled: true,
+            webhook_burst: 10,
+            webhook_per_minute: 6.0,
+            api_burst: 120,
+            api_per_minute: 120.0,
+            trust_proxy: false,
+            message: "⏳ 消息太频繁了，请{seconds}秒后再试".to_string(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 接口密钥的权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    /// 检索、比较和查询任务
+    Read,
+    /// 另外可以修改分类表和模具状态、提交分析和使用管理接口
+    Admin,
+}
+
+/// 一个接口密钥
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// 日志中显示的名称，例如使用这个密钥的系统
+    pub name: String,
+    pub key: String,
+    pub scope: ApiScope,
+}
+
+/// JSON接口的鉴权，请求头为`Authorization: Bearer <key>`，
+/// 没有配置密钥时只读接口不鉴权，管理接口停用
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// 环境变量`MATERIAL_API_KEYS`格式为`名称:权限:密钥`，用逗号分隔，追加在配置文件的密钥之后
+    pub keys: Vec<ApiKey>,
+}
+
+impl AuthConfig {
+    fn apply_env(&mut self) {
+        let Some(keys) = env("MATERIAL_API_KEYS") else {
+            return;
+        };
+        for item in keys.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let mut parts = item.splitn(3, ':');
+            let (Some(name), Some(scope), Some(key)) = (parts.next(), parts.next(), parts.next())
+            else {
+                warn!(
+                    "接口密钥格式应为 名称:权限:密钥，忽略 `{}`",
+                    item.split(':').next().unwrap_or_default()
+                );
+                continue;
+            };
+            let scope = match scope {
+                "read" => ApiScope::Read,
+                "admin" => ApiScope::Admin,
+                scope => {
+                    warn!(
+                        "接口密钥 {} 的权限 `{}` 无效，应为 read 或 admin",
+                        name, scope
+                    );
+                    continue;
+                }
+            };
+            self.keys.push(ApiKey {
+                name: name.to_string(),
+                key: key.to_string(),
+                scope,
+            });
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+}
+
+/// 模型记录的存储方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// 每个模型一个JSON文件
+    #[default]
+    Json,
+    /// SQLite数据库，需要`sqlite`功能
+    Sqlite,
+}
+
+/// 模型记录的存储
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// 环境变量`MATERIAL_MODEL_BACKEND`为`json`或`sqlite`
+    pub backend: StorageBackend,
+    /// 数据库文件，默认为`models/models.db`，环境变量`MATERIAL_MODEL_DB`
+    pub sqlite_path: Option<PathBuf>,
+    /// 检查记录变化的间隔(秒)，有变化时重新加载，0表示不检查，
+    /// 环境变量`MATERIAL_MODELS_RESCAN_SECONDS`
+    pub rescan_seconds: u64,
+    /// 启动时把模型记录迁移到当前的格式版本，见`schema_migration`，环境变量`MATERIAL_AUTO_MIGRATE`
+    pub auto_migrate: bool,
+}
+
+impl StorageConfig {
+    fn apply_env(&mut self) {
+        match env("MATERIAL_MODEL_BACKEND").as_deref() {
+            Some("json") => self.backend = StorageBackend::Json,
+            Some("sqlite") => self.backend = StorageBackend::Sqlite,
+            Some(backend) => warn!("未知的模型存储方式 `{}`，使用 {:?}", backend, self.backend),
+            None => {}
+        }
+        if let Some(path) = env("MATERIAL_MODEL_DB") {
+            self.sqlite_path = Some(PathBuf::from(path));
+        }
+        if let Some(seconds) = env("MATERIAL_MODELS_RESCAN_SECONDS").and_then(|s| s.parse().ok()) {
+            self.rescan_seconds = seconds;
+        }
+        if let Some(migrate) = env_flag("MATERIAL_AUTO_MIGRATE") {
+            self.auto_migrate = migrate;
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            backend: StorageBackend::Json,
+            sqlite_path: None,
+            rescan_seconds: 30,
+            auto_migrate: true,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// PDF分析的排队
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// 同时进行的分析数量，环境变量`MATERIAL_MAX_CONCURRENT`
+    pub max_concurrent: usize,
+    /// 检查排队位置的间隔(秒)，位置变化时更新提示消息
+    pub update_interval_seconds: u64,
+    /// 排队提示，`{position}`替换为排队位置
+    pub message: String,
+    /// 轮到任务时把排队提示改为这条消息
+    pub started_message: String,
+    /// 编辑bot消息的接口路径，`{mid}`替换为消息id，服务器地址见`BotConfig::base_url`
+    pub edit_path: String,
+    /// 只读模式下任务暂停时的提示
+    pub maintenance_message: String,
+}
+
+impl QueueConfig {
+    fn apply_env(&mut self) {
+        if let Some(max) = env("MATERIAL_MAX_CONCURRENT").and_then(|s| s.parse().ok()) {
+            self.max_concurrent = max;
+        }
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            max_concurrent: 2,
+            update_interval_seconds: 10,
+            message: "⏳ 当前分析任务较多，您的文件排在第{position}位，请稍等...".to_string(),
+            started_message: "📄 已轮到您的文件，正在分析中，请稍等...".to_string(),
+            edit_path: "/api/bot/edit/{mid}".to_string(),
+            maintenance_message: "🛠️ 系统维护中，任务已排队，维护结束后自动开始".to_string(),
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 模具库变化时的通知地址
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorpusWebhookConfig {
+    /// 通知地址，环境变量`MATERIAL_CORPUS_WEBHOOKS`，逗号分隔
+    pub urls: Vec<String>,
+    /// 签名密钥，为空时不签名，环境变量`MATERIAL_CORPUS_WEBHOOK_SECRET`
+    pub secret: Option<String>,
+    /// 每个地址的请求超时(秒)
+    pub timeout_seconds: u64,
+}
+
+impl CorpusWebhookConfig {
+    fn apply_env(&mut self) {
+        if let Some(urls) = env("MATERIAL_CORPUS_WEBHOOKS") {
+            self.urls = urls
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(secret) = env("MATERIAL_CORPUS_WEBHOOK_SECRET") {
+            self.secret = Some(secret);
         }
     }
 }
+
+impl Default for CorpusWebhookConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            urls: Vec::new(),
+            secret: None,
+            timeout_seconds: 10,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 分析前的PDF检查限制，避免误发的长文档占满分析队列
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreflightConfig {
+    /// 最多页数，环境变量`MATERIAL_MAX_PAGES`
+    pub max_pages: u32,
+    /// 所有页面渲染后的像素总数上限(百万像素)
+    pub max_megapixels: u64,
+    /// 每页的预计分析时间(秒)
+    pub seconds_per_page: u64,
+    /// 预计分析时间上限(秒)
+    pub max_estimated_seconds: u64,
+}
+
+impl PreflightConfig {
+    fn apply_env(&mut self) {
+        if let Some(max_pages) = env("MATERIAL_MAX_PAGES").and_then(|s| s.parse().ok()) {
+            self.max_pages = max_pages;
+        }
+    }
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            max_pages: 30,
+            max_megapixels: 600,
+            seconds_per_page: 20,
+            max_estimated_seconds: 900,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 文本提取前的空白页检测，空白页不发送给模型服务
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlankPageConfig {
+    /// 深色像素占比低于这个值的页面视为空白页，0表示不检测，环境变量`MATERIAL_MIN_INK_RATIO`
+    pub min_ink_ratio: f32,
+    /// 灰度低于这个值的像素视为深色
+    pub dark_threshold: u8,
+}
+
+impl BlankPageConfig {
+    fn apply_env(&mut self) {
+        if let Some(ratio) = env("MATERIAL_MIN_INK_RATIO").and_then(|s| s.parse().ok()) {
+            self.min_ink_ratio = ratio;
+        }
+    }
+}
+
+impl Default for BlankPageConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            min_ink_ratio: 0.002,
+            dark_threshold: 200,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// PDF转换出的页面图片的大小限制，300DPI的A0图纸不限制时一页有几十MB，
+/// 发送给模型服务时base64编码后更大
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConvertConfig {
+    /// 每页最多的像素数(百万)，超过时缩小，环境变量`MATERIAL_PAGE_MAX_MEGAPIXELS`
+    pub max_megapixels: u64,
+    /// 每页图片的大小上限(MB)，超过时降低JPEG质量，仍然超过时继续缩小，
+    /// 环境变量`MATERIAL_PAGE_MAX_MB`
+    pub max_megabytes: u64,
+    /// 同时保存的预览用图片的最大宽度
+    pub preview_width: u32,
+}
+
+impl ConvertConfig {
+    fn apply_env(&mut self) {
+        if let Some(megapixels) = env("MATERIAL_PAGE_MAX_MEGAPIXELS").and_then(|s| s.parse().ok()) {
+            self.max_megapixels = megapixels;
+        }
+        if let Some(megabytes) = env("MATERIAL_PAGE_MAX_MB").and_then(|s| s.parse().ok()) {
+            self.max_megabytes = megabytes;
+        }
+    }
+
+    pub fn max_pixels(&self) -> u64 {
+        self.max_megapixels * 1_000_000
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_megabytes * 1024 * 1024
+    }
+}
+
+impl Default for ConvertConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            max_megapixels: 40,
+            max_megabytes: 8,
+            preview_width: 1200,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+/// 模型服务原始回复的存档，超过保留时间或总大小时删除旧的存档
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    /// 是否保存原始回复，环境变量`MATERIAL_ARCHIVE_RESPONSES`
+    pub enabled: bool,
+    /// 保留天数，环境变量`MATERIAL_ARCHIVE_MAX_AGE_DAYS`
+    pub max_age_days: u64,
+    /// 所有任务的存档总大小上限(MB)，环境变量`MATERIAL_ARCHIVE_MAX_MB`
+    pub max_megabytes: u64,
+}
+
+impl ArchiveConfig {
+    fn apply_env(&mut self) {
+        if let Some(enabled) = env_flag("MATERIAL_ARCHIVE_RESPONSES") {
+            self.enabled = enabled;
+        }
+        if let Some(days) = env("MATERIAL_ARCHIVE_MAX_AGE_DAYS").and_then(|s| s.parse().ok()) {
+            self.max_age_days = days;
+        }
+        if let Some(megabytes) = env("MATERIAL_ARCHIVE_MAX_MB").and_then(|s| s.parse().ok()) {
+            self.max_megabytes = megabytes;
+        }
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_megabytes * 1024 * 1024
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        let mut config = Self {
+            enabled: true,
+            max_age_days: 30,
+            max_megabytes: 1024,
+        };
+        config.apply_env();
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config_file() {
+        let config = Config::parse(
+            r#"
+            [server]
+            bind = "127.0.0.1:8080"
+            base_path = "/material-test/"
+
+            [ai]
+            local_model = "qwen2.5vl:32b"
+            timeout_seconds = 120
+
+            [ai.api]
+            model_name = "qwen-vl-plus"
+
+            [storage]
+            backend = "sqlite"
+
+            [[auth.keys]]
+            name = "plm"
+            key = "k-read"
+            scope = "read"
+
+            [webhook]
+            verification = "hmac"
+
+            [calibration]
+            points = [[0.5, 0.1], [0.9, 0.95]]
+
+            [progress]
+            interval_seconds = 30
+
+            [report]
+            max_results = 5
+
+            [queue]
+            max_concurrent = 4
+
+            [blank_page]
+            dark_threshold = 180
+
+            [corpus_webhooks]
+            urls = ["https://plm.example.com/hooks/material"]
+
+            [links]
+            compare_base_url = "https://compare.example.com"
+
+            [diff.profiles.Quality]
+            dimension_weight = 0.6
+
+            [diff.profiles.tooling]
+            model_type_weight = 0.6
+            material_weight = 0.4
+
+            [diff.profiles.broken]
+            material_weight = 2.0
+
+            [diff.profiles.default]
+            finish_weight = 0.3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.bind, "127.0.0.1:8080");
+        assert_eq!(config.server.path("files"), "/material-test/files");
+        assert!(config.server.unix_socket.is_none());
+        assert_eq!(config.server.tls(), Ok(None));
+        let half = ServerConfig {
+            tls_cert: Some(PathBuf::from("cert.pem")),
+            ..ServerConfig::default()
+        };
+        assert!(half.tls().is_err());
+        assert_eq!(
+            ServerConfig {
+                base_path: String::new(),
+                ..ServerConfig::default()
+            }
+            .path("/api/v1"),
+            "/api/v1"
+        );
+        assert_eq!(config.ai.local_model, "qwen2.5vl:32b");
+        assert_eq!(config.ai.timeout_seconds, 120);
+        // 没有写的项使用默认值
+        assert_eq!(config.ai.max_retries, 3);
+        assert_eq!(config.ai.max_concurrent_requests, 4);
+        assert_eq!(config.ai.page_concurrency, 2);
+        assert_eq!(config.ai.page_timeout_seconds, 600);
+        assert!(!config.ai.fallback);
+        let api = config.ai.api.unwrap();
+        assert_eq!(api.model_name, "qwen-vl-plus");
+        assert!(api.use_compatible_mode);
+        assert_eq!(api.provider, ApiProvider::DashScope);
+        assert_eq!(
+            api.chat_url(),
+            "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions"
+        );
+        assert_eq!(config.http.connect_timeout_seconds, 10);
+        assert_eq!(config.storage.backend, StorageBackend::Sqlite);
+        assert!(config.storage.sqlite_path.is_none());
+        assert!(config.auth.enabled());
+        assert_eq!(config.auth.keys[0].scope, ApiScope::Read);
+        assert_eq!(config.webhook.verification, WebhookVerification::Hmac);
+        assert_eq!(config.webhook.header(), "X-Webhook-Signature");
+        assert!(config.webhook.compat_status);
+        // 不区分大小写，无法识别的校验方式不会当作不校验
+        let webhook: WebhookConfig = toml::from_str("verification = \"Token\"").unwrap();
+        assert_eq!(webhook.verification, WebhookVerification::Token);
+        let webhook: WebhookConfig = toml::from_str("verification = \"hmca\"").unwrap();
+        assert_eq!(
+            webhook.verification,
+            WebhookVerification::Unknown("hmca".to_string())
+        );
+        assert_eq!(config.calibration.points.len(), 2);
+        assert_eq!(config.calibration.high, 0.85);
+        assert_eq!(config.progress.interval_seconds, 30);
+        assert_eq!(config.progress.max_pings, 8);
+        assert_eq!(config.report.max_results, 5);
+        assert_eq!(config.report.preview_pages, 2);
+        assert_eq!(config.queue.max_concurrent, 4);
+        assert_eq!(config.queue.edit_path, "/api/bot/edit/{mid}");
+        assert_eq!(config.links.compare_base_url, "https://compare.example.com");
+        assert_eq!(config.preflight.max_pages, 30);
+        assert_eq!(config.blank_page.dark_threshold, 180);
+        assert_eq!(config.blank_page.min_ink_ratio, 0.002);
+        assert_eq!(config.corpus_webhooks.urls.len(), 1);
+        assert_eq!(config.corpus_webhooks.timeout_seconds, 10);
+        // 文件中的方案覆盖同名的内置方案，没有写的项使用默认方案的值，其他内置方案保留
+        let quality = config.diff.profile(Some("quality")).unwrap();
+        assert_eq!(quality.dimension_weight, 0.6);
+        assert_eq!(quality.material_weight, 0.7);
+        let tooling = config.diff.profile(Some("tooling")).unwrap();
+        assert_eq!(tooling.model_type_weight, 0.6);
+        assert_eq!(tooling.dimension_weight, 0.0);
+        assert!(config.diff.profile(Some("purchasing")).is_some());
+        assert!(config.diff.profile(None).is_some());
+        // 没有指定方案时使用配置文件修改后的默认方案
+        assert_eq!(config.diff.default_profile().finish_weight, 0.3);
+        assert_eq!(config.diff.default_profile().material_weight, 0.7);
+        // 无效的方案被忽略
+        assert!(config.diff.profile(Some("broken")).is_none());
+
+        assert!(Config::parse("[server]\nbind = 5800").is_err());
+
+        assert_eq!(parse_flag("False"), Some(false));
+        assert_eq!(parse_flag("OFF"), Some(false));
+        assert_eq!(parse_flag(" on "), Some(true));
+        assert_eq!(parse_flag("disabled"), None);
+
+        // Azure OpenAI按部署名称请求，OpenAI不受use_compatible_mode影响
+        let config = Config::parse(
+            r#"
+            [ai.api]
+            provider = "azure_openai"
+            endpoint = "https://factory.openai.azure.com/"
+            model_name = "gpt-4o"
+            deployment = "vision"
+            use_compatible_mode = false
+            "#,
+        )
+        .unwrap();
+        let mut api = config.ai.api.unwrap();
+        assert!(api.openai_format());
+        assert_eq!(
+            api.chat_url(),
+            "https://factory.openai.azure.com/openai/deployments/vision/chat/completions?api-version=2024-10-21"
+        );
+        api.provider = ApiProvider::OpenAi;
+        api.endpoint = "https://api.openai.com/v1".to_string();
+        assert_eq!(api.chat_url(), "https://api.openai.com/v1/chat/completions");
+        api.provider = ApiProvider::DashScope;
+        api.endpoint = "https://dashscope.aliyuncs.com/compatible-mode/v1".to_string();
+        assert!(!api.openai_format());
+        assert_eq!(
+            api.chat_url(),
+            "https://dashscope.aliyuncs.com/services/aigc/text-generation/generation"
+        );
+
+        // 只写seed时temperature使用默认值，不会使整个文件无效
+        let config = Config::parse("[ai.api.sampling]\nseed = 42\n").unwrap();
+        let sampling = config.ai.api.unwrap().sampling;
+        assert_eq!(sampling.seed, Some(42));
+        assert_eq!(sampling.temperature, Sampling::default().temperature);
+    }
+}
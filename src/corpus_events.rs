@@ -0,0 +1,138 @@
+//! 模具库变化的通知
+//!
+//! 模具记录新增、修改或删除时向配置的地址POST `{"events": [...]}`，
+//! 比较页面前端和PLM系统据此刷新缓存，不需要轮询。
+//! 配置了密钥时请求头`X-Material-Signature`为`hex(hmac_sha256(secret, body))`。
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+use crate::{CONFIG, HTTP_CLIENT, config::CorpusWebhookConfig};
+
+pub const SIGNATURE_HEADER: &str = "X-Material-Signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorpusEventKind {
+    /// 新增模具记录
+    Ingested,
+    /// 修改了模具记录或预览图位置
+    Corrected,
+    /// 删除模具记录
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusEvent {
+    pub event: CorpusEventKind,
+    pub source_name: String,
+    pub timestamp: String,
+}
+
+impl CorpusEvent {
+    pub fn new(event: CorpusEventKind, source_name: impl Into<String>) -> Self {
+        Self {
+            event,
+            source_name: source_name.into(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    events: &'a [CorpusEvent],
+}
+
+/// 请求体的签名
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 把事件发送到所有配置的地址，返回发送成功的地址数，失败只记录日志
+pub async fn notify(
+    client: &reqwest::Client,
+    config: &CorpusWebhookConfig,
+    events: &[CorpusEvent],
+) -> usize {
+    if events.is_empty() || config.urls.is_empty() {
+        return 0;
+    }
+    let body = match serde_json::to_vec(&Notification { events }) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("序列化模具库事件失败: {}", e);
+            return 0;
+        }
+    };
+    let mut delivered = 0;
+    for url in &config.urls {
+        let mut request = client
+            .post(url)
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(secret) = &config.secret {
+            request = request.header(SIGNATURE_HEADER, signature(secret, &body));
+        }
+        match request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => delivered += 1,
+            Err(e) => warn!("模具库事件通知失败 {}: {}", url, e),
+        }
+    }
+    info!(
+        "📣 已通知 {} 个事件到 {}/{} 个地址",
+        events.len(),
+        delivered,
+        config.urls.len()
+    );
+    delivered
+}
+
+/// 在后台用共用的客户端发送事件，可以在同步代码中调用，不在tokio运行时中时不发送
+pub fn spawn_notify(events: Vec<CorpusEvent>) {
+    if events.is_empty() {
+        return;
+    }
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(async move {
+                notify(&HTTP_CLIENT, &CONFIG.corpus_webhooks, &events).await;
+            });
+        }
+        Err(_) => warn!("不在异步运行时中，跳过 {} 个模具库事件的通知", events.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn event_payload() {
+        let events = [CorpusEvent::new(CorpusEventKind::Corrected, "ME121基座")];
+        let body = serde_json::to_value(Notification { events: &events }).unwrap();
+        assert_eq!(body["events"][0]["event"], "corrected");
+        assert_eq!(body["events"][0]["source_name"], "ME121基座");
+
+        assert_eq!(signature("secret", b"{}"), signature("secret", b"{}"));
+        assert_ne!(signature("secret", b"{}"), signature("other", b"{}"));
+
+        // 没有配置地址时不发送
+        let config = CorpusWebhookConfig {
+            urls: Vec::new(),
+            ..CorpusWebhookConfig::default()
+        };
+        assert_eq!(notify(&reqwest::Client::new(), &config, &events).await, 0);
+    }
+}
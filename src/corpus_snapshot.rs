@@ -0,0 +1,413 @@
+//! 模具数据库的快照和比较
+//!
+//! 每月的数据治理检查需要知道一段时间内新增、删除和修改了哪些模具记录，以及词表的变化。
+//! `material-cli snapshot-corpus`把当前的模具索引和词表保存为`data/snapshots/<日期>.json`，
+//! `material-cli diff-snapshots`或`GET /material/api/v1/admin/snapshots/diff`比较两个日期的快照。
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    IResult, diff::ModelJson, model_store::ModelStore, mold_status::MoldStatus, paths::data_dir,
+    taxonomy::Taxonomy,
+};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// 快照所在的数据目录
+pub fn snapshots_dir() -> PathBuf {
+    data_dir().join("snapshots")
+}
+
+/// 快照中的一条模具记录，只保留比较需要的字段
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub id: Option<String>,
+    pub model_type: Option<String>,
+    pub materials: Vec<String>,
+    pub company: Option<String>,
+    pub status: Option<MoldStatus>,
+    pub tags: Vec<String>,
+    /// 完整记录的sha256，上面的字段都没有变化时用于发现其他字段的修改
+    pub digest: String,
+}
+
+impl SnapshotEntry {
+    fn new(model: &ModelJson, status: Option<MoldStatus>) -> Self {
+        let record = serde_json::to_vec(model).unwrap_or_default();
+        Self {
+            id: model.id.clone(),
+            model_type: model.model_type.clone(),
+            materials: model.materials.clone(),
+            company: model.company.clone(),
+            status,
+            tags: model.tags.clone(),
+            digest: hex::encode(Sha256::digest(&record)),
+        }
+    }
+
+    /// 与之前的记录相比修改的字段
+    fn changed_fields(&self, before: &Self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.model_type != before.model_type {
+            fields.push("model_type");
+        }
+        if self.materials != before.materials {
+            fields.push("materials");
+        }
+        if self.company != before.company {
+            fields.push("company");
+        }
+        if self.status != before.status {
+            fields.push("status");
+        }
+        if self.tags != before.tags {
+            fields.push("tags");
+        }
+        if fields.is_empty() && self.digest != before.digest {
+            fields.push("record");
+        }
+        fields
+    }
+}
+
+/// 模具索引和词表在某一天的状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub date: String,
+    /// 按来源名称索引的模具记录
+    pub models: BTreeMap<String, SnapshotEntry>,
+    /// 模具类型分组及其写法
+    pub model_types: BTreeMap<String, BTreeSet<String>>,
+    /// 材料大类及其下的牌号
+    pub materials: BTreeMap<String, BTreeSet<String>>,
+    /// 词表没有覆盖的模具类型及其模型数
+    pub uncovered_types: BTreeMap<String, usize>,
+}
+
+impl Snapshot {
+    pub fn take(date: &str, store: &ModelStore, taxonomy: &Taxonomy) -> Self {
+        let mut snapshot = Self {
+            date: date.to_string(),
+            ..Default::default()
+        };
+        for model in store.grouped().values().flatten() {
+            let name = &model.source_directory_name;
+            snapshot
+                .models
+                .insert(name.clone(), SnapshotEntry::new(model, store.status(name)));
+            if let Some(model_type) = &model.model_type
+                && taxonomy.model_type_group(model_type).is_none()
+            {
+                *snapshot
+                    .uncovered_types
+                    .entry(model_type.clone())
+                    .or_default() += 1;
+            }
+        }
+        for group in &taxonomy.model_types {
+            snapshot
+                .model_types
+                .insert(group.name.clone(), group.aliases.iter().cloned().collect());
+        }
+        for family in &taxonomy.materials {
+            snapshot.materials.insert(
+                family.name.clone(),
+                family.grades.iter().map(|g| g.name.clone()).collect(),
+            );
+        }
+        snapshot
+    }
+
+    /// 保存为`<日期>.json`，同一天的快照会被覆盖
+    pub fn save(&self, dir: &Path) -> IResult<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.json", self.date));
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// 指定日期当天或之前最近的快照
+    pub fn find(dir: &Path, date: NaiveDate) -> IResult<Option<Self>> {
+        let mut dates: Vec<NaiveDate> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .flatten()
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    parse_date(name.strip_suffix(".json")?)
+                })
+                .filter(|d| *d <= date)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        dates.sort();
+        let Some(found) = dates.last() else {
+            return Ok(None);
+        };
+        let path = dir.join(format!("{}.json", found.format(DATE_FORMAT)));
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+}
+
+/// 今天的日期，快照的文件名
+pub fn today() -> String {
+    chrono::Local::now().format(DATE_FORMAT).to_string()
+}
+
+/// `YYYY-MM-DD`格式的日期
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text.trim(), DATE_FORMAT).ok()
+}
+
+/// 修改过的模具记录
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedModel {
+    pub source_name: String,
+    pub fields: Vec<&'static str>,
+}
+
+/// 一个分组中增加和删除的写法或牌号
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupChange {
+    pub name: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// 模型数的变化
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CountChange {
+    pub name: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// 两个快照之间的差异
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SnapshotDiff {
+    pub from: String,
+    pub to: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ChangedModel>,
+    /// 模具类型词表的变化，新增和删除的分组也列在这里
+    pub model_types: Vec<GroupChange>,
+    /// 材料词表的变化
+    pub materials: Vec<GroupChange>,
+    /// 词表没有覆盖的模具类型的模型数变化
+    pub uncovered_types: Vec<CountChange>,
+}
+
+impl SnapshotDiff {
+    pub fn new(from: &Snapshot, to: &Snapshot) -> Self {
+        let mut diff = Self {
+            from: from.date.clone(),
+            to: to.date.clone(),
+            model_types: group_changes(&from.model_types, &to.model_types),
+            materials: group_changes(&from.materials, &to.materials),
+            ..Default::default()
+        };
+        for (name, entry) in &to.models {
+            match from.models.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(before) => {
+                    let fields = entry.changed_fields(before);
+                    if !fields.is_empty() {
+                        diff.changed.push(ChangedModel {
+                            source_name: name.clone(),
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+        diff.removed = from
+            .models
+            .keys()
+            .filter(|name| !to.models.contains_key(*name))
+            .cloned()
+            .collect();
+        let types: BTreeSet<&String> = from
+            .uncovered_types
+            .keys()
+            .chain(to.uncovered_types.keys())
+            .collect();
+        for name in types {
+            let before = from.uncovered_types.get(name).copied().unwrap_or_default();
+            let after = to.uncovered_types.get(name).copied().unwrap_or_default();
+            if before != after {
+                diff.uncovered_types.push(CountChange {
+                    name: name.clone(),
+                    before,
+                    after,
+                });
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.model_types.is_empty()
+            && self.materials.is_empty()
+            && self.uncovered_types.is_empty()
+    }
+
+    /// 数据治理检查用的markdown报告
+    pub fn render(&self) -> String {
+        let mut text = format!("## 模具数据库变化 {} → {}\n", self.from, self.to);
+        if self.is_empty() {
+            text.push_str("\n没有变化\n");
+            return text;
+        }
+        let _ = writeln!(
+            text,
+            "\n新增 {} 条，删除 {} 条，修改 {} 条",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        );
+        for name in &self.added {
+            let _ = writeln!(text, "- ➕ {}", name);
+        }
+        for name in &self.removed {
+            let _ = writeln!(text, "- ➖ {}", name);
+        }
+        for changed in &self.changed {
+            let _ = writeln!(
+                text,
+                "- ✏️ {} ({})",
+                changed.source_name,
+                changed.fields.join(", ")
+            );
+        }
+        for (title, changes) in [
+            ("模具类型词表", &self.model_types),
+            ("材料词表", &self.materials),
+        ] {
+            if changes.is_empty() {
+                continue;
+            }
+            let _ = writeln!(text, "\n### {}", title);
+            for change in changes {
+                let _ = writeln!(
+                    text,
+                    "- {}: +[{}] -[{}]",
+                    change.name,
+                    change.added.join(", "),
+                    change.removed.join(", ")
+                );
+            }
+        }
+        if !self.uncovered_types.is_empty() {
+            text.push_str("\n### 词表未覆盖的模具类型\n");
+            for change in &self.uncovered_types {
+                let _ = writeln!(
+                    text,
+                    "- {}: {} → {}",
+                    change.name, change.before, change.after
+                );
+            }
+        }
+        text
+    }
+}
+
+fn group_changes(
+    from: &BTreeMap<String, BTreeSet<String>>,
+    to: &BTreeMap<String, BTreeSet<String>>,
+) -> Vec<GroupChange> {
+    let empty = BTreeSet::new();
+    let names: BTreeSet<&String> = from.keys().chain(to.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let before = from.get(name).unwrap_or(&empty);
+            let after = to.get(name).unwrap_or(&empty);
+            let change = GroupChange {
+                name: name.clone(),
+                added: after.difference(before).cloned().collect(),
+                removed: before.difference(after).cloned().collect(),
+            };
+            // 分组本身新增或删除时也列出，即使没有写法
+            let exists_changed = from.contains_key(name) != to.contains_key(name);
+            (exists_changed || !change.added.is_empty() || !change.removed.is_empty())
+                .then_some(change)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::{TempDir, fixture};
+
+    #[test]
+    fn diff_snapshots() {
+        let models = ModelJson::patch_new(fixture("models/jsons")).unwrap();
+        let taxonomy = Taxonomy::default();
+        let before = Snapshot::take(
+            "2025-09-01",
+            &ModelStore::new(models.clone(), std::env::temp_dir()),
+            &taxonomy,
+        );
+        assert_eq!(before.models.len(), 3);
+        assert!(SnapshotDiff::new(&before, &before).is_empty());
+
+        let mut models = models;
+        let removed = models.pop().unwrap().source_directory_name;
+        models[0].materials.push("PA66".to_string());
+        models[1].project_name = Some("改名".to_string());
+        let mut added = models[0].clone();
+        added.source_directory_name = "新模具".to_string();
+        added.model_type = Some("散热片".to_string());
+        models.push(added);
+        let mut taxonomy = taxonomy;
+        taxonomy.add_model_types("基座", &["侧基座".to_string()]);
+        let after = Snapshot::take(
+            "2025-10-01",
+            &ModelStore::new(models.clone(), std::env::temp_dir()),
+            &taxonomy,
+        );
+
+        let diff = SnapshotDiff::new(&before, &after);
+        assert_eq!(diff.added, ["新模具"]);
+        assert_eq!(diff.removed, [removed]);
+        assert_eq!(diff.changed.len(), 2);
+        assert!(diff.changed.iter().any(|c| c.fields == ["materials"]));
+        assert!(diff.changed.iter().any(|c| c.fields == ["record"]));
+        assert_eq!(diff.model_types[0].name, "基座");
+        assert_eq!(diff.model_types[0].added, ["侧基座"]);
+        assert_eq!(
+            diff.uncovered_types,
+            [CountChange {
+                name: "散热片".to_string(),
+                before: 0,
+                after: 1,
+            }]
+        );
+        assert!(diff.render().contains("新增 1 条，删除 1 条，修改 2 条"));
+
+        // 按日期查找当天或之前最近的快照
+        let dir = TempDir::new("snapshots");
+        let date = |text| parse_date(text).unwrap();
+        assert!(Snapshot::find(&dir, date("2025-10-15")).unwrap().is_none());
+        before.save(&dir).unwrap();
+        after.save(&dir).unwrap();
+        let found = Snapshot::find(&dir, date("2025-10-15")).unwrap().unwrap();
+        assert_eq!(found.date, "2025-10-01");
+        let found = Snapshot::find(&dir, date("2025-09-30")).unwrap().unwrap();
+        assert_eq!(found.date, "2025-09-01");
+        assert!(Snapshot::find(&dir, date("2025-08-01")).unwrap().is_none());
+        assert!(parse_date("../secret").is_none());
+    }
+}
@@ -0,0 +1,211 @@
+//! 从用户反馈构建评测数据集
+//!
+//! 导出的目录结构:
+//! ```text
+//! <out>/manifest.json
+//! <out>/samples/<job_id>/images/*.png      PDF转换出的图片
+//! <out>/samples/<job_id>/extraction.json   模型提取结果
+//! <out>/samples/<job_id>/label.json        用户的评价
+//! ```
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    IResult,
+    job::{Feedback, JobKind, JobRecord, Verdict},
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// 数据集中的一条样本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub job_id: String,
+    pub input: String,
+    pub prompt_version: Option<String>,
+    pub model_name: Option<String>,
+    /// 最新的一条评价
+    pub verdict: Verdict,
+    /// 相对数据集目录的图片路径
+    pub images: Vec<PathBuf>,
+    pub extraction: PathBuf,
+    pub label: PathBuf,
+}
+
+/// 评测样本的人工标注
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    pub verdict: Verdict,
+    pub feedback: Vec<Feedback>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created_at: String,
+    pub good: usize,
+    pub bad: usize,
+    pub samples: Vec<Sample>,
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> IResult<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(
+            dir.join(MANIFEST_FILE),
+        )?)?)
+    }
+}
+
+/// 把带有反馈的PDF分析任务导出为数据集
+pub fn export_dataset(jobs: &[JobRecord], out: &Path) -> IResult<Manifest> {
+    let mut jobs: Vec<_> = jobs
+        .iter()
+        .filter(|job| job.kind == JobKind::Pdf && job.extraction.is_some())
+        .filter(|job| job.verdict().is_some())
+        .collect();
+    jobs.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    let mut manifest = Manifest {
+        created_at: chrono::Local::now().to_rfc3339(),
+        good: 0,
+        bad: 0,
+        samples: Vec::with_capacity(jobs.len()),
+    };
+
+    for job in jobs {
+        let sample = export_sample(job, out)?;
+        match sample.verdict {
+            Verdict::Good => manifest.good += 1,
+            Verdict::Bad => manifest.bad += 1,
+        }
+        manifest.samples.push(sample);
+    }
+
+    std::fs::create_dir_all(out)?;
+    std::fs::write(
+        out.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    info!(
+        "✅ 已导出 {} 个样本到 {} (👍 {} / 👎 {})",
+        manifest.samples.len(),
+        out.display(),
+        manifest.good,
+        manifest.bad
+    );
+    Ok(manifest)
+}
+
+fn export_sample(job: &JobRecord, out: &Path) -> IResult<Sample> {
+    let sample_dir = PathBuf::from("samples").join(&job.id);
+    let images_dir = sample_dir.join("images");
+    std::fs::create_dir_all(out.join(&images_dir))?;
+
+    let mut images = Vec::new();
+    match job.images_dir.as_deref().map(std::fs::read_dir) {
+        Some(Ok(entries)) => {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_image = path
+                    .extension()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+                if !is_image {
+                    continue;
+                }
+                let image = images_dir.join(entry.file_name());
+                std::fs::copy(&path, out.join(&image))?;
+                images.push(image);
+            }
+            images.sort();
+        }
+        _ => warn!("任务 {} 的图片目录不存在，样本中没有图片", job.id),
+    }
+
+    let extraction = sample_dir.join("extraction.json");
+    std::fs::write(
+        out.join(&extraction),
+        serde_json::to_string_pretty(&job.extraction)?,
+    )?;
+
+    // 调用方已过滤掉没有反馈的任务
+    let verdict = job.verdict().unwrap_or(Verdict::Bad);
+    let label = sample_dir.join("label.json");
+    std::fs::write(
+        out.join(&label),
+        serde_json::to_string_pretty(&Label {
+            verdict,
+            feedback: job.feedback.clone(),
+        })?,
+    )?;
+
+    Ok(Sample {
+        job_id: job.id.clone(),
+        input: job.input.clone(),
+        prompt_version: job.prompt_version.clone(),
+        model_name: job.model_name.clone(),
+        verdict,
+        images,
+        extraction,
+        label,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diff::ModelJson, paths::TempDir};
+
+    #[test]
+    fn export_jobs_with_feedback() {
+        let root = TempDir::new("dataset");
+        let images_dir = root.join("output").join("a");
+        std::fs::create_dir_all(&images_dir).unwrap();
+        std::fs::write(images_dir.join("a_page_001.png"), b"png").unwrap();
+        std::fs::write(images_dir.join("notes.txt"), b"txt").unwrap();
+
+        let mut rated = JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string());
+        rated.images_dir = Some(images_dir);
+        rated.extraction = Some(ModelJson {
+            model_type: Some("基座".to_string()),
+            materials: vec!["PBT".to_string()],
+            project_name: None,
+            company: None,
+            dimensions: None,
+            drawing: None,
+            source_directory: PathBuf::new(),
+            source_directory_name: "a".to_string(),
+            extraction_timestamp: None,
+            original_pdf: None,
+            tags: Vec::new(),
+            finish: None,
+            id: None,
+            slug: None,
+            status: None,
+            provenance: None,
+            canonical_materials: Vec::new(),
+        });
+        rated.feedback.push(Feedback {
+            verdict: Verdict::Good,
+            comment: None,
+            from_uid: 1,
+            created_at: String::new(),
+        });
+        let mut unrated = rated.clone();
+        unrated.id = "unrated".to_string();
+        unrated.feedback.clear();
+
+        let out = root.join("dataset");
+        let manifest = export_dataset(&[rated.clone(), unrated], &out).unwrap();
+        assert_eq!(manifest.samples.len(), 1);
+        assert_eq!(manifest.good, 1);
+
+        let sample = &manifest.samples[0];
+        assert_eq!(sample.images.len(), 1);
+        assert!(out.join(&sample.images[0]).exists());
+        assert!(out.join(&sample.extraction).exists());
+        assert_eq!(Manifest::load(&out).unwrap().samples[0].job_id, rated.id);
+    }
+}
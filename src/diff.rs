@@ -1,19 +1,89 @@
-use std::{collections::HashMap, env::current_exe, fs, io::Cursor, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::SystemTime,
+};
 
 use base64::{Engine, prelude::BASE64_STANDARD};
 use image::ImageReader;
 use serde::{Deserialize, Serialize};
-
-use crate::ai_text_analyzer::TextExtractionResult;
+use tracing::warn;
+
+use crate::{
+    CONFIG, IMAGE_URLS, MODELS, TAXONOMY,
+    ai_text_analyzer::TextExtractionResult,
+    calibration::ScoreBand,
+    config::{ApiProvider, ReportLayout, ScoringProfile},
+    dimension::{DimensionComparison, Dimensions, compare_dimensions},
+    drawing::{DrawingFormat, ScaleConflict},
+    finish::{Finish, compare_finish},
+    model_store::ModelStore,
+    mold_status::MoldStatus,
+    paths::{portable, upload_dir},
+    pdf_converter::PageManifest,
+    query::UserQuery,
+    tags::mine_tags,
+    text_metric::TextMetric,
+    thumbnail::{ensure_preview, preview_file_name},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelJson {
     pub model_type: Option<String>,
     pub materials: Vec<String>,
+    /// 与`materials`一一对应的标准牌号，例如`PBT 3316`对应`长春 PBT 3316 GF30`，
+    /// 比较时使用，展示时仍使用原始写法
+    #[serde(default)]
+    pub canonical_materials: Vec<String>,
     pub project_name: Option<String>,
+    /// 图纸所属的公司/客户
+    #[serde(default)]
+    pub company: Option<String>,
+    /// 图纸标注的外形尺寸和公差，之前的记录中没有
+    #[serde(default)]
+    pub dimensions: Option<Dimensions>,
+    /// 标题栏中的比例和图幅，之前的记录中没有
+    #[serde(default)]
+    pub drawing: Option<DrawingFormat>,
+    /// 外部系统通过`POST /material/api/v1/diff`提交的记录可以没有来源
+    #[serde(default)]
     pub source_directory: PathBuf,
+    #[serde(default)]
     pub source_directory_name: String,
     pub extraction_timestamp: Option<String>,
+    /// 原始PDF图纸，历史数据通过`material-cli migrate-original-pdf`补全
+    #[serde(default)]
+    pub original_pdf: Option<PathBuf>,
+    /// 从技术要求中整理的标签，例如`热处理`、`表面处理:镀镍`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 表面处理、皮纹和钢材硬度要求
+    #[serde(default)]
+    pub finish: Option<Finish>,
+    /// 稳定的内部id(ULID)
+    #[serde(default)]
+    pub id: Option<String>,
+    /// 接口和链接中使用的URL安全标识
+    #[serde(default)]
+    pub slug: Option<String>,
+    /// 实体模具的状态，没有记录时视为在用
+    #[serde(default)]
+    pub status: Option<MoldStatus>,
+    /// 提取使用的模型服务和提示词版本，提取时间见`extraction_timestamp`，历史记录没有
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+/// 记录的提取来源，用于找出由较旧、较弱的模型提取的记录重新提取
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub provider: ApiProvider,
+    pub model_name: String,
+    pub prompt_version: String,
 }
 
 impl From<TextExtractionResult> for ModelJson {
@@ -23,13 +93,23 @@ impl From<TextExtractionResult> for ModelJson {
             model_type,
             materials,
             project_name,
+            company,
+            text_content,
+            finish,
+            dimensions,
+            drawing,
+            tags,
             ..
         } = value;
 
         Self {
             model_type,
+            canonical_materials: canonical_materials(&materials),
             materials,
             project_name,
+            company,
+            dimensions,
+            drawing: (!drawing.is_empty()).then_some(drawing),
             source_directory_name: image_path
                 .file_name()
                 .and_then(|s| s.to_str())
@@ -37,28 +117,141 @@ impl From<TextExtractionResult> for ModelJson {
                 .to_string(),
             source_directory: image_path,
             extraction_timestamp: None,
+            original_pdf: None,
+            // 之前的提取结果和没有启用模型整理时按关键词规则整理
+            tags: if tags.is_empty() {
+                mine_tags(&text_content)
+            } else {
+                tags
+            },
+            finish: (!finish.is_empty()).then_some(finish),
+            id: None,
+            slug: None,
+            status: None,
+            provenance: None,
         }
     }
 }
 
+/// 已解析的模型记录，文件的修改时间和大小不变时直接使用
+struct CachedModel {
+    modified: SystemTime,
+    len: u64,
+    model: ModelJson,
+}
+
+static PARSE_CACHE: LazyLock<Mutex<HashMap<PathBuf, CachedModel>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 impl ModelJson {
+    /// 既没有识别出模具类型也没有材料，无法进行有意义的比较
+    pub fn is_unidentified(&self) -> bool {
+        self.model_type
+            .as_deref()
+            .is_none_or(|t| t.trim().is_empty())
+            && self.materials.iter().all(|m| m.trim().is_empty())
+    }
+
+    /// 是否需要重新提取：没有提取来源，或者提示词版本不同，或者`model_name`不为None且模型不同
+    pub fn needs_reextraction(&self, prompt_version: &str, model_name: Option<&str>) -> bool {
+        match &self.provenance {
+            None => true,
+            Some(provenance) => {
+                provenance.prompt_version != prompt_version
+                    || model_name.is_some_and(|name| provenance.model_name != name)
+            }
+        }
+    }
+
     /// new from json use serde_json
     pub fn new(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
         let content = fs::read_to_string(path.as_path())?;
-        let model_json: ModelJson = serde_json::from_str(&content)?;
+        let mut model_json: ModelJson = serde_json::from_str(&content)?;
+        // 历史数据在Windows上生成
+        model_json.source_directory = portable(&model_json.source_directory);
+        model_json.original_pdf = model_json.original_pdf.as_deref().map(portable);
         Ok(model_json)
     }
 
+    /// 标注的外形尺寸按比例换算后超出图幅，尺寸或比例可能识别有误
+    pub fn scale_conflict(&self) -> Option<ScaleConflict> {
+        let dimensions = self.dimensions.as_ref()?;
+        self.drawing
+            .as_ref()?
+            .check(dimensions.x_max, dimensions.y_max)
+    }
+
     pub fn patch_new(path: PathBuf) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
         let mut result = Vec::new();
         for entry in fs::read_dir(path)? {
             let entry = entry?;
-            if entry.path().extension().and_then(|s| s.to_str()) == Some("json") {
-                let model_json = ModelJson::new(entry.path())?;
+            // 按年份和客户分片的子目录
+            if entry.path().is_dir() {
+                result.extend(ModelJson::patch_new(entry.path())?);
+            } else if entry.path().extension().and_then(|s| s.to_str()) == Some("json") {
+                let model_json = ModelJson::cached(entry.path(), &entry.metadata()?)?;
                 result.push(model_json);
             }
         }
-        return Ok(result);
+        Ok(result)
+    }
+
+    /// 与`patch_new`相同，但跳过无法读取的文件，返回读取的模型和失败的文件及原因
+    pub fn patch_load(path: &Path) -> (Vec<Self>, Vec<(PathBuf, String)>) {
+        let (mut models, mut failed) = (Vec::new(), Vec::new());
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => return (models, vec![(path.to_path_buf(), e.to_string())]),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                let (more, more_failed) = ModelJson::patch_load(&path);
+                models.extend(more);
+                failed.extend(more_failed);
+            } else if path.extension().and_then(|s| s.to_str()) == Some("json") {
+                match entry
+                    .metadata()
+                    .map_err(|e| e.to_string())
+                    .and_then(|metadata| {
+                        ModelJson::cached(path.clone(), &metadata).map_err(|e| e.to_string())
+                    }) {
+                    Ok(model) => models.push(model),
+                    Err(e) => failed.push((path, e)),
+                }
+            }
+        }
+        (models, failed)
+    }
+
+    /// 读取模型记录，文件未修改时使用缓存的解析结果
+    fn cached(path: PathBuf, metadata: &fs::Metadata) -> Result<Self, Box<dyn std::error::Error>> {
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        if let Some(cached) = PARSE_CACHE.lock().unwrap().get(&path)
+            && cached.modified == modified
+            && cached.len == len
+        {
+            return Ok(cached.model.clone());
+        }
+        let model = ModelJson::new(path.clone())?;
+        PARSE_CACHE.lock().unwrap().insert(
+            path,
+            CachedModel {
+                modified,
+                len,
+                model: model.clone(),
+            },
+        );
+        Ok(model)
+    }
+
+    /// 比较时使用的材料，历史数据没有标准牌号时按当前词表转换
+    pub fn comparison_materials(&self) -> Cow<'_, [String]> {
+        if self.canonical_materials.len() == self.materials.len() {
+            Cow::Borrowed(&self.canonical_materials)
+        } else {
+            Cow::Owned(canonical_materials(&self.materials))
+        }
     }
 
     /// 将 Vec<ModelJson> 通过model_type进行分组
@@ -72,14 +265,64 @@ impl ModelJson {
         map
     }
 
-    pub fn diff(models: HashMap<String, Vec<Self>>, model: Self) -> Vec<DiffResult> {
+    /// 上传的模具与候选模具`candidate`的相似度及各项得分
+    pub fn similarity(&self, candidate: &Self, profile: &ScoringProfile) -> Similarity {
+        let candidate_type = candidate.model_type.as_deref().unwrap_or("unknown");
+        let model_type = calculate_model_type_similarity(
+            candidate_type,
+            self.model_type.as_deref().unwrap_or("unknown"),
+            profile.text_metric,
+        );
+        let material = calculate_material_similarity(
+            &candidate.comparison_materials(),
+            &self.comparison_materials(),
+            Some(candidate_type),
+            profile.text_metric,
+        );
+
+        // 综合相似度：模具类型和材料按评分方案的权重加权
+        let mut percentage = profile.weighted(model_type, material);
+        // 双方都有表面处理、皮纹或钢材硬度要求时按权重计入
+        let finish = candidate
+            .finish
+            .as_ref()
+            .zip(self.finish.as_ref())
+            .and_then(|(candidate, uploaded)| compare_finish(uploaded, candidate));
+        if let Some(finish) = finish {
+            percentage = blend(percentage, finish, profile.finish_weight);
+        }
+        // 双方都有尺寸时比较尺寸和公差等级
+        let dimensions = candidate
+            .dimensions
+            .as_ref()
+            .zip(self.dimensions.as_ref())
+            .map(|(candidate, uploaded)| compare_dimensions(uploaded, candidate));
+        if let Some(score) = dimensions.as_ref().and_then(DimensionComparison::score) {
+            percentage = blend(percentage, score, profile.dimension_weight);
+        }
+
+        Similarity {
+            model_type,
+            material,
+            finish,
+            dimensions,
+            percentage,
+        }
+    }
+
+    pub fn diff(
+        models: HashMap<String, Vec<Self>>,
+        model: Self,
+        profile: &ScoringProfile,
+    ) -> Vec<DiffResult> {
         let mut results = Vec::new();
 
         for (model_type, model_info) in models {
             // 进行模具类型比较，优先全词匹配
-            let model_type_diff = improved_diff_text(
+            let model_type_diff = calculate_model_type_similarity(
                 &model_type,
                 &(model.model_type.clone().unwrap_or("unknown".to_string())),
+                profile.text_metric,
             );
 
             // 如果模具类型相似度太低，直接跳过
@@ -101,19 +344,151 @@ impl ModelJson {
                     continue;
                 }
 
-                // 计算材料相似度
-                let material_similarity =
-                    calculate_material_similarity(&cmodel.materials, &model.materials);
-
-                // 综合相似度：模具类型相似度权重0.3，材料相似度权重0.7
-                let final_percentage = model_type_diff * 0.3 + material_similarity * 0.7;
+                let similarity = model.similarity(&cmodel, profile);
 
                 // 只有相似度超过阈值才加入结果
-                if final_percentage > 0.1 {
+                if similarity.percentage > profile.min_percentage {
                     results.push(DiffResult {
                         source_directory: cmodel.source_directory.clone(),
                         source_name: cmodel.source_directory_name.clone(),
-                        percentage: final_percentage,
+                        company: cmodel.company.clone(),
+                        dimensions: similarity.dimensions,
+                        original_pdf: cmodel.original_pdf.clone(),
+                        exact_match: false,
+                        status: cmodel.status,
+                        percentage: similarity.percentage,
+                    });
+                }
+            }
+        }
+
+        results
+    }
+
+    /// 模具是否属于客户`customer`，忽略大小写和空白，任一方包含另一方即可
+    pub fn is_customer(&self, customer: &str) -> bool {
+        let normalize = |s: &str| -> String {
+            s.chars()
+                .filter(|c| !c.is_whitespace())
+                .flat_map(|c| c.to_lowercase())
+                .collect()
+        };
+        let customer = normalize(customer);
+        self.company
+            .as_deref()
+            .map(normalize)
+            .is_some_and(|company| {
+                !company.is_empty()
+                    && !customer.is_empty()
+                    && (company.contains(&customer) || customer.contains(&company))
+            })
+    }
+
+    /// 提取时间是否在`since`和`until`之间(包含边界)，日期格式为`YYYY[-MM[-DD]]`，
+    /// 指定了范围时没有提取时间的模具不算在内
+    pub fn in_period(&self, since: Option<&str>, until: Option<&str>) -> bool {
+        if since.is_none() && until.is_none() {
+            return true;
+        }
+        let Some(timestamp) = self.extraction_timestamp.as_deref() else {
+            return false;
+        };
+        // 按日期的前缀比较，例如`2024-06`与`2024-06-30T..`的前7个字符比较
+        let prefix = |bound: &str| timestamp.get(..bound.len()).unwrap_or(timestamp);
+        since.is_none_or(|since| prefix(since) >= since)
+            && until.is_none_or(|until| prefix(until) <= until)
+    }
+
+    /// 模具是否有标签`tag`，忽略大小写，标签包含`tag`即可
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim().to_lowercase();
+        !tag.is_empty() && self.tags.iter().any(|t| t.to_lowercase().contains(&tag))
+    }
+
+    /// 按模具类型检索
+    pub fn search_model_type(
+        models: &HashMap<String, Vec<Self>>,
+        model_type: &str,
+    ) -> Vec<DiffResult> {
+        let query = UserQuery {
+            model_type: Some(model_type.to_string()),
+            ..Default::default()
+        };
+        Self::search_combined(models, &query, &ScoringProfile::default())
+    }
+
+    /// 按用户的检索条件进行检索，类型和材料都给出时按评分方案的权重综合计算
+    pub fn search_combined(
+        models: &HashMap<String, Vec<Self>>,
+        query: &UserQuery,
+        profile: &ScoringProfile,
+    ) -> Vec<DiffResult> {
+        let mut results = Vec::new();
+        let query_materials = canonical_materials(&query.materials);
+        let normalize = |s: &str| -> String {
+            s.chars()
+                .filter(|c| !c.is_whitespace())
+                .flat_map(|c| c.to_lowercase())
+                .collect()
+        };
+
+        for (model_type, model_info) in models {
+            let type_similarity = query
+                .model_type
+                .as_ref()
+                .map(|t| calculate_model_type_similarity(model_type, t, profile.text_metric));
+            let exact_match = query
+                .model_type
+                .as_deref()
+                .is_some_and(|t| normalize(t) == normalize(model_type));
+
+            // 模具类型相似度太低，直接跳过
+            if type_similarity.is_some_and(|s| s < 0.1) {
+                continue;
+            }
+
+            for cmodel in model_info {
+                // 指定了客户时只在该客户的模具中检索
+                if let Some(customer) = &query.customer
+                    && !cmodel.is_customer(customer)
+                {
+                    continue;
+                }
+                if !query.tags.iter().all(|tag| cmodel.has_tag(tag)) {
+                    continue;
+                }
+                if !cmodel.in_period(query.since.as_deref(), query.until.as_deref()) {
+                    continue;
+                }
+
+                let material_similarity = (!query.materials.is_empty()).then(|| {
+                    calculate_material_similarity(
+                        &cmodel.comparison_materials(),
+                        &query_materials,
+                        Some(model_type),
+                        profile.text_metric,
+                    )
+                });
+
+                let percentage = match (type_similarity, material_similarity) {
+                    (Some(t), Some(m)) => profile.weighted(t, m),
+                    (Some(t), None) => t,
+                    (None, Some(m)) => m,
+                    // 只按客户、标签或时间检索
+                    (None, None) if !query.is_empty() => 1.0,
+                    (None, None) => continue,
+                };
+
+                if percentage > profile.min_percentage {
+                    results.push(DiffResult {
+                        source_directory: cmodel.source_directory.clone(),
+                        source_name: cmodel.source_directory_name.clone(),
+                        company: cmodel.company.clone(),
+                        dimensions: None,
+                        original_pdf: cmodel.original_pdf.clone(),
+                        exact_match,
+                        status: cmodel.status,
+                        percentage,
                     });
                 }
             }
@@ -123,8 +498,66 @@ impl ModelJson {
     }
 }
 
-/// 改进的文本相似度计算，优先全词匹配
-pub fn improved_diff_text(text1: &str, text2: &str) -> f32 {
+/// 按权重`weight`把`score`计入相似度
+fn blend(percentage: f32, score: f32, weight: f32) -> f32 {
+    let weight = weight.clamp(0.0, 1.0);
+    percentage * (1.0 - weight) + score * weight
+}
+
+/// 词表中同一分组的模具类型至少有这个相似度
+const GROUP_SIMILARITY: f32 = 0.8;
+/// 词表中同一牌号的材料至少有这个相似度
+const GRADE_SIMILARITY: f32 = 0.95;
+
+/// 转换为词表中的标准牌号，词表中没有的材料保持原样
+pub fn canonical_materials(materials: &[String]) -> Vec<String> {
+    let taxonomy = TAXONOMY.read().unwrap();
+    materials
+        .iter()
+        .map(|m| taxonomy.canonical_material(m).unwrap_or_else(|| m.clone()))
+        .collect()
+}
+
+/// 计算模具类型的相似度，词表中属于同一分组的类型视为相近
+pub fn calculate_model_type_similarity(type1: &str, type2: &str, metric: TextMetric) -> f32 {
+    let similarity = improved_diff_text(type1, type2, metric);
+    let taxonomy = TAXONOMY.read().unwrap();
+    match (
+        taxonomy.model_type_group(type1),
+        taxonomy.model_type_group(type2),
+    ) {
+        (Some(g1), Some(g2)) if g1.name == g2.name => similarity.max(GROUP_SIMILARITY),
+        _ => similarity,
+    }
+}
+
+/// 计算两个材料的相似度，词表中同一牌号的不同写法视为相同，
+/// 替代关系表中可替代的材料给予部分相似度，不可替代的材料相似度为0
+pub fn calculate_single_material_similarity(
+    material1: &str,
+    material2: &str,
+    model_type: Option<&str>,
+    metric: TextMetric,
+) -> f32 {
+    let similarity = improved_diff_text(material1, material2, metric);
+    let taxonomy = TAXONOMY.read().unwrap();
+    if let (Some((f1, g1)), Some((f2, g2))) = (
+        taxonomy.material_grade(material1),
+        taxonomy.material_grade(material2),
+    ) && f1.name == f2.name
+        && g1.name == g2.name
+    {
+        return similarity.max(GRADE_SIMILARITY);
+    }
+    match taxonomy.material_compatibility(material1, material2, model_type) {
+        Some(score) if score <= 0.0 => 0.0,
+        Some(score) => similarity.max(score),
+        None => similarity,
+    }
+}
+
+/// 改进的文本相似度计算，优先全词匹配，其他情况按`metric`比较
+pub fn improved_diff_text(text1: &str, text2: &str, metric: TextMetric) -> f32 {
     let text1_clean = text1.trim();
     let text2_clean = text2.trim();
 
@@ -140,16 +573,18 @@ pub fn improved_diff_text(text1: &str, text2: &str) -> f32 {
 
     // 检查是否有一个是另一个的子串
     if text1_clean.contains(text2_clean) || text2_clean.contains(text1_clean) {
-        let shorter_len = text1_clean.len().min(text2_clean.len()) as f32;
-        let longer_len = text1_clean.len().max(text2_clean.len()) as f32;
-        return shorter_len / longer_len;
+        let (len1, len2) = (text1_clean.chars().count(), text2_clean.chars().count());
+        return len1.min(len2) as f32 / len1.max(len2) as f32;
     }
 
-    // 分词匹配作为后备方案
-    let tokens1 = split_text_improved(text1_clean);
-    let tokens2 = split_text_improved(text2_clean);
-
-    diff_text_tokens(tokens1, tokens2)
+    match metric {
+        // 分词匹配作为后备方案
+        TextMetric::CharacterBag => diff_text_tokens(
+            split_text_improved(text1_clean),
+            split_text_improved(text2_clean),
+        ),
+        metric => metric.similarity(text1_clean, text2_clean),
+    }
 }
 
 /// 改进的分词，保留更多语义单元
@@ -221,8 +656,13 @@ pub fn diff_text_tokens(tokens1: Vec<String>, tokens2: Vec<String>) -> f32 {
     matched_count as f32 / tokens1.len().max(tokens2.len()) as f32
 }
 
-/// 计算材料列表的相似度
-pub fn calculate_material_similarity(materials1: &[String], materials2: &[String]) -> f32 {
+/// 计算材料列表的相似度，`model_type`用于匹配只对部分模具类型生效的替代关系
+pub fn calculate_material_similarity(
+    materials1: &[String],
+    materials2: &[String],
+    model_type: Option<&str>,
+    metric: TextMetric,
+) -> f32 {
     if materials1.is_empty() || materials2.is_empty() {
         return 0.0;
     }
@@ -250,7 +690,8 @@ pub fn calculate_material_similarity(materials1: &[String], materials2: &[String
         let mut best_similarity = 0.0f32;
 
         for material2 in &valid_materials2 {
-            let similarity = improved_diff_text(material1, material2);
+            let similarity =
+                calculate_single_material_similarity(material1, material2, model_type, metric);
             best_similarity = best_similarity.max(similarity);
         }
 
@@ -337,20 +778,133 @@ pub fn diff_text(text1: Vec<String>, text2: Vec<String>) -> f32 {
 }
 
 /// 进行DIff之后的结果
+/// 两个模具的相似度及各项得分
+#[derive(Debug, Clone, Serialize)]
+pub struct Similarity {
+    pub model_type: f32,
+    pub material: f32,
+    /// 表面处理、皮纹和钢材硬度，双方没有共同项目时为None
+    pub finish: Option<f32>,
+    pub dimensions: Option<DimensionComparison>,
+    /// 综合相似度
+    pub percentage: f32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiffResult {
     pub source_directory: PathBuf,
     pub source_name: String,
+    /// 模具所属的公司/客户
+    pub company: Option<String>,
+    /// 与上传图纸的尺寸和公差等级比较
+    pub dimensions: Option<DimensionComparison>,
+    /// 模具的原始PDF图纸
+    #[serde(default)]
+    pub original_pdf: Option<PathBuf>,
+    /// 模具类型与检索的类型完全一致，排在只有分组相近的结果之前
+    #[serde(default)]
+    pub exact_match: bool,
+    /// 实体模具的状态
+    #[serde(default)]
+    pub status: Option<MoldStatus>,
     /// 相似度
     pub percentage: f32,
 }
 
+/// 原始PDF的下载链接，只有上传目录中的文件可以下载
+pub fn original_pdf_url(pdf: &Path) -> Option<String> {
+    let relative = if pdf.is_absolute() {
+        pdf.strip_prefix(upload_dir()).ok()?
+    } else {
+        pdf
+    };
+    let path: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Some(IMAGE_URLS.image_url(&path.join("/")))
+}
+
 impl DiffResult {
-    pub fn sort(res: &mut Vec<Self>) -> () {
+    /// 结果中显示的名称，封存和报废的模具带有标注
+    pub fn display_name(&self) -> String {
+        format!("{}{}", self.source_name, MoldStatus::flag(self.status))
+    }
+
+    pub fn original_pdf_url(&self) -> Option<String> {
+        self.original_pdf.as_deref().and_then(original_pdf_url)
+    }
+
+    /// 结果前`max_pages`页的预览图链接，缺失的预览图会按需生成，
+    /// 第一页都无法生成时返回空。页面图片有清单时不超过清单中的页数
+    pub fn preview_urls(&self, store: &ModelStore, max_pages: usize) -> Vec<String> {
+        let image_dir = store.image_dir(&self.source_name);
+        let max_pages = PageManifest::load(&self.source_directory)
+            .map_or(max_pages, |manifest| manifest.pages.len().min(max_pages));
+        let mut urls = Vec::new();
+        for page in 0..max_pages {
+            let file_name = preview_file_name(&self.source_name, page);
+            let preview = store.img_dir().join(&image_dir).join(&file_name);
+            if let Err(e) = ensure_preview(&preview, &self.source_directory, page) {
+                if page == 0 {
+                    warn!("{} 没有预览图: {}", self.source_name, e);
+                }
+                break;
+            }
+            urls.push(IMAGE_URLS.image_url(&format!("models/imgs/{}/{}", image_dir, file_name)));
+        }
+        urls
+    }
+
+    /// 在阻塞线程中生成结果缺失的预览图并返回链接，渲染PDF页面需要调用pdftocairo，
+    /// 异步的接口和工作流不能直接调用`preview_urls`
+    pub async fn with_previews(
+        store: Arc<ModelStore>,
+        results: Vec<DiffResult>,
+        max_pages: usize,
+    ) -> Vec<(DiffResult, Vec<String>)> {
+        tokio::task::spawn_blocking(move || {
+            results
+                .into_iter()
+                .map(|result| {
+                    let pages = result.preview_urls(&store, max_pages);
+                    (result, pages)
+                })
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    /// 相似度的万分数，即显示的百分数保留两位小数，排序和报告都使用这个值
+    pub fn basis_points(&self) -> i64 {
+        (f64::from(self.percentage) * 10000.0).round() as i64
+    }
+
+    /// 报告中显示的相似度百分数，例如`87.50`
+    pub fn display_percentage(&self) -> String {
+        format!("{:.2}", self.basis_points() as f64 / 100.0)
+    }
+
+    /// 按校准后的分数划分的档次，见`Calibration`
+    pub fn band(&self) -> ScoreBand {
+        CONFIG.calibration.band(self.percentage)
+    }
+
+    /// 报告中相似度后面的分档，例如`(高度相似)`
+    pub fn display_band(&self) -> String {
+        format!("({})", self.band().label())
+    }
+
+    /// 类型完全一致的结果在前，相似度高的在前；显示的相似度相同时依次按名称和路径排序，
+    /// 每次的顺序都一致，不受模具库加载顺序影响
+    pub fn sort(res: &mut [Self]) {
         res.sort_by(|a, b| {
-            b.percentage // 注意这里改为降序排列，相似度高的在前面
-                .partial_cmp(&a.percentage)
-                .unwrap_or(std::cmp::Ordering::Equal)
+            b.exact_match
+                .cmp(&a.exact_match)
+                .then_with(|| b.basis_points().cmp(&a.basis_points()))
+                .then_with(|| a.source_name.cmp(&b.source_name))
+                .then_with(|| a.source_directory.cmp(&b.source_directory))
         });
     }
 }
@@ -366,13 +920,15 @@ impl Eq for DiffResult {}
 
 impl PartialOrd for DiffResult {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.percentage.partial_cmp(&other.percentage)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for DiffResult {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap_or(std::cmp::Ordering::Equal)
+        self.percentage
+            .partial_cmp(&other.percentage)
+            .unwrap_or(std::cmp::Ordering::Equal)
     }
 }
 
@@ -383,64 +939,144 @@ ${result_table}
 "#;
 // <img src="data:image/jpeg;base64,${base64_image}" height="400px" />
 const MD_TABLE: &str = r#"
-| 来源文件 | 相似度 |
-| --- | --- |
-| {$source} | {$percentage}% |
-<img src="${img_path}" width="400px" />
-<a href="${href}">查看模型</a>
+| 来源文件 | 客户 | 相似度 |
+| --- | --- | --- |
+| {$source} | {$company} | {$percentage}% {$band} |
+${dimensions}
+${images}
+<a href="${href}">查看模型</a>${download}
+"#;
+/// 紧凑列表中的一个结果
+const MD_COMPACT_ITEM: &str = r#"
+**{$index}. {$source}** 相似度 {$percentage}% {$band}
+客户: {$company} ${dimensions}
+[查看模型](${href})${preview}${download}
 "#;
 
-/// 将最后的结果转为markdown格式
-pub fn fmt_diff_result_to_md(results: &Vec<DiffResult>) -> String {
+/// 将最后的结果转为markdown格式，`tenant`为消息来源的VoceChat域名，用于选择比较页面前端
+pub fn fmt_diff_result_to_md(
+    results: &[DiffResult],
+    layout: ReportLayout,
+    tenant: Option<&str>,
+) -> String {
+    fmt_results_to_md(
+        "对该pdf文件进行相似度比较的结果如下:\n",
+        results,
+        layout,
+        tenant,
+    )
+}
+
+/// 将文本检索的结果转为markdown格式
+pub fn fmt_search_result_to_md(
+    query: &UserQuery,
+    results: &[DiffResult],
+    layout: ReportLayout,
+    tenant: Option<&str>,
+) -> String {
+    let mut title = String::from("检索条件");
+    if let Some(model_type) = &query.model_type {
+        title.push_str(&format!(" 类型: `{}`", model_type));
+    }
+    if !query.materials.is_empty() {
+        title.push_str(&format!(" 材料: `{}`", query.materials.join(", ")));
+    }
+    if let Some(customer) = &query.customer {
+        title.push_str(&format!(" 客户: `{}`", customer));
+    }
+    if !query.tags.is_empty() {
+        title.push_str(&format!(" 标签: `{}`", query.tags.join(", ")));
+    }
+    match (&query.since, &query.until) {
+        (Some(since), Some(until)) => title.push_str(&format!(" 时间: `{} ~ {}`", since, until)),
+        (Some(since), None) => title.push_str(&format!(" 时间: `{}之后`", since)),
+        (None, Some(until)) => title.push_str(&format!(" 时间: `{}之前`", until)),
+        (None, None) => {}
+    }
+    if let Some(profile) = &query.profile {
+        title.push_str(&format!(" (评分方案: `{}`)", profile));
+    }
+    if results.is_empty() {
+        return format!("{}\n❌ 没有找到符合条件的模具", title);
+    }
+    title.push_str(" 的检索结果如下:\n");
+    fmt_results_to_md(&title, results, layout, tenant)
+}
+
+/// 没有识别出模具类型和材料时的回复，附上页面缩略图和手动检索的格式
+pub fn fmt_unidentified_to_md(thumbnails: &[String]) -> String {
+    let mut content = String::from(
+        "⚠️ 未能从图纸中识别出模具类型和材料，没有进行相似度比较。\n\
+         请查看图纸标题栏中的名称和材料，按以下格式发送检索条件:\n\
+         ```\n- 类型: 基座;\n- 材料: PBT RG301, PA66;\n```\n",
+    );
+    for url in thumbnails {
+        content.push_str(&format!(r#"<img src="{}" width="400px" />"#, url));
+        content.push('\n');
+    }
+    content
+}
+
+fn fmt_results_to_md(
+    title: &str,
+    results: &[DiffResult],
+    layout: ReportLayout,
+    tenant: Option<&str>,
+) -> String {
+    let config = &CONFIG.report;
     let mut md = String::new();
-    md.push_str("对该pdf文件进行相似度比较的结果如下:\n");
-    let img_dir = current_exe()
-        .map_err(|e| format!("获取执行目录失败: {}", e))
-        .unwrap()
-        .parent()
-        .ok_or("无法获取执行目录的父目录")
-        .unwrap()
-        .join("data")
-        .join("upload")
-        .join("file")
-        .join("models")
-        .join("imgs");
+    md.push_str(title);
+    let store = &*MODELS.load();
+
+    if layout == ReportLayout::Compact {
+        md.push_str(&fmt_compact_results(
+            results,
+            store,
+            config.max_results,
+            tenant,
+        ));
+        return md;
+    }
 
     // 处理表格
     // 如果相似度低于50%没有必要处理
     let result_table: String = results
         .iter()
-        .take(if results.len() >= 10 {
-            10
-        } else {
-            results.len()
-        })
+        .take(config.max_results)
         .filter_map(|res| {
-            let img_path = img_dir
-                .join(&res.source_name)
-                .join(format!("{}_page_001", res.source_name));
-
-            if !img_path.exists() {
+            // 预览图缺失时尝试从页面图片或PDF生成，仍然失败才隐藏该结果
+            let pages = res.preview_urls(store, config.preview_pages);
+            if pages.is_empty() {
                 return None;
             }
-            
+            let images: Vec<String> = pages
+                .iter()
+                .map(|url| format!(r#"<img src="{}" width="400px" />"#, url))
+                .collect();
+
             Some(
                 MD_TABLE
-                    .replace("{$source}", &res.source_name)
-                    .replace("{$percentage}", &format!("{:.2}", res.percentage * 100.0))
+                    .replace("{$source}", &res.display_name())
+                    .replace("{$company}", res.company.as_deref().unwrap_or("-"))
+                    .replace("{$percentage}", &res.display_percentage())
+                    .replace("{$band}", &res.display_band())
                     .replace(
-                        "${img_path}",
-                        &format!(
-                            "https://huateng.voce.chat/api/resource/file?file_path=models/imgs/{}/{}_page_001",
-                            &res.source_name, &res.source_name
-                        ),
+                        "${dimensions}",
+                        &res.dimensions
+                            .as_ref()
+                            .map(|d| d.summary())
+                            .unwrap_or_default(),
                     )
+                    .replace("${images}", &images.join("\n"))
                     .replace(
                         "${href}",
-                        &format!(
-                            "http://45.76.31.59:3009/#/compare?file_path={}",
-                            res.source_name
-                        ),
+                        &IMAGE_URLS.compare_url(store.link_name(&res.source_name), tenant),
+                    )
+                    .replace(
+                        "${download}",
+                        &res.original_pdf_url()
+                            .map(|url| format!(r#" | <a href="{}">下载原图纸</a>"#, url))
+                            .unwrap_or_default(),
                     ),
             )
         })
@@ -455,11 +1091,55 @@ pub fn fmt_diff_result_to_md(results: &Vec<DiffResult>) -> String {
     md
 }
 
-fn fmt_diff_test(results: &Vec<DiffResult>) -> String {
+/// 每个结果一段，不使用表格，只附第一页预览图的链接
+fn fmt_compact_results(
+    results: &[DiffResult],
+    store: &ModelStore,
+    max_results: usize,
+    tenant: Option<&str>,
+) -> String {
+    results
+        .iter()
+        .take(max_results)
+        .enumerate()
+        .map(|(index, res)| {
+            let preview = res
+                .preview_urls(store, 1)
+                .first()
+                .map(|url| format!(" | [预览]({})", url))
+                .unwrap_or_default();
+            MD_COMPACT_ITEM
+                .replace("{$index}", &(index + 1).to_string())
+                .replace("{$source}", &res.display_name())
+                .replace("{$company}", res.company.as_deref().unwrap_or("-"))
+                .replace("{$percentage}", &res.display_percentage())
+                .replace("{$band}", &res.display_band())
+                .replace(
+                    "${dimensions}",
+                    &res.dimensions
+                        .as_ref()
+                        .map(|d| format!("| {}", d.summary()))
+                        .unwrap_or_default(),
+                )
+                .replace(
+                    "${href}",
+                    &IMAGE_URLS.compare_url(store.link_name(&res.source_name), tenant),
+                )
+                .replace("${preview}", &preview)
+                .replace(
+                    "${download}",
+                    &res.original_pdf_url()
+                        .map(|url| format!(" | [下载原图纸]({})", url))
+                        .unwrap_or_default(),
+                )
+        })
+        .collect()
+}
+
+fn fmt_diff_test(results: &[DiffResult]) -> String {
     let mut md = String::new();
     md.push_str("对该pdf文件进行相似度比较的结果如下:\n");
-    let img_dir =
-        PathBuf::from("D:\\work\\material_rs\\target\\debug\\data\\upload\\file\\models\\imgs");
+    let img_dir = upload_dir().join("models").join("imgs");
     // 处理表格
     // 如果相似度低于50%没有必要处理
     let result_table: String = results
@@ -485,7 +1165,8 @@ fn fmt_diff_test(results: &Vec<DiffResult>) -> String {
             Some(
                 MD_TABLE
                     .replace("{$source}", &res.source_name)
-                    .replace("{$percentage}", &format!("{:.2}", res.percentage * 100.0))
+                    .replace("{$percentage}", &res.display_percentage())
+                    .replace("{$band}", &res.display_band())
                     .replace("${base64_image}", &base64_image),
             )
         })
@@ -505,13 +1186,14 @@ mod tests {
     use std::collections::HashSet;
 
     use super::*;
+    use crate::{
+        config::DiffConfig,
+        paths::{TempDir, fixture},
+    };
 
     #[test]
     fn all_models() {
-        let models = ModelJson::patch_new(PathBuf::from(
-            "D:\\work\\material_rs\\target\\debug\\data\\upload\\file\\models\\jsons",
-        ))
-        .unwrap();
+        let models = ModelJson::patch_new(fixture("models/jsons")).unwrap();
 
         let mut set = HashSet::new();
 
@@ -527,20 +1209,29 @@ mod tests {
     #[test]
     fn diff() {
         // D:\work\material\output\json\208T-03_A基座-A3_Model_1_text_data.json
-        let model = ModelJson::new(PathBuf::from(
-            "D:\\work\\material_rs\\target\\debug\\data\\upload\\file\\models\\jsons\\ME121基座_text_data.json",
-        ))
-        .unwrap();
-        let models = ModelJson::patch_new(PathBuf::from(
-            "D:\\work\\material_rs\\target\\debug\\data\\upload\\file\\models\\jsons",
-        ))
-        .unwrap();
+        let model = ModelJson::new(fixture("models/jsons/ME121基座_text_data.json")).unwrap();
+        let models = ModelJson::patch_new(fixture("models/jsons")).unwrap();
         let sorted_models = ModelJson::sort(models);
-        let mut res = ModelJson::diff(sorted_models, model);
+        let mut res = ModelJson::diff(sorted_models, model, &ScoringProfile::default());
         DiffResult::sort(&mut res);
-        let res = fmt_diff_result_to_md(&res);
-        let md_file = "D:\\work\\material_rs\\test.md";
-        fs::write(md_file, res).expect("Failed to write markdown file");
+        let res = fmt_diff_result_to_md(&res, ReportLayout::Table, None);
+        let dir = TempDir::new("diff");
+        fs::write(dir.join("diff.md"), res).expect("Failed to write markdown file");
+    }
+
+    #[test]
+    fn diff_external_record() {
+        // 外部系统提交的记录没有来源
+        let model: ModelJson = serde_json::from_str(
+            r#"{"model_type": "基座", "materials": ["PBT RG301"], "project_name": null, "extraction_timestamp": null}"#,
+        )
+        .unwrap();
+        assert!(model.source_directory_name.is_empty());
+        let models = ModelJson::sort(ModelJson::patch_new(fixture("models/jsons")).unwrap());
+        let mut res = ModelJson::diff(models, model, &ScoringProfile::default());
+        DiffResult::sort(&mut res);
+        assert!(!res.is_empty());
+        assert!(res.windows(2).all(|w| w[0].percentage >= w[1].percentage));
     }
 
     #[test]
@@ -607,20 +1298,37 @@ mod tests {
     #[test]
     fn test_improved_diff_text() {
         // 测试完全相同
-        assert_eq!(improved_diff_text("PBT-RG301", "PBT-RG301"), 1.0);
+        assert_eq!(
+            improved_diff_text("PBT-RG301", "PBT-RG301", TextMetric::default()),
+            1.0
+        );
 
         // 测试子串匹配
-        let similarity1 = improved_diff_text("PBT", "PBT-RG301");
+        let similarity1 = improved_diff_text("PBT", "PBT-RG301", TextMetric::default());
         assert!(similarity1 > 0.3 && similarity1 < 1.0);
 
         // 测试相似材料代码
-        let similarity2 = improved_diff_text("PBT-RG301", "PBT-RG302");
+        let similarity2 = improved_diff_text("PBT-RG301", "PBT-RG302", TextMetric::default());
         assert!(similarity2 > 0.8);
 
         // 测试完全不同
-        let similarity3 = improved_diff_text("PBT", "ABS");
+        let similarity3 = improved_diff_text("PBT", "ABS", TextMetric::default());
         assert!(similarity3 < 0.5);
 
+        // 子串按字符数而不是字节数计算
+        let similarity4 = improved_diff_text("外壳", "Plug外壳", TextMetric::default());
+        assert!((similarity4 - 0.333).abs() < 0.01);
+        // 字符相同但顺序不同、多出字符的名称不再得到较高的分数
+        assert_eq!(
+            improved_diff_text("座基", "基座", TextMetric::default()),
+            0.0
+        );
+        assert_eq!(
+            improved_diff_text("89(89", "8989外壳", TextMetric::Levenshtein),
+            0.5
+        );
+        assert!(improved_diff_text("PBT-RG301", "PBT-RG302", TextMetric::JaroWinkler) > 0.9);
+
         println!("PBT vs PBT-RG301: {}", similarity1);
         println!("PBT-RG301 vs PBT-RG302: {}", similarity2);
         println!("PBT vs ABS: {}", similarity3);
@@ -632,18 +1340,408 @@ mod tests {
 
         let materials2 = vec!["PBT-RG301".to_string(), "ABS-V0".to_string()];
 
-        let similarity = calculate_material_similarity(&materials1, &materials2);
+        let similarity =
+            calculate_material_similarity(&materials1, &materials2, None, TextMetric::default());
         println!("材料相似度: {}", similarity);
         assert!(similarity > 0.7); // 应该有较高的相似度
 
         // 测试完全不同的材料
         let materials3 = vec!["Steel".to_string(), "Aluminum".to_string()];
 
-        let similarity2 = calculate_material_similarity(&materials1, &materials3);
+        let similarity2 =
+            calculate_material_similarity(&materials1, &materials3, None, TextMetric::default());
         println!("不同材料相似度: {}", similarity2);
         assert!(similarity2 < 0.3); // 应该有较低的相似度
     }
 
+    #[test]
+    fn test_is_customer() {
+        let model = ModelJson {
+            model_type: Some("基座".to_string()),
+            materials: Vec::new(),
+            canonical_materials: Vec::new(),
+            project_name: None,
+            company: Some("TCL 科技集团".to_string()),
+            dimensions: None,
+            drawing: None,
+            source_directory: PathBuf::new(),
+            source_directory_name: "a".to_string(),
+            extraction_timestamp: None,
+            original_pdf: None,
+            tags: vec!["表面处理:镀镍".to_string()],
+            finish: None,
+            id: None,
+            slug: None,
+            status: None,
+            provenance: None,
+        };
+        assert!(model.is_customer("tcl"));
+        assert!(model.has_tag("表面处理") && model.has_tag("镀镍"));
+        assert!(!model.has_tag("热处理") && !model.has_tag(" "));
+        assert!(!model.is_customer("宏发"));
+        assert!(
+            !ModelJson {
+                company: None,
+                ..model
+            }
+            .is_customer("TCL")
+        );
+    }
+
+    #[test]
+    fn test_parse_cache() {
+        let dir = TempDir::new("cache");
+        let path = dir.join("a.json");
+        let fixture = fs::read(fixture("models/jsons/ME121基座_text_data.json")).unwrap();
+        fs::write(&path, &fixture).unwrap();
+        let set_modified = |secs: u64| {
+            let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        set_modified(1_000);
+        assert_eq!(ModelJson::patch_new(dir.to_path_buf()).unwrap().len(), 1);
+
+        // 修改时间和大小不变时不重新解析
+        let mut broken = fixture.clone();
+        broken.fill(b' ');
+        fs::write(&path, &broken).unwrap();
+        set_modified(1_000);
+        assert_eq!(ModelJson::patch_new(dir.to_path_buf()).unwrap().len(), 1);
+
+        set_modified(2_000);
+        assert!(ModelJson::patch_new(dir.to_path_buf()).is_err());
+        let (models, failed) = ModelJson::patch_load(&dir);
+        assert!(models.is_empty());
+        assert_eq!(failed[0].0, path);
+    }
+
+    #[test]
+    fn test_compact_layout() {
+        let result = DiffResult {
+            source_directory: PathBuf::from("missing"),
+            source_name: "ME121基座".to_string(),
+            company: None,
+            dimensions: None,
+            original_pdf: Some(PathBuf::from("models/pdfs/ME121基座.pdf")),
+            exact_match: false,
+            status: Some(MoldStatus::Sealed),
+            percentage: 0.875,
+        };
+        let md = fmt_diff_result_to_md(&[result], ReportLayout::Compact, None);
+        // 没有预览图的结果也会列出，不使用表格
+        assert!(md.contains("**1. ME121基座 🔒封存** 相似度 87.50% (高度相似)"));
+        assert!(!md.contains("| --- |"));
+        assert!(!md.contains("预览"));
+        assert!(md.contains("[下载原图纸]("));
+        // 链接中的名称已转义
+        assert!(md.contains("models/pdfs/ME121%E5%9F%BA%E5%BA%A7.pdf"));
+    }
+
+    #[test]
+    fn test_sort_ties() {
+        let result = |name: &str, dir: &str, percentage: f32| DiffResult {
+            source_directory: PathBuf::from(dir),
+            source_name: name.to_string(),
+            company: None,
+            dimensions: None,
+            original_pdf: None,
+            exact_match: false,
+            status: None,
+            percentage,
+        };
+        // 浮点误差内的相似度视为相同，与输入顺序无关
+        for reversed in [false, true] {
+            let mut results = vec![
+                result("b", "1", 0.8),
+                result("c", "1", 0.9),
+                result("a", "2", 0.8 + 1e-7),
+                result("a", "1", 0.8),
+            ];
+            results[1].exact_match = true;
+            if reversed {
+                results.reverse();
+            }
+            DiffResult::sort(&mut results);
+            let order: Vec<(&str, &str)> = results
+                .iter()
+                .map(|r| (r.source_name.as_str(), r.source_directory.to_str().unwrap()))
+                .collect();
+            assert_eq!(order, [("c", "1"), ("a", "1"), ("a", "2"), ("b", "1")]);
+        }
+        assert_eq!(result("a", "1", 0.87499).display_percentage(), "87.50");
+        assert_eq!(result("a", "1", 0.12344).display_percentage(), "12.34");
+    }
+
+    #[test]
+    fn test_exact_type_first() {
+        let base = ModelJson::new(fixture("models/jsons/ME121基座_text_data.json")).unwrap();
+        let model = |name: &str, model_type: &str, material: &str| ModelJson {
+            model_type: Some(model_type.to_string()),
+            materials: vec![material.to_string()],
+            source_directory_name: name.to_string(),
+            ..base.clone()
+        };
+        let models = ModelJson::sort(vec![
+            model("a", "上基座", "PBT RG301"),
+            model("b", "基座", "LCP E4008"),
+            model("c", "下基座", "PBT RG301"),
+        ]);
+        let query = UserQuery {
+            model_type: Some("基座".to_string()),
+            materials: vec!["PBT RG301".to_string()],
+            ..Default::default()
+        };
+        let mut results = ModelJson::search_combined(&models, &query, &ScoringProfile::default());
+        DiffResult::sort(&mut results);
+        // 分组相近的结果相似度更高，但仍排在类型完全一致的结果之后
+        assert_eq!(results[0].source_name, "b");
+        assert!(results[0].exact_match);
+        assert!(results[1].percentage > results[0].percentage);
+    }
+
+    #[test]
+    fn test_in_period() {
+        let base = ModelJson::new(fixture("models/jsons/ME121基座_text_data.json")).unwrap();
+        let model = ModelJson {
+            extraction_timestamp: Some("2024-06-15T10:00:00+08:00".to_string()),
+            ..base.clone()
+        };
+        assert!(model.in_period(None, None));
+        assert!(model.in_period(Some("2024"), None));
+        assert!(model.in_period(Some("2024-06"), Some("2024-06")));
+        assert!(model.in_period(None, Some("2024-06-15")));
+        assert!(!model.in_period(Some("2024-07"), None));
+        assert!(!model.in_period(None, Some("2023")));
+        let unknown = ModelJson {
+            extraction_timestamp: None,
+            ..base
+        };
+        assert!(unknown.in_period(None, None));
+        assert!(!unknown.in_period(Some("2020"), None));
+    }
+
+    #[test]
+    fn test_scoring_profiles() {
+        let base = ModelJson::new(fixture("models/jsons/ME121基座_text_data.json")).unwrap();
+        let with_size = |x_max: f64| ModelJson {
+            dimensions: Some(Dimensions {
+                x_max: Some(x_max),
+                ..Default::default()
+            }),
+            ..base.clone()
+        };
+        let uploaded = with_size(100.0);
+        let (close, far) = (with_size(100.5), with_size(150.0));
+
+        let config = DiffConfig::default();
+        let default = config.profile(None).unwrap();
+        assert_eq!(
+            uploaded.similarity(&close, default).percentage,
+            uploaded.similarity(&far, default).percentage
+        );
+        // 模具部门的方案计入尺寸
+        let tooling = config.profile(Some("Tooling")).unwrap();
+        assert!(
+            uploaded.similarity(&close, tooling).percentage
+                > uploaded.similarity(&far, tooling).percentage
+        );
+        assert!(config.profile(Some("unknown")).is_none());
+    }
+
+    #[test]
+    fn test_extracted_dimensions_verdict() {
+        // 模型回复中的尺寸经过提取结果保存到上传图纸的记录上
+        let reply = serde_json::json!({
+            "x_max": 100,
+            "y_max": "60",
+            "x_tolerance": "±0.05",
+            "y_tolerance": "±0.05"
+        });
+        let mut extraction = TextExtractionResult::new_success(
+            PathBuf::from("upload"),
+            Some("基座".to_string()),
+            vec!["PBT RG301".to_string()],
+            None,
+        );
+        extraction.dimensions = Dimensions::from_extraction(&reply);
+        let uploaded = ModelJson::from(extraction);
+        assert_eq!(uploaded.dimensions.as_ref().unwrap().x_max, Some(100.0));
+
+        let candidate = ModelJson {
+            source_directory_name: "ME121基座".to_string(),
+            dimensions: Some(Dimensions {
+                x_max: Some(100.5),
+                y_max: Some(60.0),
+                x_tolerance: Some("±0.1".to_string()),
+                y_tolerance: Some("±0.02".to_string()),
+            }),
+            ..uploaded.clone()
+        };
+        let models = HashMap::from([("基座".to_string(), vec![candidate])]);
+        let results = ModelJson::diff(models, uploaded, &ScoringProfile::default());
+        let comparison = results[0].dimensions.as_ref().unwrap();
+        assert!(comparison.is_close());
+        // 候选模具长度方向的公差比图纸要求的宽
+        assert_eq!(comparison.tooling_sufficient(), Some(false));
+        assert!(comparison.summary().contains("公差等级不足"));
+    }
+
+    #[test]
+    fn test_extracted_scale_conflict() {
+        let reply = serde_json::json!({ "x_max": 300, "y_max": 120 });
+        let mut extraction = TextExtractionResult::new_success(
+            PathBuf::from("upload"),
+            Some("基座".to_string()),
+            vec!["PBT RG301".to_string()],
+            None,
+        );
+        extraction.dimensions = Dimensions::from_extraction(&reply);
+        extraction.drawing = DrawingFormat::new(Some("2:1".to_string()), Some("A4".to_string()));
+        let uploaded = ModelJson::from(extraction);
+        assert_eq!(uploaded.drawing.as_ref().unwrap().scale_ratio, Some(2.0));
+        // 600x240mm画不进A4
+        let conflict = uploaded.scale_conflict().unwrap();
+        assert!(conflict.message.contains("A4"));
+
+        // 1:1时画得下，没有比例或图幅时不做判断
+        let fits = ModelJson {
+            drawing: Some(DrawingFormat::new(
+                Some("1:1".to_string()),
+                Some("A3".to_string()),
+            )),
+            ..uploaded.clone()
+        };
+        assert!(fits.scale_conflict().is_none());
+        let unknown = ModelJson {
+            drawing: None,
+            ..uploaded
+        };
+        assert!(unknown.scale_conflict().is_none());
+    }
+
+    #[test]
+    fn test_canonical_materials() {
+        let base = ModelJson::new(fixture("models/jsons/ME121基座_text_data.json")).unwrap();
+        let model = ModelJson {
+            materials: vec!["PBT 3316".to_string(), "ABC-123".to_string()],
+            canonical_materials: Vec::new(),
+            ..base
+        };
+        // 历史数据按词表转换，词表中没有的材料保持原样
+        assert_eq!(
+            model.comparison_materials().as_ref(),
+            ["长春 PBT 3316 GF30", "ABC-123"]
+        );
+        let canonical = canonical_materials(&["长春3316 GF30 PBT".to_string()]);
+        assert_eq!(
+            calculate_material_similarity(
+                &model.comparison_materials()[..1],
+                &canonical,
+                None,
+                TextMetric::default()
+            ),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_taxonomy_ranking() {
+        fn rank(candidates: &[&'static str], score: impl Fn(&str) -> f32) -> Vec<&'static str> {
+            let mut ranked = candidates.to_vec();
+            ranked.sort_by(|a, b| score(b).total_cmp(&score(a)));
+            ranked
+        }
+
+        let metric = TextMetric::default();
+
+        // 只比较文本时字面更接近的排在前面，按词表同一分组的类型排到前面
+        let types = ["上基板", "基座板"];
+        assert_eq!(
+            rank(&types, |t| improved_diff_text("上基座", t, metric)),
+            ["上基板", "基座板"]
+        );
+        assert_eq!(
+            rank(&types, |t| calculate_model_type_similarity(
+                "上基座",
+                t,
+                metric
+            )),
+            ["基座板", "上基板"]
+        );
+
+        // 同一牌号的不同写法排在相近牌号前面
+        let materials = ["金发 PBT RG305(白)", "PBT-RG301 黑色"];
+        assert_eq!(
+            rank(&materials, |m| improved_diff_text(
+                "金发 PBT RG301(白)",
+                m,
+                metric
+            )),
+            ["金发 PBT RG305(白)", "PBT-RG301 黑色"]
+        );
+        assert_eq!(
+            rank(&materials, |m| calculate_single_material_similarity(
+                "金发 PBT RG301(白)",
+                m,
+                None,
+                metric
+            )),
+            ["PBT-RG301 黑色", "金发 PBT RG305(白)"]
+        );
+    }
+
+    #[test]
+    fn test_taxonomy_similarity() {
+        assert!(
+            calculate_model_type_similarity("基座", "上基座", TextMetric::default())
+                >= GROUP_SIMILARITY
+        );
+        assert!(calculate_model_type_similarity("基座", "外壳", TextMetric::default()) < 0.5);
+        // 词表中的中文类型名称
+        for (type1, type2, expected) in [
+            ("基座", "底座", 0.5),
+            ("上盖", "盖板", 0.0),
+            ("支架", "线圈支架", 0.5),
+            ("骨架", "线圈架", GROUP_SIMILARITY),
+            ("控制盒上壳", "外壳", 0.2),
+        ] {
+            let similarity = calculate_model_type_similarity(type1, type2, TextMetric::default());
+            assert!(
+                (similarity - expected).abs() < 0.01,
+                "{} vs {}: {}",
+                type1,
+                type2,
+                similarity
+            );
+        }
+        assert!(
+            calculate_model_type_similarity("基座", "底座", TextMetric::JaroWinkler)
+                > calculate_model_type_similarity("基座", "底座", TextMetric::Levenshtein)
+        );
+        assert!(
+            calculate_single_material_similarity(
+                "金发 PBT RG301(白)",
+                "PBT-RG301 黑色",
+                None,
+                TextMetric::default()
+            ) >= GRADE_SIMILARITY
+        );
+        // 不可替代的材料即使写法相近也不算相似
+        assert_eq!(
+            calculate_single_material_similarity("PA46", "PA4T", None, TextMetric::default()),
+            0.0
+        );
+        assert!(
+            calculate_single_material_similarity("PA66 RG301", "PA6", None, TextMetric::default())
+                >= 0.7
+        );
+    }
+
     #[test]
     fn test_split_text_improved() {
         let result = split_text_improved("PBT-RG301");
@@ -656,6 +1754,23 @@ mod tests {
         println!("分词结果2: {:?}", result2);
     }
 
+    #[test]
+    fn unidentified_extraction() {
+        let mut model = ModelJson::from(TextExtractionResult::new_success(
+            PathBuf::from("page_0.jpg"),
+            Some(" ".to_string()),
+            Vec::new(),
+            None,
+        ));
+        assert!(model.is_unidentified());
+        model.materials.push("PBT".to_string());
+        assert!(!model.is_unidentified());
+
+        let content = fmt_unidentified_to_md(&["https://example.com/page_001".to_string()]);
+        assert!(content.contains("- 类型: 基座;"));
+        assert!(content.contains(r#"<img src="https://example.com/page_001""#));
+    }
+
     #[test]
     fn copy_meta_to_png() {
         // let path = "D:\\work\\material_rs\\target\\debug\\data\\upload\\file\\models\\imgs";
@@ -0,0 +1,267 @@
+//! 考虑公差等级的尺寸比较
+//!
+//! 上传图纸与候选模具的尺寸接近并不代表模具可用：候选模具的公差(模具精度)
+//! 需要不低于上传图纸的要求，例如图纸要求±0.05时，按±0.1做的模具不够用。
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ai")]
+use crate::ai_analyzer::{AnalysisIResult, ViewAnalysis};
+
+/// 名义尺寸相差不超过这个比例视为接近
+const NOMINAL_RATIO: f64 = 0.02;
+
+/// 图纸的外形尺寸和公差
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub x_max: Option<f64>,
+    pub y_max: Option<f64>,
+    pub x_tolerance: Option<String>,
+    pub y_tolerance: Option<String>,
+}
+
+impl Dimensions {
+    /// 从文本提取回复的`x_max`、`y_max`、`x_tolerance`、`y_tolerance`读取，都没有时为None
+    pub fn from_extraction(value: &serde_json::Value) -> Option<Self> {
+        let number = |key: &str| match value.get(key)? {
+            serde_json::Value::Number(n) => n.as_f64(),
+            serde_json::Value::String(s) => s.trim().trim_end_matches("mm").trim().parse().ok(),
+            _ => None,
+        };
+        let text = |key: &str| {
+            value
+                .get(key)?
+                .as_str()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        let dimensions = Self {
+            x_max: number("x_max").filter(|v| *v > 0.0),
+            y_max: number("y_max").filter(|v| *v > 0.0),
+            x_tolerance: text("x_tolerance"),
+            y_tolerance: text("y_tolerance"),
+        };
+        (dimensions.x_max.is_some() || dimensions.y_max.is_some()).then_some(dimensions)
+    }
+}
+
+#[cfg(feature = "ai")]
+impl From<&AnalysisIResult> for Dimensions {
+    /// 使用异常修正后的最大尺寸，公差取标注该尺寸的视图
+    fn from(result: &AnalysisIResult) -> Self {
+        let x_max = result.anomalies.corrected_x_max;
+        let y_max = result.anomalies.corrected_y_max;
+        let models = || {
+            result.views.iter().filter_map(|view| match view {
+                ViewAnalysis::Model(model) => Some(model),
+                _ => None,
+            })
+        };
+        Self {
+            x_max,
+            y_max,
+            x_tolerance: models()
+                .find(|m| m.x_max.is_some() && m.x_max == x_max)
+                .and_then(|m| m.x_tolerance.clone()),
+            y_tolerance: models()
+                .find(|m| m.y_max.is_some() && m.y_max == y_max)
+                .and_then(|m| m.y_tolerance.clone()),
+        }
+    }
+}
+
+/// 单个方向的比较结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisComparison {
+    pub uploaded: f64,
+    pub candidate: f64,
+    /// 名义尺寸是否接近
+    pub close: bool,
+    /// 上传图纸要求的公差
+    pub required_tolerance: Option<f64>,
+    /// 候选模具的公差
+    pub candidate_tolerance: Option<f64>,
+    /// 候选模具的公差等级是否满足要求，任一方公差未知时为None
+    pub tooling_sufficient: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DimensionComparison {
+    pub x: Option<AxisComparison>,
+    pub y: Option<AxisComparison>,
+}
+
+impl DimensionComparison {
+    fn axes(&self) -> impl Iterator<Item = &AxisComparison> {
+        self.x.iter().chain(self.y.iter())
+    }
+
+    /// 所有方向的名义尺寸都接近
+    pub fn is_close(&self) -> bool {
+        self.axes().next().is_some() && self.axes().all(|a| a.close)
+    }
+
+    /// 候选模具的公差等级是否满足要求，任一方向不满足即为false
+    pub fn tooling_sufficient(&self) -> Option<bool> {
+        self.axes()
+            .filter_map(|a| a.tooling_sufficient)
+            .reduce(|a, b| a && b)
+    }
+
+    /// 参与相似度计算的得分: 尺寸接近的方向所占的比例，公差等级不足时减半
+    pub fn score(&self) -> Option<f32> {
+        let axes = self.axes().count();
+        if axes == 0 {
+            return None;
+        }
+        let close = self.axes().filter(|a| a.close).count() as f32 / axes as f32;
+        match self.tooling_sufficient() {
+            Some(false) => Some(close * 0.5),
+            _ => Some(close),
+        }
+    }
+
+    /// 在结果中展示的一行说明
+    pub fn summary(&self) -> String {
+        let size = if self.is_close() {
+            "✅ 尺寸接近"
+        } else {
+            "⚠️ 尺寸差异较大"
+        };
+        let tolerance = match self.tooling_sufficient() {
+            Some(true) => "✅ 公差等级满足",
+            Some(false) => "❌ 公差等级不足",
+            None => "公差未知",
+        };
+        format!("{}，{}", size, tolerance)
+    }
+}
+
+/// 比较上传图纸和候选模具的尺寸，都有尺寸的方向才会比较
+pub fn compare_dimensions(uploaded: &Dimensions, candidate: &Dimensions) -> DimensionComparison {
+    DimensionComparison {
+        x: compare_axis(
+            uploaded.x_max,
+            uploaded.x_tolerance.as_deref(),
+            candidate.x_max,
+            candidate.x_tolerance.as_deref(),
+        ),
+        y: compare_axis(
+            uploaded.y_max,
+            uploaded.y_tolerance.as_deref(),
+            candidate.y_max,
+            candidate.y_tolerance.as_deref(),
+        ),
+    }
+}
+
+fn compare_axis(
+    uploaded: Option<f64>,
+    uploaded_tolerance: Option<&str>,
+    candidate: Option<f64>,
+    candidate_tolerance: Option<&str>,
+) -> Option<AxisComparison> {
+    let (uploaded, candidate) = (uploaded?, candidate?);
+    let required_tolerance = uploaded_tolerance.and_then(parse_tolerance);
+    let candidate_tolerance = candidate_tolerance.and_then(parse_tolerance);
+    // 名义尺寸的差异在要求的公差或比例范围内都算接近
+    let allowed = (uploaded.abs() * NOMINAL_RATIO).max(required_tolerance.unwrap_or(0.0));
+    Some(AxisComparison {
+        uploaded,
+        candidate,
+        close: (uploaded - candidate).abs() <= allowed,
+        required_tolerance,
+        candidate_tolerance,
+        tooling_sufficient: required_tolerance
+            .zip(candidate_tolerance)
+            .map(|(required, candidate)| candidate <= required + f64::EPSILON),
+    })
+}
+
+/// 解析公差文字，返回最严格一侧的公差带
+///
+/// `±0.1` → 0.1，`+0.1/-0.05` → 0.05，`0.05` → 0.05
+pub fn parse_tolerance(text: &str) -> Option<f64> {
+    let mut values = Vec::new();
+    let mut current = String::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_digit() || (c == '.' && !current.is_empty()) {
+            current.push(c);
+        } else if !current.is_empty() {
+            if let Ok(value) = current.parse::<f64>() {
+                values.push(value);
+            }
+            current.clear();
+        }
+    }
+    values
+        .into_iter()
+        .filter(|v| *v > 0.0)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tolerances() {
+        assert_eq!(parse_tolerance("±0.1"), Some(0.1));
+        assert_eq!(parse_tolerance("+0.1/-0.05"), Some(0.05));
+        assert_eq!(parse_tolerance("0.05"), Some(0.05));
+        assert_eq!(parse_tolerance("+0.1/0"), Some(0.1));
+        assert_eq!(parse_tolerance("无"), None);
+    }
+
+    #[test]
+    fn dimensions_from_extraction() {
+        let dimensions = Dimensions::from_extraction(&serde_json::json!({
+            "x_max": "120.5mm",
+            "y_max": 80,
+            "x_tolerance": "±0.05",
+            "y_tolerance": ""
+        }))
+        .unwrap();
+        assert_eq!(dimensions.x_max, Some(120.5));
+        assert_eq!(dimensions.y_max, Some(80.0));
+        assert_eq!(dimensions.x_tolerance.as_deref(), Some("±0.05"));
+        assert_eq!(dimensions.y_tolerance, None);
+        // 只有公差没有尺寸时无法比较
+        assert!(Dimensions::from_extraction(&serde_json::json!({"x_tolerance": "±0.1"})).is_none());
+    }
+
+    #[test]
+    fn compare_with_tolerance_class() {
+        let uploaded = Dimensions {
+            x_max: Some(50.0),
+            y_max: Some(20.0),
+            x_tolerance: Some("±0.05".to_string()),
+            y_tolerance: None,
+        };
+        let loose = Dimensions {
+            x_max: Some(50.3),
+            y_max: Some(20.1),
+            x_tolerance: Some("±0.1".to_string()),
+            y_tolerance: Some("±0.1".to_string()),
+        };
+        let comparison = compare_dimensions(&uploaded, &loose);
+        assert!(comparison.is_close());
+        assert_eq!(comparison.tooling_sufficient(), Some(false));
+        assert_eq!(comparison.y.as_ref().unwrap().tooling_sufficient, None);
+
+        let tight = Dimensions {
+            x_tolerance: Some("±0.02".to_string()),
+            ..loose.clone()
+        };
+        assert_eq!(
+            compare_dimensions(&uploaded, &tight).tooling_sufficient(),
+            Some(true)
+        );
+
+        let far = Dimensions {
+            x_max: Some(60.0),
+            ..tight
+        };
+        assert!(!compare_dimensions(&uploaded, &far).is_close());
+        assert!(!compare_dimensions(&uploaded, &Dimensions::default()).is_close());
+    }
+}
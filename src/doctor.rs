@@ -0,0 +1,267 @@
+//! 运行环境自检
+//!
+//! 检查PDF转换工具(poppler)、SAM需要的Python模块、数据目录、密钥、模型服务和分类词表，
+//! 输出就绪报告。通过`material-cli doctor`执行，或设置`MATERIAL_STARTUP_CHECK=1`在服务启动时执行，
+//! 有错误时服务不启动。
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use crate::{
+    CONFIG,
+    blob::blobs_dir,
+    job::jobs_dir,
+    model_store::models_dir,
+    paths::{data_dir, upload_dir},
+    secrets,
+    taxonomy::{Taxonomy, taxonomy_dir},
+};
+
+/// 检查模型服务是否可以连接的超时时间
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// 检查目录是否可写时写入的临时文件
+const PROBE_FILE: &str = ".doctor";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Ok,
+    /// 部分功能不可用，服务可以运行
+    Warning,
+    /// 服务无法正常工作
+    Error,
+}
+
+impl Level {
+    fn icon(&self) -> &'static str {
+        match self {
+            Self::Ok => "✅",
+            Self::Warning => "⚠️",
+            Self::Error => "❌",
+        }
+    }
+}
+
+/// 一项检查的结果
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub level: Level,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, level: Level, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            level,
+            detail: detail.into(),
+        }
+    }
+
+    /// 成功时为Ok，失败时为指定的级别
+    fn from_result(name: &'static str, result: Result<String, String>, failure: Level) -> Self {
+        match result {
+            Ok(detail) => Self::new(name, Level::Ok, detail),
+            Err(detail) => Self::new(name, failure, detail),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// 没有错误级别的检查项
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|c| c.level < Level::Error)
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for check in &self.checks {
+            let _ = writeln!(
+                text,
+                "{} {}: {}",
+                check.level.icon(),
+                check.name,
+                check.detail
+            );
+        }
+        let errors = self
+            .checks
+            .iter()
+            .filter(|c| c.level == Level::Error)
+            .count();
+        let warnings = self
+            .checks
+            .iter()
+            .filter(|c| c.level == Level::Warning)
+            .count();
+        let _ = match (errors, warnings) {
+            (0, 0) => write!(text, "环境就绪"),
+            (0, warnings) => write!(text, "环境就绪，{} 项警告", warnings),
+            (errors, warnings) => {
+                write!(text, "环境未就绪，{} 项错误，{} 项警告", errors, warnings)
+            }
+        };
+        text
+    }
+}
+
+/// 执行全部检查
+pub async fn run(client: &reqwest::Client) -> Report {
+    let mut checks = vec![Check::from_result(
+        "PDF转换(poppler)",
+        check_poppler(),
+        Level::Error,
+    )];
+    checks.push(Check::from_result(
+        "Python依赖",
+        check_python().await,
+        Level::Warning,
+    ));
+
+    for (name, dir) in data_dirs() {
+        checks.push(Check::from_result(name, check_dir(&dir), Level::Error));
+    }
+    checks.push(Check::from_result(
+        "密钥",
+        secrets::check()
+            .map(|_| "已配置".to_string())
+            .map_err(|e| e.to_string()),
+        Level::Error,
+    ));
+    checks.push(Check::from_result(
+        "模型服务",
+        check_provider(client).await,
+        Level::Error,
+    ));
+    checks.push(Check::from_result(
+        "分类词表",
+        check_taxonomy(&taxonomy_dir()),
+        Level::Error,
+    ));
+    Report { checks }
+}
+
+fn data_dirs() -> [(&'static str, PathBuf); 5] {
+    [
+        ("数据目录", data_dir()),
+        ("上传目录", upload_dir()),
+        ("模具目录", models_dir()),
+        ("任务目录", jobs_dir()),
+        ("文件目录", blobs_dir()),
+    ]
+}
+
+/// SAM需要的Python模块
+#[cfg(feature = "sam")]
+async fn check_python() -> Result<String, String> {
+    tokio::task::spawn_blocking(|| {
+        crate::sam::check_python_dependencies()
+            .map(|_| "SAM需要的Python模块齐全".to_string())
+            .map_err(|e| format!("{}，SAM视图切分不可用", e))
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("检查Python模块失败: {}", e)))
+}
+
+/// 没有启用`sam`功能时不需要Python
+#[cfg(not(feature = "sam"))]
+async fn check_python() -> Result<String, String> {
+    Err("未启用sam功能，SAM视图切分不可用".to_string())
+}
+
+/// `pdftocairo -v`的版本信息
+fn check_poppler() -> Result<String, String> {
+    let output = Command::new("pdftocairo")
+        .arg("-v")
+        .output()
+        .map_err(|e| format!("找不到pdftocairo，请安装poppler: {}", e))?;
+    // pdftocairo把版本信息输出到stderr
+    let version = String::from_utf8_lossy(&output.stderr);
+    Ok(version
+        .lines()
+        .next()
+        .unwrap_or("pdftocairo")
+        .trim()
+        .to_string())
+}
+
+/// 目录不存在时创建，并检查是否可写
+fn check_dir(dir: &Path) -> Result<String, String> {
+    let probe = dir.join(PROBE_FILE);
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map(|_| dir.display().to_string())
+        .map_err(|e| format!("{} 不可写: {}", dir.display(), e))
+}
+
+/// 模型服务可以连接，收到任何HTTP回复都视为可以连接
+async fn check_provider(client: &reqwest::Client) -> Result<String, String> {
+    let url = match &CONFIG.ai.api {
+        Some(api) => api.endpoint.clone(),
+        None => format!("{}/api/tags", CONFIG.ai.ollama_base.trim_end_matches('/')),
+    };
+    let response = client
+        .get(&url)
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("无法连接 {}: {}", url, e))?;
+    Ok(format!("{} ({})", url, response.status()))
+}
+
+/// 词表文件格式正确，缺少的文件使用内置词表
+fn check_taxonomy(dir: &Path) -> Result<String, String> {
+    let taxonomy = Taxonomy::load(dir).map_err(|e| format!("{} 格式错误: {}", dir.display(), e))?;
+    Ok(format!(
+        "{} 组模具类型，{} 个材料族",
+        taxonomy.model_types.len(),
+        taxonomy.materials.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::TempDir;
+
+    #[test]
+    fn readiness_report() {
+        let dir = TempDir::new("doctor");
+        assert!(check_dir(&dir.join("jobs")).is_ok());
+        assert!(!dir.join("jobs").join(PROBE_FILE).exists());
+        assert!(check_taxonomy(&dir).is_ok());
+
+        std::fs::write(dir.join("file"), b"").unwrap();
+        let mut report = Report {
+            checks: vec![
+                Check::from_result("任务目录", check_dir(&dir.join("jobs")), Level::Error),
+                Check::from_result("Python依赖", Err("缺少torch".to_string()), Level::Warning),
+            ],
+        };
+        assert!(report.is_ready());
+        assert!(
+            report.render().ends_with("环境就绪，1 项警告"),
+            "{}",
+            report.render()
+        );
+
+        // 同名文件已存在时无法创建目录
+        report.checks.push(Check::from_result(
+            "文件目录",
+            check_dir(&dir.join("file")),
+            Level::Error,
+        ));
+        assert!(!report.is_ready());
+        assert!(report.render().contains("❌ 文件目录"));
+        assert!(report.render().ends_with("1 项错误，1 项警告"));
+    }
+}
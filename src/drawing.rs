@@ -0,0 +1,143 @@
+//! 图纸比例和图幅
+//!
+//! 标题栏中的比例(`1:1`、`2:1`)和图幅(A0~A4)用来检查提取出的尺寸是否合理：
+//! 按比例换算后的图上尺寸超出图幅时，说明尺寸或比例识别有误。
+use serde::{Deserialize, Serialize};
+
+/// 图纸比例和图幅
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DrawingFormat {
+    /// 标题栏中的原始比例文字
+    pub scale: Option<String>,
+    /// 图上尺寸 / 实际尺寸，`2:1`为2.0
+    pub scale_ratio: Option<f64>,
+    /// 图幅，例如`A3`
+    pub sheet_size: Option<String>,
+}
+
+impl DrawingFormat {
+    pub fn new(scale: Option<String>, sheet_size: Option<String>) -> Self {
+        let scale_ratio = scale.as_deref().and_then(parse_scale);
+        let sheet_size = sheet_size
+            .as_deref()
+            .and_then(sheet_dimensions)
+            .map(|(name, _, _)| name.to_string())
+            .or(sheet_size);
+        Self {
+            scale,
+            scale_ratio,
+            sheet_size,
+        }
+    }
+
+    /// 比例和图幅都没有识别出来
+    pub fn is_empty(&self) -> bool {
+        self.scale.is_none() && self.sheet_size.is_none()
+    }
+
+    /// 检查实际尺寸按比例换算后能否画在图幅内
+    pub fn check(&self, x_max: Option<f64>, y_max: Option<f64>) -> Option<ScaleConflict> {
+        let ratio = self.scale_ratio?;
+        let (_, long, short) = sheet_dimensions(self.sheet_size.as_deref()?)?;
+        let drawn_x = x_max.map(|x| x * ratio);
+        let drawn_y = y_max.map(|y| y * ratio);
+
+        // 图纸可能横放或竖放，只要求长边不超过图幅长边、短边不超过图幅短边
+        let (drawn_long, drawn_short) = match (drawn_x, drawn_y) {
+            (Some(x), Some(y)) => (x.max(y), x.min(y)),
+            (Some(v), None) | (None, Some(v)) => (v, 0.0),
+            (None, None) => return None,
+        };
+        if drawn_long <= long && drawn_short <= short {
+            return None;
+        }
+
+        Some(ScaleConflict {
+            drawn_x,
+            drawn_y,
+            sheet_width: long,
+            sheet_height: short,
+            message: format!(
+                "按比例{}换算的图上尺寸{}超出了{}图幅({}x{}mm)，尺寸或比例可能识别有误",
+                self.scale.as_deref().unwrap_or_default(),
+                fmt_drawn(drawn_x, drawn_y),
+                self.sheet_size.as_deref().unwrap_or_default(),
+                long,
+                short
+            ),
+        })
+    }
+}
+
+/// 尺寸与比例/图幅矛盾
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScaleConflict {
+    /// 换算后的图上尺寸(mm)
+    pub drawn_x: Option<f64>,
+    pub drawn_y: Option<f64>,
+    pub sheet_width: f64,
+    pub sheet_height: f64,
+    pub message: String,
+}
+
+fn fmt_drawn(x: Option<f64>, y: Option<f64>) -> String {
+    let fmt = |v: Option<f64>| v.map(|v| format!("{:.1}", v)).unwrap_or("-".to_string());
+    format!("{}x{}mm", fmt(x), fmt(y))
+}
+
+/// 解析`1:2`、`2：1`、`1/2`形式的比例
+pub fn parse_scale(text: &str) -> Option<f64> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let (drawn, actual) = text.split_once([':', '：', '/'])?;
+    let number = |s: &str| -> Option<f64> {
+        let s: String = s
+            .chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        s.parse().ok().filter(|v: &f64| *v > 0.0)
+    };
+    Some(number(drawn)? / number(actual)?)
+}
+
+/// 图幅名称和长短边尺寸(mm)
+pub fn sheet_dimensions(text: &str) -> Option<(&'static str, f64, f64)> {
+    const SHEETS: [(&str, f64, f64); 5] = [
+        ("A0", 1189.0, 841.0),
+        ("A1", 841.0, 594.0),
+        ("A2", 594.0, 420.0),
+        ("A3", 420.0, 297.0),
+        ("A4", 297.0, 210.0),
+    ];
+    let text = text.to_uppercase();
+    SHEETS.into_iter().find(|(name, _, _)| text.contains(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scales() {
+        assert_eq!(parse_scale("1:1"), Some(1.0));
+        assert_eq!(parse_scale("比例 2：1"), Some(2.0));
+        assert_eq!(parse_scale("1 / 2"), Some(0.5));
+        assert_eq!(parse_scale("1:0"), None);
+        assert_eq!(parse_scale("NTS"), None);
+        assert_eq!(sheet_dimensions("a3横").map(|s| s.0), Some("A3"));
+    }
+
+    #[test]
+    fn check_scale_conflict() {
+        let format = DrawingFormat::new(Some("2:1".to_string()), Some("a4".to_string()));
+        assert_eq!(format.sheet_size.as_deref(), Some("A4"));
+        assert_eq!(format.check(Some(100.0), Some(80.0)), None);
+
+        let conflict = format.check(Some(200.0), Some(80.0)).unwrap();
+        assert_eq!(conflict.drawn_x, Some(400.0));
+
+        // 缺少比例或图幅时不检查
+        let format = DrawingFormat::new(None, Some("A4".to_string()));
+        assert_eq!(format.check(Some(1000.0), None), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! 疑似重复模具的提醒
+//!
+//! 新图纸与已有模具几乎相同时，往往可以直接复用旧模具而不必重新开模。
+//! 最佳结果达到提醒阈值时在报告开头提醒提交的用户，达到更高的通知阈值时再通知主管所在的群组。
+use std::path::Path;
+
+use tracing::info;
+
+use crate::{
+    CONFIG, HTTP_CLIENT, config::DuplicateAlertConfig, diff::DiffResult, job::JobRecord,
+    mold_status::MoldStatus, workflow::send_markdown,
+};
+
+/// 相似度不低于`threshold`的最佳结果，已报废的模具无法复用，不计入
+pub fn find_duplicate(results: &[DiffResult], threshold: f32) -> Option<&DiffResult> {
+    results
+        .iter()
+        .filter(|result| result.status != Some(MoldStatus::Scrapped))
+        .filter(|result| result.percentage >= threshold)
+        .max_by_key(|result| result.basis_points())
+}
+
+fn fill(template: &str, duplicate: &DiffResult) -> String {
+    template
+        .replace("{name}", &duplicate.display_name())
+        .replace("{percentage}", &duplicate.display_percentage())
+}
+
+/// 报告开头的提醒
+pub fn warning(config: &DuplicateAlertConfig, results: &[DiffResult]) -> Option<String> {
+    find_duplicate(results, config.warn_threshold).map(|duplicate| fill(&config.warning, duplicate))
+}
+
+/// 发给主管的通知，没有配置群组或没有达到通知阈值时为None
+pub fn notification(config: &DuplicateAlertConfig, job: &JobRecord) -> Option<String> {
+    config.supervisor_gid?;
+    let duplicate = find_duplicate(&job.matches, config.notify_threshold)?;
+    let file = Path::new(&job.input)
+        .file_name()
+        .map_or(job.input.clone(), |name| {
+            name.to_string_lossy().into_owned()
+        });
+    Some(
+        fill(&config.notification, duplicate)
+            .replace("{file}", &file)
+            .replace("{uid}", &job.from_uid.to_string())
+            .replace("{job}", &job.id),
+    )
+}
+
+/// 在后台通知主管
+pub fn notify(config: &DuplicateAlertConfig, job: &JobRecord) {
+    let (Some(gid), Some(content)) = (config.supervisor_gid, notification(config, job)) else {
+        return;
+    };
+    info!("任务 {} 疑似重复模具，通知群组 {}", job.id, gid);
+    tokio::spawn(async move {
+        let bot = &CONFIG.bot;
+        let url = bot.url(&format!("/api/bot/send_to_group/{}", gid));
+        send_markdown(&HTTP_CLIENT, &url, &bot.api_key, &content).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::job::JobKind;
+
+    fn result(name: &str, percentage: f32, status: Option<MoldStatus>) -> DiffResult {
+        DiffResult {
+            source_directory: PathBuf::from(name),
+            source_name: name.to_string(),
+            company: None,
+            dimensions: None,
+            original_pdf: None,
+            exact_match: false,
+            status,
+            percentage,
+        }
+    }
+
+    #[test]
+    fn duplicate_thresholds() {
+        let config = DuplicateAlertConfig {
+            warn_threshold: 0.9,
+            notify_threshold: 0.95,
+            supervisor_gid: Some(3),
+            ..DuplicateAlertConfig::default()
+        };
+        let results = vec![
+            // 类型完全一致的结果排在前面，但不是相似度最高的
+            DiffResult {
+                exact_match: true,
+                ..result("ME120", 0.91, None)
+            },
+            result("ME121", 0.93, Some(MoldStatus::Sealed)),
+            result("ME122", 0.99, Some(MoldStatus::Scrapped)),
+        ];
+        assert_eq!(
+            warning(&config, &results).unwrap(),
+            "⚠️ 可能已有相同模具: **ME121 🔒封存**, 相似度 93.00%，请先确认能否复用"
+        );
+        assert!(warning(&config, &results[..0]).is_none());
+
+        let mut job = JobRecord::new(JobKind::Pdf, 1, 7, "uploads/ab/新基座.pdf".to_string());
+        job.matches = results;
+        // 只达到提醒阈值
+        assert!(notification(&config, &job).is_none());
+
+        job.matches.push(result("ME123", 0.9612, None));
+        let content = notification(&config, &job).unwrap();
+        assert!(content.contains("用户 7 提交的图纸 `新基座.pdf`"));
+        assert!(content.contains("**ME123**"));
+        assert!(content.contains("相似度 96.12%"));
+        assert!(content.contains(&job.id));
+
+        let config = DuplicateAlertConfig {
+            supervisor_gid: None,
+            ..config
+        };
+        assert!(notification(&config, &job).is_none());
+    }
+}
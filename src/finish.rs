@@ -0,0 +1,123 @@
+//! 表面处理、皮纹和钢材硬度要求
+//!
+//! 技术要求中常见的表面处理(镀镍、喷砂)、皮纹(VDI 24)、模具钢材(S136)和硬度(HRC48-52)
+//! 影响模具能否复用，双方都有的项目按`ScoringProfile::finish_weight`参与相似度计算。
+use serde::{Deserialize, Serialize};
+
+use crate::{diff::improved_diff_text, text_metric::TextMetric};
+
+/// VDI皮纹等级相差这么多时相似度为0
+const VDI_RANGE: f32 = 12.0;
+/// 硬度(HRC)相差这么多时相似度为0
+const HRC_RANGE: f32 = 20.0;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Finish {
+    /// 表面处理，例如`镀镍`
+    pub surface: Option<String>,
+    /// 皮纹，例如`VDI 24`
+    pub texture: Option<String>,
+    /// 模具钢材，例如`S136`
+    pub steel: Option<String>,
+    /// 硬度要求，例如`HRC48-52`
+    pub hardness: Option<String>,
+}
+
+impl Finish {
+    pub fn is_empty(&self) -> bool {
+        self.surface.is_none()
+            && self.texture.is_none()
+            && self.steel.is_none()
+            && self.hardness.is_none()
+    }
+
+    /// 缺少的项目使用`other`中的值
+    pub fn or(self, other: &Finish) -> Finish {
+        Finish {
+            surface: self.surface.or_else(|| other.surface.clone()),
+            texture: self.texture.or_else(|| other.texture.clone()),
+            steel: self.steel.or_else(|| other.steel.clone()),
+            hardness: self.hardness.or_else(|| other.hardness.clone()),
+        }
+    }
+}
+
+/// 双方都有的项目的平均相似度，没有共同项目时为None
+pub fn compare_finish(a: &Finish, b: &Finish) -> Option<f32> {
+    let text = |a: &Option<String>, b: &Option<String>| {
+        Some(improved_diff_text(
+            &normalize(a.as_deref()?),
+            &normalize(b.as_deref()?),
+            TextMetric::default(),
+        ))
+    };
+    let similarities = [
+        text(&a.surface, &b.surface),
+        numeric(a.texture.as_deref(), b.texture.as_deref(), VDI_RANGE)
+            .or_else(|| text(&a.texture, &b.texture)),
+        text(&a.steel, &b.steel),
+        numeric(a.hardness.as_deref(), b.hardness.as_deref(), HRC_RANGE)
+            .or_else(|| text(&a.hardness, &b.hardness)),
+    ];
+    let similarities: Vec<f32> = similarities.into_iter().flatten().collect();
+    (!similarities.is_empty()).then(|| similarities.iter().sum::<f32>() / similarities.len() as f32)
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_uppercase())
+        .collect()
+}
+
+/// 按数值比较，范围取中间值，例如`HRC48-52`为50
+fn numeric(a: Option<&str>, b: Option<&str>, range: f32) -> Option<f32> {
+    let (a, b) = (mid_value(a?)?, mid_value(b?)?);
+    Some((1.0 - (a - b).abs() / range).max(0.0))
+}
+
+fn mid_value(text: &str) -> Option<f32> {
+    let numbers: Vec<f32> = text
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .filter_map(|s| s.parse().ok())
+        .collect();
+    match numbers.as_slice() {
+        [] => None,
+        [value] => Some(*value),
+        [low, high, ..] => Some((low + high) / 2.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finish(texture: Option<&str>, hardness: Option<&str>) -> Finish {
+        Finish {
+            texture: texture.map(str::to_string),
+            hardness: hardness.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compare_common_items() {
+        let a = finish(Some("VDI 24"), Some("HRC48-52"));
+        assert_eq!(compare_finish(&a, &a), Some(1.0));
+
+        let b = finish(Some("VDI-30"), None);
+        assert_eq!(compare_finish(&a, &b), Some(0.5));
+
+        // 没有共同项目时不参与比较
+        let c = Finish {
+            steel: Some("S136".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(compare_finish(&a, &c), None);
+        assert_eq!(compare_finish(&Finish::default(), &a), None);
+
+        let merged = c.or(&a);
+        assert_eq!(merged.steel.as_deref(), Some("S136"));
+        assert_eq!(merged.texture.as_deref(), Some("VDI 24"));
+    }
+}
@@ -0,0 +1,165 @@
+//! 共用的对外HTTP客户端
+//!
+//! reqwest的客户端内部带连接池，整个服务共用一个，由调用方注入到分析器和提醒中。
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::{
+    AnalyzerError, IResult,
+    config::{ApiConfig, ApiProvider, HttpConfig},
+};
+
+/// 按配置创建HTTP客户端
+pub fn build_client(config: &HttpConfig) -> IResult<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_seconds))
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_seconds));
+
+    if let Some(proxy) = &config.proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| AnalyzerError::ConfigError(format!("代理地址无效 {}: {}", proxy, e)))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = &config.ca_cert {
+        let pem = std::fs::read(path).map_err(|e| {
+            AnalyzerError::ConfigError(format!("读取CA证书失败 {}: {}", path.display(), e))
+        })?;
+        let cert = reqwest::Certificate::from_pem(&pem).map_err(|e| {
+            AnalyzerError::ConfigError(format!("CA证书无效 {}: {}", path.display(), e))
+        })?;
+        builder = builder.add_root_certificate(cert);
+    }
+
+    builder
+        .build()
+        .map_err(|e| AnalyzerError::ConfigError(format!("创建HTTP客户端失败: {}", e)))
+}
+
+/// 发往模型接口的请求，附加配置中的采样参数、额外请求头和参数
+///
+/// 兼容模式下参数合并到请求体顶层，DashScope原生格式合并到`parameters`中，
+/// `extra_params`中的同名参数优先。Azure OpenAI使用`api-key`请求头鉴权。
+pub fn provider_request(
+    client: &reqwest::Client,
+    url: &str,
+    api: &ApiConfig,
+    mut payload: Value,
+) -> reqwest::RequestBuilder {
+    let params = if api.openai_format() {
+        payload.as_object_mut()
+    } else {
+        payload.get_mut("parameters").and_then(Value::as_object_mut)
+    };
+    if let Some(params) = params {
+        params.insert("temperature".to_string(), api.sampling.temperature.into());
+        if let Some(seed) = api.sampling.seed {
+            params.insert("seed".to_string(), seed.into());
+        }
+        params.extend(api.extra_params.clone());
+    }
+
+    let mut request = match api.provider {
+        ApiProvider::AzureOpenAi => client.post(url).header("api-key", &api.api_key),
+        ApiProvider::DashScope | ApiProvider::OpenAi => client
+            .post(url)
+            .header("Authorization", format!("Bearer {}", api.api_key)),
+    }
+    .header("Content-Type", "application/json");
+    for (name, value) in &api.extra_headers {
+        request = request.header(name, value);
+    }
+    request.json(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Sampling;
+
+    #[test]
+    fn build_with_settings() {
+        let config = HttpConfig {
+            proxy: Some("http://127.0.0.1:7890".to_string()),
+            ca_cert: None,
+            ..HttpConfig::default()
+        };
+        assert!(build_client(&config).is_ok());
+
+        let invalid_proxy = HttpConfig {
+            proxy: Some("::not a url".to_string()),
+            ..config.clone()
+        };
+        assert!(build_client(&invalid_proxy).is_err());
+
+        let missing_ca = HttpConfig {
+            ca_cert: Some(std::env::temp_dir().join("material_missing_ca.pem")),
+            ..config
+        };
+        assert!(build_client(&missing_ca).is_err());
+    }
+
+    #[test]
+    fn provider_extras() {
+        let mut api = ApiConfig {
+            api_key: "sk".to_string(),
+            endpoint: "https://llm.example.com/v1".to_string(),
+            model_name: "qwen-vl-max".to_string(),
+            use_compatible_mode: true,
+            extra_headers: [("X-Org-Id".to_string(), "factory".to_string())].into(),
+            extra_params: serde_json::json!({"top_p": 0.8, "temperature": 0.0})
+                .as_object()
+                .unwrap()
+                .clone(),
+            sampling: Sampling {
+                temperature: 0.1,
+                seed: Some(42),
+            },
+            ..ApiConfig::default()
+        };
+        let payload = serde_json::json!({"model": "qwen-vl-max"});
+        let request = provider_request(&reqwest::Client::new(), &api.endpoint, &api, payload)
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["X-Org-Id"], "factory");
+        assert_eq!(request.headers()["Authorization"], "Bearer sk");
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["top_p"], 0.8);
+        assert_eq!(body["seed"], 42);
+        // 额外参数优先
+        assert_eq!(body["temperature"], 0.0);
+
+        // DashScope原生格式的参数在`parameters`中
+        api.use_compatible_mode = false;
+        let payload =
+            serde_json::json!({"model": "qwen-vl-max", "parameters": {"max_tokens": 1024}});
+        let request = provider_request(&reqwest::Client::new(), &api.endpoint, &api, payload)
+            .build()
+            .unwrap();
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["parameters"]["top_p"], 0.8);
+        assert_eq!(body["parameters"]["max_tokens"], 1024);
+        assert_eq!(body["parameters"]["seed"], 42);
+        assert!(body.get("top_p").is_none());
+
+        // Azure OpenAI不使用Bearer鉴权
+        api.provider = ApiProvider::AzureOpenAi;
+        let request = provider_request(
+            &reqwest::Client::new(),
+            &api.chat_url(),
+            &api,
+            serde_json::json!({}),
+        )
+        .build()
+        .unwrap();
+        assert_eq!(request.headers()["api-key"], "sk");
+        assert!(request.headers().get("Authorization").is_none());
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["top_p"], 0.8);
+    }
+}
@@ -0,0 +1,213 @@
+//! 结果中图片链接的生成和校验
+//!
+//! 配置了签名密钥时生成带有效期的链接，`token = hex(hmac_sha256(secret, "{path}:{expires}"))`，
+//! 由`/material/files`路由(前缀见`ServerConfig::base_path`)校验后返回文件。
+//! 链接中的路径按段转义，签名使用转义前的路径，与路由解码后的路径一致。
+use hmac::{Hmac, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use sha2::Sha256;
+
+use crate::config::ImageUrlConfig;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 路径段和查询参数中需要转义的字符，只保留不需要转义的`-._~`
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+pub struct ImageUrlBuilder {
+    config: ImageUrlConfig,
+    /// 签名链接的路由路径
+    files_path: String,
+}
+
+impl ImageUrlBuilder {
+    pub fn new(config: ImageUrlConfig) -> Self {
+        Self {
+            config,
+            files_path: "/material/files".to_string(),
+        }
+    }
+
+    /// 签名链接使用的路由路径，见`ServerConfig::path`
+    pub fn with_files_path(mut self, files_path: String) -> Self {
+        self.files_path = files_path;
+        self
+    }
+
+    /// 是否生成签名链接
+    pub fn is_signed(&self) -> bool {
+        self.config.signing_secret.is_some()
+    }
+
+    /// 图片链接，`path`为相对上传目录的路径
+    pub fn image_url(&self, path: &str) -> String {
+        self.image_url_at(path, chrono::Utc::now().timestamp())
+    }
+
+    fn image_url_at(&self, path: &str, now: i64) -> String {
+        match &self.config.signing_secret {
+            Some(secret) => {
+                let expires = now + self.config.ttl_seconds as i64;
+                format!(
+                    "{}{}/{}?expires={}&token={}",
+                    self.config.base_url.trim_end_matches('/'),
+                    self.files_path,
+                    encode_path(path),
+                    expires,
+                    sign(secret, path, expires)
+                )
+            }
+            None => self.config.public_url.replace("{path}", &encode_path(path)),
+        }
+    }
+
+    /// 查看模型的页面链接，`tenant`为消息来源的VoceChat域名，没有单独配置时使用默认的前端
+    pub fn compare_url(&self, name: &str, tenant: Option<&str>) -> String {
+        let base = tenant
+            .and_then(|tenant| self.config.tenant_compare_base_urls.get(tenant))
+            .unwrap_or(&self.config.compare_base_url);
+        self.config
+            .compare_url
+            .replace("{base}", base.trim_end_matches('/'))
+            .replace("{name}", &utf8_percent_encode(name, COMPONENT).to_string())
+    }
+
+    /// 校验签名链接，`path`为路由解码后的路径，未配置密钥时一律拒绝
+    pub fn verify(&self, path: &str, expires: i64, token: &str) -> bool {
+        self.verify_at(path, expires, token, chrono::Utc::now().timestamp())
+    }
+
+    fn verify_at(&self, path: &str, expires: i64, token: &str, now: i64) -> bool {
+        let Some(secret) = &self.config.signing_secret else {
+            return false;
+        };
+        let Ok(token) = hex::decode(token) else {
+            return false;
+        };
+        expires >= now && mac(secret, path, expires).verify_slice(&token).is_ok()
+    }
+}
+
+/// 按`/`分段转义路径，保留分隔符
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| utf8_percent_encode(segment, COMPONENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn mac(secret: &str, path: &str, expires: i64) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("{}:{}", path, expires).as_bytes());
+    mac
+}
+
+fn sign(secret: &str, path: &str, expires: i64) -> String {
+    hex::encode(mac(secret, path, expires).finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder(secret: Option<&str>) -> ImageUrlBuilder {
+        ImageUrlBuilder::new(ImageUrlConfig {
+            base_url: "https://example.com/".to_string(),
+            signing_secret: secret.map(|s| s.to_string()),
+            ttl_seconds: 60,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn public_url_without_secret() {
+        let urls = builder(None);
+        assert_eq!(
+            urls.image_url("models/imgs/a/a_page_001"),
+            "https://huateng.voce.chat/api/resource/file?file_path=models/imgs/a/a_page_001"
+        );
+        assert!(!urls.verify("models/imgs/a/a_page_001", i64::MAX, ""));
+    }
+
+    #[test]
+    fn signed_url_roundtrip() {
+        let urls = builder(Some("secret"));
+        let path = "models/imgs/a/a_page_001";
+        let url = urls.image_url_at(path, 1000);
+        assert!(url.starts_with(
+            "https://example.com/material/files/models/imgs/a/a_page_001?expires=1060&token="
+        ));
+
+        let token = url.rsplit_once("token=").unwrap().1;
+        assert!(urls.verify_at(path, 1060, token, 1000));
+        // 过期
+        assert!(!urls.verify_at(path, 1060, token, 1061));
+        // 篡改路径或有效期
+        assert!(!urls.verify_at("models/imgs/b/b_page_001", 1060, token, 1000));
+        assert!(!urls.verify_at(path, 2000, token, 1000));
+        assert!(!urls.verify_at(path, 1060, "zz", 1000));
+    }
+
+    #[test]
+    fn non_ascii_names() {
+        let urls = builder(Some("secret"));
+        let path = "models/imgs/ME121 基座#2/ME121 基座#2_page_001";
+        let url = urls.image_url_at(path, 1000);
+        assert!(
+            url.starts_with(
+                "https://example.com/material/files/models/imgs/ME121%20%E5%9F%BA%E5%BA%A7%232/ME121%20%E5%9F%BA%E5%BA%A7%232_page_001?expires=1060&token="
+            ),
+            "{}",
+            url
+        );
+        // 签名的是解码后的路径
+        let token = url.rsplit_once("token=").unwrap().1;
+        assert!(urls.verify_at(path, 1060, token, 1000));
+
+        let urls = builder(None);
+        assert!(
+            urls.image_url("models/a&b/a b")
+                .ends_with("file_path=models/a%26b/a%20b")
+        );
+        let urls = ImageUrlBuilder::new(ImageUrlConfig {
+            compare_url: "{base}/#/compare?file_path={name}".to_string(),
+            compare_base_url: "https://compare.example.com".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(
+            urls.compare_url("ME121 基座#2", None),
+            "https://compare.example.com/#/compare?file_path=ME121%20%E5%9F%BA%E5%BA%A7%232"
+        );
+    }
+
+    #[test]
+    fn compare_url_per_tenant() {
+        let urls = ImageUrlBuilder::new(ImageUrlConfig {
+            compare_url: "{base}/#/compare?file_path={name}".to_string(),
+            compare_base_url: "https://compare.example.com/".to_string(),
+            tenant_compare_base_urls: [(
+                "staging.voce.chat".to_string(),
+                "https://staging.example.com".to_string(),
+            )]
+            .into(),
+            ..Default::default()
+        });
+        assert_eq!(
+            urls.compare_url("me121-k3xq2d", None),
+            "https://compare.example.com/#/compare?file_path=me121-k3xq2d"
+        );
+        assert_eq!(
+            urls.compare_url("me121-k3xq2d", Some("staging.voce.chat")),
+            "https://staging.example.com/#/compare?file_path=me121-k3xq2d"
+        );
+        assert_eq!(
+            urls.compare_url("a", Some("other.voce.chat")),
+            "https://compare.example.com/#/compare?file_path=a"
+        );
+    }
+}
@@ -0,0 +1,828 @@
+//! 后台任务登记
+//!
+//! 每个任务的记录保存在`data/jobs/<id>.json`，撤回或编辑消息时可以找到并取消对应的任务，
+//! 用户对结果的反馈也记录在任务上。
+//!
+//! 任务的中间文件放在独立的工作目录`data/jobs/<id>/`中，同名文件的任务互不影响:
+//! ```text
+//! pages/           PDF转换出的页面图片
+//! views/           SAM切分出的视图
+//! reports/         生成的报告
+//! responses/       模型服务的原始回复(gzip压缩)，见`response_archive`
+//! extraction.json  合并和每页的文本提取结果
+//! ```
+#[cfg(feature = "server")]
+use std::io::{Cursor, Write};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+#[cfg(feature = "server")]
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    IResult, JOB_EVENTS,
+    ai_text_analyzer::TextExtraction,
+    diff::{DiffResult, ModelJson},
+    job_events::JobProgress,
+    paths::{data_dir, write_atomic},
+    usage::TokenUsage,
+};
+
+/// 任务id至少需要的前缀长度
+const MIN_ID_PREFIX: usize = 6;
+/// 工作目录中的页面图片目录
+pub const PAGES_DIR: &str = "pages";
+/// 工作目录中的SAM视图目录
+pub const VIEWS_DIR: &str = "views";
+/// 工作目录中的报告目录
+pub const REPORTS_DIR: &str = "reports";
+/// 工作目录中的模型服务原始回复存档
+pub const RESPONSES_DIR: &str = "responses";
+/// 工作目录中的文本提取结果
+pub const EXTRACTION_FILE: &str = "extraction.json";
+/// 报告目录中发送给用户的markdown
+pub const REPORT_FILE: &str = "report.md";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// PDF图纸分析
+    Pdf,
+    /// 文本检索
+    Search,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// 运行中任务所处的阶段，用于轮询任务进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    /// 只读模式下暂停，恢复读写后执行
+    Paused,
+    /// 等待执行许可
+    Queued,
+    /// 准备输入，例如PDF转换为图片
+    Preparing,
+    /// 提取和比较
+    Executing,
+    /// 生成和发送报告
+    Reporting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Good,
+    Bad,
+}
+
+impl Verdict {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "good" | "好" | "👍" | "+1" => Some(Self::Good),
+            "bad" | "差" | "👎" | "-1" => Some(Self::Bad),
+            _ => None,
+        }
+    }
+}
+
+/// 用户对结果质量的反馈
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feedback {
+    pub verdict: Verdict,
+    pub comment: Option<String>,
+    pub from_uid: u64,
+    pub created_at: String,
+}
+
+/// 任务记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    /// 最近进入的阶段
+    #[serde(default)]
+    pub stage: Option<JobStage>,
+    /// 触发任务的消息
+    pub mid: u64,
+    pub from_uid: u64,
+    /// PDF路径或检索语句
+    pub input: String,
+    /// 提取使用的提示词版本
+    pub prompt_version: Option<String>,
+    /// 提取使用的模型
+    pub model_name: Option<String>,
+    /// 发送结果的消息，用于匹配对结果的表情回应
+    pub result_mid: Option<u64>,
+    /// 上传PDF的sha256，PDF分析任务才有
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// 任务的工作目录
+    #[serde(default)]
+    pub work_dir: Option<PathBuf>,
+    /// PDF转换出的图片目录
+    #[serde(default)]
+    pub images_dir: Option<PathBuf>,
+    /// 模型提取的结果
+    #[serde(default)]
+    pub extraction: Option<ModelJson>,
+    /// 报告中列出的匹配结果，按相似度排序
+    #[serde(default)]
+    pub matches: Vec<DiffResult>,
+    pub error: Option<String>,
+    /// 执行耗时
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// 执行次数(包括重试)
+    #[serde(default)]
+    pub attempts: u32,
+    /// 模型服务的token用量和估算费用
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    #[serde(default)]
+    pub feedback: Vec<Feedback>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl JobRecord {
+    pub fn new(kind: JobKind, mid: u64, from_uid: u64, input: String) -> Self {
+        let now = chrono::Local::now().to_rfc3339();
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            kind,
+            status: JobStatus::Running,
+            stage: None,
+            mid,
+            from_uid,
+            input,
+            prompt_version: None,
+            model_name: None,
+            result_mid: None,
+            content_hash: None,
+            work_dir: None,
+            images_dir: None,
+            extraction: None,
+            matches: Vec::new(),
+            error: None,
+            duration_ms: None,
+            attempts: 0,
+            usage: None,
+            feedback: Vec::new(),
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// 最新的一条反馈
+    pub fn verdict(&self) -> Option<Verdict> {
+        self.feedback.last().map(|f| f.verdict)
+    }
+}
+
+/// 某个提示词/模型版本的反馈统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FeedbackMetrics {
+    pub prompt_version: Option<String>,
+    pub model_name: Option<String>,
+    pub jobs: usize,
+    pub good: usize,
+    pub bad: usize,
+    /// good / (good + bad)
+    pub precision: Option<f32>,
+}
+
+/// 任务记录所在的数据目录
+pub fn jobs_dir() -> PathBuf {
+    data_dir().join("jobs")
+}
+
+/// 任务登记表
+pub struct JobRegistry {
+    dir: PathBuf,
+    records: RwLock<HashMap<String, JobRecord>>,
+    /// 正在运行的任务，按触发它的消息mid索引，一条消息中的多个文件对应多个任务
+    running: Mutex<HashMap<u64, Vec<(String, AbortHandle)>>>,
+    /// 运行中任务的取消信号，按任务id索引，工作流和模型服务请求收到信号后停止
+    cancellations: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl JobRegistry {
+    /// 打开任务目录并加载已有的任务记录
+    pub fn open(dir: PathBuf) -> Self {
+        let mut records = HashMap::new();
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                    continue;
+                }
+                match read_record(&path) {
+                    Ok(record) => {
+                        records.insert(record.id.clone(), record);
+                    }
+                    Err(e) => warn!("读取任务记录失败 {}: {}", path.display(), e),
+                }
+            }
+        }
+        info!("已加载 {} 个任务记录", records.len());
+
+        Self {
+            dir,
+            records: RwLock::new(records),
+            running: Mutex::new(HashMap::new()),
+            cancellations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 保存新任务，返回任务id
+    pub fn create(&self, record: JobRecord) -> String {
+        let id = record.id.clone();
+        self.save(&record);
+        self.records.write().unwrap().insert(id.clone(), record);
+        id
+    }
+
+    /// 修改任务记录并保存
+    pub fn update(&self, id: &str, f: impl FnOnce(&mut JobRecord)) -> Option<JobRecord> {
+        let mut records = self.records.write().unwrap();
+        let record = records.get_mut(id)?;
+        f(record);
+        record.updated_at = chrono::Local::now().to_rfc3339();
+        let record = record.clone();
+        drop(records);
+        self.save(&record);
+        Some(record)
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobRecord> {
+        self.records.read().unwrap().get(id).cloned()
+    }
+
+    /// 按完整id或唯一的id前缀查找任务
+    pub fn find(&self, id_prefix: &str) -> Option<JobRecord> {
+        let id_prefix = id_prefix.trim();
+        if let Some(record) = self.get(id_prefix) {
+            return Some(record);
+        }
+        if id_prefix.len() < MIN_ID_PREFIX {
+            return None;
+        }
+        let records = self.records.read().unwrap();
+        let mut matched = records.values().filter(|r| r.id.starts_with(id_prefix));
+        match (matched.next(), matched.next()) {
+            (Some(record), None) => Some(record.clone()),
+            _ => None,
+        }
+    }
+
+    /// 按结果消息查找任务
+    pub fn find_by_result_mid(&self, mid: u64) -> Option<JobRecord> {
+        self.records
+            .read()
+            .unwrap()
+            .values()
+            .find(|r| r.result_mid == Some(mid))
+            .cloned()
+    }
+
+    /// 由消息`mid`触发的任务
+    pub fn find_by_mid(&self, mid: u64) -> Vec<JobRecord> {
+        self.records
+            .read()
+            .unwrap()
+            .values()
+            .filter(|r| r.mid == mid)
+            .cloned()
+            .collect()
+    }
+
+    /// 用户`from_uid`上传的相同内容的PDF最近一次成功并保存了报告的分析，
+    /// 不返回其他用户的任务，他们的报告不能发给这个用户，也不能由这个用户`/force`重新分析
+    pub fn find_analyzed(&self, content_hash: &str, from_uid: u64) -> Option<JobRecord> {
+        self.records
+            .read()
+            .unwrap()
+            .values()
+            .filter(|r| {
+                r.kind == JobKind::Pdf
+                    && r.status == JobStatus::Succeeded
+                    && r.from_uid == from_uid
+                    && r.content_hash.as_deref() == Some(content_hash)
+            })
+            .filter(|r| {
+                self.dir
+                    .join(&r.id)
+                    .join(REPORTS_DIR)
+                    .join(REPORT_FILE)
+                    .is_file()
+            })
+            .max_by(|a, b| a.created_at.cmp(&b.created_at))
+            .cloned()
+    }
+
+    pub fn all(&self) -> Vec<JobRecord> {
+        self.records.read().unwrap().values().cloned().collect()
+    }
+
+    /// 登记一个由消息`mid`触发的任务，同一条消息之前的任务会被取消
+    pub fn track(&self, mid: u64, id: &str, handle: JoinHandle<()>) {
+        self.track_all(mid, vec![(id.to_string(), handle)]);
+    }
+
+    /// 登记由消息`mid`触发的多个任务，同一条消息之前的任务会被取消
+    pub fn track_all(&self, mid: u64, jobs: Vec<(String, JoinHandle<()>)>) {
+        let old = {
+            let mut running = self.running.lock().unwrap();
+            running.retain(|_, handles| handles.iter().any(|(_, h)| !h.is_finished()));
+            let handles = jobs
+                .into_iter()
+                .map(|(id, handle)| (id, handle.abort_handle()))
+                .collect();
+            running.insert(mid, handles)
+        };
+        for (old_id, old) in old.into_iter().flatten() {
+            self.abort(&old_id, old);
+        }
+    }
+
+    /// 取消消息`mid`对应的任务，返回是否真的取消了正在运行的任务
+    pub fn cancel(&self, mid: u64) -> bool {
+        let handles = self.running.lock().unwrap().remove(&mid);
+        let mut cancelled = false;
+        for (id, handle) in handles.into_iter().flatten() {
+            if !handle.is_finished() {
+                self.abort(&id, handle);
+                cancelled = true;
+            }
+        }
+        if cancelled {
+            info!("🛑 已取消消息 {} 对应的任务", mid);
+        }
+        cancelled
+    }
+
+    /// 任务的取消信号，任务结束后调用`finish_cancellation`
+    pub fn cancellation(&self, id: &str) -> CancellationToken {
+        self.cancellations
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// 任务已经结束，不再需要取消信号
+    pub fn finish_cancellation(&self, id: &str) {
+        self.cancellations.lock().unwrap().remove(id);
+    }
+
+    /// 取消用户`from_uid`正在运行的任务，返回取消的任务id
+    pub fn cancel_user(&self, from_uid: u64) -> Vec<String> {
+        let ids: Vec<String> = {
+            let records = self.records.read().unwrap();
+            let cancellations = self.cancellations.lock().unwrap();
+            cancellations
+                .keys()
+                .filter(|id| {
+                    records
+                        .get(*id)
+                        .is_some_and(|r| r.from_uid == from_uid && r.status == JobStatus::Running)
+                })
+                .cloned()
+                .collect()
+        };
+        for id in &ids {
+            self.mark_cancelled(id);
+        }
+        if !ids.is_empty() {
+            info!("🛑 已取消用户 {} 的任务: {:?}", from_uid, ids);
+        }
+        ids
+    }
+
+    /// 创建任务独立的工作目录并记录在任务上
+    pub fn create_work_dir(&self, id: &str) -> IResult<PathBuf> {
+        let work_dir = self.dir.join(id);
+        for sub_dir in [PAGES_DIR, VIEWS_DIR, REPORTS_DIR] {
+            std::fs::create_dir_all(work_dir.join(sub_dir))?;
+        }
+        self.update(id, |record| record.work_dir = Some(work_dir.clone()));
+        Ok(work_dir)
+    }
+
+    /// 把文本提取结果保存到任务的工作目录
+    pub fn save_extraction(&self, id: &str, extraction: &TextExtraction) -> IResult<()> {
+        let work_dir = self.dir.join(id);
+        std::fs::create_dir_all(&work_dir)?;
+        std::fs::write(
+            work_dir.join(EXTRACTION_FILE),
+            serde_json::to_string_pretty(extraction)?,
+        )?;
+        Ok(())
+    }
+
+    /// 把发送给用户的报告保存到任务的工作目录
+    pub fn save_report(&self, id: &str, content: &str) -> IResult<()> {
+        let reports_dir = self.dir.join(id).join(REPORTS_DIR);
+        std::fs::create_dir_all(&reports_dir)?;
+        std::fs::write(reports_dir.join(REPORT_FILE), content)?;
+        Ok(())
+    }
+
+    /// 把任务记录(包括匹配结果)和工作目录中的文件打包为zip，用于离线排查问题
+    ///
+    /// 任务不存在时为None。
+    #[cfg(feature = "server")]
+    pub fn artifacts(&self, id: &str) -> Option<IResult<Vec<u8>>> {
+        let record = self.get(id)?;
+        Some(self.write_artifacts(&record))
+    }
+
+    #[cfg(feature = "server")]
+    fn write_artifacts(&self, record: &JobRecord) -> IResult<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zip.start_file("job.json", options)
+            .map_err(std::io::Error::from)?;
+        zip.write_all(serde_json::to_string_pretty(record)?.as_bytes())?;
+        zip.start_file("matches.json", options)
+            .map_err(std::io::Error::from)?;
+        zip.write_all(serde_json::to_string_pretty(&record.matches)?.as_bytes())?;
+
+        let work_dir = self.dir.join(&record.id);
+        let mut dirs = vec![work_dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let Some(name) = path.strip_prefix(&work_dir).ok().and_then(|p| p.to_str()) else {
+                    continue;
+                };
+                zip.start_file(name.replace('\\', "/"), options)
+                    .map_err(std::io::Error::from)?;
+                std::io::copy(&mut std::fs::File::open(&path)?, &mut zip)?;
+            }
+        }
+        Ok(zip.finish().map_err(std::io::Error::from)?.into_inner())
+    }
+
+    /// 发送给用户的报告，任务没有完成时为None
+    pub fn report(&self, id: &str) -> Option<String> {
+        std::fs::read_to_string(self.dir.join(id).join(REPORTS_DIR).join(REPORT_FILE)).ok()
+    }
+
+    /// 任务的文本提取结果，没有进行过提取时为None
+    pub fn extraction(&self, id: &str) -> Option<TextExtraction> {
+        let content = std::fs::read_to_string(self.dir.join(id).join(EXTRACTION_FILE)).ok()?;
+        serde_json::from_str(&content)
+            .inspect_err(|e| warn!("读取提取结果失败 {}: {}", id, e))
+            .ok()
+    }
+
+    /// 记录对任务结果的反馈，替换同一用户之前的反馈
+    pub fn add_feedback(&self, id: &str, feedback: Feedback) -> Option<JobRecord> {
+        self.update(id, |record| {
+            record
+                .feedback
+                .retain(|previous| previous.from_uid != feedback.from_uid);
+            record.feedback.push(feedback)
+        })
+    }
+
+    /// 按提示词/模型版本统计反馈
+    pub fn feedback_metrics(&self) -> Vec<FeedbackMetrics> {
+        let mut groups: HashMap<(Option<String>, Option<String>), FeedbackMetrics> = HashMap::new();
+        for record in self.records.read().unwrap().values() {
+            let Some(verdict) = record.verdict() else {
+                continue;
+            };
+            let key = (record.prompt_version.clone(), record.model_name.clone());
+            let metrics = groups.entry(key).or_insert_with(|| FeedbackMetrics {
+                prompt_version: record.prompt_version.clone(),
+                model_name: record.model_name.clone(),
+                ..Default::default()
+            });
+            metrics.jobs += 1;
+            match verdict {
+                Verdict::Good => metrics.good += 1,
+                Verdict::Bad => metrics.bad += 1,
+            }
+        }
+
+        let mut metrics: Vec<_> = groups
+            .into_values()
+            .map(|mut m| {
+                m.precision = Some(m.good as f32 / (m.good + m.bad) as f32);
+                m
+            })
+            .collect();
+        metrics.sort_by(|a, b| {
+            (&a.prompt_version, &a.model_name).cmp(&(&b.prompt_version, &b.model_name))
+        });
+        metrics
+    }
+
+    fn abort(&self, id: &str, handle: AbortHandle) {
+        handle.abort();
+        self.mark_cancelled(id);
+    }
+
+    /// 发出取消信号，把运行中的任务标记为已取消
+    fn mark_cancelled(&self, id: &str) {
+        if let Some(token) = self.cancellations.lock().unwrap().remove(id) {
+            token.cancel();
+        }
+        let record = self.update(id, |record| {
+            if record.status == JobStatus::Running {
+                record.status = JobStatus::Cancelled;
+            }
+        });
+        if let Some(record) = record {
+            JOB_EVENTS.publish(id, JobProgress::finished(&record));
+        }
+    }
+
+    fn save(&self, record: &JobRecord) {
+        let result = std::fs::create_dir_all(&self.dir)
+            .map_err(Into::into)
+            .and_then(|_| write_record(&self.dir.join(format!("{}.json", record.id)), record));
+        if let Err(e) = result {
+            error!("保存任务记录失败 {}: {}", record.id, e);
+        }
+    }
+}
+
+fn read_record(path: &Path) -> IResult<JobRecord> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn write_record(path: &Path, record: &JobRecord) -> IResult<()> {
+    write_atomic(path, serde_json::to_string_pretty(record)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{ai_text_analyzer::TextExtractionResult, paths::TempDir};
+
+    fn temp_registry() -> (TempDir, JobRegistry) {
+        let dir = TempDir::new("jobs");
+        let jobs = JobRegistry::open(dir.to_path_buf());
+        (dir, jobs)
+    }
+
+    #[tokio::test]
+    async fn cancel_running_job() {
+        let (_dir, jobs) = temp_registry();
+        let id = jobs.create(JobRecord::new(JobKind::Search, 1, 1, "基座".to_string()));
+        let handle = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+        jobs.track(1, &id, handle);
+        assert!(jobs.cancel(1));
+        assert!(!jobs.cancel(1));
+        assert!(!jobs.cancel(2));
+        assert_eq!(jobs.get(&id).unwrap().status, JobStatus::Cancelled);
+
+        // 一条消息中的多个文件
+        let ids: Vec<String> = (0..2)
+            .map(|_| jobs.create(JobRecord::new(JobKind::Pdf, 3, 1, "a.pdf".to_string())))
+            .collect();
+        let handles = ids
+            .iter()
+            .map(|id| {
+                let handle = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+                (id.clone(), handle)
+            })
+            .collect();
+        jobs.track_all(3, handles);
+        assert!(jobs.cancel(3));
+        for id in &ids {
+            assert_eq!(jobs.get(id).unwrap().status, JobStatus::Cancelled);
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_user_jobs() {
+        let (_dir, jobs) = temp_registry();
+        let mine = jobs.create(JobRecord::new(JobKind::Pdf, 1, 7, "a.pdf".to_string()));
+        let other = jobs.create(JobRecord::new(JobKind::Pdf, 2, 8, "b.pdf".to_string()));
+        let done = jobs.create(JobRecord::new(JobKind::Search, 3, 7, "基座".to_string()));
+        let token = jobs.cancellation(&mine);
+        let other_token = jobs.cancellation(&other);
+        jobs.cancellation(&done);
+        jobs.update(&done, |r| r.status = JobStatus::Succeeded);
+
+        assert_eq!(jobs.cancel_user(7), vec![mine.clone()]);
+        assert!(token.is_cancelled());
+        assert!(!other_token.is_cancelled());
+        assert_eq!(jobs.get(&mine).unwrap().status, JobStatus::Cancelled);
+        assert_eq!(jobs.get(&done).unwrap().status, JobStatus::Succeeded);
+        assert!(jobs.cancel_user(7).is_empty());
+
+        // 撤回消息取消任务时同样发出取消信号
+        let handle = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+        jobs.track(2, &other, handle);
+        assert!(jobs.cancel(2));
+        assert!(other_token.is_cancelled());
+        assert!(jobs.cancel_user(8).is_empty());
+    }
+
+    #[test]
+    fn records_are_persisted() {
+        let (_dir, jobs) = temp_registry();
+        let id = jobs.create(JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string()));
+        jobs.update(&id, |r| r.result_mid = Some(9));
+
+        let reopened = JobRegistry::open(jobs.dir.clone());
+        assert_eq!(reopened.get(&id).unwrap().result_mid, Some(9));
+        assert_eq!(reopened.find(&id[..8]).unwrap().id, id);
+        assert!(reopened.find(&id[..3]).is_none());
+        assert_eq!(reopened.find_by_result_mid(9).unwrap().id, id);
+    }
+
+    #[test]
+    fn find_analyzed_pdf() {
+        let (_dir, jobs) = temp_registry();
+        let new_job = |status, created_at: &str| {
+            let mut record = JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string());
+            record.status = status;
+            record.content_hash = Some("abc".to_string());
+            record.created_at = created_at.to_string();
+            jobs.create(record)
+        };
+        let old = new_job(JobStatus::Succeeded, "2025-09-01T10:00:00+08:00");
+        let failed = new_job(JobStatus::Failed, "2025-09-03T10:00:00+08:00");
+        let no_report = new_job(JobStatus::Succeeded, "2025-09-04T10:00:00+08:00");
+        assert!(jobs.find_analyzed("abc", 1).is_none());
+        for id in [&old, &failed] {
+            jobs.save_report(id, "报告").unwrap();
+        }
+        assert_eq!(jobs.find_analyzed("abc", 1).unwrap().id, old);
+        let latest = new_job(JobStatus::Succeeded, "2025-09-02T10:00:00+08:00");
+        jobs.save_report(&latest, "报告").unwrap();
+        assert_eq!(jobs.find_analyzed("abc", 1).unwrap().id, latest);
+        assert!(jobs.get(&no_report).is_some());
+        assert!(jobs.find_analyzed("other", 1).is_none());
+        // 其他用户上传的相同文件不算
+        assert!(jobs.find_analyzed("abc", 2).is_none());
+    }
+
+    #[test]
+    fn separate_work_dirs() {
+        let (_dir, jobs) = temp_registry();
+        let a = jobs.create(JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string()));
+        let b = jobs.create(JobRecord::new(JobKind::Pdf, 2, 2, "a.pdf".to_string()));
+        let a_dir = jobs.create_work_dir(&a).unwrap();
+        let b_dir = jobs.create_work_dir(&b).unwrap();
+        assert_ne!(a_dir, b_dir);
+        assert!(a_dir.join(PAGES_DIR).is_dir());
+        assert_eq!(jobs.get(&a).unwrap().work_dir, Some(a_dir));
+
+        // 工作目录不会被当成任务记录加载
+        assert_eq!(JobRegistry::open(jobs.dir.clone()).all().len(), 2);
+    }
+
+    #[test]
+    fn save_extraction() {
+        let (_dir, jobs) = temp_registry();
+        let id = jobs.create(JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string()));
+        assert!(jobs.extraction(&id).is_none());
+
+        let page = TextExtractionResult::new_success(
+            PathBuf::from("page_0.jpg"),
+            Some("基座".to_string()),
+            vec!["PBT".to_string()],
+            None,
+        );
+        let extraction = TextExtraction {
+            merged: page.clone(),
+            pages: vec![
+                page,
+                TextExtractionResult::new_error(PathBuf::from("page_1.jpg"), "超时".to_string()),
+            ],
+            blank_pages: vec![PathBuf::from("page_2.jpg")],
+            provider: None,
+            prompt_version: "v1".to_string(),
+            usage: Default::default(),
+        };
+        jobs.save_extraction(&id, &extraction).unwrap();
+        let saved = jobs.extraction(&id).unwrap();
+        assert_eq!(saved.pages.len(), 2);
+        assert_eq!(saved.pages[1].error.as_deref(), Some("超时"));
+        assert_eq!(saved.blank_pages, [PathBuf::from("page_2.jpg")]);
+        assert_eq!(saved.merged.model_type.as_deref(), Some("基座"));
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn bundle_artifacts() {
+        let (_dir, jobs) = temp_registry();
+        assert!(jobs.artifacts("missing").is_none());
+
+        let id = jobs.create(JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string()));
+        let work_dir = jobs.create_work_dir(&id).unwrap();
+        std::fs::write(work_dir.join(PAGES_DIR).join("page_0.jpg"), b"jpg").unwrap();
+        assert!(jobs.report(&id).is_none());
+        jobs.save_report(&id, "# 报告").unwrap();
+        assert_eq!(jobs.report(&id).as_deref(), Some("# 报告"));
+
+        let bytes = jobs.artifacts(&id).unwrap().unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut names: Vec<String> = zip
+            .file_names()
+            .map(|name| name.unwrap().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "job.json",
+                "matches.json",
+                "pages/page_0.jpg",
+                "reports/report.md"
+            ]
+        );
+        let mut report = String::new();
+        std::io::Read::read_to_string(&mut zip.by_name("reports/report.md").unwrap(), &mut report)
+            .unwrap();
+        assert_eq!(report, "# 报告");
+    }
+
+    #[test]
+    fn replace_feedback_from_same_user() {
+        let (_dir, jobs) = temp_registry();
+        let id = jobs.create(JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string()));
+        let feedback = |verdict, from_uid| Feedback {
+            verdict,
+            comment: None,
+            from_uid,
+            created_at: String::new(),
+        };
+        jobs.add_feedback(&id, feedback(Verdict::Bad, 1));
+        jobs.add_feedback(&id, feedback(Verdict::Good, 2));
+        let record = jobs.add_feedback(&id, feedback(Verdict::Good, 1)).unwrap();
+        assert_eq!(record.feedback.len(), 2);
+        assert_eq!(record.feedback[0].from_uid, 2);
+        assert_eq!(record.verdict(), Some(Verdict::Good));
+    }
+
+    #[test]
+    fn feedback_metrics_by_version() {
+        let (_dir, jobs) = temp_registry();
+        for verdicts in [
+            vec![Verdict::Good],
+            vec![Verdict::Bad, Verdict::Good],
+            vec![Verdict::Bad],
+        ] {
+            let mut record = JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string());
+            record.prompt_version = Some("v1".to_string());
+            let id = jobs.create(record);
+            for verdict in verdicts {
+                jobs.add_feedback(
+                    &id,
+                    Feedback {
+                        verdict,
+                        comment: None,
+                        from_uid: 1,
+                        created_at: String::new(),
+                    },
+                );
+            }
+        }
+        // 没有反馈的任务不计入
+        jobs.create(JobRecord::new(JobKind::Pdf, 1, 1, "b.pdf".to_string()));
+
+        let metrics = jobs.feedback_metrics();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(
+            (metrics[0].jobs, metrics[0].good, metrics[0].bad),
+            (3, 2, 1)
+        );
+        assert!((metrics[0].precision.unwrap() - 0.667).abs() < 0.01);
+    }
+}
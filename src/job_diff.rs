@@ -0,0 +1,213 @@
+//! 比较两个任务的提取结果和匹配列表
+//!
+//! 用于验证提示词或模型升级：同一份PDF用不同版本分析后，逐字段列出提取结果的变化，
+//! 以及匹配结果的排名和相似度变化。
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::job::JobRecord;
+
+/// 每次提取都会变化的字段，不参与比较
+const IGNORED_FIELDS: [&str; 3] = [
+    "source_directory",
+    "source_directory_name",
+    "extraction_timestamp",
+];
+
+/// 一个字段的变化
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// 一个匹配结果在两个任务中的排名(从1开始)和相似度
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchChange {
+    pub source_name: String,
+    pub before: Option<MatchRank>,
+    pub after: Option<MatchRank>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MatchRank {
+    pub rank: usize,
+    pub percentage: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobDiff {
+    pub before: String,
+    pub after: String,
+    /// 提示词版本、模型和提取结果的字段变化
+    pub fields: Vec<FieldChange>,
+    /// 排名或相似度有变化的匹配结果，未变化的不列出
+    pub matches: Vec<MatchChange>,
+    /// 两个任务都列出的匹配结果数量
+    pub common_matches: usize,
+}
+
+impl JobDiff {
+    pub fn new(before: &JobRecord, after: &JobRecord) -> Self {
+        let mut fields = Vec::new();
+        let mut push_change = |field: &str, before: Value, after: Value| {
+            if before != after {
+                fields.push(FieldChange {
+                    field: field.to_string(),
+                    before,
+                    after,
+                });
+            }
+        };
+        push_change(
+            "prompt_version",
+            before.prompt_version.clone().into(),
+            after.prompt_version.clone().into(),
+        );
+        push_change(
+            "model_name",
+            before.model_name.clone().into(),
+            after.model_name.clone().into(),
+        );
+
+        let extraction = |job: &JobRecord| match serde_json::to_value(&job.extraction) {
+            Ok(Value::Object(map)) => map.into_iter().collect(),
+            _ => BTreeMap::new(),
+        };
+        let (old, new) = (extraction(before), extraction(after));
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            if IGNORED_FIELDS.contains(&key.as_str()) {
+                continue;
+            }
+            push_change(
+                &format!("extraction.{}", key),
+                old.get(key).cloned().unwrap_or(Value::Null),
+                new.get(key).cloned().unwrap_or(Value::Null),
+            );
+        }
+
+        let ranks = |job: &JobRecord| -> BTreeMap<String, MatchRank> {
+            job.matches
+                .iter()
+                .enumerate()
+                .map(|(index, m)| {
+                    let rank = MatchRank {
+                        rank: index + 1,
+                        percentage: m.percentage,
+                    };
+                    (m.source_name.clone(), rank)
+                })
+                .collect()
+        };
+        let (old, new) = (ranks(before), ranks(after));
+        let common_matches = old.keys().filter(|name| new.contains_key(*name)).count();
+        let mut matches: Vec<MatchChange> = old
+            .keys()
+            .chain(new.keys().filter(|name| !old.contains_key(*name)))
+            .filter_map(|name| {
+                let (before, after) = (old.get(name).copied(), new.get(name).copied());
+                let unchanged = matches!((before, after), (Some(b), Some(a))
+                    if b.rank == a.rank && (b.percentage - a.percentage).abs() < f32::EPSILON);
+                (!unchanged).then(|| MatchChange {
+                    source_name: name.clone(),
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        // 按在新结果中的排名排序，新结果中没有的排在最后
+        matches.sort_by_key(|m| {
+            (
+                m.after.map_or(usize::MAX, |r| r.rank),
+                m.before.map_or(usize::MAX, |r| r.rank),
+            )
+        });
+
+        Self {
+            before: before.id.clone(),
+            after: after.id.clone(),
+            fields,
+            matches,
+            common_matches,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        diff::{DiffResult, ModelJson},
+        job::JobKind,
+    };
+
+    fn job(prompt_version: &str, materials: &[&str], matches: &[(&str, f32)]) -> JobRecord {
+        let mut job = JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string());
+        job.prompt_version = Some(prompt_version.to_string());
+        job.extraction = Some(ModelJson {
+            model_type: Some("基座".to_string()),
+            materials: materials.iter().map(|m| m.to_string()).collect(),
+            project_name: None,
+            company: None,
+            dimensions: None,
+            drawing: None,
+            source_directory: PathBuf::from(&job.id),
+            source_directory_name: job.id.clone(),
+            extraction_timestamp: None,
+            original_pdf: None,
+            tags: Vec::new(),
+            finish: None,
+            id: None,
+            slug: None,
+            status: None,
+            provenance: None,
+            canonical_materials: Vec::new(),
+        });
+        job.matches = matches
+            .iter()
+            .map(|(name, percentage)| DiffResult {
+                source_directory: PathBuf::new(),
+                source_name: name.to_string(),
+                company: None,
+                dimensions: None,
+                original_pdf: None,
+                exact_match: false,
+                status: None,
+                percentage: *percentage,
+            })
+            .collect();
+        job
+    }
+
+    #[test]
+    fn field_and_match_changes() {
+        let before = job("v1", &["PBT"], &[("a", 0.9), ("b", 0.8), ("c", 0.7)]);
+        let after = job(
+            "v2",
+            &["PBT", "PA66"],
+            &[("b", 0.95), ("a", 0.9), ("d", 0.6)],
+        );
+        let diff = JobDiff::new(&before, &after);
+
+        let fields: Vec<_> = diff.fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, ["prompt_version", "extraction.materials"]);
+
+        let names: Vec<_> = diff
+            .matches
+            .iter()
+            .map(|m| m.source_name.as_str())
+            .collect();
+        assert_eq!(names, ["b", "a", "d", "c"]);
+        assert_eq!(diff.matches[3].after, None);
+        assert_eq!(diff.common_matches, 2);
+
+        let same = JobDiff::new(&before, &before);
+        assert!(same.fields.is_empty() && same.matches.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! 任务进度事件
+//!
+//! 工作流在阶段变化、PDF转换完成、每页提取完成、开始比较和任务结束时发布事件，
+//! `GET /material/api/v1/jobs/{id}/events`以Server-Sent Events推送给网页前端，
+//! `GET /material/api/v1/jobs/{id}/ws`以WebSocket推送，比较页面据此在分析完成前显示每页的结果。
+//! 事件只在内存中广播，不会重放订阅之前的事件，订阅时先发送任务当前的阶段。
+use std::{collections::VecDeque, convert::Infallible, time::Duration};
+
+use futures_util::{Stream, StreamExt, stream};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{
+    ai_text_analyzer::TextExtractionResult,
+    job::{JobRecord, JobStage, JobStatus},
+};
+
+/// 没有事件时发送注释或ping的间隔，避免代理关闭空闲连接
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobProgress {
+    /// 进入新的阶段
+    Stage { stage: JobStage },
+    /// PDF转换完成
+    Converted { pages: usize },
+    /// 提取完一页，`page`从1开始，附带这一页的提取结果
+    PageAnalyzed {
+        page: usize,
+        total: usize,
+        label: Option<String>,
+        success: bool,
+        result: Box<TextExtractionResult>,
+    },
+    /// 开始和模具库比较
+    Diffing,
+    /// 任务结束，之后不再有事件
+    Finished {
+        state: JobStatus,
+        error: Option<String>,
+    },
+}
+
+impl JobProgress {
+    pub fn finished(job: &JobRecord) -> Self {
+        Self::Finished {
+            state: job.status,
+            error: job.error.clone(),
+        }
+    }
+
+    /// SSE的事件名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stage { .. } => "stage",
+            Self::Converted { .. } => "converted",
+            Self::PageAnalyzed { .. } => "page_analyzed",
+            Self::Diffing => "diffing",
+            Self::Finished { .. } => "finished",
+        }
+    }
+
+    /// JSON，`event`字段为事件名称
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// 按SSE格式编码，数据为JSON
+    pub fn to_sse(&self) -> String {
+        format!("event: {}\ndata: {}\n\n", self.name(), self.to_json())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobEvent {
+    pub job_id: String,
+    pub progress: JobProgress,
+}
+
+/// 所有任务共用的事件广播
+pub struct JobEvents {
+    sender: broadcast::Sender<JobEvent>,
+}
+
+impl JobEvents {
+    /// `capacity`为每个订阅者最多积压的事件数
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// 没有订阅者时事件直接丢弃
+    pub fn publish(&self, job_id: &str, progress: JobProgress) {
+        let _ = self.sender.send(JobEvent {
+            job_id: job_id.to_string(),
+            progress,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// 一个任务的SSE流，没有事件时定期发送注释
+///
+/// `receiver`需要在读取任务记录之前订阅，避免错过两者之间的事件
+pub fn event_stream(
+    job: &JobRecord,
+    receiver: broadcast::Receiver<JobEvent>,
+) -> impl Stream<Item = Result<String, Infallible>> + Send + 'static {
+    progress_stream(job, receiver)
+        .map(|progress| Ok(progress.map_or_else(|| ": keep-alive\n\n".to_string(), |p| p.to_sse())))
+}
+
+/// 一个任务的进度事件，先发送当前阶段，任务已经结束时发送结束事件后关闭，
+/// 超过`KEEP_ALIVE`没有事件时产生None，用于保持连接
+pub fn progress_stream(
+    job: &JobRecord,
+    receiver: broadcast::Receiver<JobEvent>,
+) -> impl Stream<Item = Option<JobProgress>> + Send + 'static {
+    let mut pending = VecDeque::new();
+    if let Some(stage) = job.stage {
+        pending.push_back(JobProgress::Stage { stage });
+    }
+    let finished = job.status != JobStatus::Running;
+    if finished {
+        pending.push_back(JobProgress::finished(job));
+    }
+    let state = (job.id.clone(), receiver, pending, finished);
+    stream::unfold(
+        state,
+        |(job_id, mut receiver, mut pending, finished)| async move {
+            if let Some(progress) = pending.pop_front() {
+                return Some((Some(progress), (job_id, receiver, pending, finished)));
+            }
+            if finished {
+                return None;
+            }
+            loop {
+                match tokio::time::timeout(KEEP_ALIVE, receiver.recv()).await {
+                    Err(_) => return Some((None, (job_id, receiver, pending, false))),
+                    Ok(Ok(event)) if event.job_id == job_id => {
+                        let finished = matches!(event.progress, JobProgress::Finished { .. });
+                        let progress = Some(event.progress);
+                        return Some((progress, (job_id, receiver, pending, finished)));
+                    }
+                    Ok(Ok(_)) => {}
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        warn!("任务 {} 的事件订阅跳过了 {} 个事件", job_id, skipped);
+                    }
+                    Ok(Err(RecvError::Closed)) => return None,
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::job::JobKind;
+
+    #[tokio::test]
+    async fn stream_job_events() {
+        let events = JobEvents::new(16);
+        let mut job = JobRecord::new(JobKind::Pdf, 1, 1, "a.pdf".to_string());
+        job.stage = Some(JobStage::Executing);
+        let stream = event_stream(&job, events.subscribe());
+
+        events.publish("other", JobProgress::Diffing);
+        events.publish(
+            &job.id,
+            JobProgress::PageAnalyzed {
+                page: 3,
+                total: 7,
+                label: Some("总装图".to_string()),
+                success: true,
+                result: Box::new(TextExtractionResult::new_success(
+                    PathBuf::from("page_003.jpg"),
+                    Some("基座".to_string()),
+                    vec!["PBT".to_string()],
+                    None,
+                )),
+            },
+        );
+        events.publish(&job.id, JobProgress::Diffing);
+        events.publish(
+            &job.id,
+            JobProgress::Finished {
+                state: JobStatus::Succeeded,
+                error: None,
+            },
+        );
+        events.publish(&job.id, JobProgress::Diffing);
+
+        let sent: Vec<String> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(sent.len(), 4);
+        assert_eq!(
+            sent[0],
+            "event: stage\ndata: {\"event\":\"stage\",\"stage\":\"executing\"}\n\n"
+        );
+        assert!(sent[1].starts_with("event: page_analyzed\n"));
+        assert!(sent[1].contains("\"page\":3,\"total\":7"));
+        assert!(sent[1].contains("\"model_type\":\"基座\""));
+        assert!(sent[2].starts_with("event: diffing\n"));
+        assert!(sent[3].contains("\"state\":\"succeeded\""));
+
+        // 已经结束的任务只发送当前状态
+        job.status = JobStatus::Failed;
+        job.error = Some("PDF 转换失败".to_string());
+        let sent: Vec<String> = event_stream(&job, events.subscribe())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].starts_with("event: finished\n"));
+        assert!(sent[1].contains("PDF 转换失败"));
+    }
+}
@@ -0,0 +1,107 @@
+//! 从模型回复中提取JSON
+//!
+//! 模型经常在JSON前后附带说明文字，或把JSON放在```json代码块中，代码块可能嵌套或缺少结束标记。
+//! 依次尝试整段回复、各个代码块和第一个`{`到最后一个`}`之间的内容，都失败时返回整段回复的解析错误。
+use serde_json::Value;
+
+const FENCE: &str = "```";
+
+pub fn extract_json(content: &str) -> Result<Value, serde_json::Error> {
+    let content = content.trim();
+    let direct = serde_json::from_str::<Value>(content);
+    if direct.is_ok() {
+        return direct;
+    }
+    fenced_blocks(content)
+        .into_iter()
+        .chain(braced(content))
+        .find_map(|candidate| serde_json::from_str(candidate.trim()).ok())
+        .map_or(direct, Ok)
+}
+
+/// 代码块中的内容，没有结束标记时取到末尾
+fn fenced_blocks(content: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(FENCE) {
+        // 跳过语言标记，例如```json
+        let body =
+            rest[start + FENCE.len()..].trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        match body.find(FENCE) {
+            Some(end) => {
+                blocks.push(&body[..end]);
+                rest = &body[end + FENCE.len()..];
+            }
+            None => {
+                blocks.push(body);
+                break;
+            }
+        }
+    }
+    blocks
+}
+
+/// 第一个`{`到最后一个`}`之间的内容
+fn braced(content: &str) -> Option<&str> {
+    let start = content.find('{')?;
+    let end = content.rfind('}')?;
+    content.get(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn fenced_and_wrapped() {
+        let expected = serde_json::json!({"model_type": "基座", "materials": ["PBT"]});
+        for content in [
+            r#"{"model_type": "基座", "materials": ["PBT"]}"#,
+            "结果如下：\n```json\n{\"model_type\": \"基座\", \"materials\": [\"PBT\"]}\n```\n以上。",
+            "```json\n```json\n{\"model_type\": \"基座\", \"materials\": [\"PBT\"]}\n```\n```",
+            "```JSON{\"model_type\": \"基座\", \"materials\": [\"PBT\"]}",
+            "说明 {\"model_type\": \"基座\", \"materials\": [\"PBT\"]} 完毕",
+        ] {
+            assert_eq!(extract_json(content).unwrap(), expected, "{}", content);
+        }
+        // `}`在`{`之前
+        assert!(extract_json("} 没有结果 {").is_err());
+        assert!(extract_json("```json\n{\"model_type\": \"基").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(content in "\\PC*") {
+            let _ = extract_json(&content);
+        }
+
+        #[test]
+        fn recovers_wrapped_object(
+            model_type in "\\PC{0,12}",
+            materials in prop::collection::vec("\\PC{0,8}", 0..4),
+            prefix in "[^{}`]{0,20}",
+            suffix in "[^{}`]{0,20}",
+            fenced in any::<bool>(),
+        ) {
+            let value = serde_json::json!({"model_type": model_type, "materials": materials});
+            let json = if fenced {
+                format!("```json\n{}\n```", value)
+            } else {
+                value.to_string()
+            };
+            let content = format!("{}{}{}", prefix, json, suffix);
+            prop_assert_eq!(extract_json(&content).unwrap(), value);
+        }
+
+        #[test]
+        fn truncated_object(cut in 0usize..200) {
+            let json = r#"```json
+{"model_type": "基座（上）", "materials": ["PBT RG301，黑色", "PA66"], "text_content": ["技术要求：镀镍"]}
+```"#;
+            let cut = json.char_indices().map(|(i, _)| i).nth(cut).unwrap_or(json.len());
+            let _ = extract_json(&json[..cut]);
+        }
+    }
+}
@@ -1,40 +1,211 @@
+//! 模具图纸比对
+//!
+//! 作为库使用时的入口：
+//! - `Analyzer`(需要`ai`功能)分析PDF图纸并与模具库比较，见`analyzer`，
+//!   分析进度通过`ProgressSink`回调
+//! - `ModelStore`加载模具库，`ModelStore::search`按`UserQuery`检索
+//! - `diff`和`pdf_converter`提供相似度计算和PDF转换，默认功能即可使用
+//!
+//! HTTP服务、聊天机器人的工作流和SAM视图切分属于`server`和`sam`功能，不作为库接口，
+//! 除上面列出的类型外，其他公开模块供服务和`material-cli`使用，可能随版本变化。
+#[cfg(feature = "ai")]
 #[allow(dead_code)]
 mod ai_analyzer;
-mod ai_text_analyzer;
+pub mod ai_text_analyzer;
+#[cfg(feature = "ai")]
+pub mod analyzer;
+#[cfg(feature = "server")]
 pub mod api;
+#[cfg(feature = "server")]
+mod api_auth;
+#[cfg(feature = "ai")]
+mod blank_page;
+pub mod blob;
+pub mod calibration;
+#[cfg(feature = "server")]
+mod command;
 pub mod config;
+#[cfg(feature = "ai")]
+pub mod corpus_events;
+pub mod corpus_snapshot;
+pub mod dataset;
 #[allow(dead_code)]
 pub mod diff;
-mod pdf_converter;
+mod dimension;
+#[cfg(feature = "ai")]
+pub mod doctor;
+mod drawing;
+#[cfg(feature = "server")]
+mod duplicate_alert;
+mod finish;
+#[cfg(feature = "ai")]
+mod http;
+mod image_url;
+pub mod job;
+#[cfg(feature = "server")]
+mod job_diff;
+pub mod job_events;
+#[cfg(feature = "ai")]
+mod json_extract;
+#[cfg(feature = "server")]
+mod maintenance;
+pub mod model_reload;
+pub mod model_storage;
+pub mod model_store;
+pub mod mold_status;
+pub mod paths;
+pub mod pdf_converter;
+mod preference;
+#[cfg(feature = "server")]
+mod preflight;
+#[cfg(feature = "server")]
+mod progress;
+pub mod progress_sink;
+pub mod prompt_registry;
+pub mod query;
+mod queue;
+#[cfg(feature = "server")]
+mod rate_limit;
+pub mod response_archive;
+#[cfg(feature = "server")]
 pub mod router;
+#[cfg(feature = "sam")]
 #[allow(dead_code)]
 mod sam;
+pub mod schema_migration;
+pub mod secrets;
+pub mod suggest;
+mod tags;
+pub mod taxonomy;
+#[cfg(feature = "ai")]
+mod text;
+mod text_metric;
+mod thumbnail;
+mod usage;
+#[cfg(feature = "server")]
+mod webhook_auth;
+#[cfg(feature = "server")]
 mod workflow;
 
-use std::{collections::HashMap, env::current_exe, sync::LazyLock};
+use std::sync::{LazyLock, Mutex, RwLock};
 
+use arc_swap::ArcSwap;
 use thiserror::Error;
 
-use crate::diff::ModelJson;
+use crate::{
+    ai_text_analyzer::{TEXT_EXTRACT_PROMPT, TEXT_EXTRACT_PROMPT_VERSION},
+    blob::{BlobStore, blobs_dir},
+    image_url::ImageUrlBuilder,
+    job::{JobRegistry, jobs_dir},
+    job_events::JobEvents,
+    preference::{UserPreferences, preferences_dir},
+    prompt_registry::{PromptRegistry, prompts_dir},
+    queue::WorkQueue,
+    taxonomy::{Taxonomy, UnrecognizedTerms, taxonomy_dir},
+};
+
+#[cfg(feature = "ai")]
+pub use analyzer::{Analysis, Analyzer};
+pub use config::Config;
+pub use diff::{DiffResult, ModelJson};
+pub use model_store::ModelStore;
+pub use progress_sink::ProgressSink;
+pub use query::UserQuery;
 
 pub type IResult<T> = std::result::Result<T, AnalyzerError>;
-// 初始化一个排序好的模具比较数据
-pub static MODELS: LazyLock<HashMap<String, Vec<ModelJson>>> = LazyLock::new(|| {
-    let models_dir = current_exe()
-        .map_err(|e| format!("获取执行目录失败: {}", e))
-        .unwrap()
-        .parent()
-        .ok_or("无法获取执行目录的父目录")
-        .unwrap()
-        .join("data")
-        .join("upload")
-        .join("file")
-        .join("models")
-        .join("jsons");
-    let models = ModelJson::patch_new(models_dir).unwrap();
-    ModelJson::sort(models)
+/// 从`material.toml`和环境变量加载的配置，配置文件无效时记录错误并退出
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| {
+    Config::load().unwrap_or_else(|e| {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    })
+});
+
+/// 模具数据库，按模具类型分组并按来源名称索引，记录变化时由`model_reload`整体替换，
+/// 需要多次访问时先用`load_full`取得同一份数据，无法读取的记录被跳过
+pub static MODELS: LazyLock<ArcSwap<ModelStore>> = LazyLock::new(|| {
+    let models_dir = model_store::models_dir();
+    let store = ModelStore::open_lenient(&models_dir, &CONFIG.storage).unwrap_or_else(|e| {
+        tracing::error!("加载模具数据失败 {}: {}", models_dir.display(), e);
+        ModelStore::new(Vec::new(), models_dir.join("imgs"))
+    });
+    ArcSwap::from_pointee(store)
 });
 
+/// 模具类型和材料的分类词表，可通过管理接口在运行时更新
+pub static TAXONOMY: LazyLock<RwLock<Taxonomy>> = LazyLock::new(|| {
+    let taxonomy = Taxonomy::load(&taxonomy_dir()).unwrap_or_else(|e| {
+        tracing::error!("加载分类词表失败，使用内置词表: {}", e);
+        Taxonomy::default()
+    });
+    RwLock::new(taxonomy)
+});
+
+/// 提取结果中词表尚未覆盖的词条
+pub static UNRECOGNIZED: LazyLock<Mutex<UnrecognizedTerms>> = LazyLock::new(|| {
+    let terms = UnrecognizedTerms::load(&taxonomy_dir()).unwrap_or_else(|e| {
+        tracing::error!("加载待归类词条失败: {}", e);
+        UnrecognizedTerms::default()
+    });
+    Mutex::new(terms)
+});
+
+/// 用户的偏好设置
+pub static PREFERENCES: LazyLock<Mutex<UserPreferences>> = LazyLock::new(|| {
+    let preferences = UserPreferences::load(&preferences_dir()).unwrap_or_else(|e| {
+        tracing::error!("加载用户偏好设置失败: {}", e);
+        UserPreferences::default()
+    });
+    Mutex::new(preferences)
+});
+
+/// 上传的PDF，按内容寻址，引用计数损坏时按任务记录重新计算
+pub static BLOBS: LazyLock<BlobStore> = LazyLock::new(|| {
+    BlobStore::open(blobs_dir(), || {
+        JOBS.all()
+            .into_iter()
+            .filter_map(|job| job.content_hash)
+            .collect()
+    })
+});
+
+/// 提示词版本表，加载时登记当前的文本提取提示词
+pub static PROMPTS: LazyLock<PromptRegistry> = LazyLock::new(|| {
+    PromptRegistry::open(
+        &prompts_dir(),
+        TEXT_EXTRACT_PROMPT_VERSION,
+        TEXT_EXTRACT_PROMPT,
+    )
+});
+
+/// 后台任务记录
+pub static JOBS: LazyLock<JobRegistry> = LazyLock::new(|| JobRegistry::open(jobs_dir()));
+
+/// 任务进度事件的广播，用于推送给网页前端
+pub static JOB_EVENTS: LazyLock<JobEvents> = LazyLock::new(|| JobEvents::new(256));
+
+/// PDF分析的排队
+pub static QUEUE: LazyLock<WorkQueue> =
+    LazyLock::new(|| WorkQueue::new(CONFIG.queue.max_concurrent));
+
+/// 维护期间的只读模式
+#[cfg(feature = "server")]
+pub static MAINTENANCE: LazyLock<maintenance::Maintenance> =
+    LazyLock::new(|| maintenance::Maintenance::new(CONFIG.server.read_only));
+
+/// 结果中图片链接的生成
+pub static IMAGE_URLS: LazyLock<ImageUrlBuilder> = LazyLock::new(|| {
+    ImageUrlBuilder::new(CONFIG.links.clone()).with_files_path(CONFIG.server.path("files"))
+});
+
+/// 共用的对外HTTP客户端，配置无效时退回默认客户端
+#[cfg(feature = "ai")]
+pub static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    http::build_client(&CONFIG.http).unwrap_or_else(|e| {
+        tracing::error!("创建HTTP客户端失败，使用默认配置: {}", e);
+        reqwest::Client::new()
+    })
+});
 
 #[derive(Error, Debug)]
 pub enum AnalyzerError {
@@ -64,4 +235,8 @@ pub enum AnalyzerError {
 
     #[error("Workflow error: {0}")]
     WorkflowError(String),
+
+    #[cfg(feature = "sqlite")]
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
 }
@@ -1,13 +1,125 @@
-use material_rs::router;
-use salvo::{Listener, Server, conn::TcpListener};
+use material_rs::{
+    CONFIG, HTTP_CLIENT, config::StorageBackend, doctor, model_reload, model_store::models_dir,
+    router, schema_migration, secrets,
+};
+use salvo::{
+    Listener, Server,
+    conn::{
+        TcpListener,
+        rustls::{Keycert, RustlsConfig, ServerConfig},
+    },
+};
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt().init();
 
-    // Bind server to port 5800
-    let acceptor = TcpListener::new("0.0.0.0:5800").bind().await;
+    // Fail fast when material.toml is invalid or API keys are missing
+    std::sync::LazyLock::force(&CONFIG);
+    if let Err(e) = secrets::check() {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+
+    // Optionally verify the whole environment before accepting requests
+    if CONFIG.server.startup_check {
+        let report = doctor::run(&HTTP_CLIENT).await;
+        tracing::info!("环境检查:\n{}", report.render());
+        if !report.is_ready() {
+            std::process::exit(1);
+        }
+    }
+
+    // Bring older JSON model records up to the current format before they are loaded
+    if CONFIG.storage.auto_migrate && CONFIG.storage.backend == StorageBackend::Json {
+        match schema_migration::migrate(&models_dir(), false) {
+            Ok(report) if report.is_current() && report.invalid.is_empty() => {}
+            Ok(report) => tracing::info!("{}", report.render()),
+            Err(e) => {
+                tracing::error!("迁移模型记录失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Pick up newly ingested models without a restart
+    model_reload::spawn_rescanner(&models_dir(), &CONFIG.storage);
+
+    // Serve on a Unix socket for a local reverse proxy when configured
+    #[cfg(unix)]
+    if let Some(socket) = &CONFIG.server.unix_socket {
+        serve_unix(socket).await;
+        return;
+    }
+    #[cfg(not(unix))]
+    if CONFIG.server.unix_socket.is_some() {
+        tracing::warn!("当前系统不支持Unix socket，监听 {}", CONFIG.server.bind);
+    }
+
+    // Terminate TLS directly when a certificate and key are configured
+    let tls = match CONFIG.server.tls() {
+        Ok(tls) => tls.map(|(cert, key)| load_tls(cert, key)),
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(config) = tls {
+        let acceptor = TcpListener::new(CONFIG.server.bind.as_str())
+            .rustls(config)
+            .bind()
+            .await;
+        tracing::info!(
+            "监听 https://{}{}",
+            CONFIG.server.bind,
+            CONFIG.server.path("")
+        );
+        Server::new(acceptor).serve(router::build()).await;
+        return;
+    }
+
+    // Bind server to the configured address, 0.0.0.0:5800 by default
+    let acceptor = TcpListener::new(CONFIG.server.bind.as_str()).bind().await;
+    tracing::info!("监听 {}{}", CONFIG.server.bind, CONFIG.server.path(""));
 
     // Start serving requests
     Server::new(acceptor).serve(router::build()).await;
 }
+
+/// Load the PEM certificate chain and key, exiting on errors instead of failing on the first handshake
+fn load_tls(cert: &std::path::Path, key: &std::path::Path) -> RustlsConfig {
+    let config = Keycert::new()
+        .cert_from_path(cert)
+        .and_then(|keycert| keycert.key_from_path(key))
+        .map(RustlsConfig::new);
+    let checked = config.and_then(|config| {
+        let server: Result<ServerConfig, _> = config.clone().try_into();
+        server.map(|_| config)
+    });
+    checked.unwrap_or_else(|e| {
+        tracing::error!("加载TLS证书失败 {}: {}", cert.display(), e);
+        std::process::exit(1);
+    })
+}
+
+#[cfg(unix)]
+async fn serve_unix(socket: &std::path::Path) {
+    use std::os::unix::fs::FileTypeExt;
+
+    use salvo::conn::UnixListener;
+
+    // Remove the socket left behind by a previous run, but never a regular file
+    if let Ok(metadata) = std::fs::symlink_metadata(socket) {
+        if !metadata.file_type().is_socket() {
+            tracing::error!("{} 已存在且不是socket", socket.display());
+            std::process::exit(1);
+        }
+        if let Err(e) = std::fs::remove_file(socket) {
+            tracing::error!("删除旧的socket失败 {}: {}", socket.display(), e);
+            std::process::exit(1);
+        }
+    }
+    let acceptor = UnixListener::new(socket.to_path_buf()).bind().await;
+    tracing::info!("监听 unix:{}{}", socket.display(), CONFIG.server.path(""));
+    Server::new(acceptor).serve(router::build()).await;
+}
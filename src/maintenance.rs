@@ -0,0 +1,125 @@
+//! 维护期间的只读模式
+//!
+//! 只读模式下仍然接收webhook和分析请求，任务回复维护提示后暂停，不调用模型服务，
+//! 恢复读写后按到达顺序继续执行(PDF分析再进入排队)。修改分类词表和模具状态的接口回复503。
+//! 启动时的状态见`ServerConfig::read_only`，运行中通过管理接口切换。
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, async_trait, http::StatusCode};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::{MAINTENANCE, api::error::ApiError};
+
+pub struct Maintenance {
+    read_only: watch::Sender<bool>,
+    /// 正在等待恢复读写的任务数
+    held: AtomicUsize,
+}
+
+/// 等待中的任务，离开时(恢复读写或任务被取消)减少计数
+struct Held<'a>(&'a AtomicUsize);
+
+impl Drop for Held<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Maintenance {
+    pub fn new(read_only: bool) -> Self {
+        Self {
+            read_only: watch::Sender::new(read_only),
+            held: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        *self.read_only.borrow()
+    }
+
+    /// 切换只读模式，返回状态是否改变
+    pub fn set_read_only(&self, read_only: bool) -> bool {
+        let changed = self.read_only.send_replace(read_only) != read_only;
+        if changed {
+            if read_only {
+                info!("进入只读模式，新任务暂停执行");
+            } else {
+                info!("恢复读写，继续执行 {} 个暂停的任务", self.held());
+            }
+        }
+        changed
+    }
+
+    pub fn held(&self) -> usize {
+        self.held.load(Ordering::Relaxed)
+    }
+
+    /// 只读模式下等待恢复读写，否则立即返回
+    pub async fn wait_writable(&self) {
+        let mut receiver = self.read_only.subscribe();
+        if !*receiver.borrow_and_update() {
+            return;
+        }
+        self.held.fetch_add(1, Ordering::Relaxed);
+        let _held = Held(&self.held);
+        // 发送端与服务同时存在，不会关闭
+        let _ = receiver.wait_for(|read_only| !read_only).await;
+    }
+}
+
+/// 只读模式下拒绝修改数据的请求
+pub struct ReadOnlyGuard;
+
+#[async_trait]
+impl Handler for ReadOnlyGuard {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        if MAINTENANCE.is_read_only() {
+            res.render(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "read_only",
+                "系统维护中，暂时不能修改数据",
+                "The service is in read-only mode for maintenance",
+            ));
+            ctrl.skip_rest();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn hold_until_writable() {
+        let maintenance = Arc::new(Maintenance::new(false));
+        // 读写模式下不等待
+        maintenance.wait_writable().await;
+        assert_eq!(maintenance.held(), 0);
+
+        assert!(maintenance.set_read_only(true));
+        assert!(!maintenance.set_read_only(true));
+        let waiting = tokio::spawn({
+            let maintenance = maintenance.clone();
+            async move { maintenance.wait_writable().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(maintenance.held(), 1);
+
+        assert!(maintenance.set_read_only(false));
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(maintenance.held(), 0);
+    }
+}
@@ -0,0 +1,182 @@
+//! 模具数据的重新加载
+//!
+//! `MODELS`在启动时加载，之后定期检查存储中的记录是否变化(见`ModelRepository::version`)，
+//! 有变化时在后台加载完整的新数据并整体替换，替换前的请求继续使用旧数据，
+//! 新导入的图纸不需要重启就可以检索。替换时比较前后的来源名称，
+//! 新增和删除的模具通过`corpus_events`通知外部系统。
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+    AnalyzerError, IResult, MODELS, PROMPTS,
+    config::StorageConfig,
+    model_storage::{ModelRepository, open_repository},
+    model_store::ModelStore,
+};
+
+/// 一次重新加载的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadSummary {
+    /// 加载的模型数量
+    pub loaded: usize,
+    /// 无法读取的记录(文件路径或来源名称)和原因
+    pub failed: Vec<FailedRecord>,
+    /// 由旧的提示词版本提取的记录，最旧的在前，见`ModelStore::stale_extractions`
+    pub stale: Vec<String>,
+    /// 与替换前相比新增的模具，按来源名称排序
+    pub ingested: Vec<String>,
+    /// 与替换前相比删除的模具，按来源名称排序
+    pub deleted: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedRecord {
+    pub record: String,
+    pub error: String,
+}
+
+/// 管理接口的重建索引和后台的定期检查不同时加载，否则两次加载都和同一份旧数据比较，
+/// 新增和删除的模具会重复通知
+static RELOADING: Mutex<()> = Mutex::new(());
+
+/// 从存储中重新加载并替换`MODELS`，无法读取的记录被跳过
+pub fn reload(repository: &dyn ModelRepository, models_dir: &Path) -> IResult<ReloadSummary> {
+    let _reloading = RELOADING.lock().unwrap();
+    let (models, failed) = repository.load_lenient()?;
+    let loaded = models.len();
+    let store = ModelStore::new(models, models_dir.join("imgs"));
+    let (ingested, deleted) = corpus_changes(&MODELS.load(), &store);
+    let summary = ReloadSummary {
+        loaded,
+        failed: failed
+            .into_iter()
+            .map(|(record, error)| FailedRecord { record, error })
+            .collect(),
+        stale: store.stale_extractions(&PROMPTS, None),
+        ingested,
+        deleted,
+    };
+    for failed in &summary.failed {
+        warn!("跳过无法读取的模具记录 {}: {}", failed.record, failed.error);
+    }
+    MODELS.store(Arc::new(store));
+    info!(
+        "🔄 已重新加载 {} 个模具，新增 {} 个，删除 {} 个",
+        summary.loaded,
+        summary.ingested.len(),
+        summary.deleted.len()
+    );
+    #[cfg(feature = "ai")]
+    notify_changes(&summary);
+    if !summary.stale.is_empty() {
+        info!(
+            "{} 个模具由旧的提示词版本提取，需要重新提取",
+            summary.stale.len()
+        );
+    }
+    Ok(summary)
+}
+
+/// 替换前后的模具库相比新增和删除的来源名称
+fn corpus_changes(old: &ModelStore, new: &ModelStore) -> (Vec<String>, Vec<String>) {
+    let old_names: HashSet<&String> = old.source_names().collect();
+    let new_names: HashSet<&String> = new.source_names().collect();
+    let sorted = |names: HashSet<&&String>| {
+        let mut names: Vec<String> = names.into_iter().map(|name| name.to_string()).collect();
+        names.sort();
+        names
+    };
+    (
+        sorted(new_names.difference(&old_names).collect()),
+        sorted(old_names.difference(&new_names).collect()),
+    )
+}
+
+/// 在后台通知外部系统新增和删除的模具
+#[cfg(feature = "ai")]
+fn notify_changes(summary: &ReloadSummary) {
+    use crate::corpus_events::{CorpusEvent, CorpusEventKind, spawn_notify};
+
+    let events = summary
+        .ingested
+        .iter()
+        .map(|name| CorpusEvent::new(CorpusEventKind::Ingested, name.as_str()))
+        .chain(
+            summary
+                .deleted
+                .iter()
+                .map(|name| CorpusEvent::new(CorpusEventKind::Deleted, name.as_str())),
+        )
+        .collect();
+    spawn_notify(events);
+}
+
+/// 按配置打开存储并立即重新加载，读取记录在阻塞线程中进行
+pub async fn reload_now(models_dir: &Path, config: &StorageConfig) -> IResult<ReloadSummary> {
+    let (models_dir, config) = (models_dir.to_path_buf(), config.clone());
+    tokio::task::spawn_blocking(move || {
+        reload(open_repository(&models_dir, &config)?.as_ref(), &models_dir)
+    })
+    .await
+    .map_err(|e| AnalyzerError::WorkflowError(format!("重新加载模具失败: {}", e)))?
+}
+
+/// 在后台定期检查记录的变化，`rescan_seconds`为0时不检查
+pub fn spawn_rescanner(models_dir: &Path, config: &StorageConfig) -> Option<JoinHandle<()>> {
+    if config.rescan_seconds == 0 {
+        return None;
+    }
+    let interval = Duration::from_secs(config.rescan_seconds);
+    let (models_dir, config) = (models_dir.to_path_buf(), config.clone());
+    Some(tokio::spawn(async move {
+        let mut last = None;
+        loop {
+            let (models_dir, config) = (models_dir.clone(), config.clone());
+            match tokio::task::spawn_blocking(move || rescan(&models_dir, &config, last)).await {
+                Ok(Ok(version)) => last = Some(version),
+                Ok(Err(e)) => error!("检查模具记录的变化失败: {}", e),
+                Err(e) => error!("检查模具记录的变化失败: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }))
+}
+
+/// 记录的版本与上次检查时不同时重新加载，返回当前版本
+fn rescan(models_dir: &Path, config: &StorageConfig, last: Option<u64>) -> IResult<u64> {
+    let repository = open_repository(models_dir, config)?;
+    let version = repository.version()?;
+    if last.is_some_and(|last| last != version) {
+        reload(repository.as_ref(), models_dir)?;
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diff::ModelJson, paths::fixture};
+
+    #[test]
+    fn ingested_and_deleted() {
+        let models = ModelJson::patch_new(fixture("models/jsons")).unwrap();
+        let old = ModelStore::new(models.clone(), std::env::temp_dir());
+        let mut renamed = models;
+        let removed = renamed[0].source_directory_name.clone();
+        renamed[0].source_directory_name = "新增模具".to_string();
+        let new = ModelStore::new(renamed, std::env::temp_dir());
+
+        let (ingested, deleted) = corpus_changes(&old, &new);
+        assert_eq!(ingested, ["新增模具"]);
+        assert_eq!(deleted, [removed]);
+        assert_eq!(corpus_changes(&new, &new), (Vec::new(), Vec::new()));
+    }
+}
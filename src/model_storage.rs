@@ -0,0 +1,416 @@
+//! 模型记录的存储
+//!
+//! 默认每个模型一个JSON文件(`models/jsons/`)，也可以在`[storage]`中配置为SQLite数据库
+//! (默认`models/models.db`)。数据库在重启后不需要重新读取所有文件，导入时可以并发写入，
+//! 修改单个模型也不需要重写整个目录。两种存储都实现`ModelRepository`，
+//! 用`material-cli import-models-sqlite`把已有的JSON记录导入数据库。
+//! SQLite存储需要`sqlite`功能(`server`包含该功能)，没有启用时只能使用JSON文件。
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "sqlite")]
+use std::{sync::Mutex, time::Duration};
+
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, OptionalExtension, params};
+#[cfg(feature = "sqlite")]
+use tracing::info;
+
+use crate::{
+    AnalyzerError, IResult,
+    config::{StorageBackend, StorageConfig},
+    diff::ModelJson,
+    model_store::{ModelStore, json_files, shard, write_statuses},
+    mold_status::MoldStatus,
+};
+
+/// 默认的数据库文件，相对`models`目录
+#[cfg(feature = "sqlite")]
+const SQLITE_FILE: &str = "models.db";
+/// 其他连接正在写入时的等待时间
+#[cfg(feature = "sqlite")]
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 读取的模型，以及无法读取的记录(文件路径或来源名称)和原因
+pub type Loaded = (Vec<ModelJson>, Vec<(String, String)>);
+
+/// 模型记录的读写，模型以来源名称区分
+pub trait ModelRepository: Send + Sync {
+    /// 全部模型
+    fn all(&self) -> IResult<Vec<ModelJson>>;
+
+    /// 全部模型，跳过无法读取的记录
+    fn load_lenient(&self) -> IResult<Loaded> {
+        Ok((self.all()?, Vec::new()))
+    }
+
+    /// 按来源名称查找
+    fn get(&self, source_name: &str) -> IResult<Option<ModelJson>>;
+
+    /// 指定模具类型的模型
+    fn by_model_type(&self, model_type: &str) -> IResult<Vec<ModelJson>> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .filter(|m| m.model_type.as_deref() == Some(model_type))
+            .collect())
+    }
+
+    /// 写入模型，来源名称相同的记录被替换
+    fn upsert(&self, model: &ModelJson) -> IResult<()>;
+
+    /// 记录的版本，任何记录增加、删除或修改后都会变化，用于判断是否需要重新加载
+    fn version(&self) -> IResult<u64>;
+
+    /// 修改模具状态，`statuses`按来源名称索引，None表示清除状态，返回修改的模型的来源名称，
+    /// `store`为已经加载的模具库，用于查找记录的位置
+    fn set_statuses(
+        &self,
+        store: &ModelStore,
+        statuses: &HashMap<String, Option<MoldStatus>>,
+    ) -> IResult<Vec<String>>;
+}
+
+/// 按配置打开`models_dir`对应的存储
+pub fn open_repository(
+    models_dir: &Path,
+    config: &StorageConfig,
+) -> IResult<Box<dyn ModelRepository>> {
+    Ok(match config.backend {
+        StorageBackend::Json => Box::new(JsonRepository::new(models_dir)),
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite => {
+            let path = config
+                .sqlite_path
+                .clone()
+                .unwrap_or_else(|| models_dir.join(SQLITE_FILE));
+            Box::new(SqliteRepository::open(&path)?)
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StorageBackend::Sqlite => {
+            return Err(AnalyzerError::ConfigError(
+                "没有启用sqlite功能，不能使用SQLite存储".to_string(),
+            ));
+        }
+    })
+}
+
+/// `models/jsons/`下的JSON文件，新的模型按`<年份>/<客户>`分片保存
+pub struct JsonRepository {
+    models_dir: PathBuf,
+}
+
+impl JsonRepository {
+    pub fn new(models_dir: &Path) -> Self {
+        Self {
+            models_dir: models_dir.to_path_buf(),
+        }
+    }
+
+    fn jsons_dir(&self) -> PathBuf {
+        self.models_dir.join("jsons")
+    }
+
+    /// 模型的记录文件，已有记录时使用原来的位置
+    fn record_path(&self, model: &ModelJson) -> IResult<PathBuf> {
+        let mut files = Vec::new();
+        if self.jsons_dir().is_dir() {
+            json_files(&self.jsons_dir(), &mut files)?;
+        }
+        for path in files {
+            let record: ModelJson = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+            if record.source_directory_name == model.source_directory_name {
+                return Ok(path);
+            }
+        }
+        Ok(self
+            .jsons_dir()
+            .join(shard(model))
+            .join(format!("{}_text_data.json", model.source_directory_name)))
+    }
+}
+
+impl ModelRepository for JsonRepository {
+    fn all(&self) -> IResult<Vec<ModelJson>> {
+        if !self.jsons_dir().is_dir() {
+            return Ok(Vec::new());
+        }
+        ModelJson::patch_new(self.jsons_dir())
+            .map_err(|e| AnalyzerError::IoError(std::io::Error::other(e.to_string())))
+    }
+
+    fn load_lenient(&self) -> IResult<Loaded> {
+        if !self.jsons_dir().is_dir() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let (models, failed) = ModelJson::patch_load(&self.jsons_dir());
+        let failed = failed
+            .into_iter()
+            .map(|(path, e)| (path.display().to_string(), e))
+            .collect();
+        Ok((models, failed))
+    }
+
+    fn get(&self, source_name: &str) -> IResult<Option<ModelJson>> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .find(|m| m.source_directory_name == source_name))
+    }
+
+    fn upsert(&self, model: &ModelJson) -> IResult<()> {
+        let path = self.record_path(model)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(model)?)?;
+        Ok(())
+    }
+
+    /// 所有记录文件的路径、修改时间和大小
+    fn version(&self) -> IResult<u64> {
+        let mut files = Vec::new();
+        if self.jsons_dir().is_dir() {
+            json_files(&self.jsons_dir(), &mut files)?;
+        }
+        files.sort();
+        let mut hasher = DefaultHasher::new();
+        for path in files {
+            let metadata = std::fs::metadata(&path)?;
+            (path, metadata.modified()?, metadata.len()).hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+
+    fn set_statuses(
+        &self,
+        store: &ModelStore,
+        statuses: &HashMap<String, Option<MoldStatus>>,
+    ) -> IResult<Vec<String>> {
+        write_statuses(&self.models_dir, store, statuses)
+    }
+}
+
+/// SQLite数据库，完整记录以JSON保存，来源名称、id和模具类型单独成列用于查询
+#[cfg(feature = "sqlite")]
+pub struct SqliteRepository {
+    connection: Mutex<Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteRepository {
+    /// 打开数据库，不存在时创建
+    pub fn open(path: &Path) -> IResult<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        // WAL模式下读取不阻塞写入，多个导入进程可以同时写入
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS models (
+                source_name TEXT PRIMARY KEY,
+                id TEXT,
+                model_type TEXT,
+                record TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS models_model_type ON models (model_type);",
+        )?;
+        info!("已打开模型数据库: {}", path.display());
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> IResult<Vec<ModelJson>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(sql)?;
+        let records = statement
+            .query_map(params, |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        records
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn write(connection: &Connection, model: &ModelJson) -> IResult<()> {
+    connection.execute(
+        "INSERT INTO models (source_name, id, model_type, record, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (source_name) DO UPDATE SET
+            id = excluded.id,
+            model_type = excluded.model_type,
+            record = excluded.record,
+            updated_at = excluded.updated_at",
+        params![
+            model.source_directory_name,
+            model.id,
+            model.model_type,
+            serde_json::to_string(model)?,
+            chrono::Local::now().to_rfc3339(),
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+impl ModelRepository for SqliteRepository {
+    fn all(&self) -> IResult<Vec<ModelJson>> {
+        self.query("SELECT record FROM models ORDER BY source_name", [])
+    }
+
+    fn get(&self, source_name: &str) -> IResult<Option<ModelJson>> {
+        let connection = self.connection.lock().unwrap();
+        let record: Option<String> = connection
+            .query_row(
+                "SELECT record FROM models WHERE source_name = ?1",
+                [source_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(record.map(|r| serde_json::from_str(&r)).transpose()?)
+    }
+
+    fn by_model_type(&self, model_type: &str) -> IResult<Vec<ModelJson>> {
+        self.query(
+            "SELECT record FROM models WHERE model_type = ?1 ORDER BY source_name",
+            [model_type],
+        )
+    }
+
+    fn upsert(&self, model: &ModelJson) -> IResult<()> {
+        write(&self.connection.lock().unwrap(), model)
+    }
+
+    /// 记录数和最后修改时间，每次写入都会更新修改时间
+    fn version(&self) -> IResult<u64> {
+        let connection = self.connection.lock().unwrap();
+        let (count, updated_at): (i64, Option<String>) =
+            connection.query_row("SELECT COUNT(*), MAX(updated_at) FROM models", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        let mut hasher = DefaultHasher::new();
+        (count, updated_at).hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    fn set_statuses(
+        &self,
+        _store: &ModelStore,
+        statuses: &HashMap<String, Option<MoldStatus>>,
+    ) -> IResult<Vec<String>> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut updated = Vec::new();
+        for (name, status) in statuses {
+            let record: Option<String> = transaction
+                .query_row(
+                    "SELECT record FROM models WHERE source_name = ?1",
+                    [name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(record) = record else {
+                continue;
+            };
+            let mut model: ModelJson = serde_json::from_str(&record)?;
+            model.status = *status;
+            write(&transaction, &model)?;
+            updated.push(name.clone());
+        }
+        transaction.commit()?;
+        Ok(updated)
+    }
+}
+
+/// 把JSON文件中的模型导入数据库，已有的记录被替换，返回导入的数量
+pub fn import_json(models_dir: &Path, target: &dyn ModelRepository) -> IResult<usize> {
+    let models = JsonRepository::new(models_dir).all()?;
+    for model in &models {
+        target.upsert(model)?;
+    }
+    Ok(models.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::{TempDir, fixture};
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn sqlite_repository() {
+        let dir = TempDir::new("storage");
+        let db = SqliteRepository::open(&dir.join(SQLITE_FILE)).unwrap();
+        assert_eq!(import_json(&fixture("models"), &db).unwrap(), 3);
+        assert_eq!(db.all().unwrap().len(), 3);
+        assert_eq!(db.by_model_type("基座").unwrap().len(), 2);
+
+        let mut model = db.get("ME121基座").unwrap().unwrap();
+        model.company = Some("TCL".to_string());
+        db.upsert(&model).unwrap();
+        let statuses = HashMap::from([
+            ("ME121基座".to_string(), Some(MoldStatus::Sealed)),
+            ("不存在".to_string(), None),
+        ]);
+        let version = db.version().unwrap();
+        let store = ModelStore::new(Vec::new(), dir.to_path_buf());
+        assert_eq!(db.set_statuses(&store, &statuses).unwrap(), ["ME121基座"]);
+        assert_ne!(db.version().unwrap(), version);
+        drop(db);
+
+        // 重新打开后记录仍在
+        let db = SqliteRepository::open(&dir.join(SQLITE_FILE)).unwrap();
+        let model = db.get("ME121基座").unwrap().unwrap();
+        assert_eq!(model.company.as_deref(), Some("TCL"));
+        assert_eq!(model.status, Some(MoldStatus::Sealed));
+        assert_eq!(db.all().unwrap().len(), 3);
+        assert!(db.get("不存在").unwrap().is_none());
+    }
+
+    #[test]
+    #[cfg(not(feature = "sqlite"))]
+    fn sqlite_disabled() {
+        let config = StorageConfig {
+            backend: StorageBackend::Sqlite,
+            ..StorageConfig::default()
+        };
+        let error = open_repository(&TempDir::new("storage"), &config)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("sqlite"), "{}", error);
+    }
+
+    #[test]
+    fn json_repository() {
+        let dir = TempDir::new("storage");
+        let repository = open_repository(&dir, &StorageConfig::default()).unwrap();
+        assert!(repository.all().unwrap().is_empty());
+        let empty = repository.version().unwrap();
+
+        let mut model = JsonRepository::new(&fixture("models"))
+            .get("SEL4外壳")
+            .unwrap()
+            .unwrap();
+        repository.upsert(&model).unwrap();
+        let shard_dir = dir.join("jsons").join(shard(&model));
+        model.company = Some("TCL".to_string());
+        repository.upsert(&model).unwrap();
+
+        // 同一个模型只保存一份，修改时保留原来的位置
+        let mut files = Vec::new();
+        json_files(&dir.join("jsons"), &mut files).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with(shard_dir));
+        assert_ne!(repository.version().unwrap(), empty);
+        let saved = repository.get("SEL4外壳").unwrap().unwrap();
+        assert_eq!(saved.company.as_deref(), Some("TCL"));
+    }
+}
@@ -0,0 +1,1063 @@
+//! 模具数据库
+//!
+//! 模型记录保存在`models/jsons/<年份>/<客户>/*.json`，预览图在`models/imgs/<年份>/<客户>/<source_name>/`，
+//! 报告和比较页面通过来源名称(`source_directory_name`)查找模型。
+//! 未分片的历史数据(`models/jsons/*.json`、`models/imgs/<source_name>/`)同样可以读取，
+//! 用`material-cli shard-models`迁移。原始PDF图纸可以放在`models/pdfs/<source_name>.pdf`。
+//!
+//! 来源名称含有空格和中文，接口和比较链接优先使用稳定的id(ULID)和URL安全的slug，
+//! 历史记录用`material-cli assign-ids`补全，补全前仍可按来源名称查找。
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::{
+    CONFIG, IMAGE_URLS, IResult,
+    ai_text_analyzer::TEXT_EXTRACT_PROMPT_VERSION,
+    config::{ScoringProfile, StorageConfig},
+    diff::{DiffResult, ModelJson, Similarity, original_pdf_url},
+    model_storage::open_repository,
+    mold_status::MoldStatus,
+    paths::{portable, upload_dir, write_atomic},
+    prompt_registry::PromptRegistry,
+    query::UserQuery,
+    suggest::{SuggestIndex, Suggestion, SuggestionKind},
+    thumbnail::source_pdf,
+};
+
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+/// 没有提取时间或客户的模型所在的分片
+const UNKNOWN_SHARD: &str = "unknown";
+
+pub struct ModelStore {
+    /// 按模具类型分组的模型
+    grouped: HashMap<String, Vec<ModelJson>>,
+    /// 按来源名称索引的模型
+    by_name: HashMap<String, ModelJson>,
+    /// 按来源名称索引的分片目录
+    shards: HashMap<String, PathBuf>,
+    /// id和slug对应的来源名称
+    aliases: HashMap<String, String>,
+    /// 加载之后修改的模具状态，按来源名称索引
+    statuses: RwLock<HashMap<String, Option<MoldStatus>>>,
+    /// 检索框输入提示用的模具类型、材料和项目名称
+    suggestions: SuggestIndex,
+    img_dir: PathBuf,
+}
+
+/// 模型的一张预览图
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelImage {
+    pub name: String,
+    pub url: String,
+}
+
+/// 模型的完整记录和预览图
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelDetail {
+    #[serde(flatten)]
+    pub model: ModelJson,
+    pub images: Vec<ModelImage>,
+    /// 原始PDF图纸的下载链接
+    pub original_pdf_url: Option<String>,
+}
+
+/// 来源名称和id、slug的对应关系
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelIds {
+    pub source_name: String,
+    pub id: Option<String>,
+    pub slug: Option<String>,
+}
+
+/// 模具数据库的统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CorpusStats {
+    pub models: usize,
+    /// 按模具类型的模型数
+    pub model_types: BTreeMap<String, usize>,
+    /// 按模具状态的模型数，没有状态的记为`未标注`
+    pub statuses: BTreeMap<&'static str, usize>,
+    /// 按分片(年份/客户)的模型数
+    pub shards: BTreeMap<String, usize>,
+    /// 还没有id的历史记录数
+    pub missing_ids: usize,
+}
+
+/// 一组模型两两之间的相似度
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarityMatrix {
+    /// 模型的来源名称，顺序与请求一致
+    pub names: Vec<String>,
+    /// `scores[i][j]`为第i个模型与第j个模型比较的结果，第j个模型作为候选模具
+    pub scores: Vec<Vec<Similarity>>,
+}
+
+/// 模具数据库所在目录
+pub fn models_dir() -> PathBuf {
+    upload_dir().join("models")
+}
+
+/// 模型所在的分片`<年份>/<客户>`，年份取自提取时间
+pub fn shard(model: &ModelJson) -> PathBuf {
+    let year = model
+        .extraction_timestamp
+        .as_deref()
+        .and_then(|t| t.get(..4))
+        .filter(|y| y.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or(UNKNOWN_SHARD);
+    let customer: String = model
+        .company
+        .as_deref()
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"/\:*?"<>|"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let customer = match customer.as_str() {
+        "" | "." | ".." => UNKNOWN_SHARD,
+        customer => customer,
+    };
+    Path::new(year).join(customer)
+}
+
+impl ModelStore {
+    pub fn new(models: Vec<ModelJson>, img_dir: PathBuf) -> Self {
+        let by_name = models
+            .iter()
+            .map(|m| (m.source_directory_name.clone(), m.clone()))
+            .collect();
+        let shards = models
+            .iter()
+            .map(|m| (m.source_directory_name.clone(), shard(m)))
+            .collect();
+        let aliases = models
+            .iter()
+            .flat_map(|m| {
+                [&m.id, &m.slug]
+                    .into_iter()
+                    .flatten()
+                    .map(|alias| (alias.clone(), m.source_directory_name.clone()))
+            })
+            .collect();
+        let suggestions = SuggestIndex::new(&models);
+        Self {
+            grouped: ModelJson::sort(models),
+            by_name,
+            shards,
+            aliases,
+            statuses: RwLock::new(HashMap::new()),
+            suggestions,
+            img_dir,
+        }
+    }
+
+    /// 从`models`目录加载，包括所有分片
+    pub fn load(models_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let models = ModelJson::patch_new(models_dir.join("jsons"))?;
+        Ok(Self::new(models, models_dir.join("imgs")))
+    }
+
+    /// 从配置的存储加载，预览图仍在`models`目录中
+    pub fn open(models_dir: &Path, config: &StorageConfig) -> IResult<Self> {
+        let models = open_repository(models_dir, config)?.all()?;
+        Ok(Self::new(models, models_dir.join("imgs")))
+    }
+
+    /// 从配置的存储加载，与重新加载时一样跳过无法读取的记录并记录到日志
+    pub fn open_lenient(models_dir: &Path, config: &StorageConfig) -> IResult<Self> {
+        let (models, failed) = open_repository(models_dir, config)?.load_lenient()?;
+        for (record, error) in &failed {
+            warn!("跳过无法读取的模具记录 {}: {}", record, error);
+        }
+        Ok(Self::new(models, models_dir.join("imgs")))
+    }
+
+    /// 按模具类型分组的模型
+    pub fn grouped(&self) -> &HashMap<String, Vec<ModelJson>> {
+        &self.grouped
+    }
+
+    /// 预览图目录
+    pub fn img_dir(&self) -> &Path {
+        &self.img_dir
+    }
+
+    /// 按来源名称、id或slug查找模型
+    pub fn get(&self, key: &str) -> Option<&ModelJson> {
+        self.by_name.get(key).or_else(|| {
+            self.aliases
+                .get(key)
+                .and_then(|source_name| self.by_name.get(source_name))
+        })
+    }
+
+    /// 链接中使用的模型标识，没有slug时使用来源名称
+    pub fn link_name<'a>(&'a self, source_name: &'a str) -> &'a str {
+        self.get(source_name)
+            .and_then(|m| m.slug.as_deref())
+            .unwrap_or(source_name)
+    }
+
+    /// 模具的当前状态，包括加载之后的修改
+    pub fn status(&self, key: &str) -> Option<MoldStatus> {
+        let model = self.get(key)?;
+        match self
+            .statuses
+            .read()
+            .unwrap()
+            .get(&model.source_directory_name)
+        {
+            Some(status) => *status,
+            None => model.status,
+        }
+    }
+
+    /// 模型记录文件的位置，按分片和来源名称推断，也查找分片之前的位置，文件不存在时为None
+    pub fn record_path(&self, models_dir: &Path, key: &str) -> Option<PathBuf> {
+        let name = &self.get(key)?.source_directory_name;
+        let file = format!("{}_text_data.json", name);
+        let jsons = models_dir.join("jsons");
+        let sharded = self
+            .shards
+            .get(name)
+            .map(|shard| jsons.join(shard).join(&file));
+        sharded
+            .into_iter()
+            .chain([jsons.join(&file)])
+            .find(|path| path.is_file())
+    }
+
+    /// 修改模具状态，只修改内存中的索引，记录文件用`write_statuses`保存，
+    /// 返回模型的来源名称，找不到模型时返回None
+    pub fn set_status(&self, key: &str, status: Option<MoldStatus>) -> Option<String> {
+        let name = self.get(key)?.source_directory_name.clone();
+        self.statuses.write().unwrap().insert(name.clone(), status);
+        Some(name)
+    }
+
+    /// 填入结果的当前模具状态，`include_scrapped`为false时去掉已报废的模具
+    pub fn apply_status(&self, results: &mut Vec<DiffResult>, include_scrapped: bool) {
+        for result in results.iter_mut() {
+            if self.get(&result.source_name).is_some() {
+                result.status = self.status(&result.source_name);
+            }
+        }
+        if !include_scrapped {
+            results.retain(|r| r.status != Some(MoldStatus::Scrapped));
+        }
+    }
+
+    /// 按检索条件检索模具库，结果带有当前的模具状态并按相似度排序，
+    /// `include_scrapped`为false时去掉已报废的模具
+    pub fn search_with(
+        &self,
+        query: &UserQuery,
+        profile: &ScoringProfile,
+        include_scrapped: bool,
+    ) -> Vec<DiffResult> {
+        let mut results = ModelJson::search_combined(&self.grouped, query, profile);
+        self.apply_status(&mut results, include_scrapped);
+        DiffResult::sort(&mut results);
+        results
+    }
+
+    /// 使用配置的默认评分方案检索，见`search_with`
+    pub fn search(&self, query: &UserQuery) -> Vec<DiffResult> {
+        self.search_with(
+            query,
+            &CONFIG.diff.default_profile(),
+            CONFIG.diff.include_scrapped,
+        )
+    }
+
+    /// 与模具库中的模具比较，结果带有当前的模具状态并按相似度排序
+    pub fn compare(
+        &self,
+        model: ModelJson,
+        profile: &ScoringProfile,
+        include_scrapped: bool,
+    ) -> Vec<DiffResult> {
+        let mut results = ModelJson::diff(self.grouped.clone(), model, profile);
+        self.apply_status(&mut results, include_scrapped);
+        DiffResult::sort(&mut results);
+        results
+    }
+
+    /// 按模具类型、状态和分片统计模型数
+    pub fn stats(&self) -> CorpusStats {
+        let mut stats = CorpusStats {
+            models: self.by_name.len(),
+            ..Default::default()
+        };
+        for (model_type, models) in &self.grouped {
+            stats.model_types.insert(model_type.clone(), models.len());
+        }
+        for (name, model) in &self.by_name {
+            let status = self.status(name).map_or("未标注", |s| s.label());
+            *stats.statuses.entry(status).or_default() += 1;
+            if let Some(shard) = self.shards.get(name) {
+                *stats
+                    .shards
+                    .entry(shard.to_string_lossy().replace('\\', "/"))
+                    .or_default() += 1;
+            }
+            if model.id.is_none() {
+                stats.missing_ids += 1;
+            }
+        }
+        stats
+    }
+
+    /// 检索框的输入提示，见`SuggestIndex::suggest`
+    pub fn suggest(
+        &self,
+        query: &str,
+        kind: Option<SuggestionKind>,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        self.suggestions.suggest(query, kind, limit)
+    }
+
+    /// 模型的来源名称、id和slug
+    pub fn ids(&self, key: &str) -> Option<ModelIds> {
+        self.get(key).map(|m| ModelIds {
+            source_name: m.source_directory_name.clone(),
+            id: m.id.clone(),
+            slug: m.slug.clone(),
+        })
+    }
+
+    /// 所有模型的来源名称，没有顺序
+    pub fn source_names(&self) -> impl Iterator<Item = &String> {
+        self.by_name.keys()
+    }
+
+    /// 所有模型的来源名称、id和slug，按来源名称排序
+    pub fn all_ids(&self) -> Vec<ModelIds> {
+        let mut names: Vec<&String> = self.by_name.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| self.ids(name))
+            .collect()
+    }
+
+    /// 需要重新提取的模型的来源名称：没有提取来源的历史记录，以及使用旧的提示词版本
+    /// 或与`model_name`不同的模型提取的记录，按提示词版本从旧到新、再按名称排序
+    pub fn stale_extractions(
+        &self,
+        prompts: &PromptRegistry,
+        model_name: Option<&str>,
+    ) -> Vec<String> {
+        let active = prompts.active().unwrap_or(TEXT_EXTRACT_PROMPT_VERSION);
+        let mut stale: Vec<(usize, &str)> = self
+            .by_name
+            .values()
+            .filter(|m| m.needs_reextraction(active, model_name))
+            .map(|m| {
+                let version = m.provenance.as_ref().map(|p| p.prompt_version.as_str());
+                (prompts.priority(version), m.source_directory_name.as_str())
+            })
+            .collect();
+        stale.sort();
+        stale
+            .into_iter()
+            .map(|(_, name)| name.to_string())
+            .collect()
+    }
+
+    /// 一组模型两两之间的相似度，可以使用来源名称、id或slug，有找不到的模型时返回这些名称
+    pub fn similarity_matrix(
+        &self,
+        keys: &[String],
+        profile: &ScoringProfile,
+    ) -> Result<SimilarityMatrix, Vec<String>> {
+        let missing: Vec<String> = keys
+            .iter()
+            .filter(|key| self.get(key).is_none())
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+        let models: Vec<&ModelJson> = keys.iter().filter_map(|key| self.get(key)).collect();
+        Ok(SimilarityMatrix {
+            names: models
+                .iter()
+                .map(|m| m.source_directory_name.clone())
+                .collect(),
+            scores: models
+                .iter()
+                .map(|a| models.iter().map(|b| a.similarity(b, profile)).collect())
+                .collect(),
+        })
+    }
+
+    /// 模型预览图目录相对`models/imgs`的路径，只有未分片的目录存在时使用未分片的目录
+    pub fn image_dir(&self, source_name: &str) -> String {
+        let Some(shard) = self.shards.get(source_name) else {
+            return source_name.to_string();
+        };
+        let sharded = shard.join(source_name);
+        if !self.img_dir.join(&sharded).is_dir() && self.img_dir.join(source_name).is_dir() {
+            return source_name.to_string();
+        }
+        sharded
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// 模型的完整记录和预览图，可以使用来源名称、id或slug
+    pub fn detail(&self, key: &str) -> Option<ModelDetail> {
+        let mut model = self.get(key)?.clone();
+        model.status = self.status(key);
+        Some(ModelDetail {
+            original_pdf_url: model.original_pdf.as_deref().and_then(original_pdf_url),
+            images: self.images(&model.source_directory_name),
+            model,
+        })
+    }
+
+    /// 模型已有的预览图，按页码排序
+    pub fn images(&self, source_name: &str) -> Vec<ModelImage> {
+        let image_dir = self.image_dir(source_name);
+        let Ok(entries) = std::fs::read_dir(self.img_dir.join(&image_dir)) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .extension()
+                        .and_then(|s| s.to_str())
+                        .is_none_or(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            })
+            .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+            .collect();
+        // page_2 排在 page_10 之前，预览图可能没有扩展名
+        names.sort_by_key(|name| {
+            let stem = Path::new(name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (stem.len(), stem)
+        });
+        names
+            .into_iter()
+            .map(|name| ModelImage {
+                url: IMAGE_URLS.image_url(&format!("models/imgs/{}/{}", image_dir, name)),
+                name,
+            })
+            .collect()
+    }
+}
+
+/// 对一条记录的迁移，`path`为记录文件，返回是否修改了记录
+pub(crate) type RecordMigration = fn(&Path, &Path, &mut Map<String, Value>) -> IResult<bool>;
+
+/// 记录的来源名称
+pub(crate) fn record_name(fields: &Map<String, Value>) -> String {
+    fields
+        .get("source_directory_name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn has_value(fields: &Map<String, Value>, key: &str) -> bool {
+    fields.get(key).is_some_and(|v| !v.is_null())
+}
+
+/// 对所有记录执行迁移并保存修改的记录，记录中的其他字段原样保留，返回修改的模型的来源名称
+fn migrate_records(models_dir: &Path, migration: RecordMigration) -> IResult<Vec<String>> {
+    let mut files = Vec::new();
+    json_files(&models_dir.join("jsons"), &mut files)?;
+    let mut migrated = Vec::new();
+    for path in files {
+        let mut record: Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        let Some(fields) = record.as_object_mut() else {
+            continue;
+        };
+        if migration(models_dir, &path, fields)? {
+            let name = record_name(fields);
+            write_atomic(&path, serde_json::to_string_pretty(&record)?)?;
+            migrated.push(name);
+        }
+    }
+    Ok(migrated)
+}
+
+/// 依次查找`models/pdfs/<source_name>.pdf`和页面图片目录对应的PDF，只记录存在的文件
+pub(crate) fn fill_original_pdf(
+    models_dir: &Path,
+    _path: &Path,
+    fields: &mut Map<String, Value>,
+) -> IResult<bool> {
+    if has_value(fields, "original_pdf") {
+        return Ok(false);
+    }
+    let name = record_name(fields);
+    let source_directory = fields
+        .get("source_directory")
+        .and_then(Value::as_str)
+        .map(|s| portable(Path::new(s)));
+    let archived = models_dir.join("pdfs").join(format!("{}.pdf", name));
+    let pdf = if !name.is_empty() && archived.is_file() {
+        Some(archived)
+    } else {
+        source_directory.as_deref().and_then(source_pdf)
+    };
+    let Some(pdf) = pdf else {
+        return Ok(false);
+    };
+    info!("📎 {} 的原始图纸: {}", name, pdf.display());
+    fields.insert(
+        "original_pdf".to_string(),
+        Value::String(pdf.to_string_lossy().to_string()),
+    );
+    Ok(true)
+}
+
+/// 为没有`original_pdf`的历史记录补全原始PDF，返回更新的模型的来源名称，见`fill_original_pdf`
+pub fn migrate_original_pdfs(models_dir: &Path) -> IResult<Vec<String>> {
+    migrate_records(models_dir, fill_original_pdf)
+}
+
+/// 把未分片的模型记录和预览图移动到`<年份>/<客户>`分片，返回移动的模型的来源名称
+pub fn shard_models(models_dir: &Path) -> IResult<Vec<String>> {
+    let (jsons, imgs) = (models_dir.join("jsons"), models_dir.join("imgs"));
+    let mut moved = Vec::new();
+    for entry in std::fs::read_dir(&jsons)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+        let model: ModelJson = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        let shard = shard(&model);
+        let target = jsons
+            .join(&shard)
+            .join(path.file_name().unwrap_or_default());
+        if target.exists() {
+            warn!("分片中已有同名记录，跳过: {}", target.display());
+            continue;
+        }
+        std::fs::create_dir_all(jsons.join(&shard))?;
+        std::fs::rename(&path, &target)?;
+
+        let name = &model.source_directory_name;
+        let images = imgs.join(name);
+        let sharded_images = imgs.join(&shard).join(name);
+        if !name.is_empty() && images.is_dir() && !sharded_images.exists() {
+            std::fs::create_dir_all(imgs.join(&shard))?;
+            std::fs::rename(&images, &sharded_images)?;
+        }
+        info!("📦 {} -> {}", name, shard.display());
+        moved.push(name.clone());
+    }
+    Ok(moved)
+}
+
+/// URL安全的slug: 来源名称中的字母和数字加上id的末6位，例如`me121-k3xq2d`
+pub fn slugify(source_name: &str, id: &str) -> String {
+    let mut slug = String::new();
+    for c in source_name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let suffix = id[id.len().saturating_sub(6)..].to_lowercase();
+    if slug.is_empty() {
+        format!("model-{}", suffix)
+    } else {
+        format!("{}-{}", slug, suffix)
+    }
+}
+
+/// 为没有id的记录分配id和slug
+pub(crate) fn fill_id(
+    _models_dir: &Path,
+    _path: &Path,
+    fields: &mut Map<String, Value>,
+) -> IResult<bool> {
+    if has_value(fields, "id") {
+        return Ok(false);
+    }
+    let name = record_name(fields);
+    let id = ulid::Ulid::new().to_string();
+    let slug = slugify(&name, &id);
+    info!("🔖 {} -> {} ({})", name, slug, id);
+    fields.insert("id".to_string(), Value::String(id));
+    fields.insert("slug".to_string(), Value::String(slug));
+    Ok(true)
+}
+
+/// 为没有id的模型记录分配id和slug，返回更新的模型的来源名称
+pub fn assign_ids(models_dir: &Path) -> IResult<Vec<String>> {
+    migrate_records(models_dir, fill_id)
+}
+
+/// 补全提取时间，使用原始PDF的修改时间，没有原始PDF时使用记录文件的修改时间
+pub(crate) fn fill_timestamp(
+    _models_dir: &Path,
+    path: &Path,
+    fields: &mut Map<String, Value>,
+) -> IResult<bool> {
+    if has_value(fields, "extraction_timestamp") {
+        return Ok(false);
+    }
+    let name = record_name(fields);
+    let pdf = fields
+        .get("original_pdf")
+        .and_then(Value::as_str)
+        .map(|s| portable(Path::new(s)))
+        .filter(|pdf| pdf.is_file());
+    let modified = std::fs::metadata(pdf.as_deref().unwrap_or(path))?.modified()?;
+    let timestamp = chrono::DateTime::<chrono::Local>::from(modified).to_rfc3339();
+    info!("🕒 {} 的提取时间: {}", name, timestamp);
+    fields.insert("extraction_timestamp".to_string(), Value::String(timestamp));
+    Ok(true)
+}
+
+/// 为没有`extraction_timestamp`的历史记录补全提取时间，返回更新的模型的来源名称
+pub fn backfill_timestamps(models_dir: &Path) -> IResult<Vec<String>> {
+    migrate_records(models_dir, fill_timestamp)
+}
+
+/// 修改记录文件中的模具状态，`statuses`按来源名称索引，None表示清除状态，
+/// 返回修改的模型的来源名称，记录中的其他字段原样保留
+///
+/// 记录文件先按`store`中的分片查找，找不到时才读取所有记录，跳过无法读取的文件。
+/// 修改后的记录写入临时文件再替换，重新加载时不会读到写了一半的文件。
+pub fn write_statuses(
+    models_dir: &Path,
+    store: &ModelStore,
+    statuses: &HashMap<String, Option<MoldStatus>>,
+) -> IResult<Vec<String>> {
+    let mut found = HashMap::new();
+    let mut missing = Vec::new();
+    for name in statuses.keys() {
+        match store.record_path(models_dir, name) {
+            Some(path) => {
+                found.insert(path, name.clone());
+            }
+            None => missing.push(name.clone()),
+        }
+    }
+    if !missing.is_empty() {
+        let mut files = Vec::new();
+        json_files(&models_dir.join("jsons"), &mut files)?;
+        for path in files {
+            if !found.contains_key(&path)
+                && let Some(name) = record_source_name(&path)
+                && missing.contains(&name)
+            {
+                found.insert(path, name);
+            }
+        }
+    }
+
+    let mut updated = Vec::new();
+    for (path, name) in found {
+        let mut record: Value = match read_json(&path) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("读取模型记录失败，跳过 {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let Some(fields) = record.as_object_mut() else {
+            continue;
+        };
+        if fields.get("source_directory_name").and_then(Value::as_str) != Some(name.as_str()) {
+            continue;
+        }
+        let status = statuses[&name];
+        match status {
+            Some(status) => fields.insert("status".to_string(), serde_json::to_value(status)?),
+            None => fields.remove("status"),
+        };
+        info!("🏷️ {} 的状态: {}", name, status.map_or("-", |s| s.label()));
+        write_atomic(&path, serde_json::to_string_pretty(&record)?)?;
+        updated.push(name);
+    }
+    Ok(updated)
+}
+
+fn read_json(path: &Path) -> IResult<Value> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// 记录文件中的来源名称，无法读取时为None
+fn record_source_name(path: &Path) -> Option<String> {
+    let record = read_json(path)
+        .inspect_err(|e| warn!("读取模型记录失败，跳过 {}: {}", path.display(), e))
+        .ok()?;
+    record
+        .get("source_directory_name")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// 目录及子目录中的所有`.json`文件
+pub(crate) fn json_files(dir: &Path, files: &mut Vec<PathBuf>) -> IResult<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            json_files(&path, files)?;
+        } else if path.extension().and_then(|s| s.to_str()) == Some("json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::ApiProvider,
+        diff::Provenance,
+        paths::{TempDir, fixture},
+    };
+
+    #[test]
+    fn lookup_by_source_name() {
+        let img_dir = TempDir::new("store");
+        let store = ModelStore::new(
+            ModelJson::patch_new(fixture("models/jsons")).unwrap(),
+            img_dir.to_path_buf(),
+        );
+        let model = store.get("ME121基座").unwrap();
+        assert_eq!(model.project_name.as_deref(), Some("ME121"));
+        assert!(store.get("不存在").is_none());
+        assert_eq!(store.grouped()["基座"].len(), 2);
+
+        let images = img_dir.join("ME121基座");
+        std::fs::create_dir_all(&images).unwrap();
+        for name in ["ME121基座_page_010", "ME121基座_page_002.png", "notes.txt"] {
+            std::fs::write(images.join(name), b"img").unwrap();
+        }
+        let detail = store.detail("ME121基座").unwrap();
+        let names: Vec<_> = detail.images.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["ME121基座_page_002.png", "ME121基座_page_010"]);
+        // 链接中的名称已转义
+        assert!(
+            detail.images[0]
+                .url
+                .contains("models/imgs/ME121%E5%9F%BA%E5%BA%A7/")
+        );
+    }
+
+    #[test]
+    fn pairwise_similarity() {
+        let store = ModelStore::new(
+            ModelJson::patch_new(fixture("models/jsons")).unwrap(),
+            std::env::temp_dir(),
+        );
+        let mut names: Vec<String> = store.all_ids().into_iter().map(|i| i.source_name).collect();
+        names.truncate(3);
+        let matrix = store
+            .similarity_matrix(&names, &ScoringProfile::default())
+            .unwrap();
+        assert_eq!(matrix.names, names);
+        assert_eq!(matrix.scores.len(), names.len());
+        for (i, row) in matrix.scores.iter().enumerate() {
+            assert_eq!(row.len(), names.len());
+            assert!(row[i].percentage > 0.99);
+        }
+
+        let missing = store
+            .similarity_matrix(
+                &[names[0].clone(), "不存在".to_string()],
+                &ScoringProfile::default(),
+            )
+            .unwrap_err();
+        assert_eq!(missing, ["不存在"]);
+    }
+
+    #[test]
+    fn migrate_original_pdf() {
+        let root = TempDir::new("store");
+        let models = root.join("models");
+        std::fs::create_dir_all(models.join("jsons")).unwrap();
+        std::fs::create_dir_all(models.join("pdfs")).unwrap();
+        std::fs::create_dir_all(root.join("drawings/output/B外壳")).unwrap();
+        std::fs::write(models.join("pdfs/A基座.pdf"), b"%PDF").unwrap();
+        std::fs::write(root.join("drawings/B外壳.pdf"), b"%PDF").unwrap();
+        for (name, dir) in [
+            ("A基座", root.join("output/A基座")),
+            ("B外壳", root.join("drawings/output/B外壳")),
+            ("C外壳", root.join("output/C外壳")),
+        ] {
+            let record = serde_json::json!({
+                "model_type": "基座",
+                "materials": [],
+                "project_name": null,
+                "source_directory": dir,
+                "source_directory_name": name,
+                "extraction_timestamp": null,
+                "reviewed": true,
+            });
+            std::fs::write(
+                models
+                    .join("jsons")
+                    .join(format!("{}_text_data.json", name)),
+                record.to_string(),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(migrate_original_pdfs(&models).unwrap().len(), 2);
+        assert!(migrate_original_pdfs(&models).unwrap().is_empty());
+        let store = ModelStore::load(&models).unwrap();
+        assert_eq!(
+            store.get("A基座").unwrap().original_pdf,
+            Some(models.join("pdfs/A基座.pdf"))
+        );
+        assert_eq!(
+            store.get("B外壳").unwrap().original_pdf,
+            Some(root.join("drawings/B外壳.pdf"))
+        );
+        assert_eq!(store.get("C外壳").unwrap().original_pdf, None);
+        // 其他字段原样保留
+        let raw = std::fs::read_to_string(models.join("jsons/A基座_text_data.json")).unwrap();
+        assert!(raw.contains("reviewed"));
+    }
+
+    #[test]
+    fn shard_by_year_and_customer() {
+        let root = TempDir::new("store");
+        let models = root.copy_fixture_models();
+        std::fs::create_dir_all(models.join("imgs/ME121基座")).unwrap();
+        std::fs::write(models.join("imgs/ME121基座/ME121基座_page_001"), b"img").unwrap();
+
+        assert_eq!(shard_models(&models).unwrap().len(), 3);
+        assert!(shard_models(&models).unwrap().is_empty());
+        assert!(
+            models
+                .join("jsons/2025/unknown/ME121基座_text_data.json")
+                .is_file()
+        );
+
+        // 分片后按来源名称查找和预览图链接不变
+        let store = ModelStore::load(&models).unwrap();
+        assert_eq!(store.grouped()["基座"].len(), 2);
+        assert_eq!(store.image_dir("ME121基座"), "2025/unknown/ME121基座");
+        let detail = store.detail("ME121基座").unwrap();
+        assert!(
+            detail.images[0]
+                .url
+                .contains("models/imgs/2025/unknown/ME121%E5%9F%BA%E5%BA%A7/")
+        );
+
+        let model = ModelJson {
+            company: Some("TCL/华星".to_string()),
+            extraction_timestamp: None,
+            ..store.get("ME121基座").unwrap().clone()
+        };
+        assert_eq!(shard(&model), Path::new("unknown/TCL_华星"));
+    }
+
+    #[test]
+    fn backfill_missing_timestamps() {
+        let dir = TempDir::new("store");
+        let models = dir.copy_fixture_models();
+        let before = ModelStore::load(&models).unwrap();
+        let missing: Vec<String> = before
+            .all_ids()
+            .into_iter()
+            .map(|i| i.source_name)
+            .filter(|name| before.get(name).unwrap().extraction_timestamp.is_none())
+            .collect();
+        assert!(!missing.is_empty());
+
+        assert_eq!(backfill_timestamps(&models).unwrap(), missing);
+        assert!(backfill_timestamps(&models).unwrap().is_empty());
+        let store = ModelStore::load(&models).unwrap();
+        assert!(store.all_ids().iter().all(|i| {
+            store
+                .get(&i.source_name)
+                .unwrap()
+                .extraction_timestamp
+                .is_some()
+        }));
+        // 已有的提取时间不变
+        assert_eq!(
+            store.get("ME121基座").unwrap().extraction_timestamp,
+            before.get("ME121基座").unwrap().extraction_timestamp
+        );
+    }
+
+    #[test]
+    fn find_stale_extractions() {
+        let mut models = ModelJson::patch_new(fixture("models/jsons")).unwrap();
+        let provenance = |model_name: &str, prompt_version: &str| {
+            Some(Provenance {
+                provider: ApiProvider::DashScope,
+                model_name: model_name.to_string(),
+                prompt_version: prompt_version.to_string(),
+            })
+        };
+        models[0].provenance = provenance("qwen-vl-max", TEXT_EXTRACT_PROMPT_VERSION);
+        models[1].provenance = provenance("qwen-vl-plus", "text-extract-v1");
+        models[2].provenance = None;
+        let names: Vec<String> = models
+            .iter()
+            .map(|m| m.source_directory_name.clone())
+            .collect();
+        let store = ModelStore::new(models, std::env::temp_dir());
+        let mut prompts = PromptRegistry::default();
+        prompts.activate("text-extract-v1", "旧的提示词");
+        prompts.activate(TEXT_EXTRACT_PROMPT_VERSION, "当前的提示词");
+
+        // 没有指定模型时只比较提示词版本，没有提取来源的记录最优先
+        assert_eq!(
+            store.stale_extractions(&prompts, None),
+            [names[2].clone(), names[1].clone()]
+        );
+        // 当前版本但不是指定的模型提取的记录排在最后
+        assert_eq!(
+            store.stale_extractions(&prompts, Some("qwen-vl-plus")),
+            [names[2].clone(), names[1].clone(), names[0].clone()]
+        );
+        assert_eq!(
+            store.stale_extractions(&prompts, Some("qwen-vl-max")).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn lookup_by_id_and_slug() {
+        assert_eq!(
+            slugify("208T-03_A基座", "01J9ZK3XQ2D4F6H8K3XQ2D"),
+            "208t-03-a-k3xq2d"
+        );
+        assert_eq!(slugify("外壳", "01J9ZK3XQ2D4F6H8K3XQ2D"), "model-k3xq2d");
+
+        let dir = TempDir::new("store");
+        let models = dir.copy_fixture_models();
+        assert_eq!(assign_ids(&models).unwrap().len(), 3);
+        assert!(assign_ids(&models).unwrap().is_empty());
+
+        let store = ModelStore::load(&models).unwrap();
+        let ids = store.ids("ME121基座").unwrap();
+        let (id, slug) = (ids.id.unwrap(), ids.slug.unwrap());
+        assert_eq!(id.len(), 26);
+        assert!(slug.starts_with("me121-"));
+        assert_eq!(store.get(&id).unwrap().source_directory_name, "ME121基座");
+        assert_eq!(
+            store.detail(&slug).unwrap().model.source_directory_name,
+            "ME121基座"
+        );
+        assert_eq!(store.link_name("ME121基座"), slug);
+        assert_eq!(store.link_name("不存在"), "不存在");
+        assert_eq!(store.all_ids().len(), 3);
+    }
+
+    #[test]
+    fn open_skips_unreadable_records() {
+        let dir = TempDir::new("store");
+        let models = dir.copy_fixture_models();
+        std::fs::write(models.join("jsons/broken_text_data.json"), "{").unwrap();
+        let config = StorageConfig::default();
+        assert!(ModelStore::open(&models, &config).is_err());
+        let store = ModelStore::open_lenient(&models, &config).unwrap();
+        assert_eq!(store.source_names().count(), 3);
+    }
+
+    #[test]
+    fn update_mold_status() {
+        let dir = TempDir::new("store");
+        let models = dir.copy_fixture_models();
+        let statuses = HashMap::from([
+            ("ME121基座".to_string(), Some(MoldStatus::Scrapped)),
+            ("不存在".to_string(), Some(MoldStatus::Sealed)),
+        ]);
+        let store = ModelStore::load(&models).unwrap();
+        // 其他无法读取的记录不影响修改
+        std::fs::write(models.join("jsons/broken_text_data.json"), "{").unwrap();
+        assert_eq!(
+            write_statuses(&models, &store, &statuses).unwrap(),
+            ["ME121基座"]
+        );
+        std::fs::remove_file(models.join("jsons/broken_text_data.json")).unwrap();
+        let store = ModelStore::load(&models).unwrap();
+        assert_eq!(store.status("ME121基座"), Some(MoldStatus::Scrapped));
+
+        let base = store.get("208T-03_A基座").unwrap().clone();
+        let mut results =
+            ModelJson::diff(store.grouped().clone(), base, &ScoringProfile::default());
+        assert!(results.iter().any(|r| r.source_name == "ME121基座"));
+        let mut flagged = results.clone();
+        store.apply_status(&mut flagged, true);
+        store.apply_status(&mut results, false);
+        assert!(results.iter().all(|r| r.source_name != "ME121基座"));
+        assert!(
+            flagged
+                .iter()
+                .any(|r| r.status == Some(MoldStatus::Scrapped))
+        );
+        let base = store.get("208T-03_A基座").unwrap().clone();
+        assert_eq!(
+            store.compare(base, &ScoringProfile::default(), false).len(),
+            results.len()
+        );
+        let query = UserQuery {
+            model_type: Some("基座".to_string()),
+            ..Default::default()
+        };
+        let profile = ScoringProfile::default();
+        assert!(
+            store
+                .search_with(&query, &profile, true)
+                .iter()
+                .any(|r| r.status == Some(MoldStatus::Scrapped))
+        );
+        assert!(
+            store
+                .search_with(&query, &profile, false)
+                .iter()
+                .all(|r| r.source_name != "ME121基座")
+        );
+
+        // 内存中的修改立即生效
+        assert_eq!(
+            store
+                .set_status("ME121基座", Some(MoldStatus::InUse))
+                .as_deref(),
+            Some("ME121基座")
+        );
+        assert_eq!(
+            store.detail("ME121基座").unwrap().model.status,
+            Some(MoldStatus::InUse)
+        );
+        assert!(store.set_status("不存在", None).is_none());
+
+        let stats = store.stats();
+        assert_eq!(stats.models, 3);
+        assert_eq!(stats.model_types["基座"], 2);
+        assert_eq!(stats.statuses["在用"], 1);
+        assert_eq!(stats.statuses.values().sum::<usize>(), 3);
+        assert_eq!(stats.shards.values().sum::<usize>(), 3);
+    }
+}
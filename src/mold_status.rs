@@ -0,0 +1,136 @@
+//! 实体模具的状态
+//!
+//! 模具记录可以标记为在用、封存或报废，报废的模具默认不出现在比较和检索结果中，
+//! 封存的模具在结果中标注，避免工程师去找已经不存在的模具。
+//! 状态通过`PUT /material/api/v1/models/{source_name}/status`修改，
+//! 或用`material-cli import-mold-status`从资产系统导出的CSV批量导入。
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoldStatus {
+    /// 在用
+    #[serde(alias = "在用")]
+    InUse,
+    /// 封存
+    #[serde(alias = "封存")]
+    Sealed,
+    /// 报废
+    #[serde(alias = "报废")]
+    Scrapped,
+}
+
+impl MoldStatus {
+    /// 解析中文或英文的状态名称
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "在用" | "in_use" | "in-use" | "active" => Some(Self::InUse),
+            "封存" | "sealed" | "stored" => Some(Self::Sealed),
+            "报废" | "scrapped" | "scrap" => Some(Self::Scrapped),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::InUse => "在用",
+            Self::Sealed => "封存",
+            Self::Scrapped => "报废",
+        }
+    }
+
+    /// 结果中的标注，在用的模具不标注
+    pub fn flag(status: Option<Self>) -> &'static str {
+        match status {
+            Some(Self::Sealed) => " 🔒封存",
+            Some(Self::Scrapped) => " ⛔已报废",
+            _ => "",
+        }
+    }
+}
+
+/// CSV中的一行: 来源名称(或id、slug)和状态，空状态表示清除
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusRow {
+    pub key: String,
+    pub status: Option<MoldStatus>,
+}
+
+/// 解析资产系统导出的CSV，每行`来源名称,状态`，可以有表头，
+/// 返回有效的行和无法解析的行(行号从1开始)
+pub fn parse_csv(content: &str) -> (Vec<StatusRow>, Vec<(usize, String)>) {
+    let mut rows = Vec::new();
+    let mut invalid = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() {
+            continue;
+        }
+        let (key, status) = line.split_once(',').unwrap_or((line, ""));
+        let (key, status) = (
+            key.trim().trim_matches('"'),
+            status.trim().trim_matches('"'),
+        );
+        let status = match status {
+            "" => None,
+            status => match MoldStatus::parse(status) {
+                Some(status) => Some(status),
+                // 第一行不是有效状态时视为表头
+                None if index == 0 => continue,
+                None => {
+                    invalid.push((index + 1, line.to_string()));
+                    continue;
+                }
+            },
+        };
+        if key.is_empty() {
+            invalid.push((index + 1, line.to_string()));
+            continue;
+        }
+        rows.push(StatusRow {
+            key: key.to_string(),
+            status,
+        });
+    }
+    (rows, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status_csv() {
+        assert_eq!(MoldStatus::parse(" 报废 "), Some(MoldStatus::Scrapped));
+        assert_eq!(MoldStatus::parse("Sealed"), Some(MoldStatus::Sealed));
+        assert_eq!(
+            serde_json::from_str::<MoldStatus>(r#""在用""#).unwrap(),
+            MoldStatus::InUse
+        );
+
+        let (rows, invalid) = parse_csv(
+            "来源名称,状态\nME121基座,报废\n\"ME122外壳\",封存\nME123,\n,在用\nME124,丢失\n",
+        );
+        assert_eq!(
+            rows,
+            vec![
+                StatusRow {
+                    key: "ME121基座".to_string(),
+                    status: Some(MoldStatus::Scrapped),
+                },
+                StatusRow {
+                    key: "ME122外壳".to_string(),
+                    status: Some(MoldStatus::Sealed),
+                },
+                StatusRow {
+                    key: "ME123".to_string(),
+                    status: None,
+                },
+            ]
+        );
+        assert_eq!(
+            invalid,
+            vec![(5, ",在用".to_string()), (6, "ME124,丢失".to_string())]
+        );
+    }
+}
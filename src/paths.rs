@@ -0,0 +1,169 @@
+//! 跨平台的路径处理
+//!
+//! 服务部署在Linux上，但历史模型数据和聊天消息中的路径可能来自Windows(`D:\work\...`)，
+//! 这里统一数据目录的位置，以及`/`和`\`两种分隔符的路径的转换。
+use std::{
+    env::current_exe,
+    path::{Path, PathBuf},
+};
+
+/// 可执行文件所在目录
+pub fn exe_dir() -> PathBuf {
+    current_exe()
+        .map_err(|e| format!("获取执行目录失败: {}", e))
+        .unwrap()
+        .parent()
+        .ok_or("无法获取执行目录的父目录")
+        .unwrap()
+        .to_path_buf()
+}
+
+/// 数据目录，可通过`MATERIAL_DATA_DIR`指定，默认为执行目录下的`data`
+pub fn data_dir() -> PathBuf {
+    std::env::var("MATERIAL_DATA_DIR")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| exe_dir().join("data"))
+}
+
+/// 上传文件的根目录
+pub fn upload_dir() -> PathBuf {
+    data_dir().join("upload").join("file")
+}
+
+/// 把`/`或`\`分隔的相对路径转为本地路径，以根目录开头或包含`..`、盘符时返回None
+pub fn relative_path(text: &str) -> Option<PathBuf> {
+    if text.starts_with(['/', '\\']) {
+        return None;
+    }
+    let mut path = PathBuf::new();
+    for part in text.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            part if part.contains(':') => return None,
+            part => path.push(part),
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+/// 把其他平台记录的路径转为本平台的分隔符，`D:\a\b`在Linux上为`D:/a/b`
+pub fn portable(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '\\' {
+        PathBuf::from(text.replace('/', "\\"))
+    } else {
+        PathBuf::from(text.replace('\\', "/"))
+    }
+}
+
+/// 先写入同目录下的临时文件再重命名，写入中断或同时读取时不会得到不完整的文件
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+    std::fs::write(&temp, contents)?;
+    std::fs::rename(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+/// 测试数据目录
+#[cfg(test)]
+pub fn fixture(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(relative_path(path).expect("测试数据路径无效"))
+}
+
+/// 测试用的临时目录，离开作用域时删除
+#[cfg(test)]
+pub struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// 新建`material_{prefix}_{uuid}`目录
+    pub fn new(prefix: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("material_{}_{}", prefix, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// 把测试数据中的模型记录复制到`models/jsons`，返回`models`目录
+    pub fn copy_fixture_models(&self) -> PathBuf {
+        let models = self.0.join("models");
+        std::fs::create_dir_all(models.join("jsons")).unwrap();
+        for file in std::fs::read_dir(fixture("models/jsons")).unwrap() {
+            let file = file.unwrap();
+            std::fs::copy(file.path(), models.join("jsons").join(file.file_name())).unwrap();
+        }
+        models
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let expected: PathBuf = ["2025", "8", "7", "e034f8aa"].iter().collect();
+        assert_eq!(relative_path("2025/8/7/e034f8aa"), Some(expected.clone()));
+        assert_eq!(
+            relative_path("2025\\8\\7\\e034f8aa"),
+            Some(expected.clone())
+        );
+        assert_eq!(
+            relative_path("2025/8//7/./e034f8aa"),
+            Some(expected.clone())
+        );
+
+        assert_eq!(relative_path("../etc/passwd"), None);
+        assert_eq!(relative_path("/etc/passwd"), None);
+        assert_eq!(relative_path("a\\..\\..\\b"), None);
+        assert_eq!(relative_path("D:\\work"), None);
+        assert_eq!(relative_path(""), None);
+    }
+
+    #[test]
+    fn replace_files_atomically() {
+        let dir = TempDir::new("atomic");
+        let path = dir.join("a.json");
+        write_atomic(&path, "1").unwrap();
+        write_atomic(&path, "2").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2");
+        assert_eq!(std::fs::read_dir(&*dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn portable_paths() {
+        let path = portable(Path::new("D:\\work\\output\\ME121基座"));
+        assert_eq!(path.file_name().unwrap(), "ME121基座");
+        assert!(fixture("models/jsons").is_dir());
+    }
+}
@@ -1,8 +1,194 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
-use pdf2image::{DPI, PDF, Pages, RenderOptionsBuilder, image::ImageFormat};
+use image::{DynamicImage, RgbImage, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use pdf2image::{DPI, PDF, Pages, RenderOptionsBuilder};
+use serde::{Deserialize, Serialize};
 
-use crate::{AnalyzerError, IResult};
+use crate::{AnalyzerError, CONFIG, IResult, config::ConvertConfig};
+
+/// 渲染图纸使用的分辨率
+pub const RENDER_DPI: u32 = 300;
+/// 目录模式下默认最多同时转换的PDF数量
+const MAX_DEFAULT_WORKERS: usize = 4;
+/// 每个文档的输出目录中记录页面图片的清单
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// 预览用图片所在的子目录，文本提取只读取文档目录中的图片，不会读到预览图
+pub const PREVIEW_DIR: &str = "preview";
+/// 超过大小上限时依次尝试的JPEG质量
+const JPEG_QUALITIES: [u8; 4] = [90, 75, 60, 45];
+const PREVIEW_QUALITY: u8 = 80;
+/// 最低质量仍然超过大小上限时每次缩小的比例
+const SHRINK_RATIO: f64 = 0.75;
+const MAX_SHRINKS: usize = 8;
+
+/// 第`page`页(从0开始)的图片文件名，`page_001.jpg`，补零后按文件名排序即为页面顺序
+pub fn page_file_name(page: usize) -> String {
+    format!("page_{:03}.jpg", page + 1)
+}
+
+/// 清单中的一页
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageImage {
+    /// 分析用的图片
+    pub file: String,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub bytes: u64,
+    /// 预览用的图片，相对于文档目录
+    #[serde(default)]
+    pub preview: Option<String>,
+    /// PDF书签中这一页的标题，例如`装配图`
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// 一个PDF转换出的页面图片，使用方按清单取页面，不再猜测文件名
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageManifest {
+    /// 源PDF的文件名
+    pub source: String,
+    pub dpi: u32,
+    pub pages: Vec<PageImage>,
+}
+
+impl PageManifest {
+    /// 读取目录中的清单，没有或无法解析时返回None，由调用方按旧的文件名处理
+    pub fn load(dir: &Path) -> Option<Self> {
+        let content = std::fs::read(dir.join(MANIFEST_FILE)).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    pub fn save(&self, dir: &Path) -> IResult<()> {
+        std::fs::write(dir.join(MANIFEST_FILE), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 图片文件对应页面的书签标题
+    pub fn label(&self, file_name: &str) -> Option<&str> {
+        self.pages
+            .iter()
+            .find(|p| p.file == file_name)
+            .and_then(|p| p.label.as_deref())
+    }
+
+    /// 第`page`页(从0开始)的预览图，没有时为分析用的图片
+    pub fn preview_path(&self, dir: &Path, page: usize) -> Option<PathBuf> {
+        self.pages
+            .get(page)
+            .map(|p| dir.join(p.preview.as_ref().unwrap_or(&p.file)))
+    }
+}
+
+/// PDF书签(视图名、图纸编号等)指向的页面，页码从0开始，同一页有多个书签时用` / `连接，
+/// 没有书签或无法读取时为空
+fn outline_labels(path: &Path) -> BTreeMap<usize, String> {
+    let Ok(toc) = lopdf::Document::load(path).and_then(|doc| doc.get_toc()) else {
+        return BTreeMap::new();
+    };
+    let mut labels: BTreeMap<usize, String> = BTreeMap::new();
+    for entry in toc.toc {
+        let title = entry.title.trim();
+        if title.is_empty() || entry.page == 0 {
+            continue;
+        }
+        labels
+            .entry(entry.page - 1)
+            .and_modify(|label| {
+                label.push_str(" / ");
+                label.push_str(title);
+            })
+            .or_insert_with(|| title.to_string());
+    }
+    labels
+}
+
+fn scaled((width, height): (u32, u32), scale: f64) -> (u32, u32) {
+    (
+        ((width as f64 * scale) as u32).max(1),
+        ((height as f64 * scale) as u32).max(1),
+    )
+}
+
+fn encode_jpeg(img: &RgbImage, quality: u8) -> IResult<Vec<u8>> {
+    let mut data = Vec::new();
+    JpegEncoder::new_with_quality(&mut data, quality)
+        .encode_image(img)
+        .map_err(|e| AnalyzerError::PdfError(format!("Failed to encode image: {}", e)))?;
+    Ok(data)
+}
+
+/// 把渲染的页面编码为不超过大小限制的JPEG，返回编码后的数据和对应的图片
+fn encode_page(page: &DynamicImage, limits: &ConvertConfig) -> IResult<(Vec<u8>, RgbImage)> {
+    let pixels = page.width() as u64 * page.height() as u64;
+    let mut img = if pixels > limits.max_pixels() {
+        let scale = (limits.max_pixels() as f64 / pixels as f64).sqrt();
+        let (width, height) = scaled((page.width(), page.height()), scale);
+        page.resize_exact(width, height, FilterType::Triangle)
+            .to_rgb8()
+    } else {
+        page.to_rgb8()
+    };
+    for _ in 0..=MAX_SHRINKS {
+        for quality in JPEG_QUALITIES {
+            let data = encode_jpeg(&img, quality)?;
+            if data.len() as u64 <= limits.max_bytes() {
+                return Ok((data, img));
+            }
+        }
+        let (width, height) = scaled(img.dimensions(), SHRINK_RATIO);
+        img = image::imageops::resize(&img, width, height, FilterType::Triangle);
+    }
+    Err(AnalyzerError::PdfError(format!(
+        "Page image exceeds {}MB after compression",
+        limits.max_megabytes
+    )))
+}
+
+/// 预览用的小图
+fn encode_preview(img: &RgbImage, max_width: u32) -> IResult<Vec<u8>> {
+    if img.width() <= max_width {
+        return encode_jpeg(img, PREVIEW_QUALITY);
+    }
+    let (width, height) = scaled(img.dimensions(), max_width as f64 / img.width() as f64);
+    encode_jpeg(
+        &image::imageops::resize(img, width, height, FilterType::Triangle),
+        PREVIEW_QUALITY,
+    )
+}
+
+/// 一个PDF的转换失败
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchFailure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// 一次转换中每个PDF的结果，一个文件失败不影响其他文件
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchReport {
+    /// 转换成功的PDF
+    pub converted: Vec<PathBuf>,
+    pub failed: Vec<BatchFailure>,
+}
+
+/// 把PDF转换为图片，输出到`output_dir/<PDF文件名>`
+pub fn convert_to_image(path: &Path, output_dir: &Path) -> Result<PathBuf, String> {
+    let name = path.file_stem().ok_or("Invalid PDF file name")?;
+    let runner = PdfConverterRunner::new(path, Some(output_dir)).map_err(|e| e.to_string())?;
+    let report = runner.run().map_err(|e| e.to_string())?;
+    match report.failed.into_iter().next() {
+        None => Ok(runner.output.join(name)),
+        Some(failure) => Err(failure.error),
+    }
+}
 
 /// 用于转化pdf为png图片的运行时
 #[derive(Debug, Clone)]
@@ -12,62 +198,95 @@ pub struct PdfConverterRunner {
     /// 输出文件夹，默认为path的同级目录的/output文件夹，如果没有则创建
     pub output: PathBuf,
     pub is_dir: bool,
+    /// 目录模式下同时转换的PDF数量
+    pub workers: usize,
 }
 
 impl PdfConverterRunner {
-    pub fn new<P1, P2>(path: P1, output: Option<P2>) -> Self
+    /// 路径不存在或无法创建输出目录时返回错误
+    pub fn new<P1, P2>(path: P1, output: Option<P2>) -> IResult<Self>
     where
         P1: AsRef<Path>,
         P2: AsRef<Path>,
     {
         let path = path.as_ref().to_path_buf();
         if !path.exists() {
-            panic!("Path {:?} does not exist", path);
+            return Err(AnalyzerError::PdfError(format!(
+                "PDF path does not exist: {}",
+                path.display()
+            )));
         }
 
         let is_dir = path.is_dir();
-        let output = if output.is_none() {
-            if is_dir {
-                path.join("output")
-            } else {
-                path.parent()
-                    .map(|p| p.join("output"))
-                    .unwrap_or_else(|| PathBuf::from("output"))
-            }
-        } else {
-            output.unwrap().as_ref().to_path_buf()
+        let output = match output {
+            Some(output) => output.as_ref().to_path_buf(),
+            None if is_dir => path.join("output"),
+            None => path
+                .parent()
+                .map(|p| p.join("output"))
+                .unwrap_or_else(|| PathBuf::from("output")),
         };
 
-        if !output.exists() {
-            std::fs::create_dir_all(&output).expect("Failed to create output directory");
-        }
+        std::fs::create_dir_all(&output).map_err(|e| {
+            AnalyzerError::PdfError(format!(
+                "Failed to create output directory {}: {}",
+                output.display(),
+                e
+            ))
+        })?;
 
-        Self {
+        let workers = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_DEFAULT_WORKERS);
+        Ok(Self {
             path,
             output,
             is_dir,
-        }
+            workers,
+        })
     }
-    /// 执行转换
-    pub fn run(&self) -> IResult<()> {
-        if self.is_dir {
-            // 如果是目录，则遍历目录下的所有pdf文件
+
+    /// 执行转换，返回每个PDF的结果，只有读取目录失败时返回错误
+    pub fn run(&self) -> IResult<BatchReport> {
+        let files = if self.is_dir {
+            // 如果是目录，则转换目录下的所有pdf文件
+            let mut files = Vec::new();
             for entry in std::fs::read_dir(&self.path)? {
-                let entry = entry?;
-                if entry.path().extension().and_then(|s| s.to_str()) == Some("pdf") {
-                    let converter = PdfConverter::new(&entry.path(), &self.output);
-                    // 这里可以调用转换方法
-                    converter.run()?;
+                let path = entry?.path();
+                if path.extension().and_then(|s| s.to_str()) == Some("pdf") {
+                    files.push(path);
                 }
             }
+            files.sort();
+            files
         } else {
-            // 如果是单个文件，则直接转换
-            let converter = PdfConverter::new(&self.path, &self.output);
-            // 这里可以调用转换方法
-            converter.run()?;
-        }
+            vec![self.path.clone()]
+        };
 
-        Ok(())
+        let report = Mutex::new(BatchReport::default());
+        let next = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..self.workers.clamp(1, files.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let result = PdfConverter::new(path, &self.output).run();
+                        let mut report = report.lock().unwrap();
+                        match result {
+                            Ok(()) => report.converted.push(path.clone()),
+                            Err(e) => report.failed.push(BatchFailure {
+                                path: path.clone(),
+                                error: e.to_string(),
+                            }),
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut report = report.into_inner().unwrap();
+        report.converted.sort();
+        report.failed.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(report)
     }
 }
 
@@ -76,6 +295,8 @@ pub struct PdfConverter {
     /// pdf文件路径
     pub path: PathBuf,
     pub output: PathBuf,
+    /// 页面图片的大小限制
+    pub limits: ConvertConfig,
 }
 
 impl PdfConverter {
@@ -86,7 +307,11 @@ impl PdfConverter {
     {
         let path = path.as_ref().to_path_buf();
         let output = output.as_ref().to_path_buf();
-        Self { path, output }
+        Self {
+            path,
+            output,
+            limits: CONFIG.convert,
+        }
     }
     pub fn run(&self) -> IResult<()> {
         let name = self
@@ -103,7 +328,7 @@ impl PdfConverter {
         println!("PDF页数: {}", page_count);
 
         let option = RenderOptionsBuilder::default()
-            .resolution(DPI::Uniform(300))
+            .resolution(DPI::Uniform(RENDER_DPI))
             .pdftocairo(true)
             .build()
             .map_err(|e| {
@@ -122,19 +347,41 @@ impl PdfConverter {
         };
         println!("实际渲染页数: {}", pages.len());
 
+        let output_dir = self.output.join(name);
+        std::fs::create_dir_all(output_dir.join(PREVIEW_DIR))
+            .map_err(|e| AnalyzerError::PdfError(format!("Failed to create output dir: {}", e)))?;
+        let mut manifest = PageManifest {
+            source: self
+                .path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            dpi: RENDER_DPI,
+            pages: Vec::with_capacity(pages.len()),
+        };
+        let mut labels = outline_labels(&self.path);
         for (index, page) in pages.iter().enumerate() {
-            let filename = format!("page_{}.jpg", index);
+            let filename = page_file_name(index);
             println!("保存图片: {}", filename);
-            let output_dir = self.output.join(name);
-            if !output_dir.exists() {
-                std::fs::create_dir_all(&output_dir).map_err(|e| {
-                    AnalyzerError::PdfError(format!("Failed to create output dir: {}", e))
-                })?;
-            }
-
-            page.save_with_format(output_dir.join(&filename), ImageFormat::Jpeg)
+            let (data, img) = encode_page(page, &self.limits)?;
+            std::fs::write(output_dir.join(&filename), &data)
                 .map_err(|e| AnalyzerError::PdfError(format!("Failed to save image: {}", e)))?;
+            let preview = format!("{}/{}", PREVIEW_DIR, filename);
+            std::fs::write(
+                output_dir.join(&preview),
+                encode_preview(&img, self.limits.preview_width)?,
+            )
+            .map_err(|e| AnalyzerError::PdfError(format!("Failed to save preview: {}", e)))?;
+            manifest.pages.push(PageImage {
+                file: filename,
+                width: img.width(),
+                height: img.height(),
+                bytes: data.len() as u64,
+                preview: Some(preview),
+                label: labels.remove(&index),
+            });
         }
+        manifest.save(&output_dir)?;
 
         Ok(())
     }
@@ -143,13 +390,171 @@ impl PdfConverter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::paths::{TempDir, fixture};
 
     #[test]
-    fn test_pdf_converter() {
-        let runner = PdfConverterRunner::new(
-            "D:\\work\\material_rs\\pdfs\\03-jz.pdf",
-            Option::<String>::None,
+    fn batch_conversion() {
+        let dir = TempDir::new("pdf");
+        std::fs::copy(fixture("pdfs/03-jz.pdf"), dir.join("03-jz.pdf")).unwrap();
+        std::fs::write(dir.join("broken.pdf"), b"not a pdf").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let runner = PdfConverterRunner {
+            workers: 2,
+            ..PdfConverterRunner::new(&dir, None::<&Path>).unwrap()
+        };
+        assert_eq!(runner.output, dir.join("output"));
+        // 损坏的文件不影响其他文件，没有安装poppler时两个文件都失败
+        let report = runner.run().unwrap();
+        assert_eq!(report.converted.len() + report.failed.len(), 2);
+        assert!(
+            report
+                .failed
+                .iter()
+                .any(|f| f.path == dir.join("broken.pdf"))
+        );
+
+        // 路径不存在或输出目录无法创建时返回错误而不是panic
+        let missing = PdfConverterRunner::new(dir.join("missing.pdf"), None::<&Path>).unwrap_err();
+        assert!(
+            missing.to_string().contains("does not exist"),
+            "{}",
+            missing
+        );
+        assert!(
+            PdfConverterRunner::new(dir.join("03-jz.pdf"), Some(dir.join("notes.txt"))).is_err()
+        );
+    }
+
+    /// UTF-16BE编码的PDF文字
+    fn pdf_text(text: &str) -> lopdf::Object {
+        let mut bytes = vec![0xfe, 0xff];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        lopdf::Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+    }
+
+    #[test]
+    fn labels_from_outline() {
+        use lopdf::{Document, Object, dictionary};
+
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let page_ids: Vec<_> = (0..3)
+            .map(|_| {
+                doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+                })
+            })
+            .collect();
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => page_ids.iter().map(|id| Object::Reference(*id)).collect::<Vec<_>>(),
+                "Count" => 3,
+            }),
         );
+        let outlines_id = doc.new_object_id();
+        let items: Vec<_> = [("装配图", 0), ("零件图", 2), ("ME121-03", 2)]
+            .into_iter()
+            .map(|(title, page)| {
+                doc.add_object(dictionary! {
+                    "Title" => pdf_text(title),
+                    "Parent" => outlines_id,
+                    "Dest" => vec![Object::Reference(page_ids[page]), "Fit".into()],
+                })
+            })
+            .collect();
+        for (i, id) in items.iter().enumerate() {
+            let item = doc.get_object_mut(*id).unwrap().as_dict_mut().unwrap();
+            if let Some(next) = items.get(i + 1) {
+                item.set("Next", *next);
+            }
+        }
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => items[0],
+                "Last" => items[2],
+                "Count" => 3,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Outlines" => outlines_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let dir = TempDir::new("outline");
+        let path = dir.join("outline.pdf");
+        doc.save(&path).unwrap();
+        let labels = outline_labels(&path);
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[&0], "装配图");
+        assert_eq!(labels[&2], "零件图 / ME121-03");
+        let manifest = PageManifest {
+            source: "outline.pdf".to_string(),
+            dpi: RENDER_DPI,
+            pages: (0..3)
+                .map(|page| PageImage {
+                    file: page_file_name(page),
+                    width: 100,
+                    height: 100,
+                    bytes: 0,
+                    preview: None,
+                    label: labels.get(&page).cloned(),
+                })
+                .collect(),
+        };
+        assert_eq!(manifest.label("page_001.jpg"), Some("装配图"));
+        assert_eq!(manifest.label("page_002.jpg"), None);
+
+        // 没有书签或不是PDF
+        assert!(outline_labels(&fixture("pdfs/03-jz.pdf")).is_empty());
+        std::fs::write(dir.join("broken.pdf"), b"not a pdf").unwrap();
+        assert!(outline_labels(&dir.join("broken.pdf")).is_empty());
+    }
+
+    #[test]
+    fn page_size_budget() {
+        // 噪点图片压缩率低，大小主要由像素数决定
+        let mut seed = 7u32;
+        let page = DynamicImage::ImageRgb8(RgbImage::from_fn(1500, 1000, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let [r, g, b, _] = seed.to_le_bytes();
+            image::Rgb([r, g, b])
+        }));
+        let limits = ConvertConfig {
+            max_megapixels: 1,
+            max_megabytes: 1,
+            preview_width: 600,
+        };
+        let (data, img) = encode_page(&page, &limits).unwrap();
+        assert!(data.len() as u64 <= limits.max_bytes());
+        assert!(img.width() as u64 * img.height() as u64 <= limits.max_pixels());
+        // 保持宽高比
+        assert_eq!(img.width() * 2, img.height() * 3);
+        let decoded = image::load_from_memory(&data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), img.dimensions());
+
+        let preview = image::load_from_memory(&encode_preview(&img, 600).unwrap()).unwrap();
+        assert_eq!(preview.width(), 600);
+
+        // 不超过限制的页面保持原样
+        let small = DynamicImage::ImageRgb8(RgbImage::new(300, 200));
+        let (_, img) = encode_page(&small, &limits).unwrap();
+        assert_eq!(img.dimensions(), (300, 200));
+    }
+
+    #[test]
+    fn test_pdf_converter() {
+        let output = TempDir::new("pdf");
+        let runner =
+            PdfConverterRunner::new(fixture("pdfs/03-jz.pdf"), Some(output.to_path_buf())).unwrap();
         // assert!(runner.run().is_ok());
 
         match runner.run() {
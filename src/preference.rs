@@ -0,0 +1,108 @@
+//! 用户的偏好设置
+//!
+//! 保存在`data/preferences.json`，包括结果的展示形式(`/layout`指令)和分析使用的采样参数(`/sampling`指令)。
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    IResult,
+    config::{ReportLayout, Sampling},
+    paths::data_dir,
+};
+
+const PREFERENCES_FILE: &str = "preferences.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserPreferences {
+    /// 按用户uid记录的展示形式
+    #[serde(default)]
+    layouts: HashMap<u64, ReportLayout>,
+    /// 按用户uid记录的采样参数，没有时使用配置中的默认值
+    #[serde(default)]
+    samplings: HashMap<u64, Sampling>,
+}
+
+/// 偏好设置所在的数据目录
+pub fn preferences_dir() -> PathBuf {
+    data_dir()
+}
+
+impl UserPreferences {
+    pub fn load(dir: &Path) -> IResult<Self> {
+        let path = dir.join(PREFERENCES_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> IResult<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(
+            dir.join(PREFERENCES_FILE),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// 用户选择的展示形式
+    pub fn layout(&self, uid: u64) -> Option<ReportLayout> {
+        self.layouts.get(&uid).copied()
+    }
+
+    pub fn set_layout(&mut self, uid: u64, layout: ReportLayout) {
+        self.layouts.insert(uid, layout);
+    }
+
+    pub fn sampling(&self, uid: u64) -> Option<Sampling> {
+        self.samplings.get(&uid).copied()
+    }
+
+    /// None表示恢复默认
+    pub fn set_sampling(&mut self, uid: u64, sampling: Option<Sampling>) {
+        match sampling {
+            Some(sampling) => self.samplings.insert(uid, sampling),
+            None => self.samplings.remove(&uid),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::TempDir;
+
+    #[test]
+    fn layouts_are_persisted() {
+        let dir = TempDir::new("prefs");
+        let mut preferences = UserPreferences::load(&dir).unwrap();
+        assert_eq!(preferences.layout(1), None);
+
+        preferences.set_layout(1, ReportLayout::Compact);
+        preferences.save(&dir).unwrap();
+        let preferences = UserPreferences::load(&dir).unwrap();
+        assert_eq!(preferences.layout(1), Some(ReportLayout::Compact));
+        assert_eq!(preferences.layout(2), None);
+    }
+
+    #[test]
+    fn samplings_are_persisted() {
+        let dir = TempDir::new("prefs");
+        let mut preferences = UserPreferences::load(&dir).unwrap();
+        let sampling = Sampling {
+            temperature: 0.0,
+            seed: Some(7),
+        };
+        preferences.set_sampling(1, Some(sampling));
+        preferences.save(&dir).unwrap();
+        let mut preferences = UserPreferences::load(&dir).unwrap();
+        assert_eq!(preferences.sampling(1), Some(sampling));
+
+        preferences.set_sampling(1, None);
+        assert_eq!(preferences.sampling(1), None);
+    }
+}
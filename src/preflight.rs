@@ -0,0 +1,227 @@
+//! 分析前的PDF检查
+//!
+//! 用`pdfinfo`读取页数和页面尺寸，超过页数、渲染像素或预计耗时的限制时不启动分析，
+//! 避免误发的几百页规格书占满分析队列。
+use std::{path::Path, process::Command};
+
+use thiserror::Error;
+use tracing::{error, warn};
+
+use crate::{BLOBS, CONFIG, blob::Blob, config::PreflightConfig, pdf_converter::RENDER_DPI};
+
+/// PDF的页数和按渲染分辨率估算的规模
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfStats {
+    pub pages: u32,
+    /// 所有页面渲染后的像素总数
+    pub pixels: u64,
+    pub estimated_seconds: u64,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PreflightError {
+    #[error("📄 PDF共有{pages}页，超过了{max}页的限制。请只发送模具图纸，规格书等长文档无法分析")]
+    TooManyPages { pages: u32, max: u32 },
+
+    #[error("🖼️ PDF页面过大(渲染后约{megapixels}百万像素，限制为{max})，请发送正常图幅的图纸")]
+    TooLarge { megapixels: u64, max: u64 },
+
+    #[error("⏱️ 预计需要约{minutes}分钟，超过了{max}分钟的限制，请拆分后分别发送")]
+    TooSlow { minutes: u64, max: u64 },
+
+    #[error("❌ 无法读取PDF: {0}")]
+    Unreadable(String),
+}
+
+impl PreflightError {
+    /// 接口返回的英文说明
+    pub fn message_en(&self) -> String {
+        match self {
+            Self::TooManyPages { pages, max } => format!(
+                "The PDF has {} pages, more than the limit of {}. Only mold drawings can be analyzed",
+                pages, max
+            ),
+            Self::TooLarge { megapixels, max } => format!(
+                "The PDF pages are too large (about {} megapixels when rendered, the limit is {})",
+                megapixels, max
+            ),
+            Self::TooSlow { minutes, max } => format!(
+                "The analysis would take about {} minutes, more than the limit of {} minutes. Please split the PDF",
+                minutes, max
+            ),
+            Self::Unreadable(reason) => format!("The PDF cannot be read: {}", reason),
+        }
+    }
+}
+
+/// 检查上传的PDF，未通过时释放文件，聊天和接口上传的文件都使用
+pub fn check_upload(pdf: &Blob) -> Result<(), PreflightError> {
+    preflight(&pdf.path, &CONFIG.preflight)
+        .map(|_| ())
+        .inspect_err(|e| {
+            warn!("PDF检查未通过: {}", e);
+            if let Err(e) = BLOBS.release(&pdf.hash) {
+                error!("释放文件失败: {}", e);
+            }
+        })
+}
+
+/// 在阻塞线程中运行`check_upload`，`pdfinfo`读取大文件可能需要几秒，不能占用异步运行时的线程
+pub async fn check_upload_blocking(pdf: &Blob) -> Result<(), PreflightError> {
+    let pdf = pdf.clone();
+    tokio::task::spawn_blocking(move || check_upload(&pdf))
+        .await
+        .unwrap_or_else(|e| Err(PreflightError::Unreadable(e.to_string())))
+}
+
+/// 检查PDF是否在限制之内，`pdfinfo`不可用时跳过检查
+pub fn preflight(
+    path: &Path,
+    config: &PreflightConfig,
+) -> Result<Option<PdfStats>, PreflightError> {
+    let output = match Command::new("pdfinfo")
+        .arg("-f")
+        .arg("1")
+        .arg("-l")
+        .arg("9999")
+        .arg(path)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("无法运行pdfinfo，跳过PDF检查: {}", e);
+            return Ok(None);
+        }
+    };
+    if !output.status.success() {
+        return Err(PreflightError::Unreadable(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let stats = parse_pdfinfo(&String::from_utf8_lossy(&output.stdout), config)
+        .ok_or_else(|| PreflightError::Unreadable("无法识别页数".to_string()))?;
+    check(&stats, config)?;
+    Ok(Some(stats))
+}
+
+/// 解析`pdfinfo -f 1 -l N`的输出
+fn parse_pdfinfo(output: &str, config: &PreflightConfig) -> Option<PdfStats> {
+    let mut pages = None;
+    let mut sizes = Vec::new();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key == "Pages" {
+            pages = value.trim().parse::<u32>().ok();
+        } else if key.starts_with("Page") && key.ends_with("size") {
+            // `595.276 x 841.89 pts (A4)`
+            let mut numbers = value
+                .split_whitespace()
+                .filter_map(|s| s.parse::<f64>().ok());
+            if let (Some(width), Some(height)) = (numbers.next(), numbers.next()) {
+                sizes.push((width, height));
+            }
+        }
+    }
+    let pages = pages?;
+
+    // 1pt = 1/72英寸，只有部分页面的尺寸时其余页面按最后一页估算
+    let scale = RENDER_DPI as f64 / 72.0;
+    let page_pixels = |(width, height): (f64, f64)| (width * scale * height * scale) as u64;
+    let last = sizes.last().copied().unwrap_or((595.0, 842.0));
+    let pixels = (0..pages as usize)
+        .map(|index| page_pixels(sizes.get(index).copied().unwrap_or(last)))
+        .sum();
+
+    Some(PdfStats {
+        pages,
+        pixels,
+        estimated_seconds: pages as u64 * config.seconds_per_page,
+    })
+}
+
+fn check(stats: &PdfStats, config: &PreflightConfig) -> Result<(), PreflightError> {
+    if stats.pages > config.max_pages {
+        return Err(PreflightError::TooManyPages {
+            pages: stats.pages,
+            max: config.max_pages,
+        });
+    }
+    let megapixels = stats.pixels / 1_000_000;
+    if megapixels > config.max_megapixels {
+        return Err(PreflightError::TooLarge {
+            megapixels,
+            max: config.max_megapixels,
+        });
+    }
+    if stats.estimated_seconds > config.max_estimated_seconds {
+        return Err(PreflightError::TooSlow {
+            minutes: stats.estimated_seconds.div_ceil(60),
+            max: config.max_estimated_seconds / 60,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PDFINFO: &str = "Producer:       AutoCAD
+Pages:          2
+Page    1 size: 1190.55 x 841.89 pts (A3)
+Page    1 rot:  0
+Page    2 size: 595.276 x 841.89 pts (A4)
+Page    2 rot:  0
+";
+
+    #[test]
+    fn parse_and_check_limits() {
+        let config = PreflightConfig::default();
+        let stats = parse_pdfinfo(PDFINFO, &config).unwrap();
+        assert_eq!(stats.pages, 2);
+        // A3 + A4 在300dpi下约26百万像素
+        assert_eq!(stats.pixels / 1_000_000, 26);
+        assert!(check(&stats, &config).is_ok());
+
+        let spec = PdfStats {
+            pages: 300,
+            ..stats.clone()
+        };
+        let error = check(&spec, &config).unwrap_err();
+        assert_eq!(
+            error,
+            PreflightError::TooManyPages {
+                pages: 300,
+                max: config.max_pages
+            }
+        );
+        assert!(
+            error
+                .message_en()
+                .starts_with("The PDF has 300 pages, more than the limit of")
+        );
+
+        let huge = PdfStats {
+            pixels: 2_000_000_000,
+            ..stats.clone()
+        };
+        assert!(matches!(
+            check(&huge, &config),
+            Err(PreflightError::TooLarge { .. })
+        ));
+
+        let slow = PreflightConfig {
+            max_estimated_seconds: 30,
+            ..config.clone()
+        };
+        assert!(matches!(
+            check(&stats, &slow),
+            Err(PreflightError::TooSlow { .. })
+        ));
+
+        assert_eq!(parse_pdfinfo("Syntax Error", &config), None);
+    }
+}
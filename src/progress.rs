@@ -0,0 +1,186 @@
+//! 长时间分析时定期提醒用户任务仍在进行
+use std::{
+    future::Future,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::{
+    JOB_EVENTS, JOBS, ai_text_analyzer::TextExtractionResult, config::ProgressConfig,
+    job::JobStage, job_events::JobProgress, progress_sink::ProgressSink, workflow::send_markdown,
+};
+
+/// 工作流的进度回调：更新任务阶段，推送网页事件，并记录已提取的页数用于进度提醒
+pub struct JobSink {
+    job_id: String,
+    /// 已提取的页数和总页数
+    pages: Mutex<Option<(usize, usize)>>,
+    /// 提取失败的页数
+    failed: AtomicUsize,
+}
+
+impl JobSink {
+    pub fn new(job_id: String) -> Self {
+        Self {
+            job_id,
+            pages: Mutex::new(None),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    /// 提醒中的提取进度，例如`，已提取 3/7 页`，还没有提取完任何一页时为空
+    pub fn summary(&self) -> String {
+        let Some((page, total)) = *self.pages.lock().unwrap() else {
+            return String::new();
+        };
+        match self.failed.load(Ordering::Relaxed) {
+            0 => format!("，已提取 {}/{} 页", page, total),
+            failed => format!("，已提取 {}/{} 页({} 页失败)", page, total, failed),
+        }
+    }
+}
+
+impl ProgressSink for JobSink {
+    fn on_stage(&self, stage: JobStage) {
+        JOBS.update(&self.job_id, |job| job.stage = Some(stage));
+        JOB_EVENTS.publish(&self.job_id, JobProgress::Stage { stage });
+    }
+
+    fn on_page(&self, page: usize, total: usize, result: &TextExtractionResult) {
+        *self.pages.lock().unwrap() = Some((page, total));
+        JOB_EVENTS.publish(
+            &self.job_id,
+            JobProgress::PageAnalyzed {
+                page,
+                total,
+                label: result.page_label.clone(),
+                success: result.is_success(),
+                result: Box::new(result.clone()),
+            },
+        );
+    }
+
+    fn on_error(&self, page: Option<usize>, _error: &str) {
+        if page.is_some() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 进度提醒，由执行分析的任务自己驱动，分析结束(成功或失败)时立即停止
+pub struct ProgressNotifier {
+    config: ProgressConfig,
+    client: reqwest::Client,
+    webhook_url: String,
+    api_key: String,
+    /// 提醒中附上的提取进度
+    progress: Arc<JobSink>,
+    started: Instant,
+}
+
+impl ProgressNotifier {
+    /// 耗时从调用时开始计算
+    pub fn new(
+        config: ProgressConfig,
+        client: reqwest::Client,
+        webhook_url: String,
+        api_key: String,
+        progress: Arc<JobSink>,
+    ) -> Self {
+        Self {
+            config,
+            client,
+            webhook_url,
+            api_key,
+            progress,
+            started: Instant::now(),
+        }
+    }
+
+    /// 已用时间
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// 运行`task`，在它完成之前按配置定期发送提醒
+    pub async fn supervise<T>(&self, task: impl Future<Output = T>) -> T {
+        tokio::pin!(task);
+        let enabled = !self.config.quiet && self.config.interval_seconds > 0;
+        let interval = Duration::from_secs(self.config.interval_seconds.max(1));
+        let mut pings = 0;
+
+        loop {
+            tokio::select! {
+                // 优先检查任务是否已经结束，避免结束后再多发一次提醒
+                biased;
+                output = &mut task => return output,
+                _ = tokio::time::sleep(interval), if enabled && pings < self.config.max_pings => {
+                    pings += 1;
+                    debug!("发送第 {} 次进度提醒", pings);
+                    let message = self
+                        .config
+                        .message
+                        .replace("{elapsed}", &fmt_elapsed(self.elapsed()))
+                        .replace("{pages}", &self.progress.summary());
+                    send_markdown(&self.client, &self.webhook_url, &self.api_key, &message).await;
+                }
+            }
+        }
+    }
+}
+
+/// `75秒` → `1分15秒`
+pub fn fmt_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match (seconds / 60, seconds % 60) {
+        (0, s) => format!("{}秒", s),
+        (m, 0) => format!("{}分钟", m),
+        (m, s) => format!("{}分{}秒", m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_elapsed() {
+        assert_eq!(fmt_elapsed(Duration::from_secs(15)), "15秒");
+        assert_eq!(fmt_elapsed(Duration::from_secs(120)), "2分钟");
+        assert_eq!(fmt_elapsed(Duration::from_millis(75_900)), "1分15秒");
+    }
+
+    #[tokio::test]
+    async fn failed_task_stops_immediately() {
+        let notifier = ProgressNotifier::new(
+            ProgressConfig {
+                interval_seconds: 60,
+                ..Default::default()
+            },
+            reqwest::Client::new(),
+            String::new(),
+            String::new(),
+            Arc::new(JobSink::new(String::new())),
+        );
+        let result: Result<(), &str> = notifier.supervise(async { Err("PDF 转换失败") }).await;
+        assert_eq!(result, Err("PDF 转换失败"));
+        assert!(notifier.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn page_summary() {
+        let sink = JobSink::new("job".to_string());
+        assert_eq!(sink.summary(), "");
+        let failed = TextExtractionResult::new_error("page_002.jpg".into(), "超时".to_string());
+        sink.on_error(Some(2), "超时");
+        sink.on_page(2, 7, &failed);
+        // 整个分析失败不计入失败的页数
+        sink.on_error(None, "PDF 转换失败");
+        assert_eq!(sink.summary(), "，已提取 2/7 页(1 页失败)");
+    }
+}
@@ -0,0 +1,21 @@
+//! 分析过程的进度回调
+//!
+//! 作为库使用时通过`Analyzer::analyze_pdf_with`接收进度，服务中的工作流用同一个接口更新任务阶段、
+//! 推送网页事件和生成聊天中的进度提醒(见`progress::JobSink`)。
+//! 回调在分析任务中直接调用，不能阻塞，需要异步处理时在实现中把事件发送到通道。
+use crate::{ai_text_analyzer::TextExtractionResult, job::JobStage};
+
+/// 进度回调，所有方法默认不做任何处理，只需要实现关心的事件
+pub trait ProgressSink: Send + Sync {
+    /// 进入新的阶段
+    fn on_stage(&self, _stage: JobStage) {}
+
+    /// 提取完一页，成功或失败都会调用，`page`从1开始，不包括跳过的空白页
+    fn on_page(&self, _page: usize, _total: usize, _result: &TextExtractionResult) {}
+
+    /// 出错，`page`为提取失败的页面(之后继续提取其他页面)，None表示整个分析失败
+    fn on_error(&self, _page: Option<usize>, _error: &str) {}
+}
+
+/// 不需要进度时使用
+impl ProgressSink for () {}
@@ -0,0 +1,140 @@
+//! 提示词版本表
+//!
+//! 保存在`data/prompts.json`，记录每个提示词版本的内容和登记时间。启动时登记当前的文本提取提示词，
+//! 版本变化后由旧版本提取的模具记录视为过期，重新加载模具数据时统计这些记录，
+//! 并按提示词版本从旧到新排列，最旧的优先重新提取。
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{IResult, paths::data_dir};
+
+const PROMPTS_FILE: &str = "prompts.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptVersion {
+    pub id: String,
+    pub text: String,
+    /// 第一次登记的时间
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PromptRegistry {
+    /// 当前使用的版本
+    #[serde(default)]
+    active: Option<String>,
+    /// 按登记时间排序
+    #[serde(default)]
+    versions: Vec<PromptVersion>,
+}
+
+/// 提示词版本表所在的数据目录
+pub fn prompts_dir() -> PathBuf {
+    data_dir()
+}
+
+impl PromptRegistry {
+    pub fn load(dir: &Path) -> IResult<Self> {
+        let path = dir.join(PROMPTS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> IResult<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(PROMPTS_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 加载并登记当前的提示词，有变化时保存，失败时只在内存中登记
+    pub fn open(dir: &Path, id: &str, text: &str) -> Self {
+        let mut registry = Self::load(dir).unwrap_or_else(|e| {
+            warn!("加载提示词版本表失败: {}", e);
+            Self::default()
+        });
+        let before = registry.clone();
+        if let Some(previous) = registry.activate(id, text) {
+            info!(
+                "提示词版本从 {} 变为 {}，旧版本提取的记录需要重新提取",
+                previous, id
+            );
+        }
+        if registry != before
+            && let Err(e) = registry.save(dir)
+        {
+            warn!("保存提示词版本表失败: {}", e);
+        }
+        registry
+    }
+
+    pub fn active(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub fn versions(&self) -> &[PromptVersion] {
+        &self.versions
+    }
+
+    /// 登记并启用版本，返回之前启用的版本，没有变化或第一次登记时返回None
+    ///
+    /// 已登记的版本内容不同时更新内容并警告，修改提示词时应该同时更新版本号
+    pub fn activate(&mut self, id: &str, text: &str) -> Option<String> {
+        match self.versions.iter_mut().find(|v| v.id == id) {
+            Some(version) if version.text != text => {
+                warn!("提示词 {} 的内容已修改但版本号没有变化", id);
+                version.text = text.to_string();
+            }
+            Some(_) => {}
+            None => self.versions.push(PromptVersion {
+                id: id.to_string(),
+                text: text.to_string(),
+                created_at: chrono::Local::now().to_rfc3339(),
+            }),
+        }
+        let previous = self.active.replace(id.to_string());
+        previous.filter(|previous| previous != id)
+    }
+
+    /// 重新提取的顺序，越小越优先：没有提取来源或未登记的版本为0，其余按登记顺序从1开始
+    pub fn priority(&self, version: Option<&str>) -> usize {
+        version
+            .and_then(|version| self.versions.iter().position(|v| v.id == version))
+            .map_or(0, |index| index + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::TempDir;
+
+    #[test]
+    fn register_prompt_versions() {
+        let dir = TempDir::new("prompts");
+        let registry = PromptRegistry::open(&dir, "v1", "提取材料");
+        assert_eq!(registry.active(), Some("v1"));
+        assert_eq!(registry.versions().len(), 1);
+
+        // 重新启动时版本没有变化
+        let mut registry = PromptRegistry::open(&dir, "v1", "提取材料");
+        assert_eq!(registry.versions().len(), 1);
+        assert_eq!(
+            registry.activate("v2", "提取材料和公司"),
+            Some("v1".to_string())
+        );
+        assert_eq!(registry.activate("v2", "提取材料和公司"), None);
+        registry.save(&dir).unwrap();
+
+        let registry = PromptRegistry::load(&dir).unwrap();
+        assert_eq!(registry.active(), Some("v2"));
+        assert_eq!(registry.versions()[1].text, "提取材料和公司");
+        assert_eq!(registry.priority(None), 0);
+        assert_eq!(registry.priority(Some("v0")), 0);
+        assert_eq!(registry.priority(Some("v1")), 1);
+        assert_eq!(registry.priority(Some("v2")), 2);
+    }
+}
@@ -0,0 +1,289 @@
+//! 聊天中的文本检索语句解析
+//!
+//! 支持的写法(每项以`;`或换行分隔, 中英文标点均可):
+//! ```text
+//! - 类型: 基座;
+//! - 材料: PBT RG301, PA66;
+//! - 客户: TCL;
+//! - 标签: 热处理, 镀镍;
+//! - 方案: tooling;
+//! - since: 2023;
+//! - until: 2024-06;
+//! ```
+//! 不带任何`key:`的纯文本视为按模具类型检索, 例如直接发送`基座`。
+//! `方案`选择评分方案(见`DiffConfig::profiles`)，本身不是检索条件。
+//! `since`/`until`按模具记录的提取时间筛选，日期可以写到年、月或日，包含边界。
+
+use serde::{Deserialize, Serialize};
+
+/// 用户的检索条件
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserQuery {
+    pub model_type: Option<String>,
+    pub materials: Vec<String>,
+    /// 只检索该客户的模具
+    #[serde(default)]
+    pub customer: Option<String>,
+    /// 只检索带有全部标签的模具
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 只检索这个日期及之后提取的模具，格式为`YYYY[-MM[-DD]]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    /// 只检索这个日期及之前提取的模具
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    /// 评分方案的名称，None时使用默认方案
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+const TYPE_KEYS: [&str; 5] = ["type", "model_type", "类型", "模具类型", "名称"];
+const MATERIAL_KEYS: [&str; 5] = ["material", "materials", "材料", "材质", "物料"];
+const CUSTOMER_KEYS: [&str; 4] = ["customer", "company", "客户", "公司"];
+const TAG_KEYS: [&str; 4] = ["tag", "tags", "标签", "技术要求"];
+const PROFILE_KEYS: [&str; 3] = ["profile", "方案", "评分方案"];
+const SINCE_KEYS: [&str; 4] = ["since", "from", "起始", "开始"];
+const UNTIL_KEYS: [&str; 4] = ["until", "to", "截止", "结束"];
+/// 列表项前的符号，包括全角写法
+const BULLETS: [char; 6] = ['-', '—', '•', '·', '－', '*'];
+
+impl UserQuery {
+    /// 解析检索语句，没有任何有效条件时返回None
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        let mut query = Self::default();
+        let mut has_key = false;
+
+        for item in text.split(['\n', ';', '；']) {
+            let item = item.trim().trim_start_matches(BULLETS).trim();
+            if item.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = item.split_once([':', '：']) else {
+                continue;
+            };
+            has_key = true;
+            query.set(key, value);
+        }
+
+        // 纯文本按模具类型检索
+        if !has_key && !text.contains('\n') {
+            let model_type = text
+                .trim_matches(|c| [';', '；'].contains(&c) || BULLETS.contains(&c))
+                .trim();
+            if !model_type.is_empty() {
+                query.model_type = Some(model_type.to_string());
+            }
+        }
+
+        if query.is_empty() { None } else { Some(query) }
+    }
+
+    /// 由键值对(例如URL查询参数)组成检索条件，键与检索语句相同，没有任何有效条件时返回None
+    pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Option<Self> {
+        let mut query = Self::default();
+        for (key, value) in pairs {
+            query.set(key, value);
+        }
+        if query.is_empty() { None } else { Some(query) }
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        if TYPE_KEYS.contains(&key.as_str()) {
+            if !value.is_empty() {
+                self.model_type = Some(value.to_string());
+            }
+        } else if MATERIAL_KEYS.contains(&key.as_str()) {
+            self.materials.extend(split_list(value));
+        } else if TAG_KEYS.contains(&key.as_str()) {
+            self.tags.extend(split_list(value));
+        } else if CUSTOMER_KEYS.contains(&key.as_str()) && !value.is_empty() {
+            self.customer = Some(value.to_string());
+        } else if PROFILE_KEYS.contains(&key.as_str()) && !value.is_empty() {
+            self.profile = Some(value.to_string());
+        } else if SINCE_KEYS.contains(&key.as_str()) {
+            self.since = normalize_date(value);
+        } else if UNTIL_KEYS.contains(&key.as_str()) {
+            self.until = normalize_date(value);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.model_type.is_none()
+            && self.materials.is_empty()
+            && self.customer.is_none()
+            && self.tags.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+}
+
+/// 把`2023`、`2023/5`、`2023年5月1日`等写法统一为`2023`、`2023-05`、`2023-05-01`，无效时为None
+pub fn normalize_date(text: &str) -> Option<String> {
+    let parts: Vec<&str> = text
+        .split(['-', '/', '.', '年', '月', '日'])
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+    let number = |part: &str, max: u32| part.parse::<u32>().ok().filter(|n| (1..=max).contains(n));
+    match parts.as_slice() {
+        [year] if year.len() == 4 => Some(format!("{:04}", number(year, 9999)?)),
+        [year, month] if year.len() == 4 => Some(format!(
+            "{:04}-{:02}",
+            number(year, 9999)?,
+            number(month, 12)?
+        )),
+        [year, month, day] if year.len() == 4 => Some(format!(
+            "{:04}-{:02}-{:02}",
+            number(year, 9999)?,
+            number(month, 12)?,
+            number(day, 31)?
+        )),
+        _ => None,
+    }
+}
+
+/// 以逗号或顿号分隔的多个值
+fn split_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split([',', '，', '、'])
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+        .map(|m| m.to_string())
+}
+
+/// 检索语法说明，无法解析用户输入时回复
+pub const QUERY_HELP: &str = r#"ℹ️ 请发送PDF文件进行分析，或发送检索条件:
+```
+- 类型: 基座;
+- 材料: PBT RG301, PA66;
+- 客户: TCL;
+- 标签: 热处理;
+- since: 2023;
+```
+也可以直接发送模具类型, 例如`基座`
+表格显示不正常时，发送`/layout compact`改为紧凑列表
+已经分析过的文件会直接返回之前的结果，发送`/force 任务id`重新分析
+分析或检索还没完成时，发送`/cancel`取消"#;
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn parse_plain_text() {
+        let query = UserQuery::parse(" 基座 ").unwrap();
+        assert_eq!(query.model_type.as_deref(), Some("基座"));
+        assert!(query.materials.is_empty());
+    }
+
+    #[test]
+    fn parse_keyed_items() {
+        let query = UserQuery::parse("- 类型: 上基座;\n- 材料：PBT RG301， PA66、 ;").unwrap();
+        assert_eq!(query.model_type.as_deref(), Some("上基座"));
+        assert_eq!(query.materials, vec!["PBT RG301", "PA66"]);
+
+        let query = UserQuery::parse("- material: LCP E4008").unwrap();
+        assert_eq!(query.model_type, None);
+        assert_eq!(query.materials, vec!["LCP E4008"]);
+
+        let query = UserQuery::parse("- 类型: 基座;\n- customer: TCL;").unwrap();
+        assert_eq!(query.customer.as_deref(), Some("TCL"));
+
+        let query = UserQuery::parse("- tag: 热处理、镀镍").unwrap();
+        assert_eq!(query.tags, vec!["热处理", "镀镍"]);
+
+        let query = UserQuery::parse("- 类型: 基座;\n- profile: tooling;").unwrap();
+        assert_eq!(query.profile.as_deref(), Some("tooling"));
+
+        let query = UserQuery::parse("- since: 2023;\n- 截止：2024年6月").unwrap();
+        assert_eq!(query.since.as_deref(), Some("2023"));
+        assert_eq!(query.until.as_deref(), Some("2024-06"));
+    }
+
+    #[test]
+    fn normalize_dates() {
+        assert_eq!(normalize_date("2023").as_deref(), Some("2023"));
+        assert_eq!(normalize_date("2023/5").as_deref(), Some("2023-05"));
+        assert_eq!(
+            normalize_date("2023年5月1日").as_deref(),
+            Some("2023-05-01")
+        );
+        assert_eq!(normalize_date("2023-13"), None);
+        assert_eq!(normalize_date("23"), None);
+        assert_eq!(normalize_date("去年"), None);
+    }
+
+    #[test]
+    fn query_from_pairs() {
+        let query = UserQuery::from_pairs([
+            ("model_type", "基座"),
+            ("materials", "PBT RG301,PA66"),
+            ("material", "POM"),
+            ("since", "2023/5"),
+            ("limit", "5"),
+        ])
+        .unwrap();
+        assert_eq!(query.model_type.as_deref(), Some("基座"));
+        assert_eq!(query.materials, ["PBT RG301", "PA66", "POM"]);
+        assert_eq!(query.since.as_deref(), Some("2023-05"));
+        assert_eq!(UserQuery::from_pairs([("limit", "5")]), None);
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(UserQuery::parse(""), None);
+        assert_eq!(UserQuery::parse("- 类型: ;"), None);
+        assert_eq!(UserQuery::parse("- foo: bar"), None);
+        assert_eq!(UserQuery::parse(" ；"), None);
+        // 只有评分方案不是检索条件
+        assert_eq!(UserQuery::parse("- 方案: tooling"), None);
+        assert_eq!(
+            UserQuery::parse("基座；").unwrap().model_type.as_deref(),
+            Some("基座")
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(text in "\\PC*") {
+            if let Some(query) = UserQuery::parse(&text) {
+                prop_assert!(!query.is_empty());
+                for item in query.materials.iter().chain(&query.tags) {
+                    prop_assert!(!item.is_empty());
+                    prop_assert_eq!(item.trim(), item);
+                    prop_assert!(!item.contains([',', '，', '、']));
+                }
+            }
+        }
+
+        #[test]
+        fn mixed_width_punctuation(
+            model_type in "[A-Za-z0-9基座盖板]{1,8}",
+            materials in prop::collection::vec("[A-Za-z0-9]{1,6}( [A-Za-z0-9]{1,6})?", 1..4),
+            colons in prop::collection::vec(prop::sample::select(vec![":", "：", " : "]), 2),
+            separator in prop::sample::select(vec![";", "；", "\n", ";\n", "；\n"]),
+            comma in prop::sample::select(vec![",", "，", "、", " , "]),
+            bullet in prop::sample::select(vec!["", "- ", "－ ", "• "]),
+        ) {
+            let text = format!(
+                "{bullet}类型{}{model_type}{separator}{bullet}材料{}{}",
+                colons[0],
+                colons[1],
+                materials.join(comma),
+            );
+            let query = UserQuery::parse(&text).unwrap();
+            prop_assert_eq!(query.model_type, Some(model_type));
+            prop_assert_eq!(query.materials, materials);
+        }
+    }
+}
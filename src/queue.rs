@@ -0,0 +1,126 @@
+//! PDF分析的排队
+//!
+//! 同时进行的分析数量有限，超出时任务按到达顺序排队，排队位置用于提示用户。
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+pub struct WorkQueue {
+    semaphore: Semaphore,
+    /// 同时进行的分析数量
+    capacity: usize,
+    /// 正在等待的排队号，按到达顺序
+    waiting: Mutex<VecDeque<u64>>,
+    next_ticket: AtomicU64,
+}
+
+/// 排队号，离开队列(拿到执行许可或任务被取消)时自动移除
+pub struct QueueTicket<'a> {
+    queue: &'a WorkQueue,
+    id: u64,
+}
+
+impl WorkQueue {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            semaphore: Semaphore::new(max_concurrent.max(1)),
+            capacity: max_concurrent.max(1),
+            waiting: Mutex::new(VecDeque::new()),
+            next_ticket: AtomicU64::new(0),
+        }
+    }
+
+    /// 有空闲时直接返回执行许可
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.semaphore.try_acquire().ok()
+    }
+
+    /// 排到队尾
+    pub fn enter(&self) -> QueueTicket<'_> {
+        let id = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        self.waiting.lock().unwrap().push_back(id);
+        QueueTicket { queue: self, id }
+    }
+
+    /// 正在排队的任务数
+    pub fn waiting(&self) -> usize {
+        self.waiting.lock().unwrap().len()
+    }
+
+    /// 正在执行的任务数
+    pub fn running(&self) -> usize {
+        self.capacity - self.semaphore.available_permits()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<'a> QueueTicket<'a> {
+    /// 排队位置，1表示下一个执行
+    pub fn position(&self) -> usize {
+        let waiting = self.queue.waiting.lock().unwrap();
+        waiting
+            .iter()
+            .position(|id| *id == self.id)
+            .map_or(0, |index| index + 1)
+    }
+
+    /// 等待执行许可，信号量按请求顺序分配
+    pub async fn acquire(&self) -> SemaphorePermit<'a> {
+        self.queue
+            .semaphore
+            .acquire()
+            .await
+            .expect("排队的信号量不会被关闭")
+    }
+}
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        self.queue
+            .waiting
+            .lock()
+            .unwrap()
+            .retain(|id| *id != self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn positions_follow_arrival_order() {
+        let queue = WorkQueue::new(1);
+        let running = queue.try_acquire().unwrap();
+        assert!(queue.try_acquire().is_none());
+
+        let first = queue.enter();
+        let second = queue.enter();
+        let third = queue.enter();
+        assert_eq!(
+            (first.position(), second.position(), third.position()),
+            (1, 2, 3)
+        );
+
+        // 取消排队的任务，后面的任务前移
+        drop(second);
+        assert_eq!(third.position(), 2);
+
+        drop(running);
+        let permit = first.acquire().await;
+        drop(first);
+        assert_eq!(third.position(), 1);
+        assert_eq!(queue.waiting(), 1);
+        drop(permit);
+        let _permit = third.acquire().await;
+    }
+}
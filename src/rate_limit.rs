@@ -0,0 +1,313 @@
+//! webhook和JSON接口的限流
+//!
+//! 每个发送者一个令牌桶，容量为`burst`，按`per_minute`匀速补充。webhook按VoceChat用户(`from_uid`)区分，
+//! 请求无法解析或没有通过校验时按IP区分；JSON接口按IP区分。模型接口按调用计费，
+//! SAM分割只能串行执行，限流避免个别用户或脚本占满分析队列。
+//!
+//! 超过限制时webhook在聊天中回复一次提示(同一次限流不重复回复)，接口回复429，都带有`Retry-After`头。
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use salvo::{
+    Depot, FlowCtrl, Handler, Request, Response, async_trait,
+    http::{StatusCode, header::RETRY_AFTER},
+    writing::Json,
+};
+use tracing::info;
+
+use crate::{
+    CONFIG, HTTP_CLIENT,
+    api::{error::ApiError, pdf::WebhookRequest},
+    config::RateLimitConfig,
+    secrets::{self, WEBHOOK_SECRET},
+    webhook_auth::WebhookVerifier,
+    workflow::{bot_endpoint, send_markdown},
+};
+
+/// 超过这个数量时清理已经补满的令牌桶，避免大量不同来源占用内存
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allowed,
+    /// `first`表示这是本次限流中第一个被拒绝的请求
+    Limited {
+        retry_after: Duration,
+        first: bool,
+    },
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// 已经提示过发送者
+    notified: bool,
+}
+
+/// 按来源区分的令牌桶
+#[derive(Debug)]
+pub struct TokenBuckets {
+    capacity: f64,
+    /// 每秒补充的令牌数
+    refill: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl TokenBuckets {
+    pub fn new(burst: u32, per_minute: f64) -> Self {
+        Self {
+            capacity: f64::from(burst.max(1)),
+            refill: per_minute.max(0.0) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 取一个令牌
+    pub fn check(&self, key: &str) -> Decision {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.capacity);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+            notified: false,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.notified = false;
+            return Decision::Allowed;
+        }
+        let retry_after = if self.refill > 0.0 {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill)
+        } else {
+            Duration::MAX
+        };
+        let first = !bucket.notified;
+        bucket.notified = true;
+        Decision::Limited { retry_after, first }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill).min(self.capacity)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Webhook,
+    Api,
+}
+
+/// 限流的中间件，`Clone`后共用同一组令牌桶
+#[derive(Clone)]
+pub struct RateLimiter {
+    scope: Scope,
+    config: RateLimitConfig,
+    buckets: Arc<TokenBuckets>,
+}
+
+impl RateLimiter {
+    /// VoceChat webhook，按用户限流
+    pub fn webhook(config: &RateLimitConfig) -> Self {
+        Self {
+            scope: Scope::Webhook,
+            config: config.clone(),
+            buckets: Arc::new(TokenBuckets::new(
+                config.webhook_burst,
+                config.webhook_per_minute,
+            )),
+        }
+    }
+
+    /// JSON接口，按IP限流
+    pub fn api(config: &RateLimitConfig) -> Self {
+        Self {
+            scope: Scope::Api,
+            config: config.clone(),
+            buckets: Arc::new(TokenBuckets::new(config.api_burst, config.api_per_minute)),
+        }
+    }
+
+    /// 请求来源的IP
+    fn client_ip(&self, req: &Request) -> String {
+        let forwarded = self
+            .config
+            .trust_proxy
+            .then(|| req.header::<String>("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| {
+                let first = value.split(',').next()?.trim();
+                (!first.is_empty()).then(|| first.to_string())
+            });
+        forwarded.unwrap_or_else(|| {
+            req.remote_addr()
+                .clone()
+                .into_std()
+                .map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string())
+        })
+    }
+
+    /// 通过校验的webhook请求，请求体在这里读取后会缓存，处理函数可以再次读取
+    async fn webhook_request(req: &mut Request) -> Option<WebhookRequest> {
+        let body = req.payload().await.ok()?.clone();
+        let verifier = WebhookVerifier::new(&CONFIG.webhook, secrets::get(&WEBHOOK_SECRET));
+        let value = req.header::<String>(verifier.header());
+        verifier.verify(value.as_deref(), &body).ok()?;
+        WebhookRequest::parse(&body).ok()
+    }
+
+    fn limit_message(&self, retry_after: u64) -> String {
+        self.config
+            .message
+            .replace("{seconds}", &retry_after.to_string())
+    }
+}
+
+#[async_trait]
+impl Handler for RateLimiter {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        if !self.config.enabled {
+            return;
+        }
+        let webhook_req = match self.scope {
+            Scope::Webhook => Self::webhook_request(req).await,
+            Scope::Api => None,
+        };
+        let key = match &webhook_req {
+            Some(webhook_req) => format!("uid:{}", webhook_req.from_uid()),
+            None => format!("ip:{}", self.client_ip(req)),
+        };
+        let Decision::Limited { retry_after, first } = self.buckets.check(&key) else {
+            return;
+        };
+
+        let seconds = retry_after.as_secs_f64().ceil().min(u32::MAX.into()) as u64;
+        if first {
+            info!("{} 请求过于频繁，{}秒后恢复", key, seconds);
+        }
+        let _ = res.add_header(RETRY_AFTER, seconds.to_string(), true);
+        let message = self.limit_message(seconds);
+        match self.scope {
+            Scope::Webhook => {
+                // 同一次限流只在聊天中提示一次
+                if first && let Some(webhook_req) = webhook_req {
+                    let message = message.clone();
+                    tokio::spawn(async move {
+                        let (webhook_url, api_key) = bot_endpoint(&webhook_req);
+                        send_markdown(&HTTP_CLIENT, &webhook_url, &api_key, &message).await;
+                    });
+                }
+                // 与其他无法处理的请求一样，兼容模式下回复200，避免VoceChat重复投递
+                let status = if CONFIG.webhook.compat_status {
+                    StatusCode::OK
+                } else {
+                    StatusCode::TOO_MANY_REQUESTS
+                };
+                res.status_code(status);
+                res.render(Json(serde_json::json!({
+                    "status": status.as_u16(),
+                    "message": message,
+                })));
+            }
+            Scope::Api => res.render(ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                format!("请求过于频繁，请{}秒后再试", seconds),
+                format!("Too many requests, retry after {} seconds", seconds),
+            )),
+        }
+        ctrl.skip_rest();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::{
+        Router, Service, handler,
+        test::{ResponseExt, TestClient},
+    };
+
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        // 容量2，每秒补充1个
+        let buckets = TokenBuckets::new(2, 60.0);
+        let start = Instant::now();
+        assert_eq!(buckets.check_at("a", start), Decision::Allowed);
+        assert_eq!(buckets.check_at("a", start), Decision::Allowed);
+        assert_eq!(
+            buckets.check_at("a", start),
+            Decision::Limited {
+                retry_after: Duration::from_secs(1),
+                first: true
+            }
+        );
+        // 只提示一次
+        assert!(matches!(
+            buckets.check_at("a", start + Duration::from_millis(500)),
+            Decision::Limited { first: false, .. }
+        ));
+        // 其他来源不受影响
+        assert_eq!(buckets.check_at("b", start), Decision::Allowed);
+
+        assert_eq!(
+            buckets.check_at("a", start + Duration::from_secs(1)),
+            Decision::Allowed
+        );
+        assert!(matches!(
+            buckets.check_at("a", start + Duration::from_secs(1)),
+            Decision::Limited { first: true, .. }
+        ));
+        // 补满后不会超过容量
+        let later = start + Duration::from_secs(60);
+        assert_eq!(buckets.check_at("a", later), Decision::Allowed);
+        assert_eq!(buckets.check_at("a", later), Decision::Allowed);
+        assert!(matches!(
+            buckets.check_at("a", later),
+            Decision::Limited { .. }
+        ));
+    }
+
+    #[handler]
+    async fn ok() -> &'static str {
+        "ok"
+    }
+
+    #[tokio::test]
+    async fn limit_api_requests() {
+        let config = RateLimitConfig {
+            api_burst: 1,
+            api_per_minute: 1.0,
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::api(&config);
+        let service = Service::new(Router::new().hoop(limiter).get(ok));
+
+        let res = TestClient::get("http://127.0.0.1/").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let mut res = TestClient::get("http://127.0.0.1/").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(res.headers()["retry-after"], "60");
+        let body: serde_json::Value = res.take_json().await.unwrap();
+        assert_eq!(body["code"], "rate_limited");
+    }
+}
@@ -0,0 +1,133 @@
+//! 模型服务原始回复的存档
+//!
+//! 每页每次请求的原始回复以gzip压缩保存在任务工作目录的`responses/`中，
+//! 随任务文件一起打包下载(`jobs/{id}/artifacts.zip`)，几周后排查模型编造内容的问题时
+//! 不需要重新付费调用。存档按时间和总大小轮转，见`ArchiveConfig`。
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use tracing::{info, warn};
+
+use crate::{IResult, config::ArchiveConfig, job::RESPONSES_DIR};
+
+const EXTENSION: &str = "json.gz";
+
+/// 一个任务的回复存档目录
+#[derive(Debug, Clone)]
+pub struct ResponseArchive {
+    dir: PathBuf,
+}
+
+impl ResponseArchive {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// 保存页面`page`第`attempt`次请求的原始回复，返回存档文件
+    pub fn save(&self, page: &Path, attempt: u32, body: &str) -> IResult<PathBuf> {
+        std::fs::create_dir_all(&self.dir)?;
+        let stem = page
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "page".to_string());
+        let path = self
+            .dir
+            .join(format!("{}_attempt{}.{}", stem, attempt, EXTENSION));
+        let mut encoder = GzEncoder::new(std::fs::File::create(&path)?, Compression::default());
+        encoder.write_all(body.as_bytes())?;
+        encoder.finish()?;
+        Ok(path)
+    }
+}
+
+/// 读取存档的回复
+pub fn read(path: &Path) -> IResult<String> {
+    let mut body = String::new();
+    GzDecoder::new(std::fs::File::open(path)?).read_to_string(&mut body)?;
+    Ok(body)
+}
+
+/// 删除`jobs_dir`下所有任务中超过保留时间的存档，之后总大小仍超过上限时从最旧的开始删除，
+/// 返回删除的文件数
+pub fn rotate(jobs_dir: &Path, config: &ArchiveConfig) -> IResult<usize> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(jobs_dir)?.flatten() {
+        let Ok(archives) = std::fs::read_dir(entry.path().join(RESPONSES_DIR)) else {
+            continue;
+        };
+        for archive in archives.flatten() {
+            let metadata = archive.metadata()?;
+            files.push((metadata.modified()?, metadata.len(), archive.path()));
+        }
+    }
+    files.sort();
+
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    let now = SystemTime::now();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let mut removed = 0;
+    for (modified, len, path) in files {
+        let expired = now.duration_since(modified).unwrap_or_default() > max_age;
+        if !expired && total <= config.max_bytes() {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                total -= len;
+                removed += 1;
+            }
+            Err(e) => warn!("删除回复存档失败 {}: {}", path.display(), e),
+        }
+    }
+    if removed > 0 {
+        info!("🗑️ 已删除 {} 个旧的回复存档", removed);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::TempDir;
+
+    #[test]
+    fn archive_and_rotate() {
+        let jobs_dir = TempDir::new("archive");
+        let archive = ResponseArchive::new(jobs_dir.join("a").join(RESPONSES_DIR));
+        let body = r#"{"choices":[{"message":{"content":"基座"}}]}"#.repeat(100);
+        let first = archive
+            .save(Path::new("pages/page_0.jpg"), 1, &body)
+            .unwrap();
+        assert!(first.ends_with("page_0_attempt1.json.gz"));
+        assert_eq!(read(&first).unwrap(), body);
+        assert!(std::fs::metadata(&first).unwrap().len() < body.len() as u64);
+
+        let archive = ResponseArchive::new(jobs_dir.join("b").join(RESPONSES_DIR));
+        let second = archive.save(Path::new("page_0.jpg"), 2, &body).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&first)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(31 * 24 * 60 * 60))
+            .unwrap();
+
+        // 超过保留时间的存档被删除
+        let mut config = ArchiveConfig {
+            enabled: true,
+            max_age_days: 30,
+            max_megabytes: 1,
+        };
+        assert_eq!(rotate(&jobs_dir, &config).unwrap(), 1);
+        assert!(!first.exists() && second.exists());
+        assert_eq!(rotate(&jobs_dir, &config).unwrap(), 0);
+
+        // 超过总大小时删除旧的存档
+        config.max_megabytes = 0;
+        assert_eq!(rotate(&jobs_dir, &config).unwrap(), 1);
+        assert!(!second.exists());
+    }
+}
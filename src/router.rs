@@ -1,9 +1,56 @@
-use salvo::{Router, cors::Cors, http::Method};
+//! 路由
+//!
+//! JSON接口按版本放在`/material/api/v1`下，之后不兼容的修改放在新的版本中，
+//! 旧版本保留一段时间并标记为弃用。没有版本号的`/material/api`是v1之前的路径，
+//! 仍然可以使用，但回复带有`Deprecation`头。VoceChat的webhook、签名文件链接和`/material/admin`
+//! 管理页面不区分版本。接口的OpenAPI描述在`/material/api/openapi.json`，Swagger UI在`/material/api/docs`(静态文件编译时嵌入)，
+//! 修改路由时需要同步修改`api::openapi`，测试会检查两者一致。路径前缀`material`可以通过`ServerConfig::base_path`修改。
+//!
+//! webhook和JSON接口有限流，见`RateLimiter`，签名文件链接和管理页面的静态文件不限流。
+//! 配置了接口密钥时JSON接口需要鉴权，没有配置时管理接口停用，见`Authorize`。只读模式下修改数据的接口回复503，见`Maintenance`。
+use salvo::{
+    Depot, FlowCtrl, Handler, Request, Response, Router, async_trait,
+    cors::Cors,
+    http::{Method, header::LINK},
+};
 
-use crate::api::pdf::{workhook, workhook_check};
+use crate::{
+    CONFIG,
+    api::{
+        admin::{
+            admin_page, config_summary, corpus_stats, jobs_overview, reindex, set_maintenance,
+            snapshot_diff,
+        },
+        analyze::analyze,
+        files::signed_file,
+        job::{
+            feedback_metrics, job_artifacts, job_diff, job_events, job_extraction, job_result,
+            job_socket, job_status,
+        },
+        model::{dry_run_diff, model_detail, model_ids, similarity_matrix, update_status},
+        openapi::{openapi_json, swagger_ui},
+        pdf::{workhook, workhook_check},
+        search::{search, suggest},
+        taxonomy::{
+            add_material, add_model_type, material_taxonomy, model_type_taxonomy,
+            unrecognized_terms,
+        },
+    },
+    api_auth::Authorize,
+    config::ApiScope,
+    maintenance::ReadOnlyGuard,
+    rate_limit::RateLimiter,
+};
 
 // use crate::api::pdf::{ai_analysis, from_path, split};
 
+/// 当前版本的接口路径
+pub const API_V1: &str = "api/v1";
+/// 没有版本号的旧接口路径
+const API_LEGACY: &str = "api";
+/// 弃用标记，见RFC 9745
+const DEPRECATION: &str = "deprecation";
+
 pub fn build() -> Router {
     let cors = Cors::new()
         .allow_origin("*") // 允许所有来源
@@ -18,14 +65,125 @@ pub fn build() -> Router {
         .expose_headers("content-disposition") // 暴露特定响应头
         .max_age(3600) // 预检请求的缓存时间
         .into_handler();
+    // v1和旧路径共用同一组令牌桶
+    let api_limiter = RateLimiter::api(&CONFIG.rate_limit);
 
     // Router::with_path("api")
     //     .hoop(cors)
     //     .push(Router::with_path("pdf").post(from_path).get(split))
     //     .push(Router::with_path("ai").get(ai_analysis))
-    Router::with_path("material").hoop(cors).push(
-        Router::with_path("webhook")
-            .get(workhook_check)
-            .post(workhook),
-    )
+    let root = match CONFIG.server.base_path() {
+        "" => Router::new(),
+        base => Router::with_path(base),
+    };
+    root.hoop(cors)
+        .push(
+            Router::with_path("webhook")
+                .hoop(RateLimiter::webhook(&CONFIG.rate_limit))
+                .get(workhook_check)
+                .post(workhook),
+        )
+        .push(Router::with_path("files/{**path}").get(signed_file))
+        .push(Router::with_path("admin/{**path}").get(admin_page))
+        // 接口文档，不需要鉴权
+        .push(Router::with_path("api/openapi.json").get(openapi_json))
+        .push(Router::with_path("api/docs/{**path}").get(swagger_ui))
+        .push(
+            Router::with_path(API_V1)
+                .hoop(api_limiter.clone())
+                .push(api_v1()),
+        )
+        // 没有版本号的旧路径，与v1相同
+        .push(
+            Router::with_path(API_LEGACY)
+                .hoop(api_limiter)
+                .hoop(Deprecated { successor: API_V1 })
+                .push(api_v1()),
+        )
+}
+
+/// v1版本的JSON接口，按需要的权限分为两组，见`Authorize`
+pub(crate) fn api_v1() -> Router {
+    Router::new()
+        // 修改数据、提交分析和管理接口
+        .push(
+            Router::new()
+                .hoop(Authorize::new(&CONFIG.auth, ApiScope::Admin))
+                // 只读模式下不能修改
+                .push(
+                    Router::new()
+                        .hoop(ReadOnlyGuard)
+                        .push(
+                            Router::with_path("taxonomy")
+                                .push(Router::with_path("materials").post(add_material))
+                                .push(Router::with_path("model-types").post(add_model_type)),
+                        )
+                        .push(Router::with_path("models/{source_name}/status").put(update_status)),
+                )
+                .push(
+                    Router::with_path("admin")
+                        .push(Router::with_path("jobs").get(jobs_overview))
+                        .push(Router::with_path("corpus").get(corpus_stats))
+                        .push(Router::with_path("snapshots/diff").get(snapshot_diff))
+                        .push(Router::with_path("config").get(config_summary))
+                        .push(Router::with_path("reindex").post(reindex))
+                        .push(Router::with_path("maintenance").put(set_maintenance)),
+                )
+                .push(Router::with_path("analyze").post(analyze)),
+        )
+        // 只读的接口
+        .push(
+            Router::new()
+                .hoop(Authorize::new(&CONFIG.auth, ApiScope::Read))
+                .push(
+                    Router::with_path("taxonomy")
+                        .push(Router::with_path("materials").get(material_taxonomy))
+                        .push(Router::with_path("model-types").get(model_type_taxonomy))
+                        .push(Router::with_path("unrecognized").get(unrecognized_terms)),
+                )
+                .push(Router::with_path("feedback/metrics").get(feedback_metrics))
+                .push(Router::with_path("models/{source_name}").get(model_detail))
+                .push(Router::with_path("model-ids").get(model_ids))
+                .push(Router::with_path("similarity-matrix").post(similarity_matrix))
+                .push(Router::with_path("diff").post(dry_run_diff))
+                .push(Router::with_path("search").get(search))
+                .push(Router::with_path("suggest").get(suggest))
+                .push(Router::with_path("jobs/{id}").get(job_status))
+                .push(Router::with_path("jobs/{id}/result").get(job_result))
+                .push(Router::with_path("jobs/{a}/diff/{b}").get(job_diff))
+                .push(Router::with_path("jobs/{id}/extraction").get(job_extraction))
+                .push(Router::with_path("jobs/{id}/events").get(job_events))
+                .push(Router::with_path("jobs/{id}/ws").get(job_socket))
+                .push(Router::with_path("jobs/{id}/artifacts.zip").get(job_artifacts)),
+        )
+}
+
+/// 已弃用的接口路径，回复中带有`Deprecation`头和指向新路径的`Link`头，处理方式不变
+struct Deprecated {
+    /// 替代的路径前缀，相对`ServerConfig::base_path`
+    successor: &'static str,
+}
+
+#[async_trait]
+impl Handler for Deprecated {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let successor = req.uri().path().replacen(
+            &CONFIG.server.path(API_LEGACY),
+            &CONFIG.server.path(self.successor),
+            1,
+        );
+        let _ = res.add_header(DEPRECATION, "true", true);
+        let _ = res.add_header(
+            LINK,
+            format!("<{}>; rel=\"successor-version\"", successor),
+            true,
+        );
+        ctrl.call_next(req, depot, res).await;
+    }
 }
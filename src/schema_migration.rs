@@ -0,0 +1,248 @@
+//! 模型记录格式的版本和启动时的迁移
+//!
+//! 模型记录(`models/jsons/**/*.json`)陆续增加了原始图纸、提取时间、id等字段，
+//! 当前的格式版本保存在`models/schema.json`，没有这个文件的历史数据视为版本0。
+//! 启动时依次执行高于当前版本的迁移(见`StorageConfig::auto_migrate`)，
+//! 每条记录迁移后必须能解析为`ModelJson`，有记录无法解析时不保存这条记录，也不提升版本，
+//! 修正后下次启动重新迁移。`material-cli migrate --dry-run`只检查不写入。
+//!
+//! 新增迁移时在`MIGRATIONS`末尾追加，版本号递增，已发布的迁移不要修改。
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::{
+    AnalyzerError, IResult,
+    diff::ModelJson,
+    model_store::{
+        RecordMigration, fill_id, fill_original_pdf, fill_timestamp, json_files, record_name,
+    },
+    paths::write_atomic,
+};
+
+const SCHEMA_FILE: &str = "schema.json";
+
+pub struct Migration {
+    /// 执行后的版本
+    pub version: u32,
+    pub name: &'static str,
+    apply: RecordMigration,
+}
+
+/// 按版本排列的迁移
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "补全原始图纸",
+        apply: fill_original_pdf,
+    },
+    Migration {
+        version: 2,
+        name: "补全提取时间",
+        apply: fill_timestamp,
+    },
+    Migration {
+        version: 3,
+        name: "分配id和slug",
+        apply: fill_id,
+    },
+];
+
+/// 当前程序使用的记录格式版本
+pub fn current_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// `models/schema.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SchemaFile {
+    version: u32,
+    updated_at: Option<String>,
+}
+
+/// 模型目录中记录的格式版本，没有版本文件时为0
+pub fn read_version(models_dir: &Path) -> IResult<u32> {
+    let path = models_dir.join(SCHEMA_FILE);
+    if !path.is_file() {
+        return Ok(0);
+    }
+    let file: SchemaFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(file.version)
+}
+
+fn write_version(models_dir: &Path, version: u32) -> IResult<()> {
+    let file = SchemaFile {
+        version,
+        updated_at: Some(chrono::Local::now().to_rfc3339()),
+    };
+    write_atomic(
+        &models_dir.join(SCHEMA_FILE),
+        serde_json::to_string_pretty(&file)?,
+    )?;
+    Ok(())
+}
+
+/// 一次迁移的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    /// 每个执行的迁移修改的模型的来源名称
+    pub applied: Vec<(&'static str, Vec<String>)>,
+    /// 迁移后无法解析的记录和原因
+    pub invalid: Vec<(PathBuf, String)>,
+    pub dry_run: bool,
+}
+
+impl MigrationReport {
+    /// 已经是当前版本
+    pub fn is_current(&self) -> bool {
+        self.from == self.to
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!(
+            "记录格式版本 {} -> {}{}",
+            self.from,
+            self.to,
+            if self.dry_run { " (只检查)" } else { "" }
+        )];
+        for (name, models) in &self.applied {
+            lines.push(format!("  {}: {} 条记录", name, models.len()));
+        }
+        for (path, reason) in &self.invalid {
+            lines.push(format!("  ❌ {}: {}", path.display(), reason));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 执行高于当前版本的迁移，`dry_run`时只检查不写入；
+/// 记录的版本高于程序的版本时返回错误，避免旧程序改写新格式的记录
+pub fn migrate(models_dir: &Path, dry_run: bool) -> IResult<MigrationReport> {
+    let from = read_version(models_dir)?;
+    let to = current_version();
+    if from > to {
+        return Err(AnalyzerError::ConfigError(format!(
+            "模型记录的格式版本 {} 高于程序支持的版本 {}，请升级程序",
+            from, to
+        )));
+    }
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > from).collect();
+    let mut report = MigrationReport {
+        from,
+        to,
+        applied: pending.iter().map(|m| (m.name, Vec::new())).collect(),
+        invalid: Vec::new(),
+        dry_run,
+    };
+    if pending.is_empty() {
+        return Ok(report);
+    }
+
+    let mut files = Vec::new();
+    let jsons = models_dir.join("jsons");
+    if jsons.is_dir() {
+        json_files(&jsons, &mut files)?;
+    }
+    for path in files {
+        let mut record: Value = match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+        {
+            Ok(record) => record,
+            Err(e) => {
+                report.invalid.push((path, e));
+                continue;
+            }
+        };
+        let Some(fields) = record.as_object_mut() else {
+            report.invalid.push((path, "记录不是JSON对象".to_string()));
+            continue;
+        };
+        let mut changed = false;
+        for (migration, (_, models)) in pending.iter().zip(&mut report.applied) {
+            if (migration.apply)(models_dir, &path, fields)? {
+                models.push(record_name(fields));
+                changed = true;
+            }
+        }
+        if let Err(e) = serde_json::from_value::<ModelJson>(record.clone()) {
+            report.invalid.push((path, e.to_string()));
+            continue;
+        }
+        if changed && !dry_run {
+            write_atomic(&path, serde_json::to_string_pretty(&record)?)?;
+        }
+    }
+
+    if !dry_run {
+        if report.invalid.is_empty() {
+            write_version(models_dir, to)?;
+            info!("模型记录已迁移到版本 {}", to);
+        } else {
+            // 修正无法解析的记录后重新迁移
+            report.to = from;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::TempDir;
+
+    fn record(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join("jsons").join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn migrate_legacy_records() {
+        let dir = TempDir::new("schema");
+        std::fs::create_dir_all(dir.join("jsons")).unwrap();
+        let legacy = record(
+            &dir,
+            "ME121.json",
+            r#"{"model_type": "基座", "materials": ["PBT"], "source_directory": "missing",
+                "source_directory_name": "ME121"}"#,
+        );
+        assert_eq!(read_version(&dir).unwrap(), 0);
+
+        // 只检查时不写入
+        let report = migrate(&dir, true).unwrap();
+        assert_eq!((report.from, report.to), (0, current_version()));
+        assert_eq!(
+            report.applied[1],
+            ("补全提取时间", vec!["ME121".to_string()])
+        );
+        assert!(!std::fs::read_to_string(&legacy).unwrap().contains("\"id\""));
+        assert_eq!(read_version(&dir).unwrap(), 0);
+
+        // 无法解析的记录不保存，也不提升版本
+        let broken = record(&dir, "broken.json", r#"{"materials": "PBT"}"#);
+        let report = migrate(&dir, false).unwrap();
+        assert_eq!(report.invalid.len(), 1);
+        assert_eq!(report.to, 0);
+        assert_eq!(read_version(&dir).unwrap(), 0);
+
+        std::fs::remove_file(broken).unwrap();
+        let report = migrate(&dir, false).unwrap();
+        assert!(report.invalid.is_empty());
+        assert_eq!(read_version(&dir).unwrap(), current_version());
+        let model: ModelJson =
+            serde_json::from_str(&std::fs::read_to_string(&legacy).unwrap()).unwrap();
+        assert!(model.id.is_some());
+        assert!(model.extraction_timestamp.is_some());
+
+        // 已经是当前版本
+        assert!(migrate(&dir, false).unwrap().is_current());
+
+        write_version(&dir, current_version() + 1).unwrap();
+        assert!(migrate(&dir, false).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! 密钥
+//!
+//! 模型接口和VoceChat机器人的API key不写在代码和配置文件中，
+//! 从环境变量读取，或者从`secrets.toml`(默认在执行目录下，可以通过`MATERIAL_SECRETS_FILE`指定)读取，
+//! 环境变量优先。服务启动时用`check`检查，缺少密钥时直接退出。
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use tracing::warn;
+
+use crate::{AnalyzerError, CONFIG, IResult, config::WebhookVerification, paths::exe_dir};
+
+const SECRETS_FILE: &str = "secrets.toml";
+
+/// 一项密钥的来源
+#[derive(Debug, Clone, Copy)]
+pub struct Secret {
+    /// 环境变量
+    pub env: &'static str,
+    /// 密钥文件中的键
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+/// 模型接口(DashScope等)的API key
+pub const AI_API_KEY: Secret = Secret {
+    env: "MATERIAL_AI_API_KEY",
+    key: "ai_api_key",
+    description: "模型接口的API key",
+};
+
+/// VoceChat机器人的API key
+pub const BOT_API_KEY: Secret = Secret {
+    env: "MATERIAL_BOT_API_KEY",
+    key: "bot_api_key",
+    description: "VoceChat机器人的API key",
+};
+
+/// 校验VoceChat webhook请求的共享密钥，见`WebhookConfig::verification`
+pub const WEBHOOK_SECRET: Secret = Secret {
+    env: "MATERIAL_WEBHOOK_SECRET",
+    key: "webhook_secret",
+    description: "webhook请求的校验密钥",
+};
+
+/// 密钥文件中的内容，文件不存在或无效时为空
+static FILE_SECRETS: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    let path = std::env::var("MATERIAL_SECRETS_FILE")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| exe_dir().join(SECRETS_FILE));
+    if !path.is_file() {
+        return HashMap::new();
+    }
+    read_file(&path).unwrap_or_else(|e| {
+        warn!("读取密钥文件失败 {}: {}", path.display(), e);
+        HashMap::new()
+    })
+});
+
+fn read_file(path: &Path) -> IResult<HashMap<String, String>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+/// 解析密钥文件，每项为`键 = "值"`
+pub fn parse(content: &str) -> IResult<HashMap<String, String>> {
+    toml::from_str(content).map_err(|e| AnalyzerError::ConfigError(e.to_string()))
+}
+
+/// 密钥的值，依次查找环境变量和密钥文件
+pub fn get(secret: &Secret) -> Option<String> {
+    lookup(secret, &FILE_SECRETS)
+}
+
+fn lookup(secret: &Secret, file: &HashMap<String, String>) -> Option<String> {
+    std::env::var(secret.env)
+        .ok()
+        .or_else(|| file.get(secret.key).cloned())
+        .filter(|s| !s.trim().is_empty())
+}
+
+/// 必需的密钥，缺少时返回说明如何配置的错误
+pub fn require(secret: &Secret) -> IResult<String> {
+    get(secret).ok_or_else(|| missing(secret))
+}
+
+fn missing(secret: &Secret) -> AnalyzerError {
+    AnalyzerError::ConfigError(format!(
+        "缺少{}，请设置环境变量 {} 或在 {} 中配置 {}",
+        secret.description, secret.env, SECRETS_FILE, secret.key
+    ))
+}
+
+/// 启动时检查当前配置需要的密钥，使用本地模型时不需要模型接口的API key，
+/// 不校验webhook请求时不需要webhook的密钥，webhook的校验方式无法识别时返回错误
+pub fn check() -> IResult<()> {
+    require(&BOT_API_KEY)?;
+    if CONFIG.ai.api.is_some() {
+        require(&AI_API_KEY)?;
+    }
+    match &CONFIG.webhook.verification {
+        WebhookVerification::None => {}
+        WebhookVerification::Unknown(verification) => {
+            return Err(AnalyzerError::ConfigError(format!(
+                "未知的webhook校验方式 `{}`，应为 none、token 或 hmac",
+                verification
+            )));
+        }
+        WebhookVerification::Token | WebhookVerification::Hmac => {
+            require(&WEBHOOK_SECRET)?;
+        }
+    }
+    if !CONFIG.auth.enabled() {
+        warn!(
+            "⚠️ 没有配置接口密钥(auth.keys 或 MATERIAL_API_KEYS)，只读接口不鉴权，管理、分析和修改数据的接口已停用"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_secrets() {
+        let file = parse("ai_api_key = \"sk-test\"\nbot_api_key = \"\"\n").unwrap();
+        let secret = |env: &'static str, key: &'static str| Secret {
+            env,
+            key,
+            description: "测试密钥",
+        };
+        assert_eq!(
+            lookup(&secret("MATERIAL_TEST_UNSET_KEY", "ai_api_key"), &file).as_deref(),
+            Some("sk-test")
+        );
+        // 空值视为缺少
+        assert!(lookup(&secret("MATERIAL_TEST_UNSET_KEY", "bot_api_key"), &file).is_none());
+        assert!(parse("ai_api_key = [").is_err());
+
+        let message = missing(&AI_API_KEY).to_string();
+        assert!(message.contains("MATERIAL_AI_API_KEY"), "{}", message);
+    }
+}
@@ -0,0 +1,216 @@
+//! 检索框的输入提示
+//!
+//! 加载模具数据库时统计所有模具类型、材料和项目名称，输入时按前缀、词的前缀、
+//! 包含和编辑距离依次匹配，例如`PBT R`提示`PBT RG301`，`PBY`也能提示`PBT`开头的材料。
+//! 大小写或空白不同的写法合并为一个词，显示模具最多的写法。
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{diff::ModelJson, text_metric::levenshtein};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    ModelType,
+    Material,
+    Project,
+}
+
+/// 一条提示
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    pub text: String,
+    /// 使用这个词的模具数
+    pub count: usize,
+    /// 匹配程度，越大越靠前
+    pub score: f32,
+}
+
+/// 模具数据库中的词及使用次数
+#[derive(Debug, Clone, Default)]
+pub struct SuggestIndex {
+    /// (类别, 显示的写法, 小写并合并空白后的写法, 模具数)
+    terms: Vec<(SuggestionKind, String, String, usize)>,
+}
+
+/// 小写并把连续的空白合并为一个空格
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// 输入的字符数对应允许的编辑次数，太短的输入不做模糊匹配
+fn max_typos(len: usize) -> usize {
+    match len {
+        0..=2 => 0,
+        3..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// 匹配程度：前缀3，词的前缀2，包含1.5，模糊匹配小于1，不匹配时返回None
+fn match_score(query: &str, term: &str) -> Option<f32> {
+    if term.starts_with(query) {
+        return Some(3.0);
+    }
+    if term
+        .split([' ', '-', '_', '/'])
+        .any(|word| word.starts_with(query))
+    {
+        return Some(2.0);
+    }
+    if term.contains(query) {
+        return Some(1.5);
+    }
+    // 与词开头相同字符数的部分比较，允许少量错字
+    let len = query.chars().count();
+    let head: String = term.chars().take(len).collect();
+    let distance = levenshtein(query, &head);
+    (distance <= max_typos(len)).then(|| 1.0 - distance as f32 / len as f32)
+}
+
+impl SuggestIndex {
+    pub fn new(models: &[ModelJson]) -> Self {
+        // 每个词的各种写法及模具数
+        let mut spellings: HashMap<(SuggestionKind, String), HashMap<&str, usize>> = HashMap::new();
+        for model in models {
+            let terms = model
+                .model_type
+                .iter()
+                .map(|t| (SuggestionKind::ModelType, t))
+                .chain(
+                    model
+                        .materials
+                        .iter()
+                        .map(|m| (SuggestionKind::Material, m)),
+                )
+                .chain(
+                    model
+                        .project_name
+                        .iter()
+                        .map(|p| (SuggestionKind::Project, p)),
+                );
+            for (kind, term) in terms {
+                let term = term.trim();
+                if !term.is_empty() {
+                    *spellings
+                        .entry((kind, normalize(term)))
+                        .or_default()
+                        .entry(term)
+                        .or_default() += 1;
+                }
+            }
+        }
+        let terms = spellings
+            .into_iter()
+            .map(|((kind, normalized), spellings)| {
+                let count = spellings.values().sum();
+                let (term, _) = spellings
+                    .into_iter()
+                    .max_by(|(a, m), (b, n)| m.cmp(n).then(b.cmp(a)))
+                    .unwrap_or_default();
+                (kind, term.to_string(), normalized, count)
+            })
+            .collect();
+        Self { terms }
+    }
+
+    /// 最多返回`limit`条提示，`kind`为None时包括所有类别，按匹配程度、模具数和写法排序
+    pub fn suggest(
+        &self,
+        query: &str,
+        kind: Option<SuggestionKind>,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut suggestions: Vec<Suggestion> = self
+            .terms
+            .iter()
+            .filter(|(k, ..)| kind.is_none_or(|kind| kind == *k))
+            .filter_map(|(kind, term, normalized, count)| {
+                Some(Suggestion {
+                    kind: *kind,
+                    text: term.clone(),
+                    count: *count,
+                    score: match_score(&query, normalized)?,
+                })
+            })
+            .collect();
+        suggestions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(b.count.cmp(&a.count))
+                .then(a.text.cmp(&b.text))
+        });
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(model_type: &str, materials: &[&str], project: &str) -> ModelJson {
+        ModelJson {
+            model_type: Some(model_type.to_string()),
+            materials: materials.iter().map(|m| m.to_string()).collect(),
+            canonical_materials: vec![],
+            project_name: Some(project.to_string()),
+            company: None,
+            dimensions: None,
+            drawing: None,
+            source_directory: Default::default(),
+            source_directory_name: String::new(),
+            extraction_timestamp: None,
+            original_pdf: None,
+            tags: vec![],
+            finish: None,
+            id: None,
+            slug: None,
+            status: None,
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn suggest_terms() {
+        let index = SuggestIndex::new(&[
+            model("基座", &["PBT RG301", "PA66"], "ME121"),
+            model("基座", &["PBT RG301"], "ME122"),
+            model("底座", &["pbt  rg301", "PBT 3316"], "RG-7"),
+        ]);
+
+        // 不同写法合并
+        let suggestions = index.suggest("PBT R", None, 10);
+        assert_eq!(suggestions[0].text, "PBT RG301");
+        assert_eq!(suggestions[0].count, 3);
+        assert_eq!(suggestions[0].score, 3.0);
+
+        // 前缀优先于词的前缀
+        let texts: Vec<String> = index
+            .suggest("rg", None, 10)
+            .into_iter()
+            .map(|s| s.text)
+            .collect();
+        assert_eq!(texts, ["RG-7", "PBT RG301"]);
+
+        // 错字
+        let suggestions = index.suggest("PBY", Some(SuggestionKind::Material), 10);
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions.iter().all(|s| s.score < 1.0));
+
+        let suggestions = index.suggest("座", Some(SuggestionKind::ModelType), 1);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].text, "基座");
+        assert!(index.suggest(" ", None, 10).is_empty());
+        assert!(index.suggest("PEEK", None, 10).is_empty());
+    }
+}
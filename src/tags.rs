@@ -0,0 +1,135 @@
+//! 从图纸文字中整理技术要求标签
+//!
+//! 文本提取时模型会返回标题栏和技术要求中的原始文字(`text_content`)，
+//! 这里按关键词把热处理、表面处理、缩水率等要求整理为`类别`或`类别:要求`形式的标签，
+//! 可以用`- 标签: 热处理`检索。配置了`AiConfig::normalize_tags`时先由模型整理，
+//! 模型的结果无效或请求失败时使用关键词规则。
+
+/// 标签类别和识别该类别的关键词
+const CATEGORIES: [(&str, &[&str]); 3] = [
+    (
+        "热处理",
+        &["热处理", "淬火", "调质", "回火", "氮化", "渗碳", "HRC"],
+    ),
+    (
+        "表面处理",
+        &[
+            "表面处理",
+            "电镀",
+            "镀镍",
+            "镀锌",
+            "镀铬",
+            "镀锡",
+            "喷砂",
+            "抛光",
+            "阳极氧化",
+            "喷涂",
+            "咬花",
+            "VDI",
+        ],
+    ),
+    ("缩水率", &["缩水率", "收缩率", "缩水"]),
+];
+
+/// 标签中要求部分的最大长度(字符)
+const MAX_VALUE_CHARS: usize = 30;
+
+/// 从图纸文字中整理标签，同一类别的不同要求都保留，例如`热处理:调质`和`热处理:氮化`
+pub fn mine_tags(text_content: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for line in text_content
+        .iter()
+        .flat_map(|text| text.split(['\n', ';', '；', '。']))
+    {
+        // 技术要求通常带有编号，例如`2. 表面处理: 镀镍`
+        let line = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || ".、)） ".contains(c))
+            .trim();
+        let upper = line.to_uppercase();
+        for (category, keywords) in CATEGORIES {
+            if !keywords.iter().any(|k| upper.contains(k)) {
+                continue;
+            }
+            let value = match line.split_once([':', '：']) {
+                Some((_, value)) => value,
+                None => line.strip_prefix(category).unwrap_or(line),
+            };
+            push_tag(&mut tags, category, value);
+        }
+    }
+    tags
+}
+
+/// 模型整理的标签，`value`应为`["类别:要求", ...]`，忽略未知的类别，
+/// 没有有效的标签时返回None，改用关键词规则
+#[cfg(feature = "ai")]
+pub fn normalized_tags(value: &serde_json::Value) -> Option<Vec<String>> {
+    let mut tags = Vec::new();
+    for tag in value.as_array()?.iter().filter_map(|v| v.as_str()) {
+        let (category, requirement) = tag.split_once([':', '：']).unwrap_or((tag, ""));
+        if let Some((category, _)) = CATEGORIES.iter().find(|(c, _)| *c == category.trim()) {
+            push_tag(&mut tags, category, requirement);
+        }
+    }
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// 添加`类别`或`类别:要求`形式的标签，已有相同的标签时忽略
+fn push_tag(tags: &mut Vec<String>, category: &str, value: &str) {
+    let value: String = value.trim().chars().take(MAX_VALUE_CHARS).collect();
+    let tag = if value.is_empty() || value == category {
+        category.to_string()
+    } else {
+        format!("{}:{}", category, value)
+    };
+    if !tags.contains(&tag) {
+        tags.push(tag);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mine_requirements() {
+        let text = vec![
+            "技术要求".to_string(),
+            "1. 未注圆角R0.2; 2. 表面处理：镀镍".to_string(),
+            "3、缩水率 5‰".to_string(),
+            "淬火 HRC48-52".to_string(),
+            "4. 表面喷砂".to_string(),
+            "ME121基座".to_string(),
+        ];
+        assert_eq!(
+            mine_tags(&text),
+            [
+                "表面处理:镀镍",
+                "缩水率:5‰",
+                "热处理:淬火 HRC48-52",
+                "表面处理:表面喷砂"
+            ]
+        );
+        assert!(mine_tags(&["热处理".to_string()]) == ["热处理"]);
+        assert!(mine_tags(&[]).is_empty());
+    }
+
+    #[test]
+    fn keep_distinct_requirements() {
+        let text = vec!["1. 调质处理; 2. 氮化处理; 3. 调质处理".to_string()];
+        assert_eq!(mine_tags(&text), ["热处理:调质处理", "热处理:氮化处理"]);
+    }
+
+    #[cfg(feature = "ai")]
+    #[test]
+    fn normalized_by_model() {
+        let value = serde_json::json!(["热处理:调质", "热处理：氮化", "表面处理", "颜色:黑色", 1]);
+        assert_eq!(
+            normalized_tags(&value).unwrap(),
+            ["热处理:调质", "热处理:氮化", "表面处理"]
+        );
+        assert!(normalized_tags(&serde_json::json!(["颜色:黑色"])).is_none());
+        assert!(normalized_tags(&serde_json::json!({"tags": []})).is_none());
+    }
+}
@@ -0,0 +1,605 @@
+//! 模具类型和材料的分类词表，检索和比较时用来判断两个词条是否属于同一类
+//!
+//! 默认词表内置在`assets/taxonomy`中，数据目录下的`taxonomy/*.json`存在时优先使用。
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    IResult,
+    diff::{ModelJson, is_invalid_material},
+    paths::{data_dir, write_atomic},
+};
+
+const DEFAULT_MODEL_TYPES: &str = include_str!("../assets/taxonomy/model_types.json");
+const DEFAULT_MATERIALS: &str = include_str!("../assets/taxonomy/materials.json");
+const DEFAULT_COMPATIBILITY: &str = include_str!("../assets/taxonomy/compatibility.json");
+
+pub const MODEL_TYPES_FILE: &str = "model_types.json";
+pub const MATERIALS_FILE: &str = "materials.json";
+pub const COMPATIBILITY_FILE: &str = "compatibility.json";
+pub const UNRECOGNIZED_FILE: &str = "unrecognized.json";
+/// 每个待归类词条最多保留的来源示例
+const MAX_EXAMPLES: usize = 5;
+
+/// 模具类型分组，例如`基座`组包含`上基座`、`内基座`等
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelTypeGroup {
+    pub name: String,
+    pub aliases: Vec<String>,
+}
+
+/// 材料牌号及其各种写法
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialGrade {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// 包含厂家的完整写法，例如`长春 PBT 3316 GF30`，需要包含大类和牌号的写法
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<String>,
+}
+
+/// 材料大类(PBT/PA66...)及其下的牌号
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialFamily {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub grades: Vec<MaterialGrade>,
+}
+
+/// 两种材料之间的替代关系
+///
+/// `a`/`b`为材料大类(`PBT`)或具体牌号(`PBT/RG301`)，`score`为可替代时给出的相似度，
+/// 为0表示不可替代。`model_types`为空时对所有模具类型生效，否则只对这些分组生效。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialCompatibility {
+    pub a: String,
+    pub b: String,
+    pub score: f32,
+    #[serde(default)]
+    pub model_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Taxonomy {
+    pub model_types: Vec<ModelTypeGroup>,
+    pub materials: Vec<MaterialFamily>,
+    pub compatibility: Vec<MaterialCompatibility>,
+}
+
+impl Default for Taxonomy {
+    fn default() -> Self {
+        Self {
+            model_types: serde_json::from_str(DEFAULT_MODEL_TYPES)
+                .expect("内置的模具类型词表格式错误"),
+            materials: serde_json::from_str(DEFAULT_MATERIALS).expect("内置的材料词表格式错误"),
+            compatibility: serde_json::from_str(DEFAULT_COMPATIBILITY)
+                .expect("内置的材料替代关系格式错误"),
+        }
+    }
+}
+
+/// 词表所在的数据目录
+pub fn taxonomy_dir() -> PathBuf {
+    data_dir().join("taxonomy")
+}
+
+impl Taxonomy {
+    /// 从目录加载词表，缺少的文件使用内置词表
+    pub fn load(dir: &Path) -> IResult<Self> {
+        let mut taxonomy = Self::default();
+
+        let model_types = dir.join(MODEL_TYPES_FILE);
+        if model_types.exists() {
+            taxonomy.model_types = serde_json::from_str(&std::fs::read_to_string(&model_types)?)?;
+            info!("已加载模具类型词表: {}", model_types.display());
+        }
+
+        let materials = dir.join(MATERIALS_FILE);
+        if materials.exists() {
+            taxonomy.materials = serde_json::from_str(&std::fs::read_to_string(&materials)?)?;
+            info!("已加载材料词表: {}", materials.display());
+        }
+
+        let compatibility = dir.join(COMPATIBILITY_FILE);
+        if compatibility.exists() {
+            taxonomy.compatibility =
+                serde_json::from_str(&std::fs::read_to_string(&compatibility)?)?;
+            info!("已加载材料替代关系: {}", compatibility.display());
+        }
+
+        Ok(taxonomy)
+    }
+
+    /// 保存词表到目录，每个文件先写入临时文件再替换，读取时不会得到写了一半的词表
+    pub fn save(&self, dir: &Path) -> IResult<()> {
+        std::fs::create_dir_all(dir)?;
+        write_atomic(
+            &dir.join(MODEL_TYPES_FILE),
+            serde_json::to_string_pretty(&self.model_types)?,
+        )?;
+        write_atomic(
+            &dir.join(MATERIALS_FILE),
+            serde_json::to_string_pretty(&self.materials)?,
+        )?;
+        write_atomic(
+            &dir.join(COMPATIBILITY_FILE),
+            serde_json::to_string_pretty(&self.compatibility)?,
+        )?;
+        Ok(())
+    }
+
+    /// 向分组中添加模具类型写法，分组不存在时新建
+    pub fn add_model_types(&mut self, group: &str, names: &[String]) -> &ModelTypeGroup {
+        let index = match self.model_types.iter().position(|g| g.name == group) {
+            Some(index) => index,
+            None => {
+                self.model_types.push(ModelTypeGroup {
+                    name: group.to_string(),
+                    aliases: vec![group.to_string()],
+                });
+                self.model_types.len() - 1
+            }
+        };
+        let entry = &mut self.model_types[index];
+        merge_aliases(&mut entry.aliases, names);
+        entry
+    }
+
+    /// 向材料大类中添加牌号及写法，大类或牌号不存在时新建
+    pub fn add_material_grade(
+        &mut self,
+        family: &str,
+        grade: &str,
+        aliases: &[String],
+    ) -> &MaterialFamily {
+        let index = match self.materials.iter().position(|f| f.name == family) {
+            Some(index) => index,
+            None => {
+                self.materials.push(MaterialFamily {
+                    name: family.to_string(),
+                    aliases: vec![family.to_string()],
+                    grades: Vec::new(),
+                });
+                self.materials.len() - 1
+            }
+        };
+        let entry = &mut self.materials[index];
+        match entry.grades.iter_mut().find(|g| g.name == grade) {
+            Some(existing) => merge_aliases(&mut existing.aliases, aliases),
+            None => {
+                let mut grade_aliases = vec![grade.to_string()];
+                merge_aliases(&mut grade_aliases, aliases);
+                entry.grades.push(MaterialGrade {
+                    name: grade.to_string(),
+                    aliases: grade_aliases,
+                    canonical: None,
+                });
+            }
+        }
+        entry
+    }
+
+    /// 查找模具类型所属的分组，多个分组命中时取匹配最长的写法
+    pub fn model_type_group(&self, model_type: &str) -> Option<&ModelTypeGroup> {
+        let text = normalize(model_type);
+        self.model_types
+            .iter()
+            .filter_map(|group| longest_match(&text, &group.aliases).map(|len| (len, group)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, group)| group)
+    }
+
+    /// 查找材料所属的大类
+    pub fn material_family(&self, material: &str) -> Option<&MaterialFamily> {
+        let text = normalize(material);
+        self.materials
+            .iter()
+            .filter_map(|family| longest_match(&text, &family.aliases).map(|len| (len, family)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, family)| family)
+    }
+
+    /// 查找材料对应的牌号
+    pub fn material_grade(&self, material: &str) -> Option<(&MaterialFamily, &MaterialGrade)> {
+        let family = self.material_family(material)?;
+        let text = normalize(material);
+        family
+            .grades
+            .iter()
+            .filter_map(|grade| longest_match(&text, &grade.aliases).map(|len| (len, grade)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, grade)| (family, grade))
+    }
+
+    /// 材料的标准写法，没有配置厂家写法时为`大类 牌号`，词表中没有该牌号时返回None
+    pub fn canonical_material(&self, material: &str) -> Option<String> {
+        let (family, grade) = self.material_grade(material)?;
+        Some(
+            grade
+                .canonical
+                .clone()
+                .unwrap_or_else(|| format!("{} {}", family.name, grade.name)),
+        )
+    }
+
+    /// 两种材料在模具类型`model_type`下的替代相似度，没有对应规则时返回None
+    ///
+    /// 多条规则命中时，精确到牌号的规则优先于大类规则，限定模具类型的规则优先于通用规则。
+    pub fn material_compatibility(
+        &self,
+        material1: &str,
+        material2: &str,
+        model_type: Option<&str>,
+    ) -> Option<f32> {
+        let key1 = self.material_keys(material1)?;
+        let key2 = self.material_keys(material2)?;
+        let group = model_type
+            .and_then(|t| self.model_type_group(t))
+            .map(|g| g.name.as_str());
+
+        self.compatibility
+            .iter()
+            .filter(|rule| {
+                rule.model_types.is_empty()
+                    || group.is_some_and(|g| rule.model_types.iter().any(|t| t == g))
+            })
+            .filter_map(|rule| {
+                let a = key1.specificity(&rule.a).zip(key2.specificity(&rule.b));
+                let b = key1.specificity(&rule.b).zip(key2.specificity(&rule.a));
+                a.or(b)
+                    .map(|(s1, s2)| (s1 + s2, !rule.model_types.is_empty(), rule.score))
+            })
+            .max_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+            .map(|(_, _, score)| score)
+    }
+
+    fn material_keys(&self, material: &str) -> Option<MaterialKey> {
+        let family = self.material_family(material)?;
+        let grade = self
+            .material_grade(material)
+            .map(|(_, grade)| grade.name.clone());
+        Some(MaterialKey {
+            family: family.name.clone(),
+            grade,
+        })
+    }
+}
+
+/// 材料在词表中的大类和牌号，用于匹配替代规则
+struct MaterialKey {
+    family: String,
+    grade: Option<String>,
+}
+
+impl MaterialKey {
+    /// 规则中的写法能否匹配该材料，匹配大类返回1，匹配牌号返回2
+    fn specificity(&self, pattern: &str) -> Option<u8> {
+        match pattern.split_once('/') {
+            Some((family, grade)) => {
+                (family == self.family && self.grade.as_deref() == Some(grade)).then_some(2)
+            }
+            None => (pattern == self.family).then_some(1),
+        }
+    }
+}
+
+/// 待归类词条的出现统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TermStats {
+    pub count: u64,
+    pub last_seen: String,
+    /// 出现过该词条的来源文件
+    pub examples: Vec<String>,
+}
+
+/// 提取结果中不属于任何已知分组的模具类型和材料，用于补充词表
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnrecognizedTerms {
+    pub model_types: BTreeMap<String, TermStats>,
+    pub materials: BTreeMap<String, TermStats>,
+}
+
+/// 待归类词条列表中的一项
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingTerm<'a> {
+    pub term: &'a str,
+    #[serde(flatten)]
+    pub stats: &'a TermStats,
+}
+
+impl UnrecognizedTerms {
+    pub fn load(dir: &Path) -> IResult<Self> {
+        let path = dir.join(UNRECOGNIZED_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> IResult<()> {
+        std::fs::create_dir_all(dir)?;
+        write_atomic(
+            &dir.join(UNRECOGNIZED_FILE),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// 记录一次提取结果中词表不认识的词条，返回是否有新增
+    pub fn record(&mut self, taxonomy: &Taxonomy, model: &ModelJson) -> bool {
+        let now = chrono::Local::now().to_rfc3339();
+        let source = &model.source_directory_name;
+        let mut changed = false;
+
+        if let Some(model_type) = model.model_type.as_deref().map(str::trim)
+            && !model_type.is_empty()
+            && taxonomy.model_type_group(model_type).is_none()
+        {
+            bump(&mut self.model_types, model_type, source, &now);
+            changed = true;
+        }
+
+        for material in &model.materials {
+            let material = material.trim();
+            if !is_invalid_material(material) && taxonomy.material_family(material).is_none() {
+                bump(&mut self.materials, material, source, &now);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// 仍未被词表覆盖的词条，按出现次数降序
+    pub fn pending<'a>(
+        &'a self,
+        taxonomy: &Taxonomy,
+    ) -> (Vec<PendingTerm<'a>>, Vec<PendingTerm<'a>>) {
+        let model_types = sorted_terms(&self.model_types, |t| {
+            taxonomy.model_type_group(t).is_none()
+        });
+        let materials = sorted_terms(&self.materials, |t| taxonomy.material_family(t).is_none());
+        (model_types, materials)
+    }
+}
+
+fn bump(terms: &mut BTreeMap<String, TermStats>, term: &str, source: &str, now: &str) {
+    let stats = terms.entry(term.to_string()).or_default();
+    stats.count += 1;
+    stats.last_seen = now.to_string();
+    if stats.examples.len() < MAX_EXAMPLES && !stats.examples.iter().any(|e| e == source) {
+        stats.examples.push(source.to_string());
+    }
+}
+
+fn sorted_terms<'a>(
+    terms: &'a BTreeMap<String, TermStats>,
+    unrecognized: impl Fn(&str) -> bool,
+) -> Vec<PendingTerm<'a>> {
+    let mut pending: Vec<_> = terms
+        .iter()
+        .filter(|(term, _)| unrecognized(term))
+        .map(|(term, stats)| PendingTerm { term, stats })
+        .collect();
+    pending.sort_by_key(|p| std::cmp::Reverse(p.stats.count));
+    pending
+}
+
+/// 待归类词条的markdown摘要
+pub fn fmt_unrecognized_digest(terms: &UnrecognizedTerms, taxonomy: &Taxonomy) -> String {
+    const TOP: usize = 20;
+    let (model_types, materials) = terms.pending(taxonomy);
+    if model_types.is_empty() && materials.is_empty() {
+        return "✅ 目前没有待归类词条".to_string();
+    }
+
+    let mut md = String::from("📋 待归类词条(按出现次数排序):\n");
+    for (title, list) in [("模具类型", model_types), ("材料", materials)] {
+        if list.is_empty() {
+            continue;
+        }
+        md.push_str(&format!(
+            "\n**{}** ({}个)\n\n| 词条 | 次数 | 示例 |\n| --- | --- | --- |\n",
+            title,
+            list.len()
+        ));
+        for item in list.iter().take(TOP) {
+            md.push_str(&format!(
+                "| {} | {} | {} |\n",
+                item.term,
+                item.stats.count,
+                item.stats
+                    .examples
+                    .first()
+                    .map(String::as_str)
+                    .unwrap_or("-")
+            ));
+        }
+    }
+    md
+}
+
+/// 追加新的写法，忽略已存在的
+fn merge_aliases(aliases: &mut Vec<String>, new: &[String]) {
+    for name in new {
+        let name = name.trim();
+        if !name.is_empty() && !aliases.iter().any(|a| normalize(a) == normalize(name)) {
+            aliases.push(name.to_string());
+        }
+    }
+}
+
+/// 忽略大小写和空白
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_uppercase())
+        .collect()
+}
+
+/// 返回`text`中包含的最长写法的长度
+fn longest_match(text: &str, aliases: &[String]) -> Option<usize> {
+    aliases
+        .iter()
+        .map(|alias| normalize(alias))
+        .filter(|alias| !alias.is_empty() && text.contains(alias.as_str()))
+        .map(|alias| alias.chars().count())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_type_groups() {
+        let taxonomy = Taxonomy::default();
+        assert_eq!(taxonomy.model_type_group("上基座").unwrap().name, "基座");
+        assert_eq!(
+            taxonomy
+                .model_type_group("ZC75N基座(60A-ASSLY带护针)")
+                .unwrap()
+                .name,
+            "基座"
+        );
+        assert_eq!(taxonomy.model_type_group("基座盖板").unwrap().name, "盖板");
+        assert_eq!(
+            taxonomy.model_type_group("HAT904G 骨架").unwrap().name,
+            "骨架"
+        );
+        assert!(taxonomy.model_type_group("SHG.SPRC2C.P03-1").is_none());
+    }
+
+    #[test]
+    fn material_grades() {
+        let taxonomy = Taxonomy::default();
+        let (family, grade) = taxonomy.material_grade("金发 PBT RG301(白)").unwrap();
+        assert_eq!(
+            (family.name.as_str(), grade.name.as_str()),
+            ("PBT", "RG301")
+        );
+
+        let (family, grade) = taxonomy.material_grade("PA66 A3 GF25 VOX1(本色)").unwrap();
+        assert_eq!(
+            (family.name.as_str(), grade.name.as_str()),
+            ("PA66", "A3 GF25")
+        );
+
+        assert_eq!(
+            taxonomy.material_family("尼龙 PA6-GF30").unwrap().name,
+            "PA6"
+        );
+        assert!(taxonomy.material_family("UL94 V-0").is_none());
+
+        assert_eq!(
+            taxonomy.canonical_material("PBT-3316 黑色").as_deref(),
+            Some("长春 PBT 3316 GF30")
+        );
+        // 没有配置厂家写法的牌号
+        assert_eq!(
+            taxonomy
+                .canonical_material("PA66 A3 GF25 VOX1(本色)")
+                .as_deref(),
+            Some("PA66 A3 GF25")
+        );
+        assert_eq!(taxonomy.canonical_material("UL94 V-0"), None);
+    }
+
+    #[test]
+    fn add_entries() {
+        let mut taxonomy = Taxonomy::default();
+        let group = taxonomy.add_model_types("基座", &["下基座".to_string(), "基座".to_string()]);
+        assert_eq!(group.aliases.iter().filter(|a| *a == "基座").count(), 1);
+        assert_eq!(taxonomy.model_type_group("下基座").unwrap().name, "基座");
+
+        taxonomy.add_material_grade("PBT", "RG302", &["rg 302".to_string()]);
+        let (_, grade) = taxonomy.material_grade("PBT RG 302 黑色").unwrap();
+        assert_eq!(grade.name, "RG302");
+
+        taxonomy.add_material_grade("POM", "M90", &[]);
+        assert_eq!(taxonomy.material_grade("POM M90").unwrap().0.name, "POM");
+    }
+
+    #[test]
+    fn material_compatibility() {
+        let mut taxonomy = Taxonomy::default();
+        // 只对外壳类生效
+        assert_eq!(
+            taxonomy.material_compatibility("PBT RG301", "PET 530", Some("上外壳")),
+            Some(0.6)
+        );
+        assert_eq!(
+            taxonomy.material_compatibility("PET 530", "PBT RG301", Some("基座")),
+            None
+        );
+        assert_eq!(
+            taxonomy.material_compatibility("PA46 TE250F6", "PA4T", None),
+            Some(0.0)
+        );
+
+        // 牌号规则优先于大类规则
+        taxonomy.compatibility.push(MaterialCompatibility {
+            a: "PA66/A3 GF25".to_string(),
+            b: "PA6".to_string(),
+            score: 0.9,
+            model_types: Vec::new(),
+        });
+        assert_eq!(
+            taxonomy.material_compatibility("PA66 A3 GF25", "PA6-GF30", None),
+            Some(0.9)
+        );
+        assert_eq!(
+            taxonomy.material_compatibility("PA66 A3EG6", "PA6-GF30", None),
+            Some(0.7)
+        );
+        assert_eq!(
+            taxonomy.material_compatibility("UL94 V-0", "PA6", None),
+            None
+        );
+    }
+
+    #[test]
+    fn record_unrecognized() {
+        let mut taxonomy = Taxonomy::default();
+        let mut terms = UnrecognizedTerms::default();
+        let model = ModelJson {
+            model_type: Some("连接器".to_string()),
+            materials: vec![
+                "POM M90".to_string(),
+                "PBT RG301".to_string(),
+                "附".to_string(),
+            ],
+            project_name: None,
+            company: None,
+            dimensions: None,
+            drawing: None,
+            source_directory: PathBuf::from("a"),
+            source_directory_name: "a".to_string(),
+            extraction_timestamp: None,
+            original_pdf: None,
+            tags: Vec::new(),
+            finish: None,
+            id: None,
+            slug: None,
+            status: None,
+            provenance: None,
+            canonical_materials: Vec::new(),
+        };
+        assert!(terms.record(&taxonomy, &model));
+        assert!(terms.record(&taxonomy, &model));
+
+        let (model_types, materials) = terms.pending(&taxonomy);
+        assert_eq!(model_types[0].term, "连接器");
+        assert_eq!(model_types[0].stats.count, 2);
+        assert_eq!(model_types[0].stats.examples, vec!["a"]);
+        assert_eq!(materials.len(), 1);
+
+        taxonomy.add_material_grade("POM", "M90", &[]);
+        assert!(terms.pending(&taxonomy).1.is_empty());
+    }
+}
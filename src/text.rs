@@ -0,0 +1,46 @@
+//! 按字符边界截取字符串
+//!
+//! 模型回复和接口错误中大多是中文，按字节下标切片可能落在多字节字符中间而panic，
+//! 日志和错误信息中的截取统一使用这里的函数。
+
+/// 最多`max_chars`个字符的前缀
+pub fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+/// 日志和错误信息中使用的摘要，超出`max_chars`时以`…`结尾
+pub fn preview(text: &str, max_chars: usize) -> String {
+    let prefix = truncate_chars(text, max_chars);
+    if prefix.len() < text.len() {
+        format!("{}…", prefix)
+    } else {
+        prefix.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn cut_on_char_boundaries() {
+        let text = "基座PBT";
+        assert_eq!(truncate_chars(text, 100), text);
+        assert_eq!(truncate_chars(text, 3), "基座P");
+        assert_eq!(preview(text, 2), "基座…");
+        assert_eq!(preview(text, 5), text);
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(text in "\\PC*", max in 0usize..64) {
+            prop_assert!(text.starts_with(truncate_chars(&text, max)));
+            prop_assert!(preview(&text, max).chars().count() <= max + 1);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! 文本相似度的度量方式
+//!
+//! 模具类型、材料和表面处理的名称按字符比较。原来的`diff_text`只统计相同字符的个数，
+//! 不考虑顺序，`座基`和`基座`的相似度为1，`89(89`和`8989外壳`也有较高的分数。
+//! 默认使用编辑距离，可以在评分方案中通过`text_metric`选择其他度量。
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{diff_text, split_text};
+
+/// Jaro-Winkler中公共前缀的权重
+const PREFIX_SCALE: f32 = 0.1;
+/// Jaro-Winkler最多计入的公共前缀长度
+const MAX_PREFIX: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMetric {
+    /// 相同字符占较长文本的比例，不考虑顺序，兼容旧的评分
+    CharacterBag,
+    /// 1 - 编辑距离 / 较长文本的字符数
+    #[default]
+    Levenshtein,
+    /// Jaro-Winkler，公共前缀相同的文本得分更高，适合型号前缀相同的牌号
+    JaroWinkler,
+}
+
+impl TextMetric {
+    /// 两段文本的相似度，0到1之间，按字符(而不是字节)比较
+    pub fn similarity(&self, text1: &str, text2: &str) -> f32 {
+        match self {
+            Self::CharacterBag => diff_text(split_text(text1), split_text(text2)),
+            Self::Levenshtein => levenshtein_similarity(text1, text2),
+            Self::JaroWinkler => jaro_winkler(text1, text2),
+        }
+    }
+}
+
+/// 插入、删除、替换一个字符各算一次编辑
+pub fn levenshtein(text1: &str, text2: &str) -> usize {
+    let chars2: Vec<char> = text2.chars().collect();
+    let mut previous: Vec<usize> = (0..=chars2.len()).collect();
+    let mut current = vec![0; chars2.len() + 1];
+    for (i, c1) in text1.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, c2) in chars2.iter().enumerate() {
+            let substitution = previous[j] + usize::from(c1 != *c2);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[chars2.len()]
+}
+
+fn levenshtein_similarity(text1: &str, text2: &str) -> f32 {
+    let longer = text1.chars().count().max(text2.chars().count());
+    if longer == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(text1, text2) as f32 / longer as f32
+}
+
+fn jaro(chars1: &[char], chars2: &[char]) -> f32 {
+    if chars1.is_empty() && chars2.is_empty() {
+        return 1.0;
+    }
+    if chars1.is_empty() || chars2.is_empty() {
+        return 0.0;
+    }
+    let window = (chars1.len().max(chars2.len()) / 2).saturating_sub(1);
+    let mut matched2 = vec![false; chars2.len()];
+    let mut matches1 = Vec::new();
+    for (i, c1) in chars1.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(chars2.len());
+        if let Some(j) = (start..end).find(|&j| !matched2[j] && chars2[j] == *c1) {
+            matched2[j] = true;
+            matches1.push(*c1);
+        }
+    }
+    if matches1.is_empty() {
+        return 0.0;
+    }
+    let matches2 = chars2
+        .iter()
+        .zip(&matched2)
+        .filter(|(_, matched)| **matched)
+        .map(|(c, _)| c);
+    let transpositions = matches1
+        .iter()
+        .zip(matches2)
+        .filter(|(a, b)| a != b)
+        .count()
+        / 2;
+    let m = matches1.len() as f32;
+    (m / chars1.len() as f32 + m / chars2.len() as f32 + (m - transpositions as f32) / m) / 3.0
+}
+
+fn jaro_winkler(text1: &str, text2: &str) -> f32 {
+    let chars1: Vec<char> = text1.chars().collect();
+    let chars2: Vec<char> = text2.chars().collect();
+    let jaro = jaro(&chars1, &chars2);
+    let prefix = chars1
+        .iter()
+        .zip(&chars2)
+        .take(MAX_PREFIX)
+        .take_while(|(a, b)| a == b)
+        .count();
+    jaro + prefix as f32 * PREFIX_SCALE * (1.0 - jaro)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_metrics() {
+        assert_eq!(levenshtein("基座", "底座"), 1);
+        assert_eq!(levenshtein("89(89", "8989外壳"), 3);
+        assert_eq!(levenshtein("", "外壳"), 2);
+
+        let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+        assert!(close(
+            TextMetric::Levenshtein.similarity("基座", "底座"),
+            0.5
+        ));
+        assert!(close(
+            TextMetric::Levenshtein.similarity("上基座", "基座"),
+            0.667
+        ));
+        assert!(close(TextMetric::Levenshtein.similarity("", ""), 1.0));
+        // Winkler论文中的例子
+        assert!(close(
+            TextMetric::JaroWinkler.similarity("MARTHA", "MARHTA"),
+            0.961
+        ));
+        assert!(close(
+            TextMetric::JaroWinkler.similarity("DIXON", "DICKSONX"),
+            0.813
+        ));
+        assert!(
+            TextMetric::JaroWinkler.similarity("PBT-RG301", "PBT-RG302")
+                > TextMetric::Levenshtein.similarity("PBT-RG301", "PBT-RG302")
+        );
+
+        // 字符相同但顺序不同
+        assert_eq!(TextMetric::CharacterBag.similarity("座基", "基座"), 1.0);
+        assert_eq!(TextMetric::Levenshtein.similarity("座基", "基座"), 0.0);
+        assert_eq!(TextMetric::JaroWinkler.similarity("座基", "基座"), 0.0);
+
+        assert_eq!(
+            serde_json::from_str::<TextMetric>(r#""jaro_winkler""#).unwrap(),
+            TextMetric::JaroWinkler
+        );
+    }
+}
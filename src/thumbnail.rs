@@ -0,0 +1,204 @@
+//! 结果预览图的按需生成
+//!
+//! 模型的`{name}_page_001`等预览图缺失时，从模型的页面图片或源PDF的对应页生成并保存，
+//! 之后的结果直接使用保存的预览图。页面图片目录有转换时写入的清单时按清单取页面，
+//! 旧的目录按文件名排序。
+use std::path::{Path, PathBuf};
+
+use image::{ImageFormat, imageops::FilterType};
+use pdf2image::{DPI, PDF, Pages, RenderOptionsBuilder};
+use tracing::info;
+
+use crate::{AnalyzerError, IResult, pdf_converter::PageManifest};
+
+/// 预览图的最大宽度
+const MAX_WIDTH: u32 = 1200;
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// 模型第`page`页(从0开始)的预览图文件名，`{name}_page_001`
+pub fn preview_file_name(source_name: &str, page: usize) -> String {
+    format!("{}_page_{:03}", source_name, page + 1)
+}
+
+/// 确保第`page`页(从0开始)的预览图存在，不存在时尝试从`source_directory`生成
+pub fn ensure_preview(preview: &Path, source_directory: &Path, page: usize) -> IResult<()> {
+    if preview.exists() || preview.with_extension("png").exists() {
+        return Ok(());
+    }
+
+    let img = match page_image(source_directory, page) {
+        Some(page) => image::open(&page)
+            .map_err(|e| AnalyzerError::ImageError(format!("读取页面图片失败: {}", e)))?,
+        None => {
+            let pdf = source_pdf(source_directory).ok_or_else(|| {
+                AnalyzerError::ImageError(format!(
+                    "没有可用于生成预览图的页面或PDF: {}",
+                    source_directory.display()
+                ))
+            })?;
+            render_page(&pdf, page)?
+        }
+    };
+
+    let img = if img.width() > MAX_WIDTH {
+        img.resize(MAX_WIDTH, u32::MAX, FilterType::Triangle)
+    } else {
+        img
+    };
+    if let Some(parent) = preview.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // 预览图路径没有扩展名，需要指定格式
+    img.save_with_format(preview, ImageFormat::Png)
+        .map_err(|e| AnalyzerError::ImageError(format!("保存预览图失败: {}", e)))?;
+    info!("🖼️ 已生成预览图: {}", preview.display());
+    Ok(())
+}
+
+/// 页面图片目录中的第`page`页
+fn page_image(dir: &Path, page: usize) -> Option<PathBuf> {
+    if let Some(manifest) = PageManifest::load(dir) {
+        return manifest
+            .preview_path(dir, page)
+            .filter(|path| path.is_file());
+    }
+    let mut images: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    // page_2 排在 page_10 之前
+    images.sort_by_key(|path| (path.as_os_str().len(), path.clone()));
+    images.into_iter().nth(page)
+}
+
+/// 页面图片目录对应的源PDF: 同名的`.pdf`文件、转换时所在目录(`<pdf目录>/output/<名称>/`)
+/// 中的同名PDF或目录中的PDF
+pub(crate) fn source_pdf(dir: &Path) -> Option<PathBuf> {
+    let sibling = dir.with_extension("pdf");
+    if sibling.is_file() {
+        return Some(sibling);
+    }
+    if let Some(name) = dir.file_name()
+        && let Some(pdf_dir) = dir.parent().and_then(Path::parent)
+    {
+        let converted_from = pdf_dir.join(format!("{}.pdf", name.to_string_lossy()));
+        if converted_from.is_file() {
+            return Some(converted_from);
+        }
+    }
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.extension().and_then(|s| s.to_str()) == Some("pdf"))
+}
+
+fn render_page(pdf: &Path, page: usize) -> IResult<image::DynamicImage> {
+    let pdf = PDF::from_file(pdf)
+        .map_err(|e| AnalyzerError::PdfError(format!("Failed to load PDF: {}", e)))?;
+    if page >= pdf.page_count() as usize {
+        return Err(AnalyzerError::PdfError(format!("PDF没有第{}页", page + 1)));
+    }
+    let option = RenderOptionsBuilder::default()
+        .resolution(DPI::Uniform(100))
+        .pdftocairo(true)
+        .build()
+        .map_err(|e| AnalyzerError::PdfError(format!("Failed to build render options: {}", e)))?;
+    pdf.render(Pages::Single(page as u32), option)
+        .map_err(|e| AnalyzerError::PdfError(format!("Failed to render PDF page: {}", e)))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AnalyzerError::PdfError(format!("PDF没有第{}页", page + 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        paths::TempDir,
+        pdf_converter::{PageImage, page_file_name},
+    };
+
+    #[test]
+    fn generate_preview_from_page_image() {
+        let root = TempDir::new("thumb");
+        let source = root.join("output").join("a");
+        std::fs::create_dir_all(&source).unwrap();
+        image::RgbImage::new(2400, 100)
+            .save(source.join("page_0.jpg"))
+            .unwrap();
+        for page in [1, 10] {
+            image::RgbImage::new(100 + page, 100)
+                .save(source.join(format!("page_{}.jpg", page)))
+                .unwrap();
+        }
+
+        let preview = root.join("imgs").join("a").join("a_page_001");
+        ensure_preview(&preview, &source, 0).unwrap();
+        let img = image::ImageReader::open(&preview)
+            .unwrap()
+            .with_guessed_format()
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(img.width(), MAX_WIDTH);
+
+        let preview = root.join("imgs").join("a").join("a_page_002");
+        ensure_preview(&preview, &source, 1).unwrap();
+        let img = image::ImageReader::open(&preview)
+            .unwrap()
+            .with_guessed_format()
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(img.width(), 101);
+        assert!(ensure_preview(&root.join("a_page_004"), &source, 3).is_err());
+
+        // 没有任何来源时报错
+        let missing = root.join("imgs").join("b").join("b_page_001");
+        assert!(ensure_preview(&missing, &root.join("output").join("b"), 0).is_err());
+        assert!(!missing.exists());
+
+        // 有清单时按清单取页面，不受目录中其他图片影响
+        let source = root.join("output").join("c");
+        std::fs::create_dir_all(&source).unwrap();
+        for (page, width) in [(0, 300), (1, 200)] {
+            image::RgbImage::new(width, 100)
+                .save(source.join(page_file_name(page)))
+                .unwrap();
+        }
+        image::RgbImage::new(50, 50)
+            .save(source.join("a.jpg"))
+            .unwrap();
+        PageManifest {
+            source: "c.pdf".to_string(),
+            dpi: 300,
+            pages: vec![PageImage {
+                file: page_file_name(1),
+                width: 200,
+                height: 100,
+                bytes: 0,
+                preview: None,
+                label: None,
+            }],
+        }
+        .save(&source)
+        .unwrap();
+        let preview = root.join("imgs").join("c").join(preview_file_name("c", 0));
+        ensure_preview(&preview, &source, 0).unwrap();
+        let (width, _) = image::ImageReader::open(&preview)
+            .unwrap()
+            .with_guessed_format()
+            .unwrap()
+            .into_dimensions()
+            .unwrap();
+        assert_eq!(width, 200);
+        assert!(ensure_preview(&root.join(preview_file_name("c", 1)), &source, 1).is_err());
+    }
+}
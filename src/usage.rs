@@ -0,0 +1,110 @@
+//! 模型服务的token用量和费用估算
+//!
+//! 每次请求的回复中带有用量，OpenAI兼容格式为`usage.prompt_tokens`/`usage.completion_tokens`，
+//! DashScope原生格式为`usage.input_tokens`/`usage.output_tokens`，Ollama为顶层的
+//! `prompt_eval_count`/`eval_count`。分析器累计一个任务中所有请求(包括重试)的用量，
+//! 按配置的价格估算费用，记录在任务上并附在结果消息末尾。
+use std::ops::AddAssign;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Pricing;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenUsage {
+    /// 请求次数
+    pub requests: u32,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 按配置的价格估算的费用，没有配置价格时为0
+    pub cost: f64,
+}
+
+impl TokenUsage {
+    /// 一次请求的用量，回复中没有用量时只计请求次数
+    pub fn from_response(response: &Value, pricing: Option<&Pricing>) -> Self {
+        let fields = response.get("usage").unwrap_or(response);
+        let count = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| fields.get(*key).and_then(Value::as_u64))
+                .unwrap_or(0)
+        };
+        let input_tokens = count(&["prompt_tokens", "input_tokens", "prompt_eval_count"]);
+        let output_tokens = count(&["completion_tokens", "output_tokens", "eval_count"]);
+        Self {
+            requests: 1,
+            input_tokens,
+            output_tokens,
+            cost: pricing.map_or(0.0, |p| p.cost(input_tokens, output_tokens)),
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// 结果消息中的用量说明
+    pub fn summary(&self, currency: &str) -> String {
+        let mut text = format!(
+            "{} 次模型请求，输入 {} / 输出 {} tokens",
+            self.requests, self.input_tokens, self.output_tokens
+        );
+        if self.cost > 0.0 {
+            text.push_str(&format!("，约 {:.4} {}", self.cost, currency));
+        }
+        text
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost += other.cost;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_from_responses() {
+        let pricing = Pricing {
+            input_per_million: 3.0,
+            output_per_million: 9.0,
+            currency: "元".to_string(),
+        };
+        let openai = serde_json::json!({
+            "choices": [],
+            "usage": {"prompt_tokens": 1000, "completion_tokens": 200, "total_tokens": 1200}
+        });
+        let mut usage = TokenUsage::from_response(&openai, Some(&pricing));
+        assert_eq!((usage.input_tokens, usage.output_tokens), (1000, 200));
+        assert!((usage.cost - 0.0048).abs() < 1e-9);
+
+        let dashscope = serde_json::json!({
+            "output": {"text": ""},
+            "usage": {"input_tokens": 500, "output_tokens": 100}
+        });
+        usage += TokenUsage::from_response(&dashscope, Some(&pricing));
+        let ollama =
+            serde_json::json!({"response": "", "prompt_eval_count": 300, "eval_count": 50});
+        usage += TokenUsage::from_response(&ollama, None);
+        // 没有用量的回复只计请求次数
+        usage += TokenUsage::from_response(&serde_json::json!({}), Some(&pricing));
+
+        assert_eq!(usage.requests, 4);
+        assert_eq!(usage.input_tokens, 1800);
+        assert_eq!(usage.output_tokens, 350);
+        assert_eq!(usage.total_tokens(), 2150);
+        assert_eq!(
+            usage.summary("元"),
+            "4 次模型请求，输入 1800 / 输出 350 tokens，约 0.0072 元"
+        );
+        assert!(!TokenUsage::default().summary("元").contains('约'));
+    }
+}
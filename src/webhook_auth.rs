@@ -0,0 +1,118 @@
+//! VoceChat webhook请求的校验
+//!
+//! webhook地址是公开的，不校验时任何人都可以提交PDF触发模型分析。配置校验方式后，
+//! 请求头需要带有共享密钥，或者带有用密钥对原始请求体计算的HMAC签名，见`WebhookVerification`。
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::{WebhookConfig, WebhookVerification};
+
+/// 签名可以带的前缀，与常见的webhook签名格式一致
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+pub struct WebhookVerifier {
+    verification: WebhookVerification,
+    header: String,
+    secret: Option<String>,
+}
+
+impl WebhookVerifier {
+    pub fn new(config: &WebhookConfig, secret: Option<String>) -> Self {
+        Self {
+            verification: config.verification.clone(),
+            header: config.header().to_string(),
+            secret,
+        }
+    }
+
+    /// 携带密钥或签名的请求头
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    /// 校验请求，`value`为请求头的值，失败时返回原因；
+    /// 需要校验但没有配置密钥或校验方式无法识别时一律拒绝
+    pub fn verify(&self, value: Option<&str>, body: &[u8]) -> Result<(), &'static str> {
+        match self.verification {
+            WebhookVerification::None => return Ok(()),
+            WebhookVerification::Unknown(_) => return Err("无法识别webhook的校验方式"),
+            WebhookVerification::Token | WebhookVerification::Hmac => {}
+        }
+        let Some(secret) = self.secret.as_deref() else {
+            return Err("没有配置webhook的校验密钥");
+        };
+        let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+            return Err("缺少校验请求头");
+        };
+        let valid = match &self.verification {
+            WebhookVerification::None => true,
+            WebhookVerification::Unknown(_) => false,
+            WebhookVerification::Token => constant_time_eq(value.as_bytes(), secret.as_bytes()),
+            WebhookVerification::Hmac => {
+                let signature = value.strip_prefix(SIGNATURE_PREFIX).unwrap_or(value);
+                hex::decode(signature).is_ok_and(|signature| {
+                    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                        .expect("HMAC can take key of any size");
+                    mac.update(body);
+                    mac.verify_slice(&signature).is_ok()
+                })
+            }
+        };
+        if valid { Ok(()) } else { Err("校验失败") }
+    }
+}
+
+/// 比较时间不取决于第一个不同的字节，避免逐字节猜测密钥
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_events::signature;
+
+    fn verifier(verification: WebhookVerification, secret: Option<&str>) -> WebhookVerifier {
+        WebhookVerifier {
+            verification,
+            header: "X-Webhook-Token".to_string(),
+            secret: secret.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn verify_webhook_requests() {
+        let body = br#"{"mid": 1}"#;
+        assert!(
+            verifier(WebhookVerification::None, None)
+                .verify(None, body)
+                .is_ok()
+        );
+
+        let token = verifier(WebhookVerification::Token, Some("s3cret"));
+        assert!(token.verify(Some("s3cret"), body).is_ok());
+        assert!(token.verify(Some("s3cre"), body).is_err());
+        assert!(token.verify(None, body).is_err());
+        // 需要校验但没有密钥
+        let missing = verifier(WebhookVerification::Token, None);
+        assert!(missing.verify(Some(""), body).is_err());
+
+        let hmac = verifier(WebhookVerification::Hmac, Some("s3cret"));
+        let signed = signature("s3cret", body);
+        assert!(hmac.verify(Some(&signed), body).is_ok());
+        assert!(
+            hmac.verify(Some(&format!("sha256={}", signed)), body)
+                .is_ok()
+        );
+        assert!(hmac.verify(Some(&signed), br#"{"mid": 2}"#).is_err());
+        assert!(hmac.verify(Some("s3cret"), body).is_err());
+
+        // 无法识别的校验方式拒绝所有请求
+        let unknown = verifier(
+            WebhookVerification::Unknown("HMAC-SHA1".to_string()),
+            Some("s3cret"),
+        );
+        assert!(unknown.verify(Some(&signed), body).is_err());
+        assert!(unknown.verify(None, body).is_err());
+    }
+}
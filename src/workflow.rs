@@ -1,77 +1,442 @@
-use std::path::PathBuf;
-use tokio::task;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::SemaphorePermit,
+    task::{self, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 use crate::{
-    MODELS,
-    ai_text_analyzer::AiTextAnalyzer,
-    api::pdf::{WebhookRequest, convert_to_image},
-    config::AiConfig,
-    diff::{DiffResult, ModelJson, fmt_diff_result_to_md},
+    CONFIG, HTTP_CLIENT, IMAGE_URLS, JOB_EVENTS, JOBS, MAINTENANCE, MODELS, PREFERENCES, QUEUE,
+    TAXONOMY, UNRECOGNIZED,
+    ai_text_analyzer::{AiTextAnalyzer, TEXT_EXTRACT_PROMPT_VERSION},
+    api::pdf::WebhookRequest,
+    blob::Blob,
+    config::{ProgressConfig, ReportLayout, Sampling, ScoringProfile},
+    diff::{
+        DiffResult, ModelJson, Provenance, fmt_diff_result_to_md, fmt_search_result_to_md,
+        fmt_unidentified_to_md,
+    },
+    drawing::ScaleConflict,
+    duplicate_alert,
+    job::{JobKind, JobRecord, JobStage, JobStatus, PAGES_DIR, RESPONSES_DIR, jobs_dir},
+    job_events::JobProgress,
+    paths::upload_dir,
+    pdf_converter::{PageManifest, convert_to_image},
+    progress::{JobSink, ProgressNotifier, fmt_elapsed},
+    progress_sink::ProgressSink,
+    query::UserQuery,
+    response_archive::{self, ResponseArchive},
+    taxonomy::taxonomy_dir,
+    text::truncate_chars,
+    thumbnail::ensure_preview,
 };
 
-/// PDF 分析工作流
-pub struct PdfAnalysisWorkflow {
-    pdf_path: PathBuf,
-    webhook_url: String,
-    api_key: String,
+/// 工作流回复的目标和对应的任务
+pub struct WorkflowContext {
+    pub job_id: String,
+    pub client: reqwest::Client,
+    /// 机器人接口地址，None时不发送消息，例如通过接口提交的任务
+    pub webhook_url: Option<String>,
+    pub api_key: String,
+    /// 结果的展示形式
+    pub layout: ReportLayout,
+    /// 消息来源的VoceChat域名，用于选择比较页面前端
+    pub tenant: Option<String>,
+    /// 用户通过`/sampling`选择的采样参数，None时使用配置
+    pub sampling: Option<Sampling>,
+    /// 任务阶段和每页的进度
+    pub progress: Arc<JobSink>,
+    /// 用户取消任务的信号，见`JobRegistry::cancel_user`
+    pub cancel: CancellationToken,
 }
 
-impl PdfAnalysisWorkflow {
-    pub fn new(pdf_path: PathBuf, webhook_url: String, api_key: String) -> Self {
+impl WorkflowContext {
+    /// 回复到消息所在的会话，使用共用的HTTP客户端
+    pub fn new(job_id: String, req: &WebhookRequest) -> Self {
+        let (webhook_url, api_key) = bot_endpoint(req);
+        let preferences = PREFERENCES.lock().unwrap();
         Self {
-            pdf_path,
-            webhook_url,
+            client: HTTP_CLIENT.clone(),
+            webhook_url: Some(webhook_url),
             api_key,
+            layout: preferences
+                .layout(req.from_uid())
+                .unwrap_or(CONFIG.report.layout),
+            tenant: req.domain().map(str::to_string),
+            sampling: preferences.sampling(req.from_uid()),
+            progress: Arc::new(JobSink::new(job_id.clone())),
+            cancel: JOBS.cancellation(&job_id),
+            job_id,
         }
     }
 
-    /// 启动后台分析任务
-    pub fn start_background_analysis(self) {
-        task::spawn(async move {
-            self.run_analysis().await;
-        });
+    /// 不回复消息的任务，结果只保存在任务记录中
+    pub fn detached(job_id: String) -> Self {
+        Self {
+            client: HTTP_CLIENT.clone(),
+            webhook_url: None,
+            api_key: String::new(),
+            layout: CONFIG.report.layout,
+            tenant: None,
+            sampling: None,
+            progress: Arc::new(JobSink::new(job_id.clone())),
+            cancel: JOBS.cancellation(&job_id),
+            job_id,
+        }
     }
+}
+
+/// 后台工作流，按 prepare → execute → render → deliver 执行
+///
+/// 任务启动、进度提醒、重试、耗时统计和任务状态更新由`start`统一处理，
+/// 新的流水线只需要实现各个步骤。
+pub trait Workflow: Send + Sync + Sized + 'static {
+    type Input: Send + Sync;
+    type Output: Send;
 
-    /// 执行完整的分析流程
-    async fn run_analysis(self) {
-        info!("开始后台分析 PDF: {}", self.pdf_path.display());
+    /// 用于日志和失败提示，例如"分析"
+    fn name(&self) -> &'static str;
 
-        let result = self.perform_analysis().await;
+    fn context(&self) -> &WorkflowContext;
 
-        match result {
-            Ok(response_text) => {
-                info!("✅ 分析完成，发送结果");
-                self.send_response(&response_text).await;
+    fn job_id(&self) -> &str {
+        &self.context().job_id
+    }
+
+    /// 长时间运行时的进度提醒，None表示不提醒
+    fn progress(&self) -> Option<ProgressConfig> {
+        None
+    }
+
+    /// 是否需要排队，耗时的分析任务限制同时执行的数量
+    fn queued(&self) -> bool {
+        false
+    }
+
+    /// execute失败时的重试次数
+    fn retries(&self) -> u32 {
+        0
+    }
+
+    /// 准备输入，失败时不重试
+    fn prepare(&self) -> impl Future<Output = Result<Self::Input, String>> + Send;
+
+    fn execute(
+        &self,
+        input: &Self::Input,
+    ) -> impl Future<Output = Result<Self::Output, String>> + Send;
+
+    /// 把结果转为markdown
+    fn render(&self, output: &Self::Output) -> String;
+
+    /// 发送消息，返回发送的消息mid
+    fn deliver(&self, content: &str) -> impl Future<Output = Option<u64>> + Send {
+        let context = self.context();
+        async move {
+            let webhook_url = context.webhook_url.as_deref()?;
+            send_markdown(&context.client, webhook_url, &context.api_key, content).await
+        }
+    }
+
+    /// 在后台启动工作流
+    fn start(self) -> JoinHandle<()> {
+        task::spawn(run_workflow(self))
+    }
+}
+
+async fn run_workflow<W: Workflow>(workflow: W) {
+    let context = workflow.context();
+    // 取消时停止排队、模型服务请求和之后的步骤，不发送结果，任务记录已经标记为取消
+    tokio::select! {
+        _ = context.cancel.cancelled() => {
+            info!("🛑 {}任务已取消: {}", workflow.name(), context.job_id);
+        }
+        _ = run_steps(&workflow) => {}
+    }
+    JOBS.finish_cancellation(&context.job_id);
+}
+
+async fn run_steps<W: Workflow>(workflow: &W) {
+    let context = workflow.context();
+    let name = workflow.name();
+    // 只读模式下回复维护提示，恢复读写后再开始，暂停的时间不计入耗时
+    if MAINTENANCE.is_read_only() {
+        info!("只读模式，{}任务暂停: {}", name, context.job_id);
+        context.progress.on_stage(JobStage::Paused);
+        if let Some(webhook_url) = &context.webhook_url {
+            let message = &CONFIG.queue.maintenance_message;
+            send_markdown(&context.client, webhook_url, &context.api_key, message).await;
+        }
+        MAINTENANCE.wait_writable().await;
+    }
+    let started = Instant::now();
+    info!("开始{}任务: {}", name, context.job_id);
+
+    // 排队期间不计入执行，也不发送进度提醒
+    let _permit = if workflow.queued() {
+        context.progress.on_stage(JobStage::Queued);
+        Some(wait_in_queue(context).await)
+    } else {
+        None
+    };
+
+    let mut attempts = 0;
+    let pipeline = async {
+        context.progress.on_stage(JobStage::Preparing);
+        let input = workflow.prepare().await?;
+        context.progress.on_stage(JobStage::Executing);
+        loop {
+            attempts += 1;
+            match workflow.execute(&input).await {
+                Ok(output) => return Ok(output),
+                Err(e) if attempts <= workflow.retries() => {
+                    warn!("⚠️ {}第{}次执行失败，正在重试: {}", name, attempts, e);
+                }
+                Err(e) => return Err(e),
             }
-            Err(error_msg) => {
-                error!("❌ 分析失败: {}", error_msg);
-                self.send_response(&format!("❌ 分析失败: {}", error_msg))
-                    .await;
+        }
+    };
+    // 执行和进度提醒在同一个任务中进行，失败时提醒立即停止
+    let result = match (workflow.progress(), &context.webhook_url) {
+        (Some(config), Some(webhook_url)) => {
+            ProgressNotifier::new(
+                config,
+                context.client.clone(),
+                webhook_url.clone(),
+                context.api_key.clone(),
+                context.progress.clone(),
+            )
+            .supervise(pipeline)
+            .await
+        }
+        _ => pipeline.await,
+    };
+    let elapsed = started.elapsed();
+
+    match result {
+        Ok(output) => {
+            info!("✅ {}完成，用时 {:?}，发送结果", name, elapsed);
+            context.progress.on_stage(JobStage::Reporting);
+            let content = with_feedback_hint(&workflow.render(&output), &context.job_id);
+            if let Err(e) = JOBS.save_report(&context.job_id, &content) {
+                warn!("保存报告失败 {}: {}", context.job_id, e);
             }
+            let result_mid = workflow.deliver(&content).await;
+            finish_job(&context.job_id, result_mid, None, elapsed, attempts);
+        }
+        Err(error_msg) => {
+            error!("❌ {}失败: {}", name, error_msg);
+            workflow
+                .deliver(&format!(
+                    "❌ {}失败(用时 {}): {}",
+                    name,
+                    fmt_elapsed(elapsed),
+                    error_msg
+                ))
+                .await;
+            finish_job(&context.job_id, None, Some(error_msg), elapsed, attempts);
         }
     }
+}
+
+/// 等待执行许可，需要排队时发送排队位置并在位置变化时编辑这条消息
+async fn wait_in_queue(context: &WorkflowContext) -> SemaphorePermit<'static> {
+    if let Some(permit) = QUEUE.try_acquire() {
+        return permit;
+    }
 
-    /// 执行分析逻辑
-    async fn perform_analysis(&self) -> Result<String, String> {
+    let config = &CONFIG.queue;
+    let ticket = QUEUE.enter();
+    let message = |position: usize| config.message.replace("{position}", &position.to_string());
+    let mut position = ticket.position();
+    info!("任务 {} 排在第 {} 位", context.job_id, position);
+    let ack_mid = match &context.webhook_url {
+        Some(webhook_url) => {
+            send_markdown(
+                &context.client,
+                webhook_url,
+                &context.api_key,
+                &message(position),
+            )
+            .await
+        }
+        None => None,
+    };
+    let edit = |mid: u64, content: String| {
+        let edit_url = CONFIG
+            .bot
+            .url(&config.edit_path.replace("{mid}", &mid.to_string()));
+        async move { edit_markdown(&context.client, &edit_url, &context.api_key, &content).await }
+    };
+
+    let interval = Duration::from_secs(config.update_interval_seconds.max(1));
+    let acquire = ticket.acquire();
+    tokio::pin!(acquire);
+    let permit = loop {
+        tokio::select! {
+            biased;
+            permit = &mut acquire => break permit,
+            _ = tokio::time::sleep(interval) => {
+                let current = ticket.position();
+                if current != position {
+                    position = current;
+                    if let Some(mid) = ack_mid {
+                        edit(mid, message(position)).await;
+                    }
+                }
+            }
+        }
+    };
+    if let Some(mid) = ack_mid {
+        edit(mid, config.started_message.clone()).await;
+    }
+    permit
+}
+
+/// PDF 分析工作流
+pub struct PdfAnalysisWorkflow {
+    context: WorkflowContext,
+    pdf_path: PathBuf,
+}
+
+/// 没有识别出模具类型和材料时附上的缩略图页数
+const UNIDENTIFIED_THUMBNAILS: usize = 3;
+
+/// 转换好的图片和可用的分析器
+pub struct PdfAnalysisInput {
+    images_dir: PathBuf,
+    analyzer: AiTextAnalyzer,
+}
+
+/// PDF分析的结果
+pub enum PdfAnalysisOutput {
+    Matches {
+        results: Vec<DiffResult>,
+        /// 尺寸按比例换算后超出图幅，提醒用户核对提取结果
+        scale_conflict: Option<ScaleConflict>,
+    },
+    /// 没有识别出模具类型和材料，不进行比较，附上页面缩略图的链接
+    Unidentified(Vec<String>),
+}
+
+impl PdfAnalysisWorkflow {
+    pub fn new(context: WorkflowContext, pdf_path: PathBuf) -> Self {
+        Self { context, pdf_path }
+    }
+
+    /// 前几页的缩略图链接，缩略图保存在上传目录的`thumbnails/<任务id>/`中，
+    /// 在阻塞线程中生成
+    async fn page_thumbnails(&self, images_dir: &Path) -> Vec<String> {
+        let job_id = self.job_id().to_string();
+        let images_dir = images_dir.to_path_buf();
+        task::spawn_blocking(move || {
+            let mut urls = Vec::new();
+            for page in 0..UNIDENTIFIED_THUMBNAILS {
+                let path = format!("thumbnails/{}/page_{:03}", job_id, page + 1);
+                if let Err(e) = ensure_preview(&upload_dir().join(&path), &images_dir, page) {
+                    if page == 0 {
+                        warn!("生成缩略图失败 {}: {}", job_id, e);
+                    }
+                    break;
+                }
+                urls.push(IMAGE_URLS.image_url(&path));
+            }
+            urls
+        })
+        .await
+        .unwrap_or_default()
+    }
+}
+
+impl Workflow for PdfAnalysisWorkflow {
+    type Input = PdfAnalysisInput;
+    type Output = PdfAnalysisOutput;
+
+    fn name(&self) -> &'static str {
+        "分析"
+    }
+
+    fn context(&self) -> &WorkflowContext {
+        &self.context
+    }
+
+    fn progress(&self) -> Option<ProgressConfig> {
+        Some(CONFIG.progress.clone())
+    }
+
+    fn queued(&self) -> bool {
+        true
+    }
+
+    fn retries(&self) -> u32 {
+        1
+    }
+
+    async fn prepare(&self) -> Result<PdfAnalysisInput, String> {
         // 1. 转换 PDF 为图片
-        info!("📄 正在转换 PDF 为图片...");
-        let output_path =
-            convert_to_image(&self.pdf_path).map_err(|e| format!("PDF 转换失败: {}", e))?;
+        info!("📄 正在转换 PDF 为图片: {}", self.pdf_path.display());
+        let work_dir = JOBS
+            .create_work_dir(self.job_id())
+            .map_err(|e| format!("创建工作目录失败: {}", e))?;
+        let images_dir = convert_to_image(&self.pdf_path, &work_dir.join(PAGES_DIR))
+            .map_err(|e| format!("PDF 转换失败: {}", e))?;
+        if let Some(manifest) = PageManifest::load(&images_dir) {
+            let pages = manifest.pages.len();
+            JOB_EVENTS.publish(self.job_id(), JobProgress::Converted { pages });
+        }
 
         // 2. 初始化 AI 分析器
         info!("🤖 正在初始化 AI 分析器...");
-        let analyzer = AiTextAnalyzer::new(AiConfig::default());
+        let mut analyzer = AiTextAnalyzer::new(CONFIG.ai.clone(), self.context.client.clone())
+            .with_blank_page(CONFIG.blank_page)
+            .with_progress(self.context.progress.clone())
+            .with_cancellation(self.context.cancel.clone());
+        if let Some(sampling) = self.context.sampling {
+            analyzer = analyzer.with_sampling(sampling);
+        }
+        let archive_config = &CONFIG.archive;
+        if archive_config.enabled {
+            analyzer = analyzer.with_archive(ResponseArchive::new(work_dir.join(RESPONSES_DIR)));
+            if let Err(e) = response_archive::rotate(&jobs_dir(), archive_config) {
+                warn!("轮转回复存档失败: {}", e);
+            }
+        }
         analyzer
             .verify_api_availability()
             .map_err(|e| format!("AI 分析器初始化失败: {}", e))?;
+        JOBS.update(self.job_id(), |job| {
+            job.prompt_version = Some(TEXT_EXTRACT_PROMPT_VERSION.to_string());
+            job.model_name = analyzer.model_name().map(|m| m.to_string());
+            job.images_dir = Some(images_dir.clone());
+        });
 
+        Ok(PdfAnalysisInput {
+            images_dir,
+            analyzer,
+        })
+    }
+
+    async fn execute(&self, input: &PdfAnalysisInput) -> Result<PdfAnalysisOutput, String> {
         // 3. 提取文本信息
         info!("🔍 正在提取文本信息...");
-        let extraction_result = analyzer
-            .extract_text_from_folder(&output_path)
+        let extraction = input
+            .analyzer
+            .extract_pages_from_folder(&input.images_dir)
             .await
             .map_err(|e| format!("文本提取失败: {}", e))?;
+        if let Err(e) = JOBS.save_extraction(self.job_id(), &extraction) {
+            error!("保存提取结果失败: {}", e);
+        }
+        // 重试时累计每次执行的用量
+        JOBS.update(self.job_id(), |job| {
+            *job.usage.get_or_insert_default() += extraction.usage;
+        });
+        let extraction_result = extraction.merged;
 
         // 4. 检查提取结果
         if let Some(error) = &extraction_result.error {
@@ -80,52 +445,394 @@ impl PdfAnalysisWorkflow {
 
         // 5. 转换为 ModelJson 并进行相似度比较
         info!("📊 正在进行相似度比较...");
-        let model_json = ModelJson::from(extraction_result);
-
-        let sorted_models = MODELS.clone();
-        let mut diff_results = ModelJson::diff(sorted_models, model_json);
-        DiffResult::sort(&mut diff_results);
-        let response_text = fmt_diff_result_to_md(&diff_results);
+        let mut model_json = ModelJson::from(extraction_result);
+        model_json.original_pdf = Some(self.pdf_path.clone());
+        model_json.extraction_timestamp = Some(chrono::Local::now().to_rfc3339());
+        model_json.provenance = extraction.provider.map(|provider| Provenance {
+            provider: provider.provider,
+            model_name: provider.model_name,
+            prompt_version: extraction.prompt_version,
+        });
+        record_unrecognized_terms(&model_json);
+        JOBS.update(self.job_id(), |job| {
+            job.extraction = Some(model_json.clone());
+        });
+        // 和所有模具比较只会得到噪声，交给人工查看图纸
+        if model_json.is_unidentified() {
+            info!("未识别出模具类型和材料，跳过比较: {}", self.job_id());
+            return Ok(PdfAnalysisOutput::Unidentified(
+                self.page_thumbnails(&input.images_dir).await,
+            ));
+        }
 
-        info!("✅ 分析完成");
-        Ok(response_text)
+        let scale_conflict = model_json.scale_conflict();
+        if let Some(conflict) = &scale_conflict {
+            warn!("⚠️ {}: {}", self.job_id(), conflict.message);
+        }
+        JOB_EVENTS.publish(self.job_id(), JobProgress::Diffing);
+        let diff_results = MODELS.load().compare(
+            model_json,
+            &CONFIG.diff.default_profile(),
+            CONFIG.diff.include_scrapped,
+        );
+        record_matches(self.job_id(), &diff_results);
+        generate_previews(&diff_results).await;
+        if let Some(job) = JOBS.get(self.job_id()) {
+            duplicate_alert::notify(&CONFIG.duplicate_alert, &job);
+        }
+        Ok(PdfAnalysisOutput::Matches {
+            results: diff_results,
+            scale_conflict,
+        })
     }
 
-    /// 发送响应到 webhook
-    async fn send_response(&self, content: &str) {
-        let client = reqwest::Client::new();
-
-        match client
-            .post(&self.webhook_url)
-            .header("content-type", "text/markdown")
-            .header("x-api-key", &self.api_key)
-            .body(content.to_string())
-            .send()
-            .await
-        {
-            Ok(response) => {
-                if response.status().is_success() {
-                    info!("✅ 结果已成功发送到 webhook");
+    fn render(&self, output: &PdfAnalysisOutput) -> String {
+        match output {
+            PdfAnalysisOutput::Matches {
+                results,
+                scale_conflict,
+            } => {
+                let report = fmt_diff_result_to_md(
+                    results,
+                    self.context.layout,
+                    self.context.tenant.as_deref(),
+                );
+                // 疑似重复和尺寸异常的提醒放在最前面
+                let warnings: Vec<String> = [
+                    duplicate_alert::warning(&CONFIG.duplicate_alert, results),
+                    scale_conflict
+                        .as_ref()
+                        .map(|conflict| format!("⚠️ {}", conflict.message)),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                if warnings.is_empty() {
+                    report
                 } else {
-                    warn!("⚠️ Webhook 响应状态: {}", response.status());
+                    format!("{}\n\n{}", warnings.join("\n"), report)
                 }
             }
-            Err(e) => {
-                error!("❌ 发送 webhook 失败: {}", e);
+            PdfAnalysisOutput::Unidentified(thumbnails) => fmt_unidentified_to_md(thumbnails),
+        }
+    }
+}
+
+/// 文本检索工作流
+pub struct SearchWorkflow {
+    context: WorkflowContext,
+    query: UserQuery,
+}
+
+impl SearchWorkflow {
+    pub fn new(context: WorkflowContext, query: UserQuery) -> Self {
+        Self { context, query }
+    }
+}
+
+impl Workflow for SearchWorkflow {
+    type Input = ScoringProfile;
+    type Output = Vec<DiffResult>;
+
+    fn name(&self) -> &'static str {
+        "检索"
+    }
+
+    fn context(&self) -> &WorkflowContext {
+        &self.context
+    }
+
+    /// 检查检索条件中的评分方案
+    async fn prepare(&self) -> Result<ScoringProfile, String> {
+        let config = &CONFIG.diff;
+        config
+            .profile(self.query.profile.as_deref())
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "未知的评分方案 `{}`，可选: {}",
+                    self.query.profile.as_deref().unwrap_or_default(),
+                    config.profile_names()
+                )
+            })
+    }
+
+    async fn execute(&self, profile: &ScoringProfile) -> Result<Vec<DiffResult>, String> {
+        info!("开始后台检索: {:?}", self.query);
+        let store = MODELS.load();
+        let results = store.search_with(&self.query, profile, CONFIG.diff.include_scrapped);
+        record_matches(self.job_id(), &results);
+        generate_previews(&results).await;
+        Ok(results)
+    }
+
+    fn render(&self, output: &Vec<DiffResult>) -> String {
+        fmt_search_result_to_md(
+            &self.query,
+            output,
+            self.context.layout,
+            self.context.tenant.as_deref(),
+        )
+    }
+}
+
+/// 记录词表尚未覆盖的模具类型和材料
+fn record_unrecognized_terms(model: &ModelJson) {
+    let taxonomy = TAXONOMY.read().unwrap();
+    let mut terms = UNRECOGNIZED.lock().unwrap();
+    if terms.record(&taxonomy, model)
+        && let Err(e) = terms.save(&taxonomy_dir())
+    {
+        warn!("保存待归类词条失败: {}", e);
+    }
+}
+
+/// 在结果末尾附上模型用量、任务id和反馈方式
+fn with_feedback_hint(content: &str, job_id: &str) -> String {
+    let short_id = truncate_chars(job_id, 8);
+    let mut content = content.to_string();
+    if let Some(usage) = JOBS.get(job_id).and_then(|job| job.usage) {
+        let currency = CONFIG
+            .ai
+            .api
+            .as_ref()
+            .map_or("元", |api| &api.pricing.currency);
+        content.push_str(&format!("\n\n> 🪙 {}", usage.summary(currency)));
+    }
+    format!(
+        "{}\n\n> 任务 `{}`，对结果点 👍/👎 或发送 `/feedback {} good|bad 备注` 评价结果",
+        content, short_id, short_id
+    )
+}
+
+/// 生成报告中列出的结果缺失的预览图，`render`时预览图已经存在，不再渲染PDF页面
+async fn generate_previews(results: &[DiffResult]) {
+    let config = &CONFIG.report;
+    let shown = results.iter().take(config.max_results).cloned().collect();
+    DiffResult::with_previews(MODELS.load_full(), shown, config.preview_pages).await;
+}
+
+/// 在任务上记录报告中列出的匹配结果，用于比较不同任务的结果
+fn record_matches(job_id: &str, results: &[DiffResult]) {
+    let max_results = CONFIG.report.max_results;
+    JOBS.update(job_id, |job| {
+        job.matches = results.iter().take(max_results).cloned().collect();
+    });
+}
+
+/// 更新任务的最终状态和耗时
+fn finish_job(
+    job_id: &str,
+    result_mid: Option<u64>,
+    error: Option<String>,
+    elapsed: Duration,
+    attempts: u32,
+) {
+    let job = JOBS.update(job_id, |job| {
+        job.duration_ms = Some(elapsed.as_millis() as u64);
+        job.attempts = attempts;
+        job.status = if error.is_some() {
+            JobStatus::Failed
+        } else {
+            JobStatus::Succeeded
+        };
+        job.result_mid = result_mid;
+        job.error = error;
+    });
+    if let Some(job) = job {
+        JOB_EVENTS.publish(job_id, JobProgress::finished(&job));
+    }
+}
+
+/// 发送markdown消息到 webhook，返回发送的消息mid
+pub async fn send_markdown(
+    client: &reqwest::Client,
+    webhook_url: &str,
+    api_key: &str,
+    content: &str,
+) -> Option<u64> {
+    match client
+        .post(webhook_url)
+        .header("content-type", "text/markdown")
+        .header("x-api-key", api_key)
+        .body(content.to_string())
+        .send()
+        .await
+    {
+        Ok(response) => {
+            if response.status().is_success() {
+                info!("✅ 结果已成功发送到 webhook");
+                // bot接口返回发送的消息mid
+                response.text().await.ok()?.trim().parse().ok()
+            } else {
+                warn!("⚠️ Webhook 响应状态: {}", response.status());
+                None
             }
         }
+        Err(e) => {
+            error!("❌ 发送 webhook 失败: {}", e);
+            None
+        }
     }
 }
 
+/// 修改已发送的markdown消息，返回是否成功
+pub async fn edit_markdown(
+    client: &reqwest::Client,
+    edit_url: &str,
+    api_key: &str,
+    content: &str,
+) -> bool {
+    match client
+        .put(edit_url)
+        .header("content-type", "text/markdown")
+        .header("x-api-key", api_key)
+        .body(content.to_string())
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            warn!("⚠️ 编辑消息响应状态: {}", response.status());
+            false
+        }
+        Err(e) => {
+            error!("❌ 编辑消息失败: {}", e);
+            false
+        }
+    }
+}
+
+/// 回复给发送消息用户的 bot 接口地址和 api key
+pub fn bot_endpoint(req: &WebhookRequest) -> (String, String) {
+    let config = &CONFIG.bot;
+    let webhook_url = config.url(&format!("/api/bot/send_to_user/{}", req.from_uid()));
+    (webhook_url, config.api_key.clone())
+}
+
 /// 创建并启动 PDF 分析工作流
-pub fn create_pdf_analysis_workflow(
-    pdf_path: PathBuf,
-    req: &WebhookRequest,
-) -> PdfAnalysisWorkflow {
-    let webhook_url = format!(
-        "https://huateng.voce.chat/api/bot/send_to_user/{}",
-        req.from_uid
+pub fn create_pdf_analysis_workflow(pdf: Blob, req: &WebhookRequest) -> PdfAnalysisWorkflow {
+    let mut job = JobRecord::new(
+        JobKind::Pdf,
+        req.mid(),
+        req.from_uid(),
+        pdf.path.display().to_string(),
     );
-    let api_key = "013b93273ce0dc707e4d55a214f0b54a63bde7fe7dc803b4eda52b3bc828975a7b22756964223a322c226e6f6e6365223a223661432f436558557032674141414141646e4b666f2f76412b64774b4b455465227d".to_string();
-    PdfAnalysisWorkflow::new(pdf_path, webhook_url, api_key)
+    job.content_hash = Some(pdf.hash);
+    let job_id = JOBS.create(job);
+    PdfAnalysisWorkflow::new(WorkflowContext::new(job_id, req), pdf.path)
+}
+
+/// 创建通过接口提交的 PDF 分析工作流，不回复消息，结果通过任务接口查询
+pub fn create_detached_analysis_workflow(pdf: Blob, file_name: &str) -> PdfAnalysisWorkflow {
+    let mut job = JobRecord::new(JobKind::Pdf, 0, 0, file_name.to_string());
+    job.content_hash = Some(pdf.hash);
+    let job_id = JOBS.create(job);
+    PdfAnalysisWorkflow::new(WorkflowContext::detached(job_id), pdf.path)
+}
+
+/// 创建文本检索工作流
+pub fn create_search_workflow(query: UserQuery, mid: u64, req: &WebhookRequest) -> SearchWorkflow {
+    let input = serde_json::to_string(&query).unwrap_or_default();
+    let job_id = JOBS.create(JobRecord::new(JobKind::Search, mid, req.from_uid(), input));
+    SearchWorkflow::new(WorkflowContext::new(job_id, req), query)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+
+    /// 前`failures`次执行失败的工作流，发送的消息记录在`sent`中
+    struct FlakyWorkflow {
+        context: WorkflowContext,
+        failures: u32,
+        calls: AtomicU32,
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl FlakyWorkflow {
+        fn new(failures: u32) -> Self {
+            Self {
+                context: WorkflowContext {
+                    job_id: "flaky".to_string(),
+                    client: reqwest::Client::new(),
+                    webhook_url: None,
+                    api_key: String::new(),
+                    layout: ReportLayout::Table,
+                    tenant: None,
+                    sampling: None,
+                    progress: Arc::new(JobSink::new("flaky".to_string())),
+                    cancel: CancellationToken::new(),
+                },
+                failures,
+                calls: AtomicU32::new(0),
+                sent: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Workflow for &'static FlakyWorkflow {
+        type Input = ();
+        type Output = u32;
+
+        fn name(&self) -> &'static str {
+            "测试"
+        }
+
+        fn context(&self) -> &WorkflowContext {
+            &self.context
+        }
+
+        fn retries(&self) -> u32 {
+            1
+        }
+
+        async fn prepare(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn execute(&self, _input: &()) -> Result<u32, String> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if call <= self.failures {
+                Err(format!("第{}次失败", call))
+            } else {
+                Ok(call)
+            }
+        }
+
+        fn render(&self, output: &u32) -> String {
+            format!("第{}次成功", output)
+        }
+
+        async fn deliver(&self, content: &str) -> Option<u64> {
+            self.sent.lock().unwrap().push(content.to_string());
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_then_deliver() {
+        let workflow: &'static FlakyWorkflow = Box::leak(Box::new(FlakyWorkflow::new(1)));
+        workflow.start().await.unwrap();
+        assert_eq!(workflow.calls.load(Ordering::SeqCst), 2);
+        let sent = workflow.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("第2次成功"));
+    }
+
+    #[tokio::test]
+    async fn report_failure_after_retries() {
+        let workflow: &'static FlakyWorkflow = Box::leak(Box::new(FlakyWorkflow::new(2)));
+        workflow.start().await.unwrap();
+        assert_eq!(workflow.calls.load(Ordering::SeqCst), 2);
+        let sent = workflow.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("❌ 测试失败"));
+        assert!(sent[0].ends_with("第2次失败"));
+    }
 }
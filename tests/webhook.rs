@@ -0,0 +1,601 @@
+//! 启动完整路由，模拟VoceChat发送webhook，检查机器人发出的消息
+//!
+//! VoceChat机器人接口和模型接口都由本地的桩服务代替，数据目录使用临时目录。
+//! 桩服务和被测服务只启动一次，由所有测试共用，见`setup`。
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use material_rs::router;
+use salvo::{
+    conn::{Acceptor, TcpListener},
+    prelude::*,
+    test::{ResponseExt, TestClient},
+};
+use serde_json::{Value, json};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+/// 测试使用的admin密钥
+const API_KEY: &str = "Bearer k-it";
+
+/// 桩服务收到的机器人消息: (路径, 内容)
+static SENT: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+const EXTRACTION: &str = r#"```json
+{"model_type": "基座", "materials": ["PBT RG301"], "project_name": "ME121", "company": null, "text_content": []}
+```"#;
+
+#[handler]
+async fn bot_send(req: &mut Request, res: &mut Response) {
+    let body = req
+        .payload()
+        .await
+        .map(|body| String::from_utf8_lossy(body).to_string())
+        .unwrap_or_default();
+    SENT.lock()
+        .unwrap()
+        .push((req.uri().path().to_string(), body));
+    // bot接口返回消息mid
+    res.render("1001");
+}
+
+#[handler]
+async fn chat_completions(res: &mut Response) {
+    res.render(Json(json!({
+        "choices": [{ "message": { "content": EXTRACTION } }]
+    })));
+}
+
+/// 在单独的线程中启动桩服务，返回地址。每个测试有自己的运行时，
+/// 桩服务不能随第一个测试的运行时一起结束
+fn start_stub() -> String {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+            let addr = acceptor.holdings()[0].local_addr.clone();
+            sender
+                .send(format!("http://{}", addr.into_std().unwrap()))
+                .unwrap();
+            let router = Router::new()
+                .push(Router::with_path("api/bot/{**rest}").post(bot_send))
+                .push(Router::with_path("v1/chat/completions").post(chat_completions));
+            Server::new(acceptor).serve(router).await;
+        });
+    });
+    receiver.recv().unwrap()
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        } else {
+            std::fs::copy(&path, target).unwrap();
+        }
+    }
+}
+
+fn message(from_uid: u64, mid: u64, detail: Value) -> Value {
+    json!({
+        "created_at": 1754560852630_i64,
+        "detail": detail,
+        "domain": null,
+        "from_uid": from_uid,
+        "mid": mid,
+        "target": { "uid": 2 },
+        "type": "chat",
+        "widget_id": null
+    })
+}
+
+/// 等待发给用户`uid`的消息
+async fn wait_for_message(uid: u64) -> String {
+    let path = format!("/api/bot/send_to_user/{}", uid);
+    let started = Instant::now();
+    loop {
+        if let Some((_, body)) = SENT.lock().unwrap().iter().find(|(p, _)| *p == path) {
+            return body.clone();
+        }
+        assert!(
+            started.elapsed() < Duration::from_secs(60),
+            "没有收到发给用户{}的消息",
+            uid
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// 所有测试共用的桩服务、被测服务和数据目录
+struct Shared {
+    service: Service,
+    data_dir: PathBuf,
+    upload_dir: PathBuf,
+}
+
+/// 调用`setup`的测试数量，最后一个结束的测试删除临时数据目录
+const TESTS: usize = 12;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+/// 一个测试持有的共享环境，测试结束(包括失败)时计数
+struct Env(&'static Shared);
+
+impl std::ops::Deref for Env {
+    type Target = Shared;
+
+    fn deref(&self) -> &Shared {
+        self.0
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        if FINISHED.fetch_add(1, Ordering::SeqCst) + 1 == TESTS {
+            let _ = std::fs::remove_dir_all(&self.0.data_dir);
+        }
+    }
+}
+
+/// 第一次调用时准备数据目录、启动桩服务并设置环境变量，之后返回同一个环境
+fn setup() -> Env {
+    static SHARED: OnceLock<Shared> = OnceLock::new();
+    Env(SHARED.get_or_init(|| {
+        let stub = start_stub();
+        let data_dir = std::env::temp_dir().join(format!("material_it_{}", std::process::id()));
+        let upload_dir = data_dir.join("upload").join("file");
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        copy_dir(
+            &fixtures.join("models/jsons"),
+            &upload_dir.join("models/jsons"),
+        );
+        std::fs::create_dir_all(upload_dir.join("2025/8/7")).unwrap();
+        std::fs::copy(
+            fixtures.join("pdfs/03-jz.pdf"),
+            upload_dir.join("2025/8/7/03-jz"),
+        )
+        .unwrap();
+        // 连接不在测试之间复用，结束的测试的运行时会关闭它创建的连接
+        let config = data_dir.join("material.toml");
+        std::fs::write(&config, "[http]\npool_idle_timeout_seconds = 0\n").unwrap();
+        // SAFETY: 在访问任何全局配置之前设置，其他测试在`SHARED`初始化完成前等待，不读取环境变量
+        unsafe {
+            std::env::set_var("MATERIAL_CONFIG", &config);
+            std::env::set_var("MATERIAL_DATA_DIR", &data_dir);
+            std::env::set_var("MATERIAL_VOCECHAT_URL", &stub);
+            std::env::set_var("MATERIAL_AI_ENDPOINT", format!("{}/v1", stub));
+            std::env::set_var("MATERIAL_API_KEYS", "it:admin:k-it");
+            std::env::set_var("MATERIAL_URL_SECRET", "it-secret");
+        }
+        Shared {
+            service: Service::new(router::build()),
+            data_dir,
+            upload_dir,
+        }
+    }))
+}
+
+/// 带admin密钥的GET请求
+async fn get(service: &Service, url: &str) -> salvo::Response {
+    TestClient::get(url)
+        .add_header("authorization", API_KEY, true)
+        .send(service)
+        .await
+}
+
+fn has_poppler() -> bool {
+    std::process::Command::new("pdftoppm")
+        .arg("-v")
+        .output()
+        .is_ok()
+}
+
+/// 上传测试PDF的消息
+fn pdf_upload(from_uid: u64, mid: u64) -> Value {
+    message(
+        from_uid,
+        mid,
+        json!({
+            "content": "2025/8/7/03-jz",
+            "content_type": "vocechat/file",
+            "properties": { "content_type": "application/pdf", "name": "03-jz.pdf", "size": 1 },
+            "type": "normal"
+        }),
+    )
+}
+
+/// 已经结束的任务
+fn finished_job(
+    kind: material_rs::job::JobKind,
+    from_uid: u64,
+    name: &str,
+) -> material_rs::job::JobRecord {
+    let mut record = material_rs::job::JobRecord::new(kind, from_uid, from_uid, name.to_string());
+    record.status = material_rs::job::JobStatus::Succeeded;
+    record
+}
+
+async fn post_webhook(service: &Service, body: &Value) -> Value {
+    TestClient::post("http://127.0.0.1:5800/material/webhook")
+        .json(body)
+        .send(service)
+        .await
+        .take_json::<Value>()
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_body() {
+    let env = setup();
+    // 无效的请求体在兼容模式下仍然回复200，避免VoceChat重复投递
+    let mut invalid = TestClient::post("http://127.0.0.1:5800/material/webhook")
+        .raw_json("{\"mid\": ")
+        .send(&env.service)
+        .await;
+    assert_eq!(invalid.status_code, Some(StatusCode::OK));
+    let invalid = invalid.take_json::<Value>().await.unwrap();
+    assert!(
+        invalid["message"]
+            .as_str()
+            .unwrap()
+            .contains("无效的请求格式")
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn text_search() {
+    let env = setup();
+    // 文本消息作为检索条件
+    let text = message(
+        1,
+        1,
+        json!({ "content": "- 类型: 基座", "content_type": "text/plain", "type": "normal" }),
+    );
+    let response = post_webhook(&env.service, &text).await;
+    assert_eq!(response["message"], "🔍 正在检索，请稍等...");
+    let result = wait_for_message(1).await;
+    assert!(result.contains("类型: `基座` 的检索结果如下"), "{}", result);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn search_api() {
+    let env = setup();
+    // 同样的条件通过JSON接口检索
+    let mut search = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/search?model_type=%E5%9F%BA%E5%BA%A7&limit=1",
+    )
+    .await;
+    assert_eq!(search.status_code, Some(StatusCode::OK));
+    assert!(search.headers().get("deprecation").is_none());
+    let search = search.take_json::<Value>().await.unwrap();
+    assert_eq!(search["data"].as_array().unwrap().len(), 1);
+    assert_eq!(search["data"][0]["model_type"], "基座");
+    // 测试数据的模具目录中没有页面图片，生成不了预览图
+    assert!(search["data"][0]["pages"].as_array().unwrap().is_empty());
+
+    let search = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/search?limit=1",
+    )
+    .await;
+    assert_eq!(search.status_code, Some(StatusCode::BAD_REQUEST));
+    // 旧路径仍然可用，标记为弃用
+    assert_eq!(search.headers()["deprecation"], "true");
+    assert_eq!(
+        search.headers()["link"],
+        "</material/api/v1/search>; rel=\"successor-version\""
+    );
+    let mut suggest = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/suggest?q=%E5%9F%BA&kind=model_type",
+    )
+    .await;
+    let suggest = suggest.take_json::<Value>().await.unwrap();
+    assert_eq!(suggest["data"][0]["kind"], "model_type");
+    assert_eq!(suggest["data"][0]["text"], "基座");
+    let suggest = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/suggest?q=PBT&kind=color",
+    )
+    .await;
+    assert_eq!(suggest.status_code, Some(StatusCode::BAD_REQUEST));
+
+    // 直接上传需要multipart的file字段
+    let analyze = TestClient::post("http://127.0.0.1:5800/material/api/v1/analyze")
+        .json(&json!({}))
+        .add_header("authorization", API_KEY, true)
+        .send(&env.service)
+        .await;
+    assert_eq!(analyze.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn signed_links() {
+    let env = setup();
+    // 签名链接中的路径按段转义，路由解码后校验签名
+    let image_dir = env.upload_dir.join("models/imgs/ME121 基座#2");
+    std::fs::create_dir_all(&image_dir).unwrap();
+    std::fs::write(image_dir.join("ME121 基座#2_page_001.png"), b"png").unwrap();
+    let url =
+        material_rs::IMAGE_URLS.image_url("models/imgs/ME121 基座#2/ME121 基座#2_page_001.png");
+    assert!(url.contains("/ME121%20%E5%9F%BA%E5%BA%A7%232/"), "{}", url);
+    let mut image = TestClient::get(&url).send(&env.service).await;
+    assert_eq!(image.status_code, Some(StatusCode::OK));
+    assert_eq!(image.take_string().await.unwrap(), "png");
+    let tampered = TestClient::get(url.replace("%232", "%233"))
+        .send(&env.service)
+        .await;
+    assert_eq!(tampered.status_code, Some(StatusCode::FORBIDDEN));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn taxonomy() {
+    let env = setup();
+    // 同时添加的牌号都保存在词表中
+    let add = |grade: &str| {
+        TestClient::post("http://127.0.0.1:5800/material/api/v1/taxonomy/materials")
+            .add_header("authorization", API_KEY, true)
+            .json(&json!({ "family": "PBT", "grade": grade }))
+            .send(&env.service)
+    };
+    let (first, second) = tokio::join!(add("IT-301"), add("IT-302"));
+    assert_eq!(first.status_code, Some(StatusCode::OK));
+    assert_eq!(second.status_code, Some(StatusCode::OK));
+    let mut materials = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/taxonomy/materials",
+    )
+    .await;
+    let materials = materials.take_string().await.unwrap();
+    assert!(materials.contains("IT-301") && materials.contains("IT-302"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admin() {
+    let env = setup();
+    // 管理接口需要密钥
+    let reindex = TestClient::post("http://127.0.0.1:5800/material/api/v1/admin/reindex")
+        .send(&env.service)
+        .await;
+    assert_eq!(reindex.status_code, Some(StatusCode::UNAUTHORIZED));
+    let mut reindex = TestClient::post("http://127.0.0.1:5800/material/api/v1/admin/reindex")
+        .add_header("authorization", API_KEY, true)
+        .send(&env.service)
+        .await;
+    assert_eq!(reindex.status_code, Some(StatusCode::OK));
+    let reindex = reindex.take_json::<Value>().await.unwrap();
+    assert_eq!(reindex["data"]["failed"], 0);
+
+    // 管理页面和它使用的接口
+    let mut page = TestClient::get("http://127.0.0.1:5800/material/admin")
+        .send(&env.service)
+        .await;
+    assert_eq!(page.status_code, Some(StatusCode::OK));
+    assert!(page.take_string().await.unwrap().contains("admin.js"));
+    let script = TestClient::get("http://127.0.0.1:5800/material/admin/admin.js")
+        .send(&env.service)
+        .await;
+    assert_eq!(
+        script.headers().get("content-type").unwrap(),
+        "text/javascript; charset=utf-8"
+    );
+    let missing = TestClient::get("http://127.0.0.1:5800/material/admin/missing.js")
+        .send(&env.service)
+        .await;
+    assert_eq!(missing.status_code, Some(StatusCode::NOT_FOUND));
+    let mut corpus = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/admin/corpus",
+    )
+    .await;
+    let corpus = corpus.take_json::<Value>().await.unwrap();
+    assert_eq!(corpus["data"]["models"], reindex["data"]["loaded"]);
+    let mut jobs = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/admin/jobs",
+    )
+    .await;
+    let jobs = jobs.take_json::<Value>().await.unwrap();
+    assert!(jobs["data"]["queue"]["capacity"].as_u64().unwrap() >= 1);
+    let mut config = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/admin/config",
+    )
+    .await;
+    let config = config.take_json::<Value>().await.unwrap();
+    // 只返回密钥是否已配置
+    assert!(config["data"]["secrets"]["bot_api_key"].is_boolean());
+    let mut snapshots = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/admin/snapshots/diff?from=2000-01-01",
+    )
+    .await;
+    assert_eq!(snapshots.status_code, Some(StatusCode::NOT_FOUND));
+    let snapshots = snapshots.take_json::<Value>().await.unwrap();
+    assert_eq!(snapshots["code"], "snapshot_not_found");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn job_result() {
+    let env = setup();
+    let mut job = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/jobs/0123456789/result",
+    )
+    .await;
+    assert_eq!(job.status_code, Some(StatusCode::NOT_FOUND));
+    let body = job.take_json::<Value>().await.unwrap();
+    assert_eq!(body["code"], "job_not_found");
+
+    // 完成的任务的匹配结果带有与检索接口相同的预览图链接
+    let mut search = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/search?model_type=%E5%9F%BA%E5%BA%A7&limit=1",
+    )
+    .await;
+    let search = search.take_json::<Value>().await.unwrap();
+    let matched = material_rs::MODELS
+        .load()
+        .search(&material_rs::query::UserQuery {
+            model_type: Some("基座".to_string()),
+            ..Default::default()
+        });
+    let mut record = finished_job(material_rs::job::JobKind::Search, 1, "基座");
+    record.matches = matched.into_iter().take(1).collect();
+    let id = material_rs::JOBS.create(record);
+    let mut job = get(
+        &env.service,
+        &format!("http://127.0.0.1:5800/material/api/v1/jobs/{}/result", id),
+    )
+    .await;
+    assert_eq!(job.status_code, Some(StatusCode::OK));
+    let body = job.take_json::<Value>().await.unwrap();
+    assert_eq!(
+        body["data"]["matches"][0]["pages"],
+        search["data"][0]["pages"]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn other_users_jobs() {
+    let env = setup();
+    // 只能重新分析自己上传的文件
+    let id = material_rs::JOBS.create(finished_job(material_rs::job::JobKind::Pdf, 8, "03-jz.pdf"));
+    let force = message(
+        7,
+        4,
+        json!({ "content": format!("/force {}", id), "content_type": "text/plain", "type": "normal" }),
+    );
+    post_webhook(&env.service, &force).await;
+    let reply = wait_for_message(7).await;
+    assert!(reply.contains("不是你上传的文件"), "{}", reply);
+
+    // 只能评价自己的任务，评价不会记录到别人的任务上
+    let feedback = message(
+        10,
+        5,
+        json!({ "content": format!("/feedback {} bad", id), "content_type": "text/plain", "type": "normal" }),
+    );
+    post_webhook(&env.service, &feedback).await;
+    let reply = wait_for_message(10).await;
+    assert!(reply.contains("不能评价"), "{}", reply);
+    assert!(material_rs::JOBS.get(&id).unwrap().feedback.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pdf_analysis() {
+    let env = setup();
+    if !has_poppler() {
+        eprintln!("没有安装poppler，跳过PDF分析的测试");
+        return;
+    }
+    let response = post_webhook(&env.service, &pdf_upload(3, 2)).await;
+    assert_eq!(response["message"], "📄 收到PDF文件，正在分析中，请稍等...");
+    let result = wait_for_message(3).await;
+    assert!(result.contains("相似度比较的结果"), "{}", result);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pdf_conversion_failure() {
+    let env = setup();
+    if has_poppler() {
+        eprintln!("已经安装poppler，跳过PDF转换失败的测试");
+        return;
+    }
+    // 转换失败时也应该把失败原因发给用户
+    let response = post_webhook(&env.service, &pdf_upload(4, 3)).await;
+    assert_eq!(response["message"], "📄 收到PDF文件，正在分析中，请稍等...");
+    let result = wait_for_message(4).await;
+    assert!(result.contains("PDF 转换失败"), "{}", result);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn job_events() {
+    let env = setup();
+    // 已经结束的任务只推送当前状态，然后关闭事件流
+    let id = material_rs::JOBS.create(finished_job(material_rs::job::JobKind::Pdf, 9, "03-jz.pdf"));
+    let mut events = get(
+        &env.service,
+        &format!("http://127.0.0.1:5800/material/api/v1/jobs/{}/events", id),
+    )
+    .await;
+    assert_eq!(
+        events.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+    let events = events.take_string().await.unwrap();
+    assert!(events.ends_with("\n\n"), "{}", events);
+    assert!(events.contains("event: finished\n"), "{}", events);
+    let missing = get(
+        &env.service,
+        "http://127.0.0.1:5800/material/api/v1/jobs/0123456789/events",
+    )
+    .await;
+    assert_eq!(missing.status_code, Some(StatusCode::NOT_FOUND));
+    // 没有升级请求头时不能建立WebSocket连接
+    let socket = get(
+        &env.service,
+        &format!("http://127.0.0.1:5800/material/api/v1/jobs/{}/ws", id),
+    )
+    .await;
+    assert_eq!(socket.status_code, Some(StatusCode::BAD_REQUEST));
+
+    // 已经结束的任务推送当前状态后关闭连接，升级需要真实的连接
+    let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+    let addr = acceptor.holdings()[0].local_addr.clone();
+    tokio::spawn(Server::new(acceptor).serve(router::build()));
+    let mut request = format!(
+        "ws://{}/material/api/v1/jobs/{}/ws",
+        addr.into_std().unwrap(),
+        id
+    )
+    .into_client_request()
+    .unwrap();
+    request
+        .headers_mut()
+        .insert("authorization", API_KEY.parse().unwrap());
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+    let mut events = Vec::new();
+    while let Some(message) = socket.next().await {
+        let message = message.unwrap();
+        if message.is_close() {
+            break;
+        }
+        let progress: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        events.push(progress["event"].as_str().unwrap().to_string());
+    }
+    assert_eq!(events.last().map(String::as_str), Some("finished"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn previous_reports() {
+    let env = setup();
+    // 其他用户之前分析过相同的PDF时重新分析，只有自己上传过的才返回之前的报告
+    let hash = material_rs::blob::hash_file(&env.upload_dir.join("2025/8/7/03-jz")).unwrap();
+    let mut record = finished_job(material_rs::job::JobKind::Pdf, 5, "03-jz.pdf");
+    record.content_hash = Some(hash);
+    let previous = material_rs::JOBS.create(record);
+    material_rs::JOBS
+        .save_report(&previous, "## 用户5的报告")
+        .unwrap();
+    let response = post_webhook(&env.service, &pdf_upload(6, 6)).await;
+    assert_eq!(response["message"], "📄 收到PDF文件，正在分析中，请稍等...");
+    let result = wait_for_message(6).await;
+    assert!(!result.contains("用户5的报告"), "{}", result);
+    let response = post_webhook(&env.service, &pdf_upload(5, 7)).await;
+    let reply = response["message"].as_str().unwrap();
+    assert!(reply.contains("已经分析过"), "{}", reply);
+    assert!(reply.ends_with("## 用户5的报告"), "{}", reply);
+}